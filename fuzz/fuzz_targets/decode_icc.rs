@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io;
+
+use icc::decode_icc;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_icc(&mut io::Cursor::new(data));
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io;
+
+use jp2::decode_jp2;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_jp2(&mut io::Cursor::new(data));
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io;
+
+use jpc::decode_jpc;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_jpc(&mut io::Cursor::new(data));
+});
@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+use jpxml::{encode_jp2, encode_jpc, Representation};
+
+// The encoder reads from a file, the input is written to one per process.
+fuzz_target!(|data: &[u8]| {
+    let path = std::env::temp_dir().join(format!("encode_jpxml-{}", process::id()));
+    let mut file = fs::File::create(&path).unwrap();
+    file.write_all(data).unwrap();
+
+    for representation in [
+        Representation::Skeleton,
+        Representation::FatSkeleton,
+        Representation::Fat,
+    ] {
+        let file = fs::File::open(&path).unwrap();
+        let _ = encode_jp2(&mut io::sink(), &file, representation, "fuzz");
+        let file = fs::File::open(&path).unwrap();
+        let _ = encode_jpc(&mut io::sink(), &file, representation);
+    }
+});
@@ -0,0 +1,469 @@
+// Encoding of restricted ICC profiles, ISO/IEC 15444-1 I.3.2.
+//
+// Monochrome and three-component matrix-based Input profiles are written as
+// version 2 profiles with the tag types of ICC.1:2001-04 by default, tag data
+// elements are aligned on 4 bytes. Version 4 profiles have the profile ID
+// calculated as in ICC.1:2004-10 7.2.18 and multiLocalizedUnicodeType
+// description and copyright tags, the profile ID is zero in version 2.
+
+use std::io;
+
+use super::md5::md5;
+use super::tags::{
+    parametric_parameters, TAG_TYPE_CURVE, TAG_TYPE_MULTI_LOCALIZED_UNICODE,
+    TAG_TYPE_PARAMETRIC_CURVE, TAG_TYPE_TEXT, TAG_TYPE_TEXT_DESCRIPTION, TAG_TYPE_XYZ,
+};
+use super::{
+    Curve, ICCError, RenderingIntent, Version, XYZNumber, HEADER_SIZE, PROFILE_FILE_SIGNATURE,
+    TAG_SIGNATURE_BLUE_COLORANT, TAG_SIGNATURE_BLUE_TRC, TAG_SIGNATURE_COPYRIGHT,
+    TAG_SIGNATURE_GRAY_TRC, TAG_SIGNATURE_GREEN_COLORANT, TAG_SIGNATURE_GREEN_TRC,
+    TAG_SIGNATURE_MEDIA_WHITE_POINT, TAG_SIGNATURE_PROFILE_DESCRIPTION, TAG_SIGNATURE_RED_COLORANT,
+    TAG_SIGNATURE_RED_TRC,
+};
+
+// The D50 illuminant of the profile connection space, as encoded in
+// s15Fixed16Number values.
+const D50: XYZNumber = XYZNumber {
+    x: 0.9642,
+    y: 1.0,
+    z: 0.8249,
+};
+
+// Size of the ScriptCode description of textDescriptionType
+const SCRIPT_CODE_SIZE: usize = 67;
+
+// The signature of a tag and its data element.
+type TagElement = ([u8; 4], Vec<u8>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Device {
+    Monochrome(Curve),
+    Matrix([XYZNumber; 3], [Curve; 3]),
+}
+
+/// Builder of a restricted ICC Input profile, a monochrome profile or a
+/// three-component matrix-based profile with an XYZ profile connection space.
+///
+/// ```
+/// use cc::{Curve, ProfileBuilder};
+///
+/// let profile = ProfileBuilder::monochrome(Curve::Gamma(2.2))
+///     .description("Gamma 2.2 grey")
+///     .encode_to_vec()
+///     .unwrap();
+/// assert_eq!(&profile[36..40], b"acsp");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileBuilder {
+    device: Device,
+    version: Version,
+    date_time: [u16; 6],
+    rendering_intent: RenderingIntent,
+    media_white_point: XYZNumber,
+    description: String,
+    copyright: String,
+}
+
+impl ProfileBuilder {
+    fn new(device: Device) -> ProfileBuilder {
+        ProfileBuilder {
+            device,
+            version: Version {
+                major: 2,
+                minor: 4,
+                bugfix: 0,
+            },
+            date_time: [0; 6],
+            rendering_intent: RenderingIntent::Perceptual,
+            media_white_point: D50,
+            description: String::new(),
+            copyright: String::new(),
+        }
+    }
+
+    /// A monochrome profile of a GRAY colour space, with the tone
+    /// reproduction curve from the grey values to the luminance of the
+    /// profile connection space.
+    pub fn monochrome(gray_trc: Curve) -> ProfileBuilder {
+        ProfileBuilder::new(Device::Monochrome(gray_trc))
+    }
+
+    /// A three-component matrix-based profile of an RGB colour space, with
+    /// the red, green and blue colorants relative to D50 and the tone
+    /// reproduction curves from the red, green and blue values to their
+    /// linear values.
+    pub fn three_component_matrix(colorants: [XYZNumber; 3], trcs: [Curve; 3]) -> ProfileBuilder {
+        ProfileBuilder::new(Device::Matrix(colorants, trcs))
+    }
+
+    /// Profile version of the header, 2.4.0 by default. From version 4 the
+    /// profile ID is set and the description and copyright are
+    /// multiLocalizedUnicodeType tags.
+    pub fn version(mut self, version: Version) -> ProfileBuilder {
+        self.version = version;
+        self
+    }
+
+    /// Date and time of creation, the year, month, day, hours, minutes and
+    /// seconds, zero by default.
+    pub fn date_time(mut self, date_time: [u16; 6]) -> ProfileBuilder {
+        self.date_time = date_time;
+        self
+    }
+
+    /// Rendering intent of the header, perceptual by default.
+    pub fn rendering_intent(mut self, rendering_intent: RenderingIntent) -> ProfileBuilder {
+        self.rendering_intent = rendering_intent;
+        self
+    }
+
+    /// Media white point, D50 by default.
+    pub fn media_white_point(mut self, media_white_point: XYZNumber) -> ProfileBuilder {
+        self.media_white_point = media_white_point;
+        self
+    }
+
+    /// Profile description, a 7-bit ASCII string.
+    pub fn description(mut self, description: &str) -> ProfileBuilder {
+        self.description = description.to_owned();
+        self
+    }
+
+    /// Copyright text, a 7-bit ASCII string.
+    pub fn copyright(mut self, copyright: &str) -> ProfileBuilder {
+        self.copyright = copyright.to_owned();
+        self
+    }
+
+    // Tags of the profile in the order of the tag table, the errors of a tag
+    // have the offset of its entry in the table.
+    fn tags(&self) -> Result<Vec<TagElement>, ICCError> {
+        let mut tags: Vec<TagElement> = vec![];
+        let description = if self.version.major >= 4 {
+            encode_multi_localized_unicode(
+                TAG_SIGNATURE_PROFILE_DESCRIPTION,
+                entry_offset(tags.len()),
+                &self.description,
+            )?
+        } else {
+            encode_text_description(
+                TAG_SIGNATURE_PROFILE_DESCRIPTION,
+                entry_offset(tags.len()),
+                &self.description,
+            )?
+        };
+        tags.push((TAG_SIGNATURE_PROFILE_DESCRIPTION, description));
+        tags.push((
+            TAG_SIGNATURE_MEDIA_WHITE_POINT,
+            encode_xyz(
+                TAG_SIGNATURE_MEDIA_WHITE_POINT,
+                entry_offset(tags.len()),
+                &self.media_white_point,
+            )?,
+        ));
+        let copyright = if self.version.major >= 4 {
+            encode_multi_localized_unicode(
+                TAG_SIGNATURE_COPYRIGHT,
+                entry_offset(tags.len()),
+                &self.copyright,
+            )?
+        } else {
+            encode_text(
+                TAG_SIGNATURE_COPYRIGHT,
+                entry_offset(tags.len()),
+                &self.copyright,
+            )?
+        };
+        tags.push((TAG_SIGNATURE_COPYRIGHT, copyright));
+        match &self.device {
+            Device::Monochrome(gray_trc) => {
+                tags.push((
+                    TAG_SIGNATURE_GRAY_TRC,
+                    encode_curve(TAG_SIGNATURE_GRAY_TRC, entry_offset(tags.len()), gray_trc)?,
+                ));
+            }
+            Device::Matrix(colorants, trcs) => {
+                let colorant_signatures = [
+                    TAG_SIGNATURE_RED_COLORANT,
+                    TAG_SIGNATURE_GREEN_COLORANT,
+                    TAG_SIGNATURE_BLUE_COLORANT,
+                ];
+                for (signature, colorant) in colorant_signatures.iter().zip(colorants) {
+                    tags.push((
+                        *signature,
+                        encode_xyz(*signature, entry_offset(tags.len()), colorant)?,
+                    ));
+                }
+                let trc_signatures = [
+                    TAG_SIGNATURE_RED_TRC,
+                    TAG_SIGNATURE_GREEN_TRC,
+                    TAG_SIGNATURE_BLUE_TRC,
+                ];
+                for (signature, trc) in trc_signatures.iter().zip(trcs) {
+                    tags.push((
+                        *signature,
+                        encode_curve(*signature, entry_offset(tags.len()), trc)?,
+                    ));
+                }
+            }
+        }
+        Ok(tags)
+    }
+
+    fn header(&self, size: u32) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&size.to_be_bytes());
+        header[8] = self.version.major;
+        header[9] = (self.version.minor << 4) | (self.version.bugfix & 0x0f);
+        // Profile/device class, Input
+        header[12..16].copy_from_slice(b"scnr");
+        let colour_space = match self.device {
+            Device::Monochrome(_) => b"GRAY",
+            Device::Matrix(..) => b"RGB ",
+        };
+        header[16..20].copy_from_slice(colour_space);
+        header[20..24].copy_from_slice(b"XYZ ");
+        for (bytes, value) in header[24..36].chunks_exact_mut(2).zip(&self.date_time) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        header[36..40].copy_from_slice(&PROFILE_FILE_SIGNATURE);
+        let rendering_intent: u32 = match self.rendering_intent {
+            RenderingIntent::Perceptual => 0,
+            RenderingIntent::MediaRelativeColorimetric => 1,
+            RenderingIntent::Saturation => 2,
+            RenderingIntent::ICCAbsoluteColorimetric => 3,
+            RenderingIntent::Reserved { value } => value,
+        };
+        header[64..68].copy_from_slice(&rendering_intent.to_be_bytes());
+        // D50 is within the range of s15Fixed16Number
+        header[68..80].copy_from_slice(&xyz_number(&D50).unwrap());
+        header
+    }
+
+    /// Encode the profile, see `encode_to_vec`.
+    pub fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), ICCError> {
+        writer
+            .write_all(&self.encode_to_vec()?)
+            .map_err(|source| ICCError::Io { offset: 0, source })
+    }
+
+    /// Encode the profile.
+    ///
+    /// The tag data elements follow the tag table in its order, each is
+    /// aligned on 4 bytes, and tags with identical data elements share them.
+    /// The profile ID of the header of version 4 profiles is the MD5
+    /// fingerprint of the profile, version 2 profiles have none.
+    pub fn encode_to_vec(&self) -> Result<Vec<u8>, ICCError> {
+        let tags = self.tags()?;
+
+        let mut table: Vec<u8> = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut elements: Vec<u8> = vec![];
+        let start = HEADER_SIZE + table.len() + tags.len() * 12;
+        // Offsets of the data elements already written
+        let mut offsets: Vec<(usize, &[u8])> = vec![];
+        for (signature, element) in &tags {
+            let offset = match offsets.iter().find(|(_, other)| *other == &element[..]) {
+                Some((offset, _)) => *offset,
+                None => {
+                    let offset = start + elements.len();
+                    elements.extend_from_slice(element);
+                    elements.resize(elements.len().div_ceil(4) * 4, 0);
+                    offsets.push((offset, element));
+                    offset
+                }
+            };
+            table.extend_from_slice(signature);
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            table.extend_from_slice(&(element.len() as u32).to_be_bytes());
+        }
+
+        let size = start + elements.len();
+        if size > u32::MAX as usize {
+            return Err(ICCError::InvalidSize {
+                size: u32::MAX,
+                offset: 0,
+            });
+        }
+        let mut profile = self.header(size as u32).to_vec();
+        profile.extend_from_slice(&table);
+        profile.extend_from_slice(&elements);
+
+        if self.version.major >= 4 {
+            let id = profile_id(&profile);
+            profile[84..100].copy_from_slice(&id);
+        }
+        Ok(profile)
+    }
+}
+
+/// The profile ID of a profile, the MD5 fingerprint of the profile with the
+/// profile flags, rendering intent and profile ID fields of the header set
+/// to zero.
+pub fn profile_id(profile: &[u8]) -> [u8; 16] {
+    let mut profile = profile.to_vec();
+    for range in [44..48, 64..68, 84..100].iter() {
+        for byte in profile.iter_mut().take(range.end).skip(range.start) {
+            *byte = 0;
+        }
+    }
+    md5(&profile)
+}
+
+fn s15_fixed16(value: f64) -> Option<[u8; 4]> {
+    let value = (value * 65536.0).round();
+    if value < i32::MIN as f64 || value > i32::MAX as f64 {
+        return None;
+    }
+    Some((value as i32).to_be_bytes())
+}
+
+fn xyz_number(xyz: &XYZNumber) -> Option<[u8; 12]> {
+    let mut bytes = [0; 12];
+    bytes[0..4].copy_from_slice(&s15_fixed16(xyz.x)?);
+    bytes[4..8].copy_from_slice(&s15_fixed16(xyz.y)?);
+    bytes[8..12].copy_from_slice(&s15_fixed16(xyz.z)?);
+    Some(bytes)
+}
+
+// The type signature and reserved field which start each data element.
+fn element(type_signature: [u8; 4]) -> Vec<u8> {
+    let mut element = type_signature.to_vec();
+    element.extend_from_slice(&[0; 4]);
+    element
+}
+
+// Offset of the entry of the tag table of the tag at the index.
+fn entry_offset(index: usize) -> u64 {
+    (HEADER_SIZE + 4 + 12 * index) as u64
+}
+
+fn ascii(
+    signature: [u8; 4],
+    offset: u64,
+    type_signature: [u8; 4],
+    text: &str,
+) -> Result<Vec<u8>, ICCError> {
+    if !text.bytes().all(|byte| byte.is_ascii() && byte != 0) {
+        return Err(ICCError::TagMalformed {
+            signature,
+            type_signature,
+            offset,
+        });
+    }
+    let mut ascii = text.as_bytes().to_vec();
+    ascii.push(0);
+    Ok(ascii)
+}
+
+fn encode_xyz(signature: [u8; 4], offset: u64, xyz: &XYZNumber) -> Result<Vec<u8>, ICCError> {
+    let mut data = element(TAG_TYPE_XYZ);
+    data.extend_from_slice(&xyz_number(xyz).ok_or(ICCError::TagMalformed {
+        signature,
+        type_signature: TAG_TYPE_XYZ,
+        offset,
+    })?);
+    Ok(data)
+}
+
+fn encode_curve(signature: [u8; 4], offset: u64, curve: &Curve) -> Result<Vec<u8>, ICCError> {
+    let malformed = |type_signature| ICCError::TagMalformed {
+        signature,
+        type_signature,
+        offset,
+    };
+    let data = match curve {
+        Curve::Identity => {
+            let mut data = element(TAG_TYPE_CURVE);
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data
+        }
+        Curve::Gamma(gamma) => {
+            // The gamma is a u8Fixed8Number
+            let gamma = (gamma * 256.0).round();
+            if !(0.0..=65535.0).contains(&gamma) {
+                return Err(malformed(TAG_TYPE_CURVE));
+            }
+            let mut data = element(TAG_TYPE_CURVE);
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(&(gamma as u16).to_be_bytes());
+            data
+        }
+        Curve::Table(table) => {
+            // Tables of fewer than two entries would be read as the identity
+            // or a gamma
+            if table.len() < 2 || table.len() > u32::MAX as usize {
+                return Err(malformed(TAG_TYPE_CURVE));
+            }
+            let mut data = element(TAG_TYPE_CURVE);
+            data.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            for entry in table {
+                data.extend_from_slice(&entry.to_be_bytes());
+            }
+            data
+        }
+        Curve::Parametric {
+            function,
+            parameters,
+        } => {
+            if parametric_parameters(*function) != Some(parameters.len()) {
+                return Err(malformed(TAG_TYPE_PARAMETRIC_CURVE));
+            }
+            let mut data = element(TAG_TYPE_PARAMETRIC_CURVE);
+            data.extend_from_slice(&function.to_be_bytes());
+            data.extend_from_slice(&[0; 2]);
+            for parameter in parameters {
+                data.extend_from_slice(
+                    &s15_fixed16(*parameter).ok_or_else(|| malformed(TAG_TYPE_PARAMETRIC_CURVE))?,
+                );
+            }
+            data
+        }
+    };
+    Ok(data)
+}
+
+fn encode_text(signature: [u8; 4], offset: u64, text: &str) -> Result<Vec<u8>, ICCError> {
+    let mut data = element(TAG_TYPE_TEXT);
+    data.extend_from_slice(&ascii(signature, offset, TAG_TYPE_TEXT, text)?);
+    Ok(data)
+}
+
+fn encode_text_description(
+    signature: [u8; 4],
+    offset: u64,
+    description: &str,
+) -> Result<Vec<u8>, ICCError> {
+    let description = ascii(signature, offset, TAG_TYPE_TEXT_DESCRIPTION, description)?;
+    let mut data = element(TAG_TYPE_TEXT_DESCRIPTION);
+    data.extend_from_slice(&(description.len() as u32).to_be_bytes());
+    data.extend_from_slice(&description);
+    // No Unicode description, its language code and count
+    data.extend_from_slice(&[0; 8]);
+    // No ScriptCode description, its code, count and fixed size string
+    data.extend_from_slice(&[0; 3]);
+    data.extend_from_slice(&[0; SCRIPT_CODE_SIZE]);
+    Ok(data)
+}
+
+// A multiLocalizedUnicodeType data element of a single en-US record, the
+// string follows the record.
+fn encode_multi_localized_unicode(
+    signature: [u8; 4],
+    offset: u64,
+    text: &str,
+) -> Result<Vec<u8>, ICCError> {
+    ascii(signature, offset, TAG_TYPE_MULTI_LOCALIZED_UNICODE, text)?;
+    let text: Vec<u8> = text
+        .encode_utf16()
+        .flat_map(|unit| unit.to_be_bytes().to_vec())
+        .collect();
+    let mut data = element(TAG_TYPE_MULTI_LOCALIZED_UNICODE);
+    // Number of records and the record size
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(&12u32.to_be_bytes());
+    data.extend_from_slice(b"enUS");
+    data.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    data.extend_from_slice(&28u32.to_be_bytes());
+    data.extend_from_slice(&text);
+    Ok(data)
+}
@@ -1,83 +1,617 @@
-#![allow(dead_code)]
-
-use log::warn;
+use log::{debug, warn};
 use std::error;
-use std::io;
+use std::fmt;
+use std::io::{self, Read};
+use std::str;
+
+mod encode;
+mod md5;
+mod restricted;
+mod tags;
+
+pub use encode::{profile_id, ProfileBuilder};
+pub use restricted::{
+    check_restricted_profile, RestrictedProfileError, RestrictedProfileType, Violation,
+};
+pub use tags::{Curve, LocalizedString, TagData, XYZNumber};
+
+use tags::{decode_tag_data, read_xyz_number};
+
+/// An error decoding or encoding a profile, the offset of each variant is
+/// the byte offset from the start of the profile of the field or tag in
+/// error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ICCError {
+    InvalidSignature {
+        signature: [u8; 4],
+        offset: u64,
+    },
+    InvalidSize {
+        size: u32,
+        offset: u64,
+    },
+    InvalidTagCount {
+        count: u32,
+        offset: u64,
+    },
+    /// A tag whose data element, at the offset, exceeds the profile.
+    TagOutOfBounds {
+        signature: [u8; 4],
+        offset: u64,
+        size: u32,
+    },
+    /// A tag whose data element is malformed, the offset is of its data
+    /// element when decoding and of its entry of the tag table when encoding.
+    TagMalformed {
+        signature: [u8; 4],
+        type_signature: [u8; 4],
+        offset: u64,
+    },
+    Io {
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl ICCError {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::InvalidSignature { offset, .. }
+            | Self::InvalidSize { offset, .. }
+            | Self::InvalidTagCount { offset, .. }
+            | Self::TagOutOfBounds { offset, .. }
+            | Self::TagMalformed { offset, .. }
+            | Self::Io { offset, .. } => *offset,
+        }
+    }
+}
+
+impl error::Error for ICCError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ICCError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidSignature { signature, offset } => {
+                write!(
+                    f,
+                    "invalid profile file signature {:?} at offset {}",
+                    String::from_utf8_lossy(signature),
+                    offset
+                )
+            }
+            Self::InvalidSize { size, offset } => {
+                write!(f, "invalid profile size {} at offset {}", size, offset)
+            }
+            Self::InvalidTagCount { count, offset } => {
+                write!(
+                    f,
+                    "tag count {} at offset {} exceeds the profile size",
+                    count, offset
+                )
+            }
+            Self::TagOutOfBounds {
+                signature,
+                offset,
+                size,
+            } => {
+                write!(
+                    f,
+                    "tag {:?} at offset {} of size {} exceeds the profile size",
+                    String::from_utf8_lossy(signature),
+                    offset,
+                    size
+                )
+            }
+            Self::TagMalformed {
+                signature,
+                type_signature,
+                offset,
+            } => {
+                write!(
+                    f,
+                    "malformed tag {:?} of type {:?} at offset {}",
+                    String::from_utf8_lossy(signature),
+                    String::from_utf8_lossy(type_signature),
+                    offset
+                )
+            }
+            Self::Io { offset, source } => {
+                write!(f, "{} at offset {}", source, offset)
+            }
+        }
+    }
+}
+
+const HEADER_SIZE: usize = 128;
+
+// The profile file signature, 'acsp'
+const PROFILE_FILE_SIGNATURE: [u8; 4] = *b"acsp";
+
+/// Profile/device class signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileClass {
+    Input,
+    Display,
+    Output,
+    DeviceLink,
+    ColourSpaceConversion,
+    Abstract,
+    NamedColour,
+    Unknown { signature: [u8; 4] },
+}
+
+impl ProfileClass {
+    fn new(signature: [u8; 4]) -> ProfileClass {
+        match &signature {
+            b"scnr" => ProfileClass::Input,
+            b"mntr" => ProfileClass::Display,
+            b"prtr" => ProfileClass::Output,
+            b"link" => ProfileClass::DeviceLink,
+            b"spac" => ProfileClass::ColourSpaceConversion,
+            b"abst" => ProfileClass::Abstract,
+            b"nmcl" => ProfileClass::NamedColour,
+            _ => ProfileClass::Unknown { signature },
+        }
+    }
+}
+
+/// Colour space signature, of the data colour space or of the profile
+/// connection space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourSpace {
+    Xyz,
+    Lab,
+    Luv,
+    YCbCr,
+    Yxy,
+    Rgb,
+    Gray,
+    Hsv,
+    Hls,
+    Cmyk,
+    Cmy,
+    Other { signature: [u8; 4] },
+}
+
+impl ColourSpace {
+    fn new(signature: [u8; 4]) -> ColourSpace {
+        match &signature {
+            b"XYZ " => ColourSpace::Xyz,
+            b"Lab " => ColourSpace::Lab,
+            b"Luv " => ColourSpace::Luv,
+            b"YCbr" => ColourSpace::YCbCr,
+            b"Yxy " => ColourSpace::Yxy,
+            b"RGB " => ColourSpace::Rgb,
+            b"GRAY" => ColourSpace::Gray,
+            b"HSV " => ColourSpace::Hsv,
+            b"HLS " => ColourSpace::Hls,
+            b"CMYK" => ColourSpace::Cmyk,
+            b"CMY " => ColourSpace::Cmy,
+            _ => ColourSpace::Other { signature },
+        }
+    }
+}
+
+/// Rendering intent of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    MediaRelativeColorimetric,
+    Saturation,
+    ICCAbsoluteColorimetric,
+    Reserved { value: u32 },
+}
+
+/// Profile version, the major, minor and bug fix revisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub bugfix: u8,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.bugfix)
+    }
+}
+
+/// The 128 byte profile header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileHeader {
+    size: [u8; 4],
+    cmm: [u8; 4],
+    version: [u8; 4],
+    class: [u8; 4],
+    colour_space: [u8; 4],
+    pcs: [u8; 4],
+    date_time: [u8; 12],
+    signature: [u8; 4],
+    platform: [u8; 4],
+    flags: [u8; 4],
+    manufacturer: [u8; 4],
+    model: [u8; 4],
+    attributes: [u8; 8],
+    rendering_intent: [u8; 4],
+    illuminant: [u8; 12],
+    creator: [u8; 4],
+    id: [u8; 16],
+}
+
+impl ProfileHeader {
+    fn new(header: &[u8; HEADER_SIZE]) -> ProfileHeader {
+        let field = |start: usize, end: usize| &header[start..end];
+        let mut profile_header = ProfileHeader {
+            size: [0; 4],
+            cmm: [0; 4],
+            version: [0; 4],
+            class: [0; 4],
+            colour_space: [0; 4],
+            pcs: [0; 4],
+            date_time: [0; 12],
+            signature: [0; 4],
+            platform: [0; 4],
+            flags: [0; 4],
+            manufacturer: [0; 4],
+            model: [0; 4],
+            attributes: [0; 8],
+            rendering_intent: [0; 4],
+            illuminant: [0; 12],
+            creator: [0; 4],
+            id: [0; 16],
+        };
+        profile_header.size.copy_from_slice(field(0, 4));
+        profile_header.cmm.copy_from_slice(field(4, 8));
+        profile_header.version.copy_from_slice(field(8, 12));
+        profile_header.class.copy_from_slice(field(12, 16));
+        profile_header.colour_space.copy_from_slice(field(16, 20));
+        profile_header.pcs.copy_from_slice(field(20, 24));
+        profile_header.date_time.copy_from_slice(field(24, 36));
+        profile_header.signature.copy_from_slice(field(36, 40));
+        profile_header.platform.copy_from_slice(field(40, 44));
+        profile_header.flags.copy_from_slice(field(44, 48));
+        profile_header.manufacturer.copy_from_slice(field(48, 52));
+        profile_header.model.copy_from_slice(field(52, 56));
+        profile_header.attributes.copy_from_slice(field(56, 64));
+        profile_header
+            .rendering_intent
+            .copy_from_slice(field(64, 68));
+        profile_header.illuminant.copy_from_slice(field(68, 80));
+        profile_header.creator.copy_from_slice(field(80, 84));
+        profile_header.id.copy_from_slice(field(84, 100));
+        profile_header
+    }
+
+    /// Size of the profile in bytes.
+    pub fn size(&self) -> u32 {
+        u32::from_be_bytes(self.size)
+    }
+
+    /// Preferred CMM type signature.
+    pub fn cmm(&self) -> [u8; 4] {
+        self.cmm
+    }
+
+    pub fn version(&self) -> Version {
+        Version {
+            major: self.version[0],
+            minor: self.version[1] >> 4,
+            bugfix: self.version[1] & 0x0f,
+        }
+    }
+
+    pub fn class(&self) -> ProfileClass {
+        ProfileClass::new(self.class)
+    }
+
+    /// Colour space of the data.
+    pub fn colour_space(&self) -> ColourSpace {
+        ColourSpace::new(self.colour_space)
+    }
+
+    /// Profile connection space.
+    pub fn pcs(&self) -> ColourSpace {
+        ColourSpace::new(self.pcs)
+    }
+
+    /// Date and time of creation, the year, month, day, hours, minutes and
+    /// seconds.
+    pub fn date_time(&self) -> [u16; 6] {
+        let mut date_time = [0; 6];
+        for (value, bytes) in date_time.iter_mut().zip(self.date_time.chunks_exact(2)) {
+            *value = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+        date_time
+    }
+
+    /// Profile file signature, 'acsp'.
+    pub fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+
+    /// Primary platform signature.
+    pub fn platform(&self) -> [u8; 4] {
+        self.platform
+    }
+
+    /// Profile flags, bit 0 for an embedded profile and bit 1 for a profile
+    /// which cannot be used independently of the embedded colour data.
+    pub fn flags(&self) -> u32 {
+        u32::from_be_bytes(self.flags)
+    }
+
+    /// Device manufacturer signature.
+    pub fn manufacturer(&self) -> [u8; 4] {
+        self.manufacturer
+    }
+
+    /// Device model signature.
+    pub fn model(&self) -> [u8; 4] {
+        self.model
+    }
+
+    /// Device attributes.
+    pub fn attributes(&self) -> u64 {
+        u64::from_be_bytes(self.attributes)
+    }
+
+    pub fn rendering_intent(&self) -> RenderingIntent {
+        match u32::from_be_bytes(self.rendering_intent) {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::MediaRelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::ICCAbsoluteColorimetric,
+            value => RenderingIntent::Reserved { value },
+        }
+    }
+
+    /// Illuminant of the profile connection space, D50.
+    pub fn illuminant(&self) -> XYZNumber {
+        // The field is 12 bytes long
+        read_xyz_number(&self.illuminant, 0).unwrap()
+    }
+
+    /// Profile creator signature.
+    pub fn creator(&self) -> [u8; 4] {
+        self.creator
+    }
+
+    /// Profile ID, an MD5 fingerprint of the profile or zero when not
+    /// calculated.
+    pub fn id(&self) -> [u8; 16] {
+        self.id
+    }
+}
 
 // Each tag signature in the tag table must be unique;
 // a profile cannot contain more than one tag with the same signature.
-struct Tag {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
     signature: [u8; 4],
     offset: [u8; 4], // uInt32Number
     size: [u8; 4],   // uInt32Number
+    data: TagData,
 }
 
 impl Tag {
     // A four byte value registered with the ICC
-    fn signature(&self) -> [u8; 4] {
+    pub fn signature(&self) -> [u8; 4] {
         self.signature
     }
 
     // An address within an ICC profile, relative to byte zero of the file.
-    fn offset(&self) -> u32 {
+    pub fn offset(&self) -> u32 {
         u32::from_be_bytes(self.offset)
     }
 
     // The number of bytes in the tag data element.
-    fn size(&self) -> u32 {
+    pub fn size(&self) -> u32 {
         u32::from_be_bytes(self.size)
     }
-}
 
-#[derive(Debug)]
-pub struct ICCProfile {}
+    pub fn data(&self) -> &TagData {
+        &self.data
+    }
+}
 
-pub fn decode_icc<R: io::Read + io::Seek>(
-    reader: &mut R,
-) -> Result<ICCProfile, Box<dyn error::Error>> {
-    let icc_start_position = reader.stream_position()?;
+pub const TAG_SIGNATURE_RED_COLORANT: [u8; 4] = *b"rXYZ";
+pub const TAG_SIGNATURE_GREEN_COLORANT: [u8; 4] = *b"gXYZ";
+pub const TAG_SIGNATURE_BLUE_COLORANT: [u8; 4] = *b"bXYZ";
+pub const TAG_SIGNATURE_RED_TRC: [u8; 4] = *b"rTRC";
+pub const TAG_SIGNATURE_GREEN_TRC: [u8; 4] = *b"gTRC";
+pub const TAG_SIGNATURE_BLUE_TRC: [u8; 4] = *b"bTRC";
+pub const TAG_SIGNATURE_GRAY_TRC: [u8; 4] = *b"kTRC";
+pub const TAG_SIGNATURE_MEDIA_WHITE_POINT: [u8; 4] = *b"wtpt";
+pub const TAG_SIGNATURE_PROFILE_DESCRIPTION: [u8; 4] = *b"desc";
+pub const TAG_SIGNATURE_COPYRIGHT: [u8; 4] = *b"cprt";
 
-    let mut header: [u8; 128] = [0; 128];
-    reader.read_exact(&mut header)?;
+#[derive(Debug, Clone, PartialEq)]
+pub struct ICCProfile {
+    header: ProfileHeader,
+    tags: Vec<Tag>,
+}
 
-    let mut tag_count: [u8; 4] = [0; 4];
-    reader.read_exact(&mut tag_count)?;
+impl ICCProfile {
+    pub fn header(&self) -> &ProfileHeader {
+        &self.header
+    }
 
-    let tag_table_size = u32::from_be_bytes(tag_count) as usize;
+    /// Tags of the tag table, in the order of the table.
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
 
-    let mut tag_table: Vec<Tag> = Vec::with_capacity(tag_table_size as usize);
+    /// Data of the tag with the signature.
+    pub fn tag(&self, signature: &[u8; 4]) -> Option<&TagData> {
+        self.tags
+            .iter()
+            .find(|tag| &tag.signature == signature)
+            .map(|tag| &tag.data)
+    }
 
-    let mut largest_offset: u32 = 0;
-    let mut largest_size: u32 = 0;
-    loop {
-        let mut tag = Tag {
-            signature: [0; 4],
-            offset: [0; 4],
-            size: [0; 4],
-        };
-        reader.read_exact(&mut tag.signature)?;
-        reader.read_exact(&mut tag.offset)?;
-        reader.read_exact(&mut tag.size)?;
+    fn xyz_tag(&self, signature: &[u8; 4]) -> Option<XYZNumber> {
+        match self.tag(signature)? {
+            TagData::Xyz(values) => values.first().copied(),
+            _ => None,
+        }
+    }
 
-        if tag.offset() > largest_offset {
-            largest_offset = tag.offset();
-            largest_size = tag.size();
+    fn curve_tag(&self, signature: &[u8; 4]) -> Option<&Curve> {
+        match self.tag(signature)? {
+            TagData::Curve(curve) => Some(curve),
+            _ => None,
         }
+    }
 
-        tag_table.push(tag);
+    pub fn red_colorant(&self) -> Option<XYZNumber> {
+        self.xyz_tag(&TAG_SIGNATURE_RED_COLORANT)
+    }
 
-        if tag_table.len() == 6 {
-            break;
-        }
+    pub fn green_colorant(&self) -> Option<XYZNumber> {
+        self.xyz_tag(&TAG_SIGNATURE_GREEN_COLORANT)
+    }
+
+    pub fn blue_colorant(&self) -> Option<XYZNumber> {
+        self.xyz_tag(&TAG_SIGNATURE_BLUE_COLORANT)
+    }
+
+    pub fn red_trc(&self) -> Option<&Curve> {
+        self.curve_tag(&TAG_SIGNATURE_RED_TRC)
+    }
+
+    pub fn green_trc(&self) -> Option<&Curve> {
+        self.curve_tag(&TAG_SIGNATURE_GREEN_TRC)
+    }
+
+    pub fn blue_trc(&self) -> Option<&Curve> {
+        self.curve_tag(&TAG_SIGNATURE_BLUE_TRC)
+    }
+
+    pub fn gray_trc(&self) -> Option<&Curve> {
+        self.curve_tag(&TAG_SIGNATURE_GRAY_TRC)
+    }
+
+    pub fn media_white_point(&self) -> Option<XYZNumber> {
+        self.xyz_tag(&TAG_SIGNATURE_MEDIA_WHITE_POINT)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.tag(&TAG_SIGNATURE_PROFILE_DESCRIPTION)?.text()
+    }
+
+    pub fn copyright(&self) -> Option<&str> {
+        self.tag(&TAG_SIGNATURE_COPYRIGHT)?.text()
     }
+}
+
+/// Decode an ICC profile, the reader is left at the end of the profile given
+/// by the size of its header.
+pub fn decode_icc<R: io::Read + io::Seek>(reader: &mut R) -> Result<ICCProfile, ICCError> {
+    let mut header: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+    reader
+        .read_exact(&mut header)
+        .map_err(|source| ICCError::Io { offset: 0, source })?;
+    let header = ProfileHeader::new(&header);
 
-    reader.seek(io::SeekFrom::Start(
-        icc_start_position + largest_offset as u64 + largest_size as u64,
-    ))?;
+    if header.signature() != PROFILE_FILE_SIGNATURE {
+        return Err(ICCError::InvalidSignature {
+            signature: header.signature(),
+            offset: 36,
+        });
+    }
+    debug!("Profile size {:?}", header.size());
+    debug!("Profile version {}", header.version());
+    debug!("Profile class {:?}", header.class());
 
-    let mut unknown: [u8; 4] = [0; 4];
-    reader.read_exact(&mut unknown)?;
-    warn!("unknown bytes 3 {:?}", unknown);
+    // The header is followed by at least the tag count
+    let size = header.size();
+    if (size as usize) < HEADER_SIZE + 4 {
+        return Err(ICCError::InvalidSize { size, offset: 0 });
+    }
+    let mut profile: Vec<u8> = vec![0; HEADER_SIZE];
+    if let Err(source) = reader
+        .by_ref()
+        .take(size as u64 - HEADER_SIZE as u64)
+        .read_to_end(&mut profile)
+    {
+        return Err(ICCError::Io {
+            offset: profile.len() as u64,
+            source,
+        });
+    }
+    if profile.len() != size as usize {
+        return Err(ICCError::InvalidSize { size, offset: 0 });
+    }
+
+    let mut tag_count: [u8; 4] = [0; 4];
+    tag_count.copy_from_slice(&profile[HEADER_SIZE..HEADER_SIZE + 4]);
+    let tag_count = u32::from_be_bytes(tag_count);
+    let tag_table_end = (HEADER_SIZE as u64 + 4) + tag_count as u64 * 12;
+    if tag_table_end > size as u64 {
+        return Err(ICCError::InvalidTagCount {
+            count: tag_count,
+            offset: HEADER_SIZE as u64,
+        });
+    }
+    debug!("Tag count {:?}", tag_count);
+
+    let mut tags: Vec<Tag> = Vec::with_capacity(tag_count as usize);
+    for entry in profile[HEADER_SIZE + 4..tag_table_end as usize].chunks_exact(12) {
+        let mut signature: [u8; 4] = [0; 4];
+        let mut offset: [u8; 4] = [0; 4];
+        let mut tag_size: [u8; 4] = [0; 4];
+        signature.copy_from_slice(&entry[0..4]);
+        offset.copy_from_slice(&entry[4..8]);
+        tag_size.copy_from_slice(&entry[8..12]);
+
+        let start = u32::from_be_bytes(offset) as u64;
+        let end = start + u32::from_be_bytes(tag_size) as u64;
+        if end > size as u64 {
+            return Err(ICCError::TagOutOfBounds {
+                signature,
+                offset: start,
+                size: u32::from_be_bytes(tag_size),
+            });
+        }
+        let element = &profile[start as usize..end as usize];
+
+        let data = decode_tag_data(element).ok_or_else(|| {
+            let mut type_signature: [u8; 4] = [0; 4];
+            if let Some(bytes) = element.get(0..4) {
+                type_signature.copy_from_slice(bytes);
+            }
+            ICCError::TagMalformed {
+                signature,
+                type_signature,
+                offset: start,
+            }
+        })?;
+        if let TagData::Unknown { type_signature, .. } = &data {
+            debug!(
+                "Tag {:?} of unknown type {:?}",
+                str::from_utf8(&signature),
+                str::from_utf8(type_signature)
+            );
+        }
+        if tags.iter().any(|tag| tag.signature == signature) {
+            warn!("Duplicate tag {:?}", str::from_utf8(&signature));
+        }
+
+        tags.push(Tag {
+            signature,
+            offset,
+            size: tag_size,
+            data,
+        });
+    }
 
-    Ok(ICCProfile {})
+    Ok(ICCProfile { header, tags })
 }
@@ -0,0 +1,393 @@
+// Conformance of restricted ICC profiles, ISO/IEC 15444-1 I.3.2.
+//
+// A restricted ICC profile is a Monochrome Input or Three-Component
+// Matrix-Based Input profile, ICC.1:1998-09 6.3.1, converting the data to
+// the XYZ profile connection space. Display profiles of the same structure
+// are also accepted, as allowed by later editions of ISO/IEC 15444-1.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::tags::{
+    decode_tag_data, TAG_TYPE_CURVE, TAG_TYPE_MULTI_LOCALIZED_UNICODE, TAG_TYPE_PARAMETRIC_CURVE,
+    TAG_TYPE_TEXT, TAG_TYPE_TEXT_DESCRIPTION, TAG_TYPE_XYZ,
+};
+use super::{
+    decode_icc, ColourSpace, ProfileClass, HEADER_SIZE, PROFILE_FILE_SIGNATURE,
+    TAG_SIGNATURE_BLUE_COLORANT, TAG_SIGNATURE_BLUE_TRC, TAG_SIGNATURE_COPYRIGHT,
+    TAG_SIGNATURE_GRAY_TRC, TAG_SIGNATURE_GREEN_COLORANT, TAG_SIGNATURE_GREEN_TRC,
+    TAG_SIGNATURE_MEDIA_WHITE_POINT, TAG_SIGNATURE_PROFILE_DESCRIPTION, TAG_SIGNATURE_RED_COLORANT,
+    TAG_SIGNATURE_RED_TRC,
+};
+
+/// The kind of a restricted ICC profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestrictedProfileType {
+    Monochrome,
+    ThreeComponentMatrix,
+}
+
+/// A requirement of ISO/IEC 15444-1 I.3.2 not met by a profile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// The profile is shorter than its header and tag count.
+    Truncated {
+        length: usize,
+    },
+    /// The profile file signature is not 'acsp'.
+    InvalidSignature {
+        signature: [u8; 4],
+    },
+    /// The size of the header differs from the length of the profile.
+    SizeMismatch {
+        size: u32,
+        length: usize,
+    },
+    /// The class is not input or display.
+    Class {
+        class: ProfileClass,
+    },
+    /// The colour space is not greyscale or RGB.
+    ColourSpace {
+        colour_space: ColourSpace,
+    },
+    /// The profile connection space is not XYZ.
+    Pcs {
+        pcs: ColourSpace,
+    },
+    /// The tag table extends beyond the profile.
+    TagTableOutOfBounds {
+        count: u32,
+    },
+    TagMissing {
+        signature: [u8; 4],
+    },
+    TagDuplicate {
+        signature: [u8; 4],
+    },
+    /// The tag data extends beyond the profile.
+    TagOutOfBounds {
+        signature: [u8; 4],
+        offset: u32,
+        size: u32,
+    },
+    /// The tag data overlaps the header or the tag table.
+    TagOverlapsHeader {
+        signature: [u8; 4],
+    },
+    /// The tag data overlaps the data of another tag, tags may only share
+    /// identical data.
+    TagOverlap {
+        signature: [u8; 4],
+        other: [u8; 4],
+    },
+    /// The tag type is not allowed for the tag.
+    TagType {
+        signature: [u8; 4],
+        type_signature: [u8; 4],
+    },
+    /// The tag data cannot be decoded.
+    TagMalformed {
+        signature: [u8; 4],
+    },
+}
+
+fn signature_string(signature: &[u8; 4]) -> String {
+    String::from_utf8_lossy(signature).into_owned()
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated { length } => write!(f, "profile of {} bytes is truncated", length),
+            Self::InvalidSignature { signature } => write!(
+                f,
+                "invalid profile file signature {:?}",
+                signature_string(signature)
+            ),
+            Self::SizeMismatch { size, length } => write!(
+                f,
+                "profile size {} differs from the profile length {}",
+                size, length
+            ),
+            Self::Class { class } => {
+                write!(f, "profile class {:?} is not input or display", class)
+            }
+            Self::ColourSpace { colour_space } => {
+                write!(f, "colour space {:?} is not greyscale or RGB", colour_space)
+            }
+            Self::Pcs { pcs } => write!(f, "profile connection space {:?} is not XYZ", pcs),
+            Self::TagTableOutOfBounds { count } => {
+                write!(f, "tag table of {} tags exceeds the profile", count)
+            }
+            Self::TagMissing { signature } => {
+                write!(f, "required tag {:?} missing", signature_string(signature))
+            }
+            Self::TagDuplicate { signature } => {
+                write!(f, "duplicate tag {:?}", signature_string(signature))
+            }
+            Self::TagOutOfBounds {
+                signature,
+                offset,
+                size,
+            } => write!(
+                f,
+                "tag {:?} at offset {} of size {} exceeds the profile",
+                signature_string(signature),
+                offset,
+                size
+            ),
+            Self::TagOverlapsHeader { signature } => write!(
+                f,
+                "tag {:?} overlaps the header or the tag table",
+                signature_string(signature)
+            ),
+            Self::TagOverlap { signature, other } => write!(
+                f,
+                "tag {:?} overlaps {:?}",
+                signature_string(signature),
+                signature_string(other)
+            ),
+            Self::TagType {
+                signature,
+                type_signature,
+            } => write!(
+                f,
+                "tag {:?} has unexpected type {:?}",
+                signature_string(signature),
+                signature_string(type_signature)
+            ),
+            Self::TagMalformed { signature } => {
+                write!(f, "malformed tag {:?}", signature_string(signature))
+            }
+        }
+    }
+}
+
+/// The violations of ISO/IEC 15444-1 I.3.2 of a profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestrictedProfileError {
+    violations: Vec<Violation>,
+}
+
+impl RestrictedProfileError {
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+impl error::Error for RestrictedProfileError {}
+impl fmt::Display for RestrictedProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not a restricted ICC profile: ")?;
+        for (index, violation) in self.violations.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+// Tag types allowed for each tag of a restricted profile.
+const TAG_TYPES: [([u8; 4], &[[u8; 4]]); 10] = [
+    (TAG_SIGNATURE_RED_COLORANT, &[TAG_TYPE_XYZ]),
+    (TAG_SIGNATURE_GREEN_COLORANT, &[TAG_TYPE_XYZ]),
+    (TAG_SIGNATURE_BLUE_COLORANT, &[TAG_TYPE_XYZ]),
+    (
+        TAG_SIGNATURE_RED_TRC,
+        &[TAG_TYPE_CURVE, TAG_TYPE_PARAMETRIC_CURVE],
+    ),
+    (
+        TAG_SIGNATURE_GREEN_TRC,
+        &[TAG_TYPE_CURVE, TAG_TYPE_PARAMETRIC_CURVE],
+    ),
+    (
+        TAG_SIGNATURE_BLUE_TRC,
+        &[TAG_TYPE_CURVE, TAG_TYPE_PARAMETRIC_CURVE],
+    ),
+    (
+        TAG_SIGNATURE_GRAY_TRC,
+        &[TAG_TYPE_CURVE, TAG_TYPE_PARAMETRIC_CURVE],
+    ),
+    (TAG_SIGNATURE_MEDIA_WHITE_POINT, &[TAG_TYPE_XYZ]),
+    (
+        TAG_SIGNATURE_PROFILE_DESCRIPTION,
+        &[TAG_TYPE_TEXT_DESCRIPTION, TAG_TYPE_MULTI_LOCALIZED_UNICODE],
+    ),
+    (
+        TAG_SIGNATURE_COPYRIGHT,
+        &[TAG_TYPE_TEXT, TAG_TYPE_MULTI_LOCALIZED_UNICODE],
+    ),
+];
+
+// Tags required by every restricted profile, and by each kind.
+const REQUIRED_TAGS: [[u8; 4]; 3] = [
+    TAG_SIGNATURE_PROFILE_DESCRIPTION,
+    TAG_SIGNATURE_MEDIA_WHITE_POINT,
+    TAG_SIGNATURE_COPYRIGHT,
+];
+const MONOCHROME_TAGS: [[u8; 4]; 1] = [TAG_SIGNATURE_GRAY_TRC];
+const THREE_COMPONENT_MATRIX_TAGS: [[u8; 4]; 6] = [
+    TAG_SIGNATURE_RED_COLORANT,
+    TAG_SIGNATURE_GREEN_COLORANT,
+    TAG_SIGNATURE_BLUE_COLORANT,
+    TAG_SIGNATURE_RED_TRC,
+    TAG_SIGNATURE_GREEN_TRC,
+    TAG_SIGNATURE_BLUE_TRC,
+];
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_signature(data: &[u8], offset: usize) -> [u8; 4] {
+    [
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ]
+}
+
+/// Check that a profile is a restricted ICC profile of ISO/IEC 15444-1
+/// I.3.2, such as the PROFILE field of a Colour Specification box, reporting
+/// every violation found.
+pub fn check_restricted_profile(
+    data: &[u8],
+) -> Result<RestrictedProfileType, RestrictedProfileError> {
+    let mut violations: Vec<Violation> = vec![];
+    let error = |violations| Err(RestrictedProfileError { violations });
+
+    if data.len() < HEADER_SIZE + 4 {
+        return error(vec![Violation::Truncated { length: data.len() }]);
+    }
+    let signature = read_signature(data, 36);
+    if signature != PROFILE_FILE_SIGNATURE {
+        return error(vec![Violation::InvalidSignature { signature }]);
+    }
+    let size = read_u32(data, 0);
+    if size as usize != data.len() {
+        violations.push(Violation::SizeMismatch {
+            size,
+            length: data.len(),
+        });
+    }
+
+    // Header
+    let class = ProfileClass::new(read_signature(data, 12));
+    if class != ProfileClass::Input && class != ProfileClass::Display {
+        violations.push(Violation::Class { class });
+    }
+    let colour_space = ColourSpace::new(read_signature(data, 16));
+    let (profile_type, transformation_tags): (_, &[[u8; 4]]) = match colour_space {
+        ColourSpace::Gray => (Some(RestrictedProfileType::Monochrome), &MONOCHROME_TAGS),
+        ColourSpace::Rgb => (
+            Some(RestrictedProfileType::ThreeComponentMatrix),
+            &THREE_COMPONENT_MATRIX_TAGS,
+        ),
+        _ => {
+            violations.push(Violation::ColourSpace { colour_space });
+            (None, &[])
+        }
+    };
+    let pcs = ColourSpace::new(read_signature(data, 20));
+    if pcs != ColourSpace::Xyz {
+        violations.push(Violation::Pcs { pcs });
+    }
+
+    // Tag table, bounds and overlaps are checked against the data
+    let count = read_u32(data, HEADER_SIZE);
+    let tag_table_end = (HEADER_SIZE as u64 + 4) + count as u64 * 12;
+    if tag_table_end > data.len() as u64 {
+        violations.push(Violation::TagTableOutOfBounds { count });
+        return error(violations);
+    }
+    let mut elements: Vec<([u8; 4], u64, u64)> = vec![];
+    for index in 0..count as usize {
+        let entry = HEADER_SIZE + 4 + index * 12;
+        let signature = read_signature(data, entry);
+        let offset = read_u32(data, entry + 4);
+        let tag_size = read_u32(data, entry + 8);
+        let start = offset as u64;
+        let end = start + tag_size as u64;
+
+        if elements.iter().any(|(other, _, _)| *other == signature) {
+            violations.push(Violation::TagDuplicate { signature });
+        }
+        if end > data.len() as u64 {
+            violations.push(Violation::TagOutOfBounds {
+                signature,
+                offset,
+                size: tag_size,
+            });
+            continue;
+        }
+        if start < tag_table_end {
+            violations.push(Violation::TagOverlapsHeader { signature });
+        }
+        // Tags may share the same data element
+        if let Some((other, _, _)) = elements.iter().find(|(_, other_start, other_end)| {
+            (start, end) != (*other_start, *other_end) && start < *other_end && *other_start < end
+        }) {
+            violations.push(Violation::TagOverlap {
+                signature,
+                other: *other,
+            });
+        }
+        elements.push((signature, start, end));
+    }
+    if !violations.is_empty() {
+        return error(violations);
+    }
+
+    // Tags, decoded once the profile is known to be well formed
+    let profile = match decode_icc(&mut io::Cursor::new(data)) {
+        Ok(profile) => profile,
+        Err(_) => {
+            // Tags which cannot be decoded are found by decoding each one
+            for tag_data in data[HEADER_SIZE + 4..tag_table_end as usize].chunks_exact(12) {
+                let signature = read_signature(tag_data, 0);
+                let start = read_u32(tag_data, 4) as usize;
+                let end = start + read_u32(tag_data, 8) as usize;
+                if decode_tag_data(&data[start..end]).is_none() {
+                    violations.push(Violation::TagMalformed { signature });
+                }
+            }
+            return error(violations);
+        }
+    };
+    for signature in REQUIRED_TAGS.iter().chain(transformation_tags) {
+        if profile.tag(signature).is_none() {
+            violations.push(Violation::TagMissing {
+                signature: *signature,
+            });
+        }
+    }
+    for tag in profile.tags() {
+        let type_signature = tag.data().type_signature();
+        let allowed = TAG_TYPES
+            .iter()
+            .find(|(signature, _)| *signature == tag.signature());
+        if let Some((_, types)) = allowed {
+            if !types.contains(&type_signature) {
+                violations.push(Violation::TagType {
+                    signature: tag.signature(),
+                    type_signature,
+                });
+            }
+        }
+    }
+
+    match profile_type {
+        Some(profile_type) if violations.is_empty() => Ok(profile_type),
+        _ => error(violations),
+    }
+}
@@ -0,0 +1,285 @@
+// Tag types of ICC.1:2001-04 and ICC.1:2004-10 used by restricted ICC
+// profiles, ISO/IEC 15444-1 I.3.2.
+
+use std::fmt;
+
+/// An XYZNumber, three s15Fixed16Number values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct XYZNumber {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl fmt::Display for XYZNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "X {:.4} Y {:.4} Z {:.4}", self.x, self.y, self.z)
+    }
+}
+
+/// A one-dimensional tone reproduction curve, of curveType or
+/// parametricCurveType.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    /// A curveType without entries, the identity response.
+    Identity,
+
+    /// A curveType with one entry, a u8Fixed8Number gamma.
+    Gamma(f64),
+
+    /// A curveType table, the output values are from 0 to 65535 and are
+    /// interpolated between the entries.
+    Table(Vec<u16>),
+
+    /// A parametricCurveType, the function type from 0 to 4 and its
+    /// parameters g, a, b, c, d, e and f.
+    Parametric { function: u16, parameters: Vec<f64> },
+}
+
+impl Curve {
+    /// Output value of an input value, both from 0 to 1.
+    pub fn evaluate(&self, x: f64) -> f64 {
+        match self {
+            Curve::Identity => x,
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) if table.is_empty() => x,
+            Curve::Table(table) if table.len() == 1 => table[0] as f64 / 65535.0,
+            Curve::Table(table) => {
+                let position = x.clamp(0.0, 1.0) * (table.len() - 1) as f64;
+                let index = (position.floor() as usize).min(table.len() - 2);
+                let fraction = position - index as f64;
+                let low = table[index] as f64;
+                let high = table[index + 1] as f64;
+                (low + (high - low) * fraction) / 65535.0
+            }
+            Curve::Parametric {
+                function,
+                parameters,
+            } => {
+                let parameter = |index: usize| parameters.get(index).copied().unwrap_or(0.0);
+                let (g, a, b, c, d, e, f) = (
+                    parameter(0),
+                    parameter(1),
+                    parameter(2),
+                    parameter(3),
+                    parameter(4),
+                    parameter(5),
+                    parameter(6),
+                );
+                match function {
+                    0 => x.powf(g),
+                    1 if x >= -b / a => (a * x + b).powf(g),
+                    1 => 0.0,
+                    2 if x >= -b / a => (a * x + b).powf(g) + c,
+                    2 => c,
+                    3 if x >= d => (a * x + b).powf(g),
+                    3 => c * x,
+                    _ if x >= d => (a * x + b).powf(g) + e,
+                    _ => c * x + f,
+                }
+            }
+        }
+    }
+}
+
+/// Number of parameters of each function type of parametricCurveType.
+pub(crate) fn parametric_parameters(function: u16) -> Option<usize> {
+    match function {
+        0 => Some(1),
+        1 => Some(3),
+        2 => Some(4),
+        3 => Some(5),
+        4 => Some(7),
+        _ => None,
+    }
+}
+
+/// A string of a multiLocalizedUnicodeType, with its ISO 639-1 language code
+/// and ISO 3166-1 country code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalizedString {
+    pub language: [u8; 2],
+    pub country: [u8; 2],
+    pub text: String,
+}
+
+/// The decoded data of a tag, by its tag type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagData {
+    /// XYZType, 'XYZ '.
+    Xyz(Vec<XYZNumber>),
+
+    /// curveType, 'curv', or parametricCurveType, 'para'.
+    Curve(Curve),
+
+    /// textType, 'text', a 7-bit ASCII string.
+    Text(String),
+
+    /// textDescriptionType, 'desc', the invariant 7-bit ASCII description.
+    TextDescription(String),
+
+    /// multiLocalizedUnicodeType, 'mluc'.
+    MultiLocalizedUnicode(Vec<LocalizedString>),
+
+    /// Other tag types, with the data following the type signature and
+    /// reserved field.
+    Unknown {
+        type_signature: [u8; 4],
+        data: Vec<u8>,
+    },
+}
+
+impl TagData {
+    /// The type signature of the tag type.
+    pub fn type_signature(&self) -> [u8; 4] {
+        match self {
+            TagData::Xyz(_) => TAG_TYPE_XYZ,
+            TagData::Curve(Curve::Parametric { .. }) => TAG_TYPE_PARAMETRIC_CURVE,
+            TagData::Curve(_) => TAG_TYPE_CURVE,
+            TagData::Text(_) => TAG_TYPE_TEXT,
+            TagData::TextDescription(_) => TAG_TYPE_TEXT_DESCRIPTION,
+            TagData::MultiLocalizedUnicode(_) => TAG_TYPE_MULTI_LOCALIZED_UNICODE,
+            TagData::Unknown { type_signature, .. } => *type_signature,
+        }
+    }
+
+    /// The string of a text tag, the English string or the first string of a
+    /// multiLocalizedUnicodeType.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            TagData::Text(text) | TagData::TextDescription(text) => Some(text),
+            TagData::MultiLocalizedUnicode(strings) => strings
+                .iter()
+                .find(|string| &string.language == b"en")
+                .or_else(|| strings.first())
+                .map(|string| string.text.as_str()),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) const TAG_TYPE_XYZ: [u8; 4] = *b"XYZ ";
+pub(crate) const TAG_TYPE_CURVE: [u8; 4] = *b"curv";
+pub(crate) const TAG_TYPE_PARAMETRIC_CURVE: [u8; 4] = *b"para";
+pub(crate) const TAG_TYPE_TEXT: [u8; 4] = *b"text";
+pub(crate) const TAG_TYPE_TEXT_DESCRIPTION: [u8; 4] = *b"desc";
+pub(crate) const TAG_TYPE_MULTI_LOCALIZED_UNICODE: [u8; 4] = *b"mluc";
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub(crate) fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<f64> {
+    Some(read_u32(data, offset)? as i32 as f64 / 65536.0)
+}
+
+pub(crate) fn read_xyz_number(data: &[u8], offset: usize) -> Option<XYZNumber> {
+    Some(XYZNumber {
+        x: read_s15_fixed16(data, offset)?,
+        y: read_s15_fixed16(data, offset + 4)?,
+        z: read_s15_fixed16(data, offset + 8)?,
+    })
+}
+
+// A 7-bit ASCII string, up to its NUL terminator.
+fn ascii(data: &[u8]) -> String {
+    let end = data
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// Decode the data of a tag, from its type signature, None when the data is
+/// malformed.
+pub(crate) fn decode_tag_data(data: &[u8]) -> Option<TagData> {
+    let mut type_signature = [0; 4];
+    type_signature.copy_from_slice(data.get(0..4)?);
+    // The type signature is followed by 4 reserved bytes
+    let contents = data.get(8..)?;
+
+    let tag_data = match type_signature {
+        TAG_TYPE_XYZ => {
+            if contents.len() < 12 {
+                return None;
+            }
+            TagData::Xyz(
+                (0..contents.len() / 12)
+                    .map(|index| read_xyz_number(contents, index * 12))
+                    .collect::<Option<_>>()?,
+            )
+        }
+        TAG_TYPE_CURVE => {
+            let count = read_u32(contents, 0)? as usize;
+            match count {
+                0 => TagData::Curve(Curve::Identity),
+                1 => TagData::Curve(Curve::Gamma(read_u16(contents, 4)? as f64 / 256.0)),
+                _ => {
+                    if contents.len() < 4 + count.checked_mul(2)? {
+                        return None;
+                    }
+                    TagData::Curve(Curve::Table(
+                        (0..count)
+                            .map(|index| read_u16(contents, 4 + index * 2))
+                            .collect::<Option<_>>()?,
+                    ))
+                }
+            }
+        }
+        TAG_TYPE_PARAMETRIC_CURVE => {
+            let function = read_u16(contents, 0)?;
+            let parameters = (0..parametric_parameters(function)?)
+                .map(|index| read_s15_fixed16(contents, 4 + index * 4))
+                .collect::<Option<_>>()?;
+            TagData::Curve(Curve::Parametric {
+                function,
+                parameters,
+            })
+        }
+        TAG_TYPE_TEXT => TagData::Text(ascii(contents)),
+        TAG_TYPE_TEXT_DESCRIPTION => {
+            // The ASCII count includes the NUL terminator, the Unicode and
+            // ScriptCode descriptions which follow are not decoded
+            let count = read_u32(contents, 0)? as usize;
+            let description = contents.get(4..4usize.checked_add(count)?)?;
+            TagData::TextDescription(ascii(description))
+        }
+        TAG_TYPE_MULTI_LOCALIZED_UNICODE => {
+            let records = read_u32(contents, 0)? as usize;
+            let record_size = read_u32(contents, 4)? as usize;
+            if record_size < 12 {
+                return None;
+            }
+            let mut strings: Vec<LocalizedString> = vec![];
+            for index in 0..records {
+                let record = 8usize.checked_add(index.checked_mul(record_size)?)?;
+                let record = contents.get(record..record.checked_add(12)?)?;
+                let length = read_u32(record, 4)? as usize;
+                // The offset is from the start of the tag
+                let offset = read_u32(record, 8)? as usize;
+                let text = data.get(offset..offset.checked_add(length)?)?;
+                let units: Vec<u16> = text
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                strings.push(LocalizedString {
+                    language: [record[0], record[1]],
+                    country: [record[2], record[3]],
+                    text: String::from_utf16_lossy(&units),
+                });
+            }
+            TagData::MultiLocalizedUnicode(strings)
+        }
+        _ => TagData::Unknown {
+            type_signature,
+            data: contents.to_vec(),
+        },
+    };
+    Some(tag_data)
+}
@@ -0,0 +1,227 @@
+use std::io::Cursor;
+
+use cc::{
+    check_restricted_profile, decode_icc, profile_id, ColourSpace, Curve, ICCError, ProfileBuilder,
+    ProfileClass, RenderingIntent, RestrictedProfileType, TagData, Version, XYZNumber,
+};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The sRGB colorants adapted to D50.
+const SRGB_COLORANTS: [XYZNumber; 3] = [
+    XYZNumber {
+        x: 0.4361,
+        y: 0.2225,
+        z: 0.0139,
+    },
+    XYZNumber {
+        x: 0.3851,
+        y: 0.7169,
+        z: 0.0971,
+    },
+    XYZNumber {
+        x: 0.1431,
+        y: 0.0606,
+        z: 0.7141,
+    },
+];
+
+fn srgb_trc() -> Curve {
+    Curve::Parametric {
+        function: 3,
+        parameters: vec![2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045],
+    }
+}
+
+#[test]
+fn test_profile_id() {
+    // The profile flags, rendering intent and profile ID are not part of
+    // the fingerprint
+    assert_eq!(
+        hex(&profile_id(&[0; 128])),
+        "f09f35a5637839458e462e6350ecbce4"
+    );
+    let header: Vec<u8> = (0..128).collect();
+    assert_eq!(
+        hex(&profile_id(&header)),
+        "da5b9b612ec00fee278b36c5b5c8e2f7"
+    );
+}
+
+#[test]
+fn test_encode_three_component_matrix() {
+    let data = ProfileBuilder::three_component_matrix(
+        SRGB_COLORANTS,
+        [srgb_trc(), srgb_trc(), srgb_trc()],
+    )
+    .description("sRGB")
+    .copyright("No copyright")
+    .date_time([2020, 1, 2, 3, 4, 5])
+    .rendering_intent(RenderingIntent::MediaRelativeColorimetric)
+    .encode_to_vec()
+    .expect("encoding should succeed");
+
+    assert_eq!(data.len() % 4, 0);
+    assert_eq!(
+        check_restricted_profile(&data).expect("profile should be restricted"),
+        RestrictedProfileType::ThreeComponentMatrix
+    );
+
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let header = profile.header();
+    assert_eq!(header.size() as usize, data.len());
+    assert_eq!(
+        header.version(),
+        Version {
+            major: 2,
+            minor: 4,
+            bugfix: 0
+        }
+    );
+    assert_eq!(header.class(), ProfileClass::Input);
+    assert_eq!(header.colour_space(), ColourSpace::Rgb);
+    assert_eq!(header.pcs(), ColourSpace::Xyz);
+    assert_eq!(header.date_time(), [2020, 1, 2, 3, 4, 5]);
+    assert_eq!(
+        header.rendering_intent(),
+        RenderingIntent::MediaRelativeColorimetric
+    );
+    assert!((header.illuminant().x - 0.9642).abs() < 1e-4);
+    // Version 2 profiles have no profile ID
+    assert_eq!(header.id(), [0; 16]);
+    assert!(data[84..100].iter().all(|byte| *byte == 0));
+
+    assert_eq!(profile.description(), Some("sRGB"));
+    assert_eq!(profile.copyright(), Some("No copyright"));
+    let red = profile.red_colorant().unwrap();
+    assert!((red.x - 0.4361).abs() < 1e-4);
+    assert!((red.z - 0.0139).abs() < 1e-4);
+    assert!((profile.media_white_point().unwrap().z - 0.8249).abs() < 1e-4);
+
+    // The identical curves share their data element, which is aligned
+    let trcs: Vec<_> = profile
+        .tags()
+        .iter()
+        .filter(|tag| tag.signature().ends_with(b"TRC"))
+        .collect();
+    assert_eq!(trcs.len(), 3);
+    assert!(trcs.iter().all(|tag| tag.offset() == trcs[0].offset()));
+    assert!(profile
+        .tags()
+        .iter()
+        .all(|tag| tag.offset() % 4 == 0 && tag.offset() as usize >= 128 + 4 + 12 * 9));
+    match profile.green_trc() {
+        Some(Curve::Parametric {
+            function,
+            parameters,
+        }) => {
+            assert_eq!(*function, 3);
+            assert_eq!(parameters.len(), 5);
+            assert!((parameters[0] - 2.4).abs() < 1e-4);
+        }
+        curve => panic!("unexpected curve {:?}", curve),
+    }
+}
+
+#[test]
+fn test_encode_monochrome() {
+    let table = Curve::Table(vec![0, 1000, 30000, 65535]);
+    let data = ProfileBuilder::monochrome(table.clone())
+        .description("Grey")
+        .encode_to_vec()
+        .expect("encoding should succeed");
+    assert_eq!(
+        check_restricted_profile(&data).expect("profile should be restricted"),
+        RestrictedProfileType::Monochrome
+    );
+
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+    assert_eq!(profile.header().colour_space(), ColourSpace::Gray);
+    assert_eq!(profile.gray_trc(), Some(&table));
+    assert_eq!(profile.tags().len(), 4);
+    assert_eq!(profile.copyright(), Some(""));
+    assert_eq!(
+        profile.tag(b"desc").map(TagData::type_signature),
+        Some(*b"desc")
+    );
+
+    // Gamma and identity curves are curveType tags, the gamma is a
+    // u8Fixed8Number
+    for curve in [Curve::Gamma(1.75), Curve::Identity].iter() {
+        let mut data: Vec<u8> = vec![];
+        ProfileBuilder::monochrome(curve.clone())
+            .encode(&mut data)
+            .expect("encoding should succeed");
+        let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+        assert_eq!(profile.gray_trc(), Some(curve));
+    }
+}
+
+#[test]
+fn test_encode_version_4() {
+    let data = ProfileBuilder::monochrome(Curve::Gamma(2.2))
+        .version(Version {
+            major: 4,
+            minor: 3,
+            bugfix: 0,
+        })
+        .description("Grey")
+        .copyright("No copyright")
+        .encode_to_vec()
+        .expect("encoding should succeed");
+    assert_eq!(
+        check_restricted_profile(&data).expect("profile should be restricted"),
+        RestrictedProfileType::Monochrome
+    );
+
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let header = profile.header();
+    assert_eq!(header.version().major, 4);
+    assert_eq!(header.id(), profile_id(&data));
+    assert_ne!(header.id(), [0; 16]);
+
+    // The description and copyright are multiLocalizedUnicodeType tags
+    for signature in [b"desc", b"cprt"].iter() {
+        assert_eq!(
+            profile.tag(signature).map(TagData::type_signature),
+            Some(*b"mluc")
+        );
+    }
+    assert_eq!(profile.description(), Some("Grey"));
+    assert_eq!(profile.copyright(), Some("No copyright"));
+    match profile.tag(b"desc") {
+        Some(TagData::MultiLocalizedUnicode(strings)) => {
+            assert_eq!(strings.len(), 1);
+            assert_eq!(&strings[0].language, b"en");
+            assert_eq!(&strings[0].country, b"US");
+        }
+        tag => panic!("unexpected tag {:?}", tag),
+    }
+}
+
+#[test]
+fn test_encode_errors() {
+    let invalid = [
+        ProfileBuilder::monochrome(Curve::Parametric {
+            function: 1,
+            parameters: vec![2.2],
+        }),
+        ProfileBuilder::monochrome(Curve::Gamma(-1.0)),
+        ProfileBuilder::monochrome(Curve::Table(vec![0])),
+        ProfileBuilder::monochrome(Curve::Identity).description("Gr\u{e9}y"),
+        ProfileBuilder::monochrome(Curve::Identity).media_white_point(XYZNumber {
+            x: 40000.0,
+            y: 1.0,
+            z: 1.0,
+        }),
+    ];
+    for builder in invalid.iter() {
+        let error = builder.encode_to_vec().expect_err("encoding should fail");
+        match error {
+            ICCError::TagMalformed { .. } => {}
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+}
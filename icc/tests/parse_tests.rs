@@ -0,0 +1,279 @@
+use std::{error, fs, io, io::Cursor, path::Path};
+
+use cc::{
+    decode_icc, ColourSpace, Curve, ICCError, ProfileClass, RenderingIntent, TagData, Version,
+};
+
+// The restricted ICC profile of the first Colour Specification box of a
+// sample file.
+fn sample_profile(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join(name);
+    let data = fs::read(path).expect("file should exist");
+    let index = data
+        .windows(5)
+        .position(|window| window == b"colr\x02")
+        .expect("profile should exist");
+    let length = u32::from_be_bytes([
+        data[index - 4],
+        data[index - 3],
+        data[index - 2],
+        data[index - 1],
+    ]) as usize;
+    data[index + 7..index - 4 + length].to_vec()
+}
+
+#[test]
+fn test_sample_file5_profile() {
+    let data = sample_profile("file5.jp2");
+    let mut reader = Cursor::new(&data);
+    let profile = decode_icc(&mut reader).expect("decoding should succeed");
+    assert_eq!(reader.position(), data.len() as u64);
+
+    let header = profile.header();
+    assert_eq!(header.size(), 546);
+    assert_eq!(header.cmm(), [0; 4]);
+    assert_eq!(
+        header.version(),
+        Version {
+            major: 2,
+            minor: 2,
+            bugfix: 0
+        }
+    );
+    assert_eq!(header.class(), ProfileClass::Input);
+    assert_eq!(header.colour_space(), ColourSpace::Rgb);
+    assert_eq!(header.pcs(), ColourSpace::Xyz);
+    assert_eq!(header.date_time(), [2001, 8, 30, 13, 32, 37]);
+    assert_eq!(header.signature(), *b"acsp");
+    assert_eq!(header.flags(), 1);
+    assert_eq!(header.manufacturer(), *b"KODA");
+    assert_eq!(header.model(), *b"ROMM");
+    assert_eq!(header.rendering_intent(), RenderingIntent::Perceptual);
+    assert!((header.illuminant().x - 0.9642).abs() < 0.0001);
+    assert_eq!(header.illuminant().y, 1.0);
+    assert_eq!(header.creator(), *b"JPEG");
+    assert_eq!(header.id(), [0; 16]);
+
+    let signatures: Vec<[u8; 4]> = profile.tags().iter().map(|tag| tag.signature()).collect();
+    assert_eq!(
+        signatures,
+        [
+            *b"desc", *b"cprt", *b"wtpt", *b"rXYZ", *b"gXYZ", *b"bXYZ", *b"rTRC", *b"gTRC",
+            *b"bTRC"
+        ]
+    );
+    assert_eq!(profile.tags()[0].offset(), 240);
+    assert_eq!(profile.tags()[0].size(), 133);
+
+    assert_eq!(
+        profile.description(),
+        Some("Restricted ICC profile describing ROMM-RGB")
+    );
+    assert_eq!(
+        profile.copyright(),
+        Some("Copyright 2001 EKC-RICC Reference")
+    );
+    assert_eq!(profile.media_white_point(), Some(header.illuminant()));
+    let red = profile.red_colorant().unwrap();
+    assert!((red.x - 0.7977).abs() < 0.0001);
+    assert!((red.y - 0.2880).abs() < 0.0001);
+    assert_eq!(red.z, 0.0);
+    assert!(profile.green_colorant().is_some());
+    assert!(profile.blue_colorant().is_some());
+    assert_eq!(profile.red_trc(), Some(&Curve::Gamma(461.0 / 256.0)));
+    assert_eq!(profile.green_trc(), profile.red_trc());
+    assert_eq!(profile.blue_trc(), profile.red_trc());
+    assert!(profile.gray_trc().is_none());
+}
+
+#[test]
+fn test_sample_file7_profile() {
+    let data = sample_profile("file7.jp2");
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+
+    assert_eq!(profile.header().cmm(), *b"APPL");
+    assert_eq!(profile.header().platform(), *b"MSFT");
+    assert_eq!(profile.tags().len(), 12);
+    assert_eq!(
+        profile.description(),
+        Some("16-bit e-sRGB JP2 restricted (to sRGB) profile")
+    );
+    assert_eq!(
+        profile.tag(b"dmnd").and_then(TagData::text),
+        Some("Hewlett-Packard")
+    );
+    match profile.red_trc() {
+        Some(Curve::Table(table)) => {
+            assert_eq!(table.len(), 2048);
+            assert_eq!(table[0], 0);
+            assert_eq!(table[2047], 65535);
+        }
+        curve => panic!("unexpected curve {:?}", curve),
+    }
+    assert_eq!(profile.red_trc().unwrap().evaluate(1.0), 1.0);
+}
+
+#[test]
+fn test_sample_file8_profile() {
+    let data = sample_profile("file8.jp2");
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+
+    assert_eq!(profile.header().colour_space(), ColourSpace::Gray);
+    assert_eq!(profile.tags().len(), 4);
+    assert_eq!(profile.gray_trc(), Some(&Curve::Gamma(461.0 / 256.0)));
+    assert!(profile.red_colorant().is_none());
+}
+
+fn profile(tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut header = vec![0; 128];
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data: Vec<u8> = vec![];
+    let start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+    }
+    let mut profile = [header, table, data].concat();
+    let length = profile.len() as u32;
+    profile[0..4].copy_from_slice(&length.to_be_bytes());
+    profile
+}
+
+#[test]
+fn test_parametric_curve_and_unicode_tags() {
+    let para = [
+        &b"para\0\0\0\0\0\x00\0\0"[..],
+        &((2.2f64 * 65536.0).round() as i32).to_be_bytes(),
+    ]
+    .concat();
+    let mluc = [
+        &b"mluc\0\0\0\0"[..],
+        &2u32.to_be_bytes(),
+        &12u32.to_be_bytes(),
+        b"deDE",
+        &4u32.to_be_bytes(),
+        &40u32.to_be_bytes(),
+        b"enUS",
+        &4u32.to_be_bytes(),
+        &44u32.to_be_bytes(),
+        &[0, b'A', 0, b'b'],
+        &[0, b'H', 0, b'i'],
+    ]
+    .concat();
+    let data = profile(&[
+        (b"rTRC", para),
+        (b"desc", mluc),
+        (b"vend", b"ABCD\0\0\0\0\x01\x02".to_vec()),
+    ]);
+    let profile = decode_icc(&mut Cursor::new(&data)).expect("decoding should succeed");
+
+    assert_eq!(profile.header().class(), ProfileClass::Display);
+    match profile.red_trc() {
+        Some(Curve::Parametric {
+            function,
+            parameters,
+        }) => {
+            assert_eq!(*function, 0);
+            assert!((parameters[0] - 2.2).abs() < 0.0001);
+        }
+        curve => panic!("unexpected curve {:?}", curve),
+    }
+    assert!((profile.red_trc().unwrap().evaluate(0.5) - 0.5f64.powf(2.2)).abs() < 0.0001);
+    assert_eq!(profile.description(), Some("Hi"));
+    match profile.tag(b"desc") {
+        Some(TagData::MultiLocalizedUnicode(strings)) => {
+            assert_eq!(strings.len(), 2);
+            assert_eq!(strings[0].language, *b"de");
+            assert_eq!(strings[0].country, *b"DE");
+            assert_eq!(strings[0].text, "Ab");
+        }
+        data => panic!("unexpected tag data {:?}", data),
+    }
+    assert_eq!(
+        profile.tag(b"vend"),
+        Some(&TagData::Unknown {
+            type_signature: *b"ABCD",
+            data: vec![1, 2]
+        })
+    );
+}
+
+#[test]
+fn test_invalid_profiles() {
+    let valid = profile(&[(b"wtpt", b"XYZ \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec())]);
+
+    let mut data = valid.clone();
+    data[36..40].copy_from_slice(b"xxxx");
+    let error = decode_icc(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    assert!(matches!(
+        error,
+        ICCError::InvalidSignature { offset: 36, .. }
+    ));
+
+    // The profile is shorter than its size
+    let error =
+        decode_icc(&mut Cursor::new(&valid[..valid.len() - 1])).expect_err("decoding should fail");
+    assert!(matches!(error, ICCError::InvalidSize { offset: 0, .. }));
+
+    // The profile is shorter than its header
+    let error = decode_icc(&mut Cursor::new(&valid[..100])).expect_err("decoding should fail");
+    match &error {
+        ICCError::Io { offset: 0, source } => {
+            assert_eq!(source.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert!(error::Error::source(&error).is_some());
+
+    let mut data = valid.clone();
+    data[131] = 3;
+    let error = decode_icc(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    assert!(matches!(
+        error,
+        ICCError::InvalidTagCount {
+            count: 3,
+            offset: 128
+        }
+    ));
+
+    let mut data = valid.clone();
+    data[143] = 21;
+    let error = decode_icc(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    match error {
+        ICCError::TagOutOfBounds {
+            signature,
+            offset,
+            size,
+        } => {
+            assert_eq!(signature, *b"wtpt");
+            assert_eq!(offset, 144);
+            assert_eq!(size, 21);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    let mut data = valid;
+    data[143] = 16;
+    let error = decode_icc(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    assert_eq!(error.offset(), 144);
+    match error {
+        ICCError::TagMalformed {
+            signature,
+            type_signature,
+            ..
+        } => {
+            assert_eq!(signature, *b"wtpt");
+            assert_eq!(type_signature, *b"XYZ ");
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
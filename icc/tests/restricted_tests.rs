@@ -0,0 +1,256 @@
+use std::{fs, path::Path};
+
+use cc::{check_restricted_profile, ColourSpace, ProfileClass, RestrictedProfileType, Violation};
+
+// The restricted ICC profile of the first Colour Specification box of a
+// sample file.
+fn sample_profile(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join(name);
+    let data = fs::read(path).expect("file should exist");
+    let index = data
+        .windows(5)
+        .position(|window| window == b"colr\x02")
+        .expect("profile should exist");
+    let length = u32::from_be_bytes([
+        data[index - 4],
+        data[index - 3],
+        data[index - 2],
+        data[index - 1],
+    ]) as usize;
+    data[index + 7..index - 4 + length].to_vec()
+}
+
+const XYZ: &[u8] = b"XYZ \0\0\0\0\0\0\xf6\xd6\0\x01\0\0\0\0\xd3\x2d";
+const CURVE: &[u8] = b"curv\0\0\0\0\0\0\0\x01\x02\x33";
+const DESCRIPTION: &[u8] = b"desc\0\0\0\0\0\0\0\x02A\0";
+const COPYRIGHT: &[u8] = b"text\0\0\0\0C\0";
+
+// A profile with the tags, given as signature, offset and size of their data
+// element, and the data following the tag table.
+fn profile(
+    class: &[u8; 4],
+    colour_space: &[u8; 4],
+    tags: &[(&[u8; 4], u32, u32)],
+    data: &[u8],
+) -> Vec<u8> {
+    let mut header = [0; 128];
+    header[12..16].copy_from_slice(class);
+    header[16..20].copy_from_slice(colour_space);
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    for (signature, offset, size) in tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&offset.to_be_bytes());
+        table.extend_from_slice(&size.to_be_bytes());
+    }
+    let mut profile = [&header[..], &table, data].concat();
+    let length = profile.len() as u32;
+    profile[0..4].copy_from_slice(&length.to_be_bytes());
+    profile
+}
+
+// A monochrome profile with its tags laid out after a table of four tags.
+fn monochrome_profile() -> Vec<u8> {
+    let start = 128 + 4 + 4 * 12;
+    let mut tags: Vec<(&[u8; 4], u32, u32)> = vec![];
+    let mut data: Vec<u8> = vec![];
+    for (signature, element) in [
+        (b"desc", DESCRIPTION),
+        (b"cprt", COPYRIGHT),
+        (b"wtpt", XYZ),
+        (b"kTRC", CURVE),
+    ] {
+        tags.push((signature, (start + data.len()) as u32, element.len() as u32));
+        data.extend_from_slice(element);
+    }
+    profile(b"scnr", b"GRAY", &tags, &data)
+}
+
+fn violations(data: &[u8]) -> Vec<Violation> {
+    check_restricted_profile(data)
+        .expect_err("the profile should not conform")
+        .violations()
+        .to_vec()
+}
+
+#[test]
+fn test_sample_profiles() {
+    assert_eq!(
+        check_restricted_profile(&sample_profile("file5.jp2")),
+        Ok(RestrictedProfileType::ThreeComponentMatrix)
+    );
+    assert_eq!(
+        check_restricted_profile(&sample_profile("file7.jp2")),
+        Ok(RestrictedProfileType::ThreeComponentMatrix)
+    );
+    assert_eq!(
+        check_restricted_profile(&sample_profile("file8.jp2")),
+        Ok(RestrictedProfileType::Monochrome)
+    );
+}
+
+#[test]
+fn test_monochrome_profile() {
+    let data = monochrome_profile();
+    assert_eq!(
+        check_restricted_profile(&data),
+        Ok(RestrictedProfileType::Monochrome)
+    );
+
+    // Display profiles are accepted
+    let mut display = data.clone();
+    display[12..16].copy_from_slice(b"mntr");
+    assert_eq!(
+        check_restricted_profile(&display),
+        Ok(RestrictedProfileType::Monochrome)
+    );
+
+    // Tags may share their data
+    let mut shared = data;
+    shared[128 + 4 + 3 * 12 + 4..128 + 4 + 3 * 12 + 8]
+        .copy_from_slice(&(128u32 + 4 + 4 * 12 + 14 + 10).to_be_bytes());
+    shared[128 + 4 + 3 * 12 + 8..128 + 4 + 3 * 12 + 12].copy_from_slice(&20u32.to_be_bytes());
+    assert_eq!(
+        violations(&shared),
+        [Violation::TagType {
+            signature: *b"kTRC",
+            type_signature: *b"XYZ "
+        }]
+    );
+}
+
+#[test]
+fn test_header_violations() {
+    let mut data = monochrome_profile();
+    data[12..16].copy_from_slice(b"prtr");
+    data[16..20].copy_from_slice(b"CMYK");
+    data[20..24].copy_from_slice(b"Lab ");
+    assert_eq!(
+        violations(&data),
+        [
+            Violation::Class {
+                class: ProfileClass::Output
+            },
+            Violation::ColourSpace {
+                colour_space: ColourSpace::Cmyk
+            },
+            Violation::Pcs {
+                pcs: ColourSpace::Lab
+            },
+        ]
+    );
+
+    let data = monochrome_profile();
+    assert_eq!(
+        violations(&data[..100]),
+        [Violation::Truncated { length: 100 }]
+    );
+    let mut invalid = data.clone();
+    invalid[36] = b'x';
+    assert_eq!(
+        violations(&invalid),
+        [Violation::InvalidSignature {
+            signature: *b"xcsp"
+        }]
+    );
+    let padded = [&data[..], &[0; 4]].concat();
+    assert_eq!(
+        violations(&padded),
+        [Violation::SizeMismatch {
+            size: data.len() as u32,
+            length: data.len() + 4
+        }]
+    );
+}
+
+#[test]
+fn test_tag_violations() {
+    let start = 128 + 4 + 3 * 12;
+
+    // The three-component matrix tags are missing
+    let data = profile(
+        b"scnr",
+        b"RGB ",
+        &[
+            (b"desc", start, 14),
+            (b"cprt", start + 14, 10),
+            (b"wtpt", start + 24, 20),
+        ],
+        &[DESCRIPTION, COPYRIGHT, XYZ].concat(),
+    );
+    let missing: Vec<Violation> = violations(&data);
+    assert_eq!(
+        missing,
+        [*b"rXYZ", *b"gXYZ", *b"bXYZ", *b"rTRC", *b"gTRC", *b"bTRC"]
+            .iter()
+            .map(|signature| Violation::TagMissing {
+                signature: *signature
+            })
+            .collect::<Vec<_>>()
+    );
+
+    // Bounds and overlaps
+    let data = profile(
+        b"scnr",
+        b"GRAY",
+        &[
+            (b"desc", start, 14),
+            (b"cprt", start + 10, 10),
+            (b"wtpt", 100, 20),
+            (b"kTRC", start + 24, 100),
+        ],
+        &[DESCRIPTION, COPYRIGHT, CURVE].concat(),
+    );
+    // The table has one more tag than laid out by start
+    assert_eq!(
+        violations(&data),
+        [
+            Violation::TagOverlapsHeader {
+                signature: *b"desc"
+            },
+            Violation::TagOverlapsHeader {
+                signature: *b"cprt"
+            },
+            Violation::TagOverlap {
+                signature: *b"cprt",
+                other: *b"desc"
+            },
+            Violation::TagOverlapsHeader {
+                signature: *b"wtpt"
+            },
+            Violation::TagOutOfBounds {
+                signature: *b"kTRC",
+                offset: start + 24,
+                size: 100
+            },
+        ]
+    );
+
+    // Malformed tags and duplicate tags
+    let mut data = monochrome_profile();
+    let curve_count = data.len() - 6;
+    data[curve_count] = 9;
+    assert_eq!(
+        violations(&data),
+        [Violation::TagMalformed {
+            signature: *b"kTRC"
+        }]
+    );
+    let start = 128 + 4 + 2 * 12;
+    let data = profile(
+        b"scnr",
+        b"GRAY",
+        &[(b"kTRC", start, 14), (b"kTRC", start, 14)],
+        CURVE,
+    );
+    assert_eq!(
+        violations(&data),
+        [Violation::TagDuplicate {
+            signature: *b"kTRC"
+        }]
+    );
+}
@@ -0,0 +1,235 @@
+// Low-level traversal of the box structure of JP2 family files.
+//
+// The iterator only reads box headers, the contents of boxes are skipped by
+// their length so any box can be located without being decoded.
+
+use std::io;
+
+use super::{decode_box_header, AtOffset, BoxHeader, BoxType, JP2Error, Limits};
+
+// jp2h, res\040 and uinf from ISO/IEC 15444-1 and the superboxes of
+// ISO/IEC 15444-2 (jpch, jplh, cgrp, ftbl, comp, asoc and drep).
+const SUPERBOX_TYPES: [BoxType; 10] = [
+    super::BOX_TYPE_HEADER,
+    super::BOX_TYPE_RESOLUTION,
+    super::BOX_TYPE_UUID_INFO,
+    super::BOX_TYPE_CODESTREAM_HEADER,
+    super::BOX_TYPE_COMPOSITING_LAYER_HEADER,
+    super::BOX_TYPE_COLOUR_GROUP,
+    super::BOX_TYPE_FRAGMENT_TABLE,
+    super::BOX_TYPE_COMPOSITION,
+    super::BOX_TYPE_ASSOCIATION,
+    super::BOX_TYPE_DESIRED_REPRODUCTIONS,
+];
+
+/// Whether a box of the given type contains only other boxes.
+pub fn is_superbox(box_type: [u8; 4]) -> bool {
+    SUPERBOX_TYPES.contains(&box_type)
+}
+
+/// A box found by `BoxIterator`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxInfo {
+    /// Type of the box (TBox).
+    pub box_type: [u8; 4],
+
+    /// Length of the box header, 8 or 16 when the XLBox field is present.
+    pub header_length: u8,
+
+    /// Offset of the box contents (DBox) from the start of the source.
+    pub offset: u64,
+
+    /// Length of the box contents, a box with a LBox of 0 extends to the end
+    /// of its superbox or of the source.
+    pub length: u64,
+
+    /// Number of superboxes containing the box, 0 for top-level boxes.
+    pub depth: usize,
+}
+
+impl BoxInfo {
+    /// Offset of the box header from the start of the source.
+    pub fn start(&self) -> u64 {
+        self.offset - self.header_length as u64
+    }
+
+    /// Offset of the end of the box from the start of the source.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+
+    /// Whether the iterator descends into the box.
+    pub fn is_superbox(&self) -> bool {
+        is_superbox(self.box_type)
+    }
+}
+
+/// Iterator over the boxes of a JP2 family file, in the order they are found
+/// in the file.
+///
+/// Superboxes are returned before the boxes they contain, see `is_superbox`
+/// for the superboxes which are descended into. The contents of other boxes
+/// are not read, they can be read through `reader` using the offset and length
+/// of each box.
+///
+/// Iteration stops after the first error, such as a box extending beyond its
+/// superbox or the end of the source, or a box nested in more superboxes than
+/// the `max_nesting_depth` of the limits.
+pub struct BoxIterator<'a, R: io::Read + io::Seek> {
+    reader: &'a mut R,
+    limits: Limits,
+    position: u64,
+    end: Option<u64>,
+    // End of each superbox containing the current position.
+    superboxes: Vec<u64>,
+    done: bool,
+}
+
+impl<'a, R: io::Read + io::Seek> BoxIterator<'a, R> {
+    /// Create an iterator over the boxes from the current position of the
+    /// reader to the end of the source.
+    pub fn new(reader: &'a mut R) -> BoxIterator<'a, R> {
+        BoxIterator::with_limits(reader, Limits::default())
+    }
+
+    /// Create an iterator over the boxes from the current position of the
+    /// reader to the end of the source, within the nesting depth of `limits`.
+    pub fn with_limits(reader: &'a mut R, limits: Limits) -> BoxIterator<'a, R> {
+        BoxIterator {
+            reader,
+            limits,
+            position: 0,
+            end: None,
+            superboxes: vec![],
+            done: false,
+        }
+    }
+
+    /// The underlying reader, the iterator seeks to the next box itself so the
+    /// reader may be used between boxes.
+    pub fn reader(&mut self) -> &mut R {
+        self.reader
+    }
+
+    fn next_box(&mut self) -> Result<Option<BoxInfo>, JP2Error> {
+        let end = match self.end {
+            Some(end) => end,
+            None => {
+                self.position = self.reader.stream_position().at(0)?;
+                let end = self.reader.seek(io::SeekFrom::End(0)).at(self.position)?;
+                self.end = Some(end);
+                end
+            }
+        };
+
+        // Leave the superboxes which have been read entirely
+        while let Some(superbox_end) = self.superboxes.last() {
+            if self.position < *superbox_end {
+                break;
+            }
+            self.superboxes.pop();
+        }
+        if self.position >= end {
+            return Ok(None);
+        }
+        let parent_end = *self.superboxes.last().unwrap_or(&end);
+        let position = self.position;
+        let info = read_box(
+            self.reader,
+            self.position,
+            parent_end,
+            self.superboxes.len(),
+        )?;
+        if info.is_superbox() {
+            self.limits
+                .check_nesting_depth(self.superboxes.len() as u32 + 1)
+                .at(position)?;
+            self.superboxes.push(info.end());
+            self.position = info.offset;
+        } else {
+            self.position = info.end();
+        }
+
+        Ok(Some(info))
+    }
+}
+
+// Read the header of the box at the given position, within a superbox or
+// source ending at parent_end.
+fn read_box<R: io::Read + io::Seek>(
+    reader: &mut R,
+    position: u64,
+    parent_end: u64,
+    depth: usize,
+) -> Result<BoxInfo, JP2Error> {
+    reader.seek(io::SeekFrom::Start(position)).at(position)?;
+    let BoxHeader {
+        box_length,
+        box_type,
+        header_length,
+        extends_to_end,
+        ..
+    } = decode_box_header(reader)?;
+    let offset = position + header_length as u64;
+
+    // A LBox of 0 extends the box to the end of its superbox
+    let length = if extends_to_end {
+        parent_end.saturating_sub(offset)
+    } else {
+        box_length
+    };
+
+    if offset > parent_end || length > parent_end - offset {
+        return Err(JP2Error::BoxMalformed {
+            box_type,
+            offset: position,
+        });
+    }
+
+    Ok(BoxInfo {
+        box_type,
+        header_length,
+        offset,
+        length,
+        depth,
+    })
+}
+
+// The boxes directly contained in the contents of a superbox, or in a range of
+// the source.
+pub(crate) fn child_boxes<R: io::Read + io::Seek>(
+    reader: &mut R,
+    offset: u64,
+    length: u64,
+) -> Result<Vec<BoxInfo>, JP2Error> {
+    let end = offset + length;
+    let mut boxes: Vec<BoxInfo> = vec![];
+    let mut position = offset;
+    while position < end {
+        let info = read_box(reader, position, end, 0)?;
+        position = info.end();
+        boxes.push(info);
+    }
+    Ok(boxes)
+}
+
+impl<'a, R: io::Read + io::Seek> Iterator for BoxIterator<'a, R> {
+    type Item = Result<BoxInfo, JP2Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_box() {
+            Ok(Some(info)) => Some(Ok(info)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
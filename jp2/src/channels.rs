@@ -0,0 +1,326 @@
+// Mapping of decoded codestream components to the channels of the image,
+// ISO/IEC 15444-1 I.5.3.4 to I.5.3.6.
+//
+// Components are mapped to channels directly or through the palette, as given
+// by the Component Mapping box, and the Channel Definition box gives the type
+// and colour of each channel.
+
+use super::{
+    BitDepth, ChannelDefinitionBox, ChannelTypes, ColourSpecificationBox,
+    ColourSpecificationMethods, ComponentMappingBox, HeaderSuperBox, JBox, JP2Error, PaletteBox,
+    BOX_TYPE_CHANNEL_DEFINITION, BOX_TYPE_COMPONENT_MAPPING, BOX_TYPE_PALETTE,
+};
+
+// Channel association of a channel associated with the whole image, and of a
+// channel without association.
+const ASSOCIATION_WHOLE_IMAGE: u16 = 0;
+const ASSOCIATION_NONE: u16 = u16::MAX;
+
+/// Samples of a component decoded from the codestream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentSamples {
+    /// Bit depth of the component, from 1 to 38.
+    pub bit_depth: u8,
+
+    /// Whether the samples are signed values.
+    pub signed: bool,
+
+    /// Samples of the component, in raster order.
+    pub samples: Vec<i32>,
+}
+
+/// A channel of the image, created from a codestream component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageChannel {
+    index: u16,
+    channel_type: ChannelTypes,
+    association: u16,
+    bit_depth: u8,
+    signed: bool,
+    samples: Vec<i32>,
+}
+
+impl ImageChannel {
+    /// Index of the channel, as numbered by the Component Mapping box or the
+    /// codestream.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn channel_type(&self) -> ChannelTypes {
+        self.channel_type
+    }
+
+    /// Channel association (Asoc), 0 for the whole image, the colour number
+    /// starting from 1, or 65535 without association.
+    pub fn association(&self) -> u16 {
+        self.association
+    }
+
+    /// The colour number of a colour channel, starting from 1.
+    pub fn colour(&self) -> Option<u16> {
+        match (self.channel_type, self.association) {
+            (ChannelTypes::ColourImageData, ASSOCIATION_WHOLE_IMAGE)
+            | (ChannelTypes::ColourImageData, ASSOCIATION_NONE) => None,
+            (ChannelTypes::ColourImageData, colour) => Some(colour),
+            _ => None,
+        }
+    }
+
+    /// Whether the channel is an opacity or premultiplied opacity channel.
+    pub fn is_opacity(&self) -> bool {
+        matches!(
+            self.channel_type,
+            ChannelTypes::Opacity | ChannelTypes::PremultipliedOpacity
+        )
+    }
+
+    pub fn is_premultiplied(&self) -> bool {
+        self.channel_type == ChannelTypes::PremultipliedOpacity
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+
+    pub fn samples(&self) -> &[i32] {
+        &self.samples
+    }
+
+    pub fn into_samples(self) -> Vec<i32> {
+        self.samples
+    }
+}
+
+/// Create the channels of the image from the decoded components.
+///
+/// The channels are returned in a known order: the colour channels in the
+/// order of their colours, followed by opacity and premultiplied opacity
+/// channels, followed by the other channels in the order of their index.
+///
+/// Without a Channel Definition box, the first channels are the colours of
+/// the colourspace, colours gives their number, and other channels have an
+/// unspecified type.
+pub fn map_channels(
+    components: &[ComponentSamples],
+    palette_box: Option<&PaletteBox>,
+    component_mapping_box: Option<&ComponentMappingBox>,
+    channel_definition_box: Option<&ChannelDefinitionBox>,
+    colours: usize,
+) -> Result<Vec<ImageChannel>, JP2Error> {
+    let mut channels = create_channels(components, palette_box, component_mapping_box)?;
+
+    match channel_definition_box {
+        Some(channel_definition_box) => {
+            // Channels without a description have an unspecified type
+            for channel in &mut channels {
+                channel.channel_type = ChannelTypes::Unspecified { value: u16::MAX };
+                channel.association = ASSOCIATION_NONE;
+            }
+            let mut described = vec![false; channels.len()];
+            for description in channel_definition_box.channels() {
+                let index = description.channel_index() as usize;
+                if index >= channels.len() {
+                    return Err(JP2Error::BoxMalformed {
+                        box_type: BOX_TYPE_CHANNEL_DEFINITION,
+                        offset: channel_definition_box.offset(),
+                    });
+                }
+                // A channel may be described more than once, such as an
+                // opacity channel of several colours, the first description
+                // is used.
+                if described[index] {
+                    continue;
+                }
+                described[index] = true;
+                channels[index].channel_type = description.channel_type();
+                channels[index].association = description.channel_association();
+            }
+        }
+        None => {
+            for (index, channel) in channels.iter_mut().enumerate() {
+                if index < colours {
+                    channel.channel_type = ChannelTypes::ColourImageData;
+                    channel.association = index as u16 + 1;
+                } else {
+                    channel.channel_type = ChannelTypes::Unspecified { value: u16::MAX };
+                    channel.association = ASSOCIATION_NONE;
+                }
+            }
+        }
+    }
+
+    // Sorting is stable, so channels of the same order keep their index order
+    channels.sort_by_key(|channel| match channel.colour() {
+        Some(colour) => (0, colour),
+        None if channel.is_opacity() => (1, 0),
+        None => (2, 0),
+    });
+    Ok(channels)
+}
+
+// Channels in index order, before their definition is applied.
+fn create_channels(
+    components: &[ComponentSamples],
+    palette_box: Option<&PaletteBox>,
+    component_mapping_box: Option<&ComponentMappingBox>,
+) -> Result<Vec<ImageChannel>, JP2Error> {
+    let channel = |index: usize, bit_depth: u8, signed: bool, samples: Vec<i32>| ImageChannel {
+        index: index as u16,
+        channel_type: ChannelTypes::ColourImageData,
+        association: ASSOCIATION_NONE,
+        bit_depth,
+        signed,
+        samples,
+    };
+
+    let component_mapping_box = match (component_mapping_box, palette_box) {
+        (Some(component_mapping_box), _) => component_mapping_box,
+        // Component i is mapped to channel i
+        (None, None) => {
+            return Ok(components
+                .iter()
+                .enumerate()
+                .map(|(index, component)| {
+                    channel(
+                        index,
+                        component.bit_depth,
+                        component.signed,
+                        component.samples.clone(),
+                    )
+                })
+                .collect())
+        }
+        // A Palette box shall be accompanied by a Component Mapping box
+        (None, Some(palette_box)) => {
+            return Err(JP2Error::BoxMissing {
+                box_type: BOX_TYPE_COMPONENT_MAPPING,
+                offset: palette_box.offset(),
+            })
+        }
+    };
+
+    let malformed = || JP2Error::BoxMalformed {
+        box_type: BOX_TYPE_COMPONENT_MAPPING,
+        offset: component_mapping_box.offset(),
+    };
+
+    let mut channels: Vec<ImageChannel> = vec![];
+    for (index, component_map) in component_mapping_box.component_map().iter().enumerate() {
+        let component = components
+            .get(component_map.component() as usize)
+            .ok_or_else(malformed)?;
+
+        if !component_map.is_palette_mapping() {
+            if component_map.mapping_type() != 0 {
+                return Err(malformed());
+            }
+            channels.push(channel(
+                index,
+                component.bit_depth,
+                component.signed,
+                component.samples.clone(),
+            ));
+            continue;
+        }
+
+        let palette_box = palette_box.ok_or(JP2Error::BoxMissing {
+            box_type: BOX_TYPE_PALETTE,
+            offset: component_mapping_box.offset(),
+        })?;
+        let generated_component = palette_box
+            .generated_components()
+            .get(component_map.palette() as usize)
+            .ok_or_else(malformed)?;
+        let entries: Vec<i32> = (0..palette_box.num_entries() as usize)
+            .map(|j| generated_component.entry(j))
+            .collect::<Option<_>>()
+            .ok_or(JP2Error::BoxMalformed {
+                box_type: BOX_TYPE_PALETTE,
+                offset: palette_box.offset(),
+            })?;
+        if entries.is_empty() {
+            return Err(JP2Error::BoxMalformed {
+                box_type: BOX_TYPE_PALETTE,
+                offset: palette_box.offset(),
+            });
+        }
+
+        // Indices outside of the palette are clamped to its first and last
+        // entries
+        let last = entries.len() as i64 - 1;
+        let samples: Vec<i32> = component
+            .samples
+            .iter()
+            .map(|sample| entries[(*sample as i64).clamp(0, last) as usize])
+            .collect();
+        let bit_depth = generated_component.bit_depth();
+        channels.push(channel(
+            index,
+            bit_depth.value(),
+            matches!(bit_depth, BitDepth::Signed { .. }),
+            samples,
+        ));
+    }
+    Ok(channels)
+}
+
+// Number of colours of the colourspace given by a Colour Specification box,
+// when known.
+fn colourspace_colours(colour_specification_box: &ColourSpecificationBox) -> Option<usize> {
+    match colour_specification_box.method() {
+        ColourSpecificationMethods::EnumeratedColourSpace => {
+            match colour_specification_box.enumerated_colour_space()? {
+                // Bi-level, greyscale and bi-level 2
+                0 | 15 | 17 => Some(1),
+                // CMYK and YCCK
+                12 | 13 => Some(4),
+                // YCbCr, PhotoYCC, CMY, sRGB, sYCC, CIELab, e-sRGB, ROMM-RGB,
+                // YPbPr, e-sYCC and CIEJab
+                1 | 3 | 4 | 9 | 11 | 14 | 16 | 18 | 19 | 20 | 21 | 22 | 23 | 24 | 25 => Some(3),
+                _ => None,
+            }
+        }
+        ColourSpecificationMethods::RestrictedICCProfile => {
+            // Data colour space of the profile header
+            let profile = colour_specification_box.restricted_icc_profile()?;
+            match profile.get(16..20)? {
+                b"GRAY" => Some(1),
+                b"RGB " | b"YCbr" | b"Lab " | b"XYZ " => Some(3),
+                b"CMYK" => Some(4),
+                _ => None,
+            }
+        }
+        ColourSpecificationMethods::Reserved { .. } => None,
+    }
+}
+
+impl HeaderSuperBox {
+    /// Create the channels of the image from the decoded components, using
+    /// the Palette, Component Mapping and Channel Definition boxes, see
+    /// `map_channels`.
+    ///
+    /// The number of colours is given by the first Colour Specification box,
+    /// when the colourspace is not known all channels are colour channels.
+    pub fn map_channels(
+        &self,
+        components: &[ComponentSamples],
+    ) -> Result<Vec<ImageChannel>, JP2Error> {
+        let colours = self
+            .colour_specification_boxes
+            .first()
+            .and_then(colourspace_colours)
+            .unwrap_or(usize::MAX);
+        map_channels(
+            components,
+            self.palette_box.as_ref(),
+            self.component_mapping_box.as_ref(),
+            self.channel_definition_box.as_ref(),
+            colours,
+        )
+    }
+}
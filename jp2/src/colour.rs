@@ -0,0 +1,305 @@
+// Conversion of the colour channels of an image to sRGB, ISO/IEC 15444-1
+// I.5.3.3 and ISO/IEC 15444-2 M.11.7.
+//
+// The enumerated sRGB, greyscale and sYCC colourspaces are converted
+// directly, restricted ICC profiles of ISO/IEC 15444-1 I.3.2, monochrome and
+// three-component matrix-based profiles, are converted through the XYZ
+// profile connection space.
+
+use std::io;
+
+use icc::{decode_icc, ColourSpace, Curve};
+
+use super::channels::ImageChannel;
+use super::{
+    ColourSpecificationBox, ColourSpecificationMethods, HeaderSuperBox, JP2Error,
+    BOX_TYPE_COLOUR_SPECIFICATION,
+};
+
+const ENUMERATED_COLOUR_SPACE_SRGB: u32 = 16;
+const ENUMERATED_COLOUR_SPACE_GREYSCALE: u32 = 17;
+const ENUMERATED_COLOUR_SPACE_SYCC: u32 = 18;
+
+// Conversion of linear XYZ relative to the D50 illuminant of the profile
+// connection space to linear sRGB, with Bradford chromatic adaptation to D65.
+const XYZ_D50_TO_LINEAR_SRGB: [[f64; 3]; 3] = [
+    [3.1338561, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+
+// How the colour channels are converted to sRGB.
+#[derive(Debug, Clone, PartialEq)]
+enum Conversion {
+    Srgb,
+    Greyscale,
+    Sycc,
+    Monochrome(Curve),
+    Matrix([[f64; 3]; 3], [Curve; 3]),
+}
+
+impl Conversion {
+    fn colours(&self) -> usize {
+        match self {
+            Conversion::Greyscale | Conversion::Monochrome(_) => 1,
+            _ => 3,
+        }
+    }
+
+    // Nonlinear sRGB values, from 0 to 1, of normalised colour values.
+    fn convert(&self, values: [f64; 3]) -> [f64; 3] {
+        match self {
+            Conversion::Srgb => values,
+            Conversion::Greyscale => [values[0]; 3],
+            Conversion::Sycc => {
+                // IEC 61966-2-1/Amd 1, chroma values are centred on zero
+                let [y, cb, cr] = values;
+                [
+                    y + 1.402 * cr,
+                    y - 0.344136 * cb - 0.714136 * cr,
+                    y + 1.772 * cb,
+                ]
+            }
+            Conversion::Monochrome(curve) => {
+                // Grey of the profile connection space is the white point
+                // chromaticity, which maps to grey of sRGB
+                [encode_srgb(curve.evaluate(clamp(values[0]))); 3]
+            }
+            Conversion::Matrix(matrix, curves) => {
+                let mut linear = [0.0; 3];
+                for (value, (x, curve)) in linear.iter_mut().zip(values.iter().zip(curves)) {
+                    *value = curve.evaluate(clamp(*x));
+                }
+                let xyz = multiply(matrix, linear);
+                let srgb = multiply(&XYZ_D50_TO_LINEAR_SRGB, xyz);
+                [
+                    encode_srgb(srgb[0]),
+                    encode_srgb(srgb[1]),
+                    encode_srgb(srgb[2]),
+                ]
+            }
+        }
+    }
+}
+
+fn multiply(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    let mut result = [0.0; 3];
+    for (value, row) in result.iter_mut().zip(matrix) {
+        *value = row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+    }
+    result
+}
+
+fn clamp(value: f64) -> f64 {
+    value.clamp(0.0, 1.0)
+}
+
+// The sRGB transfer function of IEC 61966-2-1, from linear to nonlinear
+// values.
+fn encode_srgb(linear: f64) -> f64 {
+    let linear = clamp(linear);
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Conversion of a restricted ICC profile, None if the profile is not a
+// monochrome or three-component matrix-based profile.
+fn restricted_icc_conversion(profile: &[u8]) -> Option<Conversion> {
+    let profile = decode_icc(&mut io::Cursor::new(profile)).ok()?;
+    if profile.header().pcs() != ColourSpace::Xyz {
+        return None;
+    }
+    match profile.header().colour_space() {
+        ColourSpace::Gray => Some(Conversion::Monochrome(profile.gray_trc()?.clone())),
+        ColourSpace::Rgb => {
+            let red = profile.red_colorant()?;
+            let green = profile.green_colorant()?;
+            let blue = profile.blue_colorant()?;
+            Some(Conversion::Matrix(
+                [
+                    [red.x, green.x, blue.x],
+                    [red.y, green.y, blue.y],
+                    [red.z, green.z, blue.z],
+                ],
+                [
+                    profile.red_trc()?.clone(),
+                    profile.green_trc()?.clone(),
+                    profile.blue_trc()?.clone(),
+                ],
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn conversion(colour_specification_box: &ColourSpecificationBox) -> Option<Conversion> {
+    match colour_specification_box.method() {
+        ColourSpecificationMethods::EnumeratedColourSpace => {
+            match colour_specification_box.enumerated_colour_space()? {
+                ENUMERATED_COLOUR_SPACE_SRGB => Some(Conversion::Srgb),
+                ENUMERATED_COLOUR_SPACE_GREYSCALE => Some(Conversion::Greyscale),
+                ENUMERATED_COLOUR_SPACE_SYCC => Some(Conversion::Sycc),
+                _ => None,
+            }
+        }
+        ColourSpecificationMethods::RestrictedICCProfile => {
+            restricted_icc_conversion(colour_specification_box.restricted_icc_profile()?)
+        }
+        ColourSpecificationMethods::Reserved { .. } => None,
+    }
+}
+
+// Rank of the colourspace approximation, accurate first and not specified
+// last.
+fn approximation_rank(colour_specification_box: &ColourSpecificationBox) -> u8 {
+    match colour_specification_box.colourspace_approximation() {
+        0 => u8::MAX,
+        approximation => approximation,
+    }
+}
+
+/// The Colour Specification box used for the conversion to sRGB.
+///
+/// Of the boxes whose colourspace can be converted, the box with the highest
+/// precedence is used, then the box with the most accurate colourspace
+/// approximation and then the first box. As every box of a JP2 file has the
+/// same precedence and approximation, this is the first box which can be
+/// converted.
+pub fn srgb_colour_specification(
+    colour_specification_boxes: &[ColourSpecificationBox],
+) -> Option<&ColourSpecificationBox> {
+    select(colour_specification_boxes).map(|(colour_specification_box, _)| colour_specification_box)
+}
+
+fn select(
+    colour_specification_boxes: &[ColourSpecificationBox],
+) -> Option<(&ColourSpecificationBox, Conversion)> {
+    colour_specification_boxes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, colour_specification_box)| {
+            conversion(colour_specification_box)
+                .map(|conversion| (index, colour_specification_box, conversion))
+        })
+        .min_by_key(|(index, colour_specification_box, _)| {
+            (
+                -(colour_specification_box.precedence() as i16),
+                approximation_rank(colour_specification_box),
+                *index,
+            )
+        })
+        .map(|(_, colour_specification_box, conversion)| (colour_specification_box, conversion))
+}
+
+// Value of a sample from 0 to 1, signed samples are offset to be unsigned.
+fn normalise(sample: i32, bit_depth: u8, signed: bool) -> f64 {
+    let bit_depth = bit_depth.max(1) as i32;
+    let offset = if signed {
+        2f64.powi(bit_depth - 1)
+    } else {
+        0.0
+    };
+    (sample as f64 + offset) / (2f64.powi(bit_depth) - 1.0)
+}
+
+/// Convert the colour channels of an image to 8-bit sRGB pixels.
+///
+/// The colour channels are those with a colour association, in the order
+/// returned by `map_channels`, opacity and other channels are ignored. The
+/// colour channels shall have the same number of samples, subsampled
+/// components need to be upsampled first.
+///
+/// The colourspace is given by the Colour Specification box selected by
+/// `srgb_colour_specification`, such as the boxes of a JP2 Header box or of a
+/// Colour Group box.
+pub fn convert_to_srgb(
+    colour_specification_boxes: &[ColourSpecificationBox],
+    channels: &[ImageChannel],
+) -> Result<Vec<[u8; 3]>, JP2Error> {
+    let (colour_specification_box, conversion) = match select(colour_specification_boxes) {
+        Some(selected) => selected,
+        None if colour_specification_boxes.is_empty() => {
+            return Err(JP2Error::BoxMissing {
+                box_type: BOX_TYPE_COLOUR_SPECIFICATION,
+                offset: 0,
+            })
+        }
+        None => {
+            return Err(JP2Error::UnsupportedColourSpace {
+                offset: colour_specification_boxes[0].offset,
+            })
+        }
+    };
+
+    let colour_channels: Vec<&ImageChannel> = channels
+        .iter()
+        .filter(|channel| channel.colour().is_some())
+        .collect();
+    let colours = conversion.colours();
+    let length = colour_channels
+        .first()
+        .map(|channel| channel.samples().len())
+        .unwrap_or(0);
+    if colour_channels.len() != colours
+        || colour_channels
+            .iter()
+            .any(|channel| channel.samples().len() != length)
+    {
+        return Err(JP2Error::ColourChannelsMismatch {
+            expected: colours,
+            found: colour_channels.len(),
+            offset: colour_specification_box.offset,
+        });
+    }
+
+    // Chroma channels of sYCC are centred on zero
+    let offsets: [f64; 3] = match conversion {
+        Conversion::Sycc => {
+            let mut offsets = [0.0; 3];
+            for (offset, channel) in offsets.iter_mut().zip(&colour_channels).skip(1) {
+                let bit_depth = channel.bit_depth().max(1) as i32;
+                *offset = 2f64.powi(bit_depth - 1) / (2f64.powi(bit_depth) - 1.0);
+            }
+            offsets
+        }
+        _ => [0.0; 3],
+    };
+
+    let mut pixels: Vec<[u8; 3]> = Vec::with_capacity(length);
+    for index in 0..length {
+        let mut values = [0.0; 3];
+        for (colour, channel) in colour_channels.iter().enumerate() {
+            values[colour] = normalise(
+                channel.samples()[index],
+                channel.bit_depth(),
+                channel.signed(),
+            ) - offsets[colour];
+        }
+        let srgb = conversion.convert(values);
+        pixels.push([
+            (clamp(srgb[0]) * 255.0).round() as u8,
+            (clamp(srgb[1]) * 255.0).round() as u8,
+            (clamp(srgb[2]) * 255.0).round() as u8,
+        ]);
+    }
+    Ok(pixels)
+}
+
+impl HeaderSuperBox {
+    /// Convert the colour channels of the image to 8-bit sRGB pixels, using
+    /// the Colour Specification boxes of the header, see `convert_to_srgb`.
+    pub fn convert_to_srgb(&self, channels: &[ImageChannel]) -> Result<Vec<[u8; 3]>, JP2Error> {
+        convert_to_srgb(&self.colour_specification_boxes, channels)
+    }
+}
+
+impl ColourSpecificationBox {
+    /// Whether the colourspace of the box can be converted to sRGB by
+    /// `convert_to_srgb`.
+    pub fn is_srgb_convertible(&self) -> bool {
+        conversion(self).is_some()
+    }
+}
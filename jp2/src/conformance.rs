@@ -0,0 +1,500 @@
+// Conformance of JP2 files to the requirements of ISO/IEC 15444-1 Annex I.
+//
+// Files which cannot be parsed are rejected by decode_jp2 whatever the
+// options, the requirements checked here leave the file readable. A lenient
+// decode records them as warnings of the file, a strict decode fails on the
+// first of them.
+
+use std::fmt;
+
+use jpc::{CodestreamError, ImageAndTileSizeMarkerSegment, Limits};
+use log::warn;
+
+use super::{
+    BoxType, HeaderSuperBox, JBox, UUIDInfoSuperBox, BOX_TYPE_BITS_PER_COMPONENT,
+    BOX_TYPE_CONTIGUOUS_CODESTREAM, BOX_TYPE_DATA_ENTRY_URL, BOX_TYPE_FILE_TYPE,
+    BOX_TYPE_IMAGE_HEADER, BOX_TYPE_INTELLECTUAL_PROPERTY, BOX_TYPE_UUID_LIST,
+    COMPRESSION_TYPE_WAVELET,
+};
+
+/// Options of `decode_jp2_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    strict: bool,
+    limits: Limits,
+}
+
+impl DecodeOptions {
+    /// Fail on the first requirement of Annex I not met by the file.
+    pub fn strict() -> DecodeOptions {
+        DecodeOptions {
+            strict: true,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Record the requirements of Annex I not met by the file as warnings,
+    /// the default of `decode_jp2`.
+    pub fn lenient() -> DecodeOptions {
+        DecodeOptions {
+            strict: false,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Fail with a `LimitError` on a box or value of the Image Header box
+    /// exceeding the limits, instead of the default limits.
+    pub fn with_limits(mut self, limits: Limits) -> DecodeOptions {
+        self.limits = limits;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+}
+
+/// A requirement of ISO/IEC 15444-1 Annex I not met by a file which can
+/// still be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Nonconformance {
+    /// The File Type box has a minor version other than 0, I.5.2.
+    MinorVersion { min_version: u32 },
+    /// A box which shall occur once occurs again.
+    BoxDuplicate,
+    /// A box required by another box or by the file is missing.
+    BoxMissing,
+    /// A box is present where it shall not be.
+    BoxUnexpected,
+    /// The Image Header box has a compression type other than 7, I.5.3.1.
+    CompressionType { compression_type: u8 },
+    /// The Image Header box has a Colourspace Unknown value other than 0 or
+    /// 1, I.5.3.1.
+    ColourspaceUnknown { colourspace_unknown: u8 },
+    /// The Image Header box has an Intellectual Property value other than 0
+    /// or 1, I.5.3.1.
+    IntellectualProperty { intellectual_property: u8 },
+    /// A Colour Specification box has a precedence other than 0, I.5.3.3.
+    Precedence { precedence: i8 },
+    /// A Colour Specification box has a colourspace approximation above 4,
+    /// I.5.3.3.
+    Approximation { approximation: u8 },
+    /// A Data Entry URL box has a version or flags other than 0, I.7.3.2.
+    VersionFlags { version: u8, flags: u32 },
+    /// The first codestream does not start with the SOC marker and a SIZ
+    /// marker segment.
+    CodestreamMalformed,
+    /// The height of the Image Header box differs from Ysiz - YOsiz of the
+    /// codestream, I.5.3.1.
+    HeightMismatch { height: u32, codestream_height: u32 },
+    /// The width of the Image Header box differs from Xsiz - XOsiz of the
+    /// codestream, I.5.3.1.
+    WidthMismatch { width: u32, codestream_width: u32 },
+    /// The number of components of the Image Header box differs from Csiz of
+    /// the codestream, I.5.3.1.
+    ComponentsMismatch {
+        components: u16,
+        codestream_components: u16,
+    },
+    /// The bit depth of a component given by the Image Header box or the Bits
+    /// Per Component box differs from Ssiz of the codestream, both encoded
+    /// as the bit depth minus 1 with the sign in the high bit, I.5.3.2.
+    BitDepthMismatch {
+        component: u16,
+        bits: u8,
+        codestream_bits: u8,
+    },
+}
+
+// A bit depth encoded as BPC and Ssiz, as text.
+fn bit_depth(bits: u8) -> String {
+    let signedness = if bits & 0x80 == 0x80 {
+        "signed"
+    } else {
+        "unsigned"
+    };
+    format!("{} bit {}", (bits & 0x7f) + 1, signedness)
+}
+
+impl fmt::Display for Nonconformance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MinorVersion { min_version } => {
+                write!(f, "minor version {} is not 0", min_version)
+            }
+            Self::BoxDuplicate => write!(f, "box shall occur only once"),
+            Self::BoxMissing => write!(f, "required box missing"),
+            Self::BoxUnexpected => write!(f, "box shall not be present"),
+            Self::CompressionType { compression_type } => {
+                write!(f, "compression type {} is not 7", compression_type)
+            }
+            Self::ColourspaceUnknown {
+                colourspace_unknown,
+            } => write!(
+                f,
+                "colourspace unknown value {} is not 0 or 1",
+                colourspace_unknown
+            ),
+            Self::IntellectualProperty {
+                intellectual_property,
+            } => write!(
+                f,
+                "intellectual property value {} is not 0 or 1",
+                intellectual_property
+            ),
+            Self::Precedence { precedence } => write!(f, "precedence {} is not 0", precedence),
+            Self::Approximation { approximation } => {
+                write!(f, "colourspace approximation {} is above 4", approximation)
+            }
+            Self::VersionFlags { version, flags } => {
+                write!(f, "version {} and flags {} are not 0", version, flags)
+            }
+            Self::CodestreamMalformed => {
+                write!(f, "codestream does not start with SOC and SIZ markers")
+            }
+            Self::HeightMismatch {
+                height,
+                codestream_height,
+            } => write!(
+                f,
+                "height {} differs from {} of the codestream",
+                height, codestream_height
+            ),
+            Self::WidthMismatch {
+                width,
+                codestream_width,
+            } => write!(
+                f,
+                "width {} differs from {} of the codestream",
+                width, codestream_width
+            ),
+            Self::ComponentsMismatch {
+                components,
+                codestream_components,
+            } => write!(
+                f,
+                "{} components differ from {} of the codestream",
+                components, codestream_components
+            ),
+            Self::BitDepthMismatch {
+                component,
+                bits,
+                codestream_bits,
+            } => write!(
+                f,
+                "component {} of {} differs from {} of the codestream",
+                component,
+                bit_depth(*bits),
+                bit_depth(*codestream_bits)
+            ),
+        }
+    }
+}
+
+/// A nonconformance of a box, the offset of its contents is unknown for
+/// missing boxes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeWarning {
+    pub box_type: BoxType,
+    pub offset: Option<u64>,
+    pub nonconformance: Nonconformance,
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "box type {:?}", self.box_type)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ": {}", self.nonconformance)
+    }
+}
+
+// Warnings of a file being decoded, in the order they are found.
+#[derive(Debug, Default)]
+pub(crate) struct Warnings(pub(crate) Vec<DecodeWarning>);
+
+impl Warnings {
+    pub(crate) fn push(
+        &mut self,
+        box_type: BoxType,
+        offset: Option<u64>,
+        nonconformance: Nonconformance,
+    ) {
+        let warning = DecodeWarning {
+            box_type,
+            offset,
+            nonconformance,
+        };
+        warn!("{}", warning);
+        self.0.push(warning);
+    }
+
+    pub(crate) fn check_min_version(&mut self, offset: u64, min_version: u32) {
+        // The value of this field shall be zero.
+        if min_version != 0 {
+            self.push(
+                BOX_TYPE_FILE_TYPE,
+                Some(offset),
+                Nonconformance::MinorVersion { min_version },
+            );
+        }
+    }
+
+    pub(crate) fn check_header(&mut self, header_box: &HeaderSuperBox) {
+        // The JP2 Header box contains one Image Header box, others are
+        // ignored.
+        for offset in &header_box.ignored_image_headers {
+            self.push(
+                BOX_TYPE_IMAGE_HEADER,
+                Some(*offset),
+                Nonconformance::BoxDuplicate,
+            );
+        }
+
+        let image_header_box = &header_box.image_header_box;
+        let offset = Some(image_header_box.offset());
+        let compression_type = image_header_box.compression_type();
+        if compression_type != COMPRESSION_TYPE_WAVELET {
+            self.push(
+                BOX_TYPE_IMAGE_HEADER,
+                offset,
+                Nonconformance::CompressionType { compression_type },
+            );
+        }
+        let colourspace_unknown = image_header_box.colourspace_unknown();
+        if colourspace_unknown > 1 {
+            self.push(
+                BOX_TYPE_IMAGE_HEADER,
+                offset,
+                Nonconformance::ColourspaceUnknown {
+                    colourspace_unknown,
+                },
+            );
+        }
+        let intellectual_property = image_header_box.intellectual_property();
+        if intellectual_property > 1 {
+            self.push(
+                BOX_TYPE_IMAGE_HEADER,
+                offset,
+                Nonconformance::IntellectualProperty {
+                    intellectual_property,
+                },
+            );
+        }
+
+        // The Bits Per Component box shall be present if and only if the bit
+        // depths of the components vary, a BPC of 255.
+        let varying_bits = image_header_box.components_bits[0] == 255;
+        match &header_box.bits_per_component_box {
+            Some(bits_per_component_box) if !varying_bits => self.push(
+                BOX_TYPE_BITS_PER_COMPONENT,
+                Some(bits_per_component_box.offset()),
+                Nonconformance::BoxUnexpected,
+            ),
+            None if varying_bits => self.push(
+                BOX_TYPE_BITS_PER_COMPONENT,
+                None,
+                Nonconformance::BoxMissing,
+            ),
+            _ => {}
+        }
+
+        // In JP2 files the precedence shall be 0. The approximation should
+        // be 0 and is ignored by readers, the values of ISO/IEC 15444-2 up to
+        // 4 are accepted as used by the conformance files of ISO/IEC 15444-4.
+        // Colour Specification boxes after the first are ignored by JP2
+        // readers, and may be meant for JPX readers.
+        if let Some(colour_specification_box) = header_box.colour_specification_boxes.first() {
+            let offset = Some(colour_specification_box.offset());
+            let precedence = colour_specification_box.precedence();
+            if precedence != 0 {
+                self.push(
+                    colour_specification_box.identifier(),
+                    offset,
+                    Nonconformance::Precedence { precedence },
+                );
+            }
+            let approximation = colour_specification_box.colourspace_approximation();
+            if approximation > 4 {
+                self.push(
+                    colour_specification_box.identifier(),
+                    offset,
+                    Nonconformance::Approximation { approximation },
+                );
+            }
+        }
+    }
+
+    pub(crate) fn check_uuid_info(&mut self, uuid_info_box: &UUIDInfoSuperBox) {
+        // A UUID Info box contains one UUID List box and one Data Entry URL
+        // box.
+        let children = [
+            (BOX_TYPE_UUID_LIST, uuid_info_box.uuid_list.len()),
+            (
+                BOX_TYPE_DATA_ENTRY_URL,
+                uuid_info_box.data_entry_url_box.len(),
+            ),
+        ];
+        for (box_type, count) in children {
+            if count == 0 {
+                self.push(box_type, None, Nonconformance::BoxMissing);
+            }
+        }
+        for uuid_list_box in uuid_info_box.uuid_list.iter().skip(1) {
+            self.push(
+                BOX_TYPE_UUID_LIST,
+                Some(uuid_list_box.offset()),
+                Nonconformance::BoxDuplicate,
+            );
+        }
+        for (index, data_entry_url_box) in uuid_info_box.data_entry_url_box.iter().enumerate() {
+            let offset = Some(data_entry_url_box.offset());
+            if index > 0 {
+                self.push(
+                    BOX_TYPE_DATA_ENTRY_URL,
+                    offset,
+                    Nonconformance::BoxDuplicate,
+                );
+            }
+            let (version, flags) = (data_entry_url_box.version(), data_entry_url_box.flags());
+            if version != 0 || flags != 0 {
+                self.push(
+                    BOX_TYPE_DATA_ENTRY_URL,
+                    offset,
+                    Nonconformance::VersionFlags { version, flags },
+                );
+            }
+        }
+    }
+
+    pub(crate) fn check_intellectual_property(
+        &mut self,
+        header_box: &HeaderSuperBox,
+        intellectual_property_offset: Option<u64>,
+    ) {
+        // The Intellectual Property box is present if and only if the IPR
+        // field of the Image Header box is 1.
+        let intellectual_property = header_box.image_header_box.intellectual_property();
+        match intellectual_property_offset {
+            Some(offset) if intellectual_property == 0 => self.push(
+                BOX_TYPE_INTELLECTUAL_PROPERTY,
+                Some(offset),
+                Nonconformance::BoxUnexpected,
+            ),
+            None if intellectual_property == 1 => self.push(
+                BOX_TYPE_INTELLECTUAL_PROPERTY,
+                None,
+                Nonconformance::BoxMissing,
+            ),
+            _ => {}
+        }
+    }
+
+    // The Image Header box repeats the size of the image area, the number of
+    // components and their bit depths from the SIZ marker segment of the
+    // first codestream, as does the Bits Per Component box for bit depths
+    // which vary. The compression type is checked with the header.
+    pub(crate) fn check_codestream(
+        &mut self,
+        header_box: &HeaderSuperBox,
+        offset: u64,
+        siz: Result<ImageAndTileSizeMarkerSegment, CodestreamError>,
+    ) {
+        let offset = Some(offset);
+        let siz = match siz {
+            Ok(siz) => siz,
+            Err(_) => {
+                self.push(
+                    BOX_TYPE_CONTIGUOUS_CODESTREAM,
+                    offset,
+                    Nonconformance::CodestreamMalformed,
+                );
+                return;
+            }
+        };
+
+        let image_header_box = &header_box.image_header_box;
+        let bits_per_component = header_box
+            .bits_per_component_box
+            .as_ref()
+            .map(|bits_per_component_box| bits_per_component_box.bits_per_component.as_slice());
+        for nonconformance in codestream_mismatches(
+            image_header_box.height(),
+            image_header_box.width(),
+            image_header_box.components_num(),
+            image_header_box.components_bits[0],
+            bits_per_component,
+            &siz,
+        ) {
+            self.push(BOX_TYPE_CONTIGUOUS_CODESTREAM, offset, nonconformance);
+        }
+    }
+}
+
+// The height, width, number of components and bit depths of the Image Header
+// box, given by the Bits Per Component box for a BPC of 255, which differ
+// from the SIZ marker segment. Without the Bits Per Component box, reported
+// with the header, the bit depths are unknown and not compared, as are those
+// of a number of components which differs.
+pub(crate) fn codestream_mismatches(
+    height: u32,
+    width: u32,
+    components: u16,
+    bits: u8,
+    bits_per_component: Option<&[u8]>,
+    siz: &ImageAndTileSizeMarkerSegment,
+) -> Vec<Nonconformance> {
+    let mut nonconformances: Vec<Nonconformance> = vec![];
+    let codestream_height = siz
+        .reference_grid_height()
+        .wrapping_sub(siz.image_vertical_offset());
+    if height != codestream_height {
+        nonconformances.push(Nonconformance::HeightMismatch {
+            height,
+            codestream_height,
+        });
+    }
+    let codestream_width = siz
+        .reference_grid_width()
+        .wrapping_sub(siz.image_horizontal_offset());
+    if width != codestream_width {
+        nonconformances.push(Nonconformance::WidthMismatch {
+            width,
+            codestream_width,
+        });
+    }
+    if components != siz.no_components() {
+        nonconformances.push(Nonconformance::ComponentsMismatch {
+            components,
+            codestream_components: siz.no_components(),
+        });
+        return nonconformances;
+    }
+
+    let bits: Vec<u8> = match (bits, bits_per_component) {
+        (255, Some(bits_per_component)) => bits_per_component.to_vec(),
+        (255, None) => return nonconformances,
+        (bits, _) => vec![bits; components as usize],
+    };
+    for (component, bits) in (0..components).zip(bits) {
+        let index = component as usize;
+        let (precision, signed) = match (siz.precision(index), siz.values_are_signed(index)) {
+            (Ok(precision), Ok(signed)) => (precision, signed),
+            _ => continue,
+        };
+        let codestream_bits = ((signed as u8) << 7) | (precision - 1) as u8;
+        if bits != codestream_bits {
+            nonconformances.push(Nonconformance::BitDepthMismatch {
+                component,
+                bits,
+                codestream_bits,
+            });
+        }
+    }
+    nonconformances
+}
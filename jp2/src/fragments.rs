@@ -0,0 +1,283 @@
+// Reassembly of data stored in fragments, ISO/IEC 15444-2 M.11.15 to M.11.18.
+//
+// A Fragment List box lists the fragments of a codestream or box in order,
+// each fragment is in the file containing the list or in a file referenced by
+// a URL of the Data Reference box.
+
+use std::fs;
+use std::io::{self, Seek};
+use std::path::{Path, PathBuf};
+
+use super::jpx::{DataReferenceBox, Fragment, FragmentListBox, JPXFile};
+use super::{
+    JBox, JP2Error, BOX_TYPE_DATA_ENTRY_URL, BOX_TYPE_DATA_REFERENCE, BOX_TYPE_FRAGMENT_LIST,
+    BOX_TYPE_FRAGMENT_TABLE,
+};
+
+/// Read and seek view of the data stored in the fragments of a Fragment List
+/// box, such as a codestream of a Fragment Table box.
+///
+/// Fragments in the file containing the list are read from the given reader,
+/// the files of other fragments are opened when the view is created. Files
+/// are only opened within a base directory given by the caller, as the URLs
+/// are those of an untrusted file. Only local file URLs are supported,
+/// relative URLs are resolved against the base directory.
+pub struct FragmentReader<R: io::Read + io::Seek> {
+    reader: R,
+    // Opened files by data reference, from a data reference of 1.
+    files: Vec<Option<io::BufReader<fs::File>>>,
+    fragments: Vec<Fragment>,
+    // Offset of each fragment in the reassembled data.
+    starts: Vec<u64>,
+    length: u64,
+    position: u64,
+}
+
+impl<R: io::Read + io::Seek> FragmentReader<R> {
+    /// Create a view of the fragments listed by the Fragment List box, reader
+    /// is the file containing the box. Fragments in other files are only read
+    /// with a base_directory, such as the directory of the file, which the
+    /// files shall be in.
+    pub fn new(
+        reader: R,
+        fragment_list_box: &FragmentListBox,
+        data_reference_box: Option<&DataReferenceBox>,
+        base_directory: Option<&Path>,
+    ) -> Result<FragmentReader<R>, JP2Error> {
+        let data_entry_url_boxes = match data_reference_box {
+            Some(data_reference_box) => data_reference_box.data_entry_url_boxes(),
+            None => &[],
+        };
+        let mut files: Vec<Option<io::BufReader<fs::File>>> = vec![];
+        files.resize_with(data_entry_url_boxes.len(), || None);
+
+        let mut fragments: Vec<Fragment> = vec![];
+        let mut starts: Vec<u64> = vec![];
+        let mut length: u64 = 0;
+        for fragment in fragment_list_box.fragments() {
+            let data_reference = fragment.data_reference() as usize;
+            if data_reference > 0 && files.get(data_reference - 1).is_none() {
+                if data_reference_box.is_none() {
+                    return Err(JP2Error::BoxMissing {
+                        box_type: BOX_TYPE_DATA_REFERENCE,
+                        offset: fragment_list_box.offset(),
+                    });
+                }
+                return Err(JP2Error::BoxMalformed {
+                    box_type: BOX_TYPE_FRAGMENT_LIST,
+                    offset: fragment_list_box.offset(),
+                });
+            }
+            if data_reference > 0 && files[data_reference - 1].is_none() {
+                let data_entry_url_box = &data_entry_url_boxes[data_reference - 1];
+                let offset = data_entry_url_box.offset();
+                let url = data_entry_url_box
+                    .location()
+                    .map_err(|_| JP2Error::BoxMalformed {
+                        box_type: BOX_TYPE_DATA_ENTRY_URL,
+                        offset,
+                    })?;
+                let path = resolve_url(url, offset, base_directory)?;
+                let file =
+                    fs::File::open(path).map_err(|source| JP2Error::Io { offset, source })?;
+                files[data_reference - 1] = Some(io::BufReader::new(file));
+            }
+
+            // Empty fragments are skipped, so each offset is in one fragment
+            if fragment.length() == 0 {
+                continue;
+            }
+            fragments.push(*fragment);
+            starts.push(length);
+            length += fragment.length() as u64;
+        }
+
+        Ok(FragmentReader {
+            reader,
+            files,
+            fragments,
+            starts,
+            length,
+            position: 0,
+        })
+    }
+
+    /// Length of the reassembled data.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// The reader of the file containing the Fragment List box.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: io::Read + io::Seek> io::Read for FragmentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = match self.starts.binary_search(&self.position) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        let fragment = self.fragments[index];
+        let within = self.position - self.starts[index];
+        let available = fragment.length() as u64 - within;
+        let size = available.min(buf.len() as u64) as usize;
+
+        let offset = fragment.offset().checked_add(within).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "fragment offset overflows")
+        })?;
+        match fragment.data_reference() {
+            0 => {
+                self.reader.seek(io::SeekFrom::Start(offset))?;
+                self.reader.read_exact(&mut buf[..size])?;
+            }
+            data_reference => {
+                // Files of every data reference were opened by new
+                let file = self.files[data_reference as usize - 1].as_mut().unwrap();
+                file.seek(io::SeekFrom::Start(offset))?;
+                file.read_exact(&mut buf[..size])?;
+            }
+        }
+        self.position += size as u64;
+        Ok(size)
+    }
+}
+
+impl<R: io::Read + io::Seek> io::Seek for FragmentReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+// Path of the file referenced by a URL of a Data Entry URL box, as specified
+// by RFC 8089 for file URLs. URLs without a scheme are relative references,
+// offset is of the box of the URL. The path, with symbolic links and parent
+// components resolved, shall be within the base directory.
+fn resolve_url(url: &str, offset: u64, base_directory: Option<&Path>) -> Result<PathBuf, JP2Error> {
+    let unsupported = || JP2Error::UnsupportedURL {
+        url: url.to_string(),
+        offset,
+    };
+
+    let path = match url.find(':') {
+        Some(index) if is_scheme(&url[..index]) => {
+            if !url[..index].eq_ignore_ascii_case("file") {
+                return Err(unsupported());
+            }
+            let rest = &url[index + 1..];
+            match rest.strip_prefix("//") {
+                // Only an empty host or localhost is on this machine
+                Some(authority) => {
+                    let (host, path) = match authority.find('/') {
+                        Some(index) => authority.split_at(index),
+                        None => (authority, ""),
+                    };
+                    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+                        return Err(unsupported());
+                    }
+                    path
+                }
+                None => rest,
+            }
+        }
+        _ => url,
+    };
+    let path = PathBuf::from(percent_decode(path).ok_or_else(unsupported)?);
+
+    let base_directory = match base_directory {
+        Some(base_directory) => base_directory
+            .canonicalize()
+            .map_err(|source| JP2Error::Io { offset, source })?,
+        None => {
+            return Err(JP2Error::UnresolvedURL {
+                url: url.to_string(),
+                offset,
+            })
+        }
+    };
+    // An absolute path replaces the base directory when joined
+    let path = base_directory
+        .join(path)
+        .canonicalize()
+        .map_err(|source| JP2Error::Io { offset, source })?;
+    if !path.starts_with(&base_directory) {
+        return Err(JP2Error::ForbiddenURL {
+            url: url.to_string(),
+            offset,
+        });
+    }
+    Ok(path)
+}
+
+// A URL scheme starts with a letter, single letters are taken as drive letters.
+fn is_scheme(scheme: &str) -> bool {
+    scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = path.get(index + 1..index + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl JPXFile {
+    /// View of the codestream of the n-th Fragment Table box, see
+    /// `FragmentReader`.
+    pub fn fragmented_codestream<R: io::Read + io::Seek>(
+        &self,
+        index: usize,
+        reader: R,
+        base_directory: Option<&Path>,
+    ) -> Result<FragmentReader<R>, JP2Error> {
+        match self.fragment_table_boxes().get(index) {
+            Some(fragment_table_box) => FragmentReader::new(
+                reader,
+                &fragment_table_box.fragment_list_box,
+                self.data_reference_box().as_ref(),
+                base_directory,
+            ),
+            None => Err(JP2Error::BoxMissing {
+                box_type: BOX_TYPE_FRAGMENT_TABLE,
+                offset: 0,
+            }),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// GeoJP2, georeferencing of JP2 files by a UUID box containing a degenerate
+// GeoTIFF, as written by GDAL.
+//
+// The GeoTIFF is a 1x1 TIFF image whose first IFD carries the GeoTIFF tags,
+// ModelPixelScale, ModelTiepoint or ModelTransformation and the GeoKey
+// directory, which apply to the image of the JP2 file.
+
+use super::tiff::Tiff;
+use super::{JBox, JP2Error, JP2File, UUIDBox, BOX_TYPE_UUID};
+
+/// UUID of the GeoJP2 box, b14bf8bd-083d-4b43-a5ae-8cd7d5a6ce03.
+pub const UUID_GEOJP2: [u8; 16] = [
+    0xb1, 0x4b, 0xf8, 0xbd, 0x08, 0x3d, 0x4b, 0x43, 0xa5, 0xae, 0x8c, 0xd7, 0xd5, 0xa6, 0xce, 0x03,
+];
+
+// GeoTIFF tags
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_MODEL_TRANSFORMATION: u16 = 34264;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GEO_DOUBLE_PARAMS: u16 = 34736;
+const TAG_GEO_ASCII_PARAMS: u16 = 34737;
+
+/// GTModelTypeGeoKey, 1 for projected, 2 for geographic and 3 for geocentric
+/// coordinate systems.
+pub const GEO_KEY_MODEL_TYPE: u16 = 1024;
+/// GTRasterTypeGeoKey, 1 for PixelIsArea and 2 for PixelIsPoint.
+pub const GEO_KEY_RASTER_TYPE: u16 = 1025;
+pub const GEO_KEY_CITATION: u16 = 1026;
+/// GeographicTypeGeoKey, the EPSG code of a geographic coordinate system.
+pub const GEO_KEY_GEOGRAPHIC_TYPE: u16 = 2048;
+pub const GEO_KEY_GEOGRAPHIC_CITATION: u16 = 2049;
+/// ProjectedCSTypeGeoKey, the EPSG code of a projected coordinate system.
+pub const GEO_KEY_PROJECTED_CS_TYPE: u16 = 3072;
+pub const GEO_KEY_PROJECTED_CITATION: u16 = 3073;
+
+const RASTER_TYPE_PIXEL_IS_POINT: u16 = 2;
+
+// Coordinate system codes which are not EPSG codes
+const USER_DEFINED: u16 = 32767;
+
+/// Value of a GeoKey.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoKeyValue {
+    /// A SHORT value stored in the GeoKey directory.
+    Short(u16),
+
+    /// DOUBLE values of GeoDoubleParams.
+    Double(Vec<f64>),
+
+    /// An ASCII string of GeoAsciiParams, without its '|' terminator.
+    Ascii(String),
+}
+
+/// A GeoKey of the GeoKey directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoKey {
+    id: u16,
+    value: GeoKeyValue,
+}
+
+impl GeoKey {
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn value(&self) -> &GeoKeyValue {
+        &self.value
+    }
+}
+
+/// Georeferencing of a GeoJP2 box.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoJP2 {
+    pixel_scale: Option<[f64; 3]>,
+    tiepoints: Vec<[f64; 6]>,
+    transformation: Option<[f64; 16]>,
+    geo_keys: Vec<GeoKey>,
+}
+
+impl GeoJP2 {
+    /// ModelPixelScale, the size of a pixel in model coordinates, as the
+    /// scale of X, Y and Z.
+    pub fn pixel_scale(&self) -> Option<[f64; 3]> {
+        self.pixel_scale
+    }
+
+    /// ModelTiepoint, each point as the raster coordinates I, J and K and the
+    /// model coordinates X, Y and Z.
+    pub fn tiepoints(&self) -> &[[f64; 6]] {
+        &self.tiepoints
+    }
+
+    /// ModelTransformation, the 4x4 matrix from raster to model coordinates
+    /// in row-major order.
+    pub fn transformation(&self) -> Option<[f64; 16]> {
+        self.transformation
+    }
+
+    /// GeoKeys of the GeoKey directory, in the order of the directory.
+    pub fn geo_keys(&self) -> &[GeoKey] {
+        &self.geo_keys
+    }
+
+    pub fn geo_key(&self, id: u16) -> Option<&GeoKeyValue> {
+        self.geo_keys
+            .iter()
+            .find(|geo_key| geo_key.id == id)
+            .map(|geo_key| &geo_key.value)
+    }
+
+    fn short(&self, id: u16) -> Option<u16> {
+        match self.geo_key(id)? {
+            GeoKeyValue::Short(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn ascii(&self, id: u16) -> Option<&str> {
+        match self.geo_key(id)? {
+            GeoKeyValue::Ascii(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// GTModelTypeGeoKey.
+    pub fn model_type(&self) -> Option<u16> {
+        self.short(GEO_KEY_MODEL_TYPE)
+    }
+
+    /// GTRasterTypeGeoKey.
+    pub fn raster_type(&self) -> Option<u16> {
+        self.short(GEO_KEY_RASTER_TYPE)
+    }
+
+    /// EPSG code of the coordinate reference system, ProjectedCSTypeGeoKey
+    /// or otherwise GeographicTypeGeoKey. User-defined coordinate systems
+    /// have no code.
+    pub fn crs_code(&self) -> Option<u16> {
+        [GEO_KEY_PROJECTED_CS_TYPE, GEO_KEY_GEOGRAPHIC_TYPE]
+            .iter()
+            .filter_map(|id| self.short(*id))
+            .find(|code| *code != 0 && *code != USER_DEFINED)
+    }
+
+    /// Description of the coordinate reference system, from the citation
+    /// GeoKeys.
+    pub fn citation(&self) -> Option<&str> {
+        [
+            GEO_KEY_PROJECTED_CITATION,
+            GEO_KEY_GEOGRAPHIC_CITATION,
+            GEO_KEY_CITATION,
+        ]
+        .iter()
+        .find_map(|id| self.ascii(*id))
+    }
+
+    /// Affine transformation from the pixel to the model coordinates, as the
+    /// X origin, pixel width, row rotation, Y origin, column rotation and
+    /// pixel height.
+    ///
+    /// The transformation is given by ModelTransformation, or by a single
+    /// tie point and the pixel scale. The origin is the corner of the top
+    /// left pixel, it is moved by half a pixel for PixelIsPoint rasters.
+    pub fn geotransform(&self) -> Option<[f64; 6]> {
+        let mut geotransform = match (self.transformation, self.pixel_scale, &self.tiepoints[..]) {
+            (Some(matrix), _, _) => [
+                matrix[3], matrix[0], matrix[1], matrix[7], matrix[4], matrix[5],
+            ],
+            (None, Some(scale), [tiepoint]) => [
+                tiepoint[3] - tiepoint[0] * scale[0],
+                scale[0],
+                0.0,
+                tiepoint[4] + tiepoint[1] * scale[1],
+                0.0,
+                -scale[1],
+            ],
+            _ => return None,
+        };
+        if self.raster_type() == Some(RASTER_TYPE_PIXEL_IS_POINT) {
+            geotransform[0] -= 0.5 * geotransform[1] + 0.5 * geotransform[2];
+            geotransform[3] -= 0.5 * geotransform[4] + 0.5 * geotransform[5];
+        }
+        Some(geotransform)
+    }
+}
+
+fn decode_geotiff(data: &[u8]) -> Option<GeoJP2> {
+    let tiff = Tiff::new(data)?;
+    let (fields, _) = tiff.ifd(tiff.first_ifd()?)?;
+    let field = |tag: u16| fields.iter().find(|field| field.tag == tag);
+
+    let mut geojp2 = GeoJP2::default();
+    if let Some(field) = field(TAG_MODEL_PIXEL_SCALE) {
+        let scale = tiff.doubles(field)?;
+        geojp2.pixel_scale = Some([*scale.first()?, *scale.get(1)?, *scale.get(2)?]);
+    }
+    if let Some(field) = field(TAG_MODEL_TIEPOINT) {
+        geojp2.tiepoints = tiff
+            .doubles(field)?
+            .chunks_exact(6)
+            .map(|tiepoint| {
+                let mut array = [0.0; 6];
+                array.copy_from_slice(tiepoint);
+                array
+            })
+            .collect();
+    }
+    if let Some(field) = field(TAG_MODEL_TRANSFORMATION) {
+        let matrix = tiff.doubles(field)?;
+        let mut array = [0.0; 16];
+        array.copy_from_slice(matrix.get(0..16)?);
+        geojp2.transformation = Some(array);
+    }
+
+    if let Some(directory) = field(TAG_GEO_KEY_DIRECTORY) {
+        let directory = tiff.shorts(directory)?;
+        let doubles = match field(TAG_GEO_DOUBLE_PARAMS) {
+            Some(field) => tiff.doubles(field)?,
+            None => vec![],
+        };
+        let ascii = match field(TAG_GEO_ASCII_PARAMS) {
+            Some(field) => tiff.ascii(field)?,
+            None => String::new(),
+        };
+
+        // The header is the version, revision, minor revision and the number
+        // of keys, each key is its ID, tag location, count and value or index
+        let keys = *directory.get(3)? as usize;
+        for key in directory.get(4..4 + keys * 4)?.chunks_exact(4) {
+            let (id, location, count, value) = (key[0], key[1], key[2] as usize, key[3]);
+            let start = value as usize;
+            let value = match location {
+                0 => GeoKeyValue::Short(value),
+                TAG_GEO_DOUBLE_PARAMS => {
+                    GeoKeyValue::Double(doubles.get(start..start + count)?.to_vec())
+                }
+                TAG_GEO_ASCII_PARAMS => {
+                    let text = ascii.get(start..start + count)?;
+                    GeoKeyValue::Ascii(text.trim_end_matches(&['|', '\0'][..]).to_owned())
+                }
+                TAG_GEO_KEY_DIRECTORY => GeoKeyValue::Short(*directory.get(start)?),
+                _ => continue,
+            };
+            geojp2.geo_keys.push(GeoKey { id, value });
+        }
+    }
+    Some(geojp2)
+}
+
+impl UUIDBox {
+    /// Whether the box is a GeoJP2 box.
+    pub fn is_geojp2(&self) -> bool {
+        self.uuid == UUID_GEOJP2
+    }
+
+    /// Decode the georeferencing of a GeoJP2 box, None if the box is not a
+    /// GeoJP2 box.
+    pub fn geojp2(&self) -> Option<Result<GeoJP2, JP2Error>> {
+        if !self.is_geojp2() {
+            return None;
+        }
+        Some(
+            decode_geotiff(&self.data).ok_or_else(|| JP2Error::BoxMalformed {
+                box_type: BOX_TYPE_UUID,
+                offset: self.offset(),
+            }),
+        )
+    }
+}
+
+impl JP2File {
+    /// Decode the georeferencing of the first GeoJP2 box of the file.
+    pub fn geojp2(&self) -> Option<Result<GeoJP2, JP2Error>> {
+        self.uuid.iter().find_map(UUIDBox::geojp2)
+    }
+}
@@ -0,0 +1,436 @@
+// GMLJP2, georeferencing of JPEG 2000 files by GML, OGC 05-047r3 (version 1)
+// and OGC 08-085r8 (version 2).
+//
+// The GML is stored in an Association box labelled 'gml.data', which contains
+// an Association box labelled 'gml.root-instance' with the XML box of the
+// root instance, a feature collection (version 1) or a coverage collection
+// (version 2). The georeferencing of the image is the RectifiedGrid of the
+// first coverage.
+
+use std::str;
+
+use super::{
+    AssociatedBox, AssociationSuperBox, JBox, JP2Error, JP2File, JPXFile, LabelBox, Limits, XMLBox,
+    BOX_TYPE_ASSOCIATION,
+};
+
+/// Label of the Association box containing the GML.
+pub const LABEL_GML_DATA: &str = "gml.data";
+
+/// Label of the Association box containing the root instance.
+pub const LABEL_GML_ROOT_INSTANCE: &str = "gml.root-instance";
+
+// An element of an XML document, by its local name without namespace
+// prefix.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| local_name(attribute) == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The first element of the name, in document order, including this
+    // element.
+    fn find(&self, name: &str) -> Option<&Element> {
+        let mut stack: Vec<&Element> = vec![self];
+        while let Some(element) = stack.pop() {
+            if element.name == name {
+                return Some(element);
+            }
+            stack.extend(element.children.iter().rev());
+        }
+        None
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn parse_attributes(tag: &str) -> Option<Vec<(String, String)>> {
+    let mut attributes: Vec<(String, String)> = vec![];
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let equals = rest.find('=')?;
+        let name = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let end = value[1..].find(quote)? + 1;
+        attributes.push((name.to_owned(), unescape(&value[1..end])));
+        rest = value[end + 1..].trim_start();
+    }
+    Some(attributes)
+}
+
+// Parse the root element of an XML document, None if the document is not
+// well-formed or nests elements deeper than the default limits. Declarations,
+// processing instructions and comments are skipped.
+fn parse_xml(xml: &str) -> Option<Element> {
+    let limits = Limits::default();
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        stack.last_mut()?.text.push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>")?;
+            stack.last_mut()?.text.push_str(&cdata[..end]);
+            rest = &cdata[end + 3..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[rest.find("-->")? + 3..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[rest.find("?>")? + 2..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[rest.find('>')? + 1..];
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>')?;
+            let element = stack.pop()?;
+            if element.name != local_name(end_tag[..end].trim()) || stack.is_empty() {
+                return None;
+            }
+            stack.last_mut()?.children.push(element);
+            rest = &end_tag[end + 1..];
+        } else {
+            let end = rest.find('>')?;
+            let (tag, empty) = match rest[1..end].strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (&rest[1..end], false),
+            };
+            let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+            let element = Element {
+                name: local_name(&tag[..name_end]).to_owned(),
+                attributes: parse_attributes(&tag[name_end..])?,
+                ..Default::default()
+            };
+            // The element is nested in the elements of the stack but the
+            // document
+            limits.check_nesting_depth(stack.len() as u32 - 1).ok()?;
+            if empty {
+                stack.last_mut()?.children.push(element);
+            } else {
+                stack.push(element);
+            }
+            rest = &rest[end + 1..];
+        }
+    }
+    if stack.len() != 1 {
+        return None;
+    }
+    stack.pop()?.children.into_iter().next()
+}
+
+fn parse_numbers<T: str::FromStr>(text: &str) -> Option<Vec<T>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse().ok())
+        .collect()
+}
+
+/// The RectifiedGrid of a GMLJP2 coverage, the grid of the image with its
+/// origin and offset vectors in the coordinate reference system.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RectifiedGrid {
+    low: Vec<i64>,
+    high: Vec<i64>,
+    origin: Vec<f64>,
+    offset_vectors: Vec<Vec<f64>>,
+    srs_name: Option<String>,
+}
+
+impl RectifiedGrid {
+    fn new(grid: &Element) -> Option<RectifiedGrid> {
+        let envelope = grid.find("GridEnvelope");
+        let limit = |name: &str| -> Option<Vec<i64>> {
+            match envelope.and_then(|envelope| envelope.find(name)) {
+                Some(element) => parse_numbers(&element.text),
+                None => Some(vec![]),
+            }
+        };
+        let origin = grid.find("origin")?;
+        let point = origin.find("Point");
+        let position = origin
+            .find("pos")
+            .or_else(|| origin.find("coordinates"))
+            .unwrap_or(origin);
+        let offset_vectors: Vec<&Element> = grid.children("offsetVector").collect();
+
+        let srs_name = grid
+            .attribute("srsName")
+            .or_else(|| point.and_then(|point| point.attribute("srsName")))
+            .or_else(|| {
+                offset_vectors
+                    .iter()
+                    .find_map(|offset_vector| offset_vector.attribute("srsName"))
+            })
+            .map(str::to_owned);
+
+        Some(RectifiedGrid {
+            low: limit("low")?,
+            high: limit("high")?,
+            origin: parse_numbers(&position.text)?,
+            offset_vectors: offset_vectors
+                .iter()
+                .map(|offset_vector| parse_numbers(&offset_vector.text))
+                .collect::<Option<_>>()?,
+            srs_name,
+        })
+    }
+
+    /// Low limits of the grid envelope.
+    pub fn low(&self) -> &[i64] {
+        &self.low
+    }
+
+    /// High limits of the grid envelope, inclusive.
+    pub fn high(&self) -> &[i64] {
+        &self.high
+    }
+
+    /// Position of the centre of the first pixel.
+    pub fn origin(&self) -> &[f64] {
+        &self.origin
+    }
+
+    /// Offset vectors of the grid axes, the columns then the rows.
+    pub fn offset_vectors(&self) -> &[Vec<f64>] {
+        &self.offset_vectors
+    }
+
+    /// Name of the coordinate reference system, such as
+    /// 'urn:ogc:def:crs:EPSG::4326'.
+    pub fn srs_name(&self) -> Option<&str> {
+        self.srs_name.as_deref()
+    }
+
+    /// Affine transformation from the pixel to the model coordinates, as the
+    /// X origin, pixel width, row rotation, Y origin, column rotation and
+    /// pixel height, as in `GeoJP2::geotransform`.
+    ///
+    /// The origin of the grid is the centre of the first pixel, the origin of
+    /// the transformation is its corner. The coordinates are in the axis
+    /// order of the GML.
+    pub fn geotransform(&self) -> Option<[f64; 6]> {
+        if self.origin.len() < 2 || self.offset_vectors.len() < 2 {
+            return None;
+        }
+        let (columns, rows) = (&self.offset_vectors[0], &self.offset_vectors[1]);
+        if columns.len() < 2 || rows.len() < 2 {
+            return None;
+        }
+        Some([
+            self.origin[0] - 0.5 * columns[0] - 0.5 * rows[0],
+            columns[0],
+            rows[0],
+            self.origin[1] - 0.5 * columns[1] - 0.5 * rows[1],
+            columns[1],
+            rows[1],
+        ])
+    }
+}
+
+/// GMLJP2 georeferencing of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GMLJP2 {
+    root_instance: String,
+    rectified_grid: Option<RectifiedGrid>,
+}
+
+impl GMLJP2 {
+    /// The XML of the root instance.
+    pub fn root_instance(&self) -> &str {
+        &self.root_instance
+    }
+
+    /// The RectifiedGrid of the first coverage of the root instance.
+    pub fn rectified_grid(&self) -> Option<&RectifiedGrid> {
+        self.rectified_grid.as_ref()
+    }
+
+    /// Affine transformation of the RectifiedGrid, see
+    /// `RectifiedGrid::geotransform`.
+    pub fn geotransform(&self) -> Option<[f64; 6]> {
+        self.rectified_grid.as_ref()?.geotransform()
+    }
+
+    /// Name of the coordinate reference system of the RectifiedGrid.
+    pub fn srs_name(&self) -> Option<&str> {
+        self.rectified_grid.as_ref()?.srs_name()
+    }
+}
+
+// Whether the first box of an Association box is a Label box of the label.
+fn is_labelled(association_box: &AssociationSuperBox, label: &str) -> bool {
+    matches!(
+        association_box.boxes().first(),
+        Some(AssociatedBox::Label(label_box)) if label_box.label() == label
+    )
+}
+
+fn root_instance(association_box: &AssociationSuperBox) -> Option<&XMLBox> {
+    association_box
+        .boxes()
+        .iter()
+        .find_map(|associated_box| match associated_box {
+            AssociatedBox::Association(root_instance) => Some(root_instance),
+            _ => None,
+        })
+        .filter(|root_instance| is_labelled(root_instance, LABEL_GML_ROOT_INSTANCE))?
+        .boxes()
+        .iter()
+        .find_map(|associated_box| match associated_box {
+            AssociatedBox::Xml(xml_box) => Some(xml_box),
+            _ => None,
+        })
+}
+
+fn decode_gml(association_box: &AssociationSuperBox) -> Option<GMLJP2> {
+    let root_instance = str::from_utf8(&root_instance(association_box)?.xml).ok()?;
+    let root = parse_xml(root_instance)?;
+    let rectified_grid = match root.find("RectifiedGrid") {
+        Some(grid) => Some(RectifiedGrid::new(grid)?),
+        None => None,
+    };
+    Some(GMLJP2 {
+        root_instance: root_instance.to_owned(),
+        rectified_grid,
+    })
+}
+
+/// Decode the GMLJP2 georeferencing of the first Association box labelled
+/// 'gml.data', None if there is no such box.
+pub fn decode_gmljp2(
+    association_boxes: &[AssociationSuperBox],
+) -> Option<Result<GMLJP2, JP2Error>> {
+    let association_box = association_boxes
+        .iter()
+        .find(|association_box| is_labelled(association_box, LABEL_GML_DATA))?;
+    Some(
+        decode_gml(association_box).ok_or_else(|| JP2Error::BoxMalformed {
+            box_type: BOX_TYPE_ASSOCIATION,
+            offset: association_box.offset(),
+        }),
+    )
+}
+
+/// Create the Association box of a minimal GMLJP2 file, a feature collection
+/// with a RectifiedGridCoverage of the first codestream.
+///
+/// The image is `width` by `height` pixels, the geotransform is as returned
+/// by `GMLJP2::geotransform` and `srs_name` is the name of the coordinate
+/// reference system, such as 'urn:ogc:def:crs:EPSG::32633'. GMLJP2 files
+/// should list 'jpx\040' in the compatibility list of the File Type box.
+pub fn gmljp2_association_box(
+    width: u32,
+    height: u32,
+    geotransform: [f64; 6],
+    srs_name: &str,
+) -> AssociationSuperBox {
+    let [x, column_x, row_x, y, column_y, row_y] = geotransform;
+    let srs_name = escape(srs_name);
+    let xml = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<gml:FeatureCollection xmlns:gml="http://www.opengis.net/gml" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.opengis.net/gml http://schemas.opengis.net/gml/3.1.1/profiles/gmlJP2Profile/1.0.0/gmlJP2Profile.xsd">
+  <gml:boundedBy>
+    <gml:Null>withheld</gml:Null>
+  </gml:boundedBy>
+  <gml:featureMember>
+    <gml:FeatureCollection>
+      <gml:featureMember>
+        <gml:RectifiedGridCoverage dimension="2" gml:id="RGC0001">
+          <gml:rectifiedGridDomain>
+            <gml:RectifiedGrid dimension="2">
+              <gml:limits>
+                <gml:GridEnvelope>
+                  <gml:low>0 0</gml:low>
+                  <gml:high>{} {}</gml:high>
+                </gml:GridEnvelope>
+              </gml:limits>
+              <gml:axisName>x</gml:axisName>
+              <gml:axisName>y</gml:axisName>
+              <gml:origin>
+                <gml:Point gml:id="P0001" srsName="{}">
+                  <gml:pos>{} {}</gml:pos>
+                </gml:Point>
+              </gml:origin>
+              <gml:offsetVector srsName="{}">{} {}</gml:offsetVector>
+              <gml:offsetVector srsName="{}">{} {}</gml:offsetVector>
+            </gml:RectifiedGrid>
+          </gml:rectifiedGridDomain>
+          <gml:rangeSet>
+            <gml:File>
+              <gml:fileName>gmljp2://codestream/0</gml:fileName>
+              <gml:fileStructure>Record Interleaved</gml:fileStructure>
+            </gml:File>
+          </gml:rangeSet>
+        </gml:RectifiedGridCoverage>
+      </gml:featureMember>
+    </gml:FeatureCollection>
+  </gml:featureMember>
+</gml:FeatureCollection>
+"#,
+        width as i64 - 1,
+        height as i64 - 1,
+        srs_name,
+        x + 0.5 * column_x + 0.5 * row_x,
+        y + 0.5 * column_y + 0.5 * row_y,
+        srs_name,
+        column_x,
+        column_y,
+        srs_name,
+        row_x,
+        row_y,
+    );
+
+    AssociationSuperBox::new(vec![
+        AssociatedBox::Label(LabelBox::new(LABEL_GML_DATA)),
+        AssociatedBox::Association(AssociationSuperBox::new(vec![
+            AssociatedBox::Label(LabelBox::new(LABEL_GML_ROOT_INSTANCE)),
+            AssociatedBox::Xml(XMLBox::new(xml.into_bytes())),
+        ])),
+    ])
+}
+
+impl JP2File {
+    /// Decode the GMLJP2 georeferencing of the file, see `decode_gmljp2`.
+    pub fn gmljp2(&self) -> Option<Result<GMLJP2, JP2Error>> {
+        decode_gmljp2(&self.associations)
+    }
+}
+
+impl JPXFile {
+    /// Decode the GMLJP2 georeferencing of the file, see `decode_gmljp2`.
+    pub fn gmljp2(&self) -> Option<Result<GMLJP2, JP2Error>> {
+        decode_gmljp2(self.association_boxes())
+    }
+}
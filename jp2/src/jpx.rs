@@ -0,0 +1,2033 @@
+// JPX extended file format, ISO/IEC 15444-2 Annex M.
+//
+// The boxes of the JP2 file format are reused, boxes defined by Annex M are
+// decoded into the types of this module and collected by `decode_jpx` into a
+// `JPXFile`.
+
+use log::{info, warn};
+use std::io;
+use std::str;
+
+use super::boxes::{child_boxes, BoxInfo};
+use super::*;
+
+// Decode a box with contents of the length and at the offset given by info,
+// leaving the reader at the end of the box.
+macro_rules! decode_box {
+    ($box_type:ty, $reader:expr, $info:expr) => {{
+        let mut jbox = <$box_type>::default();
+        jbox.length = $info.length;
+        jbox.offset = $info.offset;
+        $reader
+            .seek(io::SeekFrom::Start($info.offset))
+            .at($info.offset)?;
+        jbox.decode($reader)?;
+        $reader
+            .seek(io::SeekFrom::Start($info.end()))
+            .at($info.offset)?;
+        jbox
+    }};
+}
+
+fn duplicate(info: &BoxInfo) -> JP2Error {
+    JP2Error::BoxDuplicate {
+        box_type: info.box_type,
+        offset: info.offset,
+    }
+}
+
+fn malformed(box_type: BoxType, offset: u64) -> JP2Error {
+    JP2Error::BoxMalformed { box_type, offset }
+}
+
+fn ignore(info: &BoxInfo, superbox_type: BoxType) {
+    warn!(
+        "Unexpected box type {:?} at {} in {:?}, ignoring",
+        info.box_type, info.offset, superbox_type
+    );
+}
+
+fn encode_superbox<W: io::Write>(
+    writer: &mut W,
+    box_type: BoxType,
+    contents: &[u8],
+) -> io::Result<()> {
+    encode_box_header(writer, box_type, contents.len() as u64)?;
+    writer.write_all(contents)?;
+    Ok(())
+}
+
+fn read_u16<R: io::Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buffer: [u8; 2] = [0; 2];
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_be_bytes(buffer))
+}
+
+fn read_u32<R: io::Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer: [u8; 4] = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+fn read_u64<R: io::Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buffer: [u8; 8] = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+}
+
+/// A standard feature of a Reader Requirements box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StandardFeature {
+    feature: u16,
+    mask: Vec<u8>,
+}
+
+impl StandardFeature {
+    /// Standard feature flag (SF), as listed in ISO/IEC 15444-2 Table M.14.
+    pub fn feature(&self) -> u16 {
+        self.feature
+    }
+
+    /// Standard mask (SM), the bits of the mask correspond to those of the
+    /// Fully Understand Aspects and Decode Completely masks.
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+}
+
+/// A vendor feature of a Reader Requirements box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendorFeature {
+    feature: [u8; 16],
+    mask: Vec<u8>,
+}
+
+impl VendorFeature {
+    /// Vendor feature (VF), a UUID.
+    pub fn feature(&self) -> [u8; 16] {
+        self.feature
+    }
+
+    /// Vendor mask (VM).
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+}
+
+// M.11.1
+//
+// Reader Requirements box
+//
+// This box specifies the features needed to fully understand and to decode
+// the file, a JPX file shall contain a Reader Requirements box immediately
+// after the File Type box.
+#[derive(Debug, Default)]
+pub struct ReaderRequirementsBox {
+    length: u64,
+    offset: u64,
+    fully_understand_aspects_mask: Vec<u8>,
+    decode_completely_mask: Vec<u8>,
+    standard_features: Vec<StandardFeature>,
+    vendor_features: Vec<VendorFeature>,
+}
+
+impl ReaderRequirementsBox {
+    /// Mask length (ML), the number of bytes of each mask.
+    pub fn mask_length(&self) -> u8 {
+        self.fully_understand_aspects_mask.len() as u8
+    }
+
+    /// Fully Understand Aspects mask (FUAM).
+    pub fn fully_understand_aspects_mask(&self) -> &[u8] {
+        &self.fully_understand_aspects_mask
+    }
+
+    /// Decode Completely mask (DCM).
+    pub fn decode_completely_mask(&self) -> &[u8] {
+        &self.decode_completely_mask
+    }
+
+    pub fn standard_features(&self) -> &[StandardFeature] {
+        &self.standard_features
+    }
+
+    pub fn vendor_features(&self) -> &[VendorFeature] {
+        &self.vendor_features
+    }
+}
+
+impl JBox for ReaderRequirementsBox {
+    // The type of a Reader Requirements box shall be ‘rreq’ (0x7272 6571).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_READER_REQUIREMENTS
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        let mut mask_length: [u8; 1] = [0; 1];
+        reader.read_exact(&mut mask_length).at(self.offset)?;
+        let mask_length = mask_length[0] as usize;
+
+        // The box shall hold the masks and the number of features
+        if (self.length as usize) < 1 + 2 * mask_length + 4 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+
+        self.fully_understand_aspects_mask = read_vec(reader, mask_length).at(self.offset)?;
+        self.decode_completely_mask = read_vec(reader, mask_length).at(self.offset)?;
+
+        let standard_features = read_u16(reader).at(self.offset)?;
+        self.standard_features = Vec::with_capacity(standard_features as usize);
+        for _ in 0..standard_features {
+            let feature = read_u16(reader).at(self.offset)?;
+            let mask = read_vec(reader, mask_length).at(self.offset)?;
+            self.standard_features
+                .push(StandardFeature { feature, mask });
+        }
+
+        let vendor_features = read_u16(reader).at(self.offset)?;
+        self.vendor_features = Vec::with_capacity(vendor_features as usize);
+        for _ in 0..vendor_features {
+            let mut feature: [u8; 16] = [0; 16];
+            reader.read_exact(&mut feature).at(self.offset)?;
+            let mask = read_vec(reader, mask_length).at(self.offset)?;
+            self.vendor_features.push(VendorFeature { feature, mask });
+        }
+
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![self.mask_length()];
+        contents.extend(&self.fully_understand_aspects_mask);
+        contents.extend(&self.decode_completely_mask);
+        contents.extend((self.standard_features.len() as u16).to_be_bytes());
+        for standard_feature in &self.standard_features {
+            contents.extend(standard_feature.feature.to_be_bytes());
+            contents.extend(&standard_feature.mask);
+        }
+        contents.extend((self.vendor_features.len() as u16).to_be_bytes());
+        for vendor_feature in &self.vendor_features {
+            contents.extend(vendor_feature.feature);
+            contents.extend(&vendor_feature.mask);
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.13
+//
+// Label box
+//
+// This box contains a textual label for the box which contains it, or for the
+// first box of an Association box.
+#[derive(Debug, Default)]
+pub struct LabelBox {
+    length: u64,
+    offset: u64,
+    label: Vec<u8>,
+}
+
+impl LabelBox {
+    /// Create a Label box for encoding.
+    pub fn new(label: &str) -> LabelBox {
+        LabelBox {
+            length: label.len() as u64,
+            offset: 0,
+            label: label.as_bytes().to_vec(),
+        }
+    }
+
+    /// Label, encoded as UTF-8.
+    pub fn label(&self) -> String {
+        String::from_utf8_lossy(&self.label).to_string()
+    }
+}
+
+impl JBox for LabelBox {
+    // The type of a Label box shall be ‘lbl\040’ (0x6C62 6C20).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_LABEL
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.label = read_vec(reader, self.length as usize).at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_superbox(writer, self.identifier(), &self.label).at(self.offset)
+    }
+}
+
+/// Entity an Association box is associated with, as given by a Number List
+/// box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssociationNumber {
+    /// The rendered result of the file.
+    RenderedResult,
+    /// Codestream, by index in the file.
+    Codestream(u32),
+    /// Compositing layer, by index in the file.
+    CompositingLayer(u32),
+    Reserved(u32),
+}
+
+impl AssociationNumber {
+    fn new(value: u32) -> AssociationNumber {
+        match value >> 24 {
+            0 if value == 0 => AssociationNumber::RenderedResult,
+            1 => AssociationNumber::Codestream(value & 0xFF_FFFF),
+            2 => AssociationNumber::CompositingLayer(value & 0xFF_FFFF),
+            _ => AssociationNumber::Reserved(value),
+        }
+    }
+}
+
+// M.11.12
+//
+// Number List box
+//
+// This box specifies the codestreams, compositing layers or rendered result
+// an Association box is associated with.
+#[derive(Debug, Default)]
+pub struct NumberListBox {
+    length: u64,
+    offset: u64,
+    numbers: Vec<u32>,
+}
+
+impl NumberListBox {
+    /// Association numbers (AN).
+    pub fn numbers(&self) -> &[u32] {
+        &self.numbers
+    }
+
+    pub fn associations(&self) -> Vec<AssociationNumber> {
+        self.numbers
+            .iter()
+            .map(|number| AssociationNumber::new(*number))
+            .collect()
+    }
+}
+
+impl JBox for NumberListBox {
+    // The type of a Number List box shall be ‘nlst’ (0x6E6C 7374).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_NUMBER_LIST
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if !self.length.is_multiple_of(4) {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        self.numbers = Vec::with_capacity(self.length as usize / 4);
+        for _ in 0..self.length / 4 {
+            self.numbers.push(read_u32(reader).at(self.offset)?);
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let contents: Vec<u8> = self
+            .numbers
+            .iter()
+            .flat_map(|number| number.to_be_bytes())
+            .collect();
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+/// Region of interest of a ROI Description box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    in_codestream: u8,
+    region_type: u8,
+    priority: u8,
+    horizontal_offset: u32,
+    vertical_offset: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Region {
+    /// Whether the region is coded as a region of interest in the codestream
+    /// (R).
+    pub fn in_codestream(&self) -> bool {
+        self.in_codestream == 1
+    }
+
+    /// Region type (Rtyp), 0 for a rectangle and 1 for an ellipse.
+    pub fn region_type(&self) -> u8 {
+        self.region_type
+    }
+
+    /// Region priority (Rcp), 0 being the highest.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Horizontal offset of the region (RXo) on the reference grid.
+    pub fn horizontal_offset(&self) -> u32 {
+        self.horizontal_offset
+    }
+
+    /// Vertical offset of the region (RYo) on the reference grid.
+    pub fn vertical_offset(&self) -> u32 {
+        self.vertical_offset
+    }
+
+    /// Width of the region (RWo).
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the region (RHo).
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+// M.11.14
+//
+// ROI Description box
+//
+// This box describes the regions of interest of a codestream.
+#[derive(Debug, Default)]
+pub struct ROIDescriptionBox {
+    length: u64,
+    offset: u64,
+    regions: Vec<Region>,
+}
+
+impl ROIDescriptionBox {
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+}
+
+impl JBox for ROIDescriptionBox {
+    // The type of a ROI Description box shall be ‘roid’ (0x726F 6964).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_ROI_DESCRIPTION
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        let mut regions: [u8; 1] = [0; 1];
+        reader.read_exact(&mut regions).at(self.offset)?;
+        if self.length != 1 + 19 * regions[0] as u64 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+
+        self.regions = Vec::with_capacity(regions[0] as usize);
+        for _ in 0..regions[0] {
+            let mut fields: [u8; 3] = [0; 3];
+            reader.read_exact(&mut fields).at(self.offset)?;
+            self.regions.push(Region {
+                in_codestream: fields[0],
+                region_type: fields[1],
+                priority: fields[2],
+                horizontal_offset: read_u32(reader).at(self.offset)?,
+                vertical_offset: read_u32(reader).at(self.offset)?,
+                width: read_u32(reader).at(self.offset)?,
+                height: read_u32(reader).at(self.offset)?,
+            });
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![self.regions.len() as u8];
+        for region in &self.regions {
+            contents.extend([region.in_codestream, region.region_type, region.priority]);
+            contents.extend(region.horizontal_offset.to_be_bytes());
+            contents.extend(region.vertical_offset.to_be_bytes());
+            contents.extend(region.width.to_be_bytes());
+            contents.extend(region.height.to_be_bytes());
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.4
+//
+// Codestream Header box (superbox)
+//
+// This box contains the header information of a single codestream, the n-th
+// Codestream Header box is associated with the n-th codestream of the file.
+// Boxes not present are taken from the JP2 Header box.
+#[derive(Debug, Default)]
+pub struct CodestreamHeaderSuperBox {
+    length: u64,
+    offset: u64,
+    pub label_box: Option<LabelBox>,
+    pub image_header_box: Option<ImageHeaderBox>,
+    pub bits_per_component_box: Option<BitsPerComponentBox>,
+    pub palette_box: Option<PaletteBox>,
+    pub component_mapping_box: Option<ComponentMappingBox>,
+    pub roi_description_box: Option<ROIDescriptionBox>,
+}
+
+impl JBox for CodestreamHeaderSuperBox {
+    // The type of a Codestream Header box shall be ‘jpch’ (0x6A70 6368).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_CODESTREAM_HEADER
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        for info in child_boxes(reader, self.offset, self.length)? {
+            match info.box_type {
+                BOX_TYPE_LABEL if self.label_box.is_none() => {
+                    self.label_box = Some(decode_box!(LabelBox, reader, info));
+                }
+                BOX_TYPE_IMAGE_HEADER if self.image_header_box.is_none() => {
+                    self.image_header_box = Some(decode_box!(ImageHeaderBox, reader, info));
+                }
+                BOX_TYPE_BITS_PER_COMPONENT if self.bits_per_component_box.is_none() => {
+                    // The number of components is given by the length of the
+                    // box, the Image Header box may be in the JP2 Header box.
+                    let mut bits_per_component_box = BitsPerComponentBox {
+                        length: info.length,
+                        offset: info.offset,
+                        components_num: info.length as u16,
+                        bits_per_component: vec![0; info.length as usize],
+                    };
+                    reader
+                        .seek(io::SeekFrom::Start(info.offset))
+                        .at(info.offset)?;
+                    bits_per_component_box.decode(reader)?;
+                    self.bits_per_component_box = Some(bits_per_component_box);
+                }
+                BOX_TYPE_PALETTE if self.palette_box.is_none() => {
+                    self.palette_box = Some(decode_box!(PaletteBox, reader, info));
+                }
+                BOX_TYPE_COMPONENT_MAPPING if self.component_mapping_box.is_none() => {
+                    self.component_mapping_box =
+                        Some(decode_box!(ComponentMappingBox, reader, info));
+                }
+                BOX_TYPE_ROI_DESCRIPTION if self.roi_description_box.is_none() => {
+                    self.roi_description_box = Some(decode_box!(ROIDescriptionBox, reader, info));
+                }
+                BOX_TYPE_LABEL
+                | BOX_TYPE_IMAGE_HEADER
+                | BOX_TYPE_BITS_PER_COMPONENT
+                | BOX_TYPE_PALETTE
+                | BOX_TYPE_COMPONENT_MAPPING
+                | BOX_TYPE_ROI_DESCRIPTION => return Err(duplicate(&info)),
+                _ => ignore(&info, self.identifier()),
+            }
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        if let Some(label_box) = &self.label_box {
+            label_box.encode(&mut contents)?;
+        }
+        if let Some(image_header_box) = &self.image_header_box {
+            image_header_box.encode(&mut contents)?;
+        }
+        if let Some(bits_per_component_box) = &self.bits_per_component_box {
+            bits_per_component_box.encode(&mut contents)?;
+        }
+        if let Some(palette_box) = &self.palette_box {
+            palette_box.encode(&mut contents)?;
+        }
+        if let Some(component_mapping_box) = &self.component_mapping_box {
+            component_mapping_box.encode(&mut contents)?;
+        }
+        if let Some(roi_description_box) = &self.roi_description_box {
+            roi_description_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.7
+//
+// Colour Group box (superbox)
+//
+// This box contains the Colour Specification boxes of a compositing layer, of
+// which a reader uses one.
+#[derive(Debug, Default)]
+pub struct ColourGroupSuperBox {
+    length: u64,
+    offset: u64,
+    pub colour_specification_boxes: Vec<ColourSpecificationBox>,
+}
+
+impl JBox for ColourGroupSuperBox {
+    // The type of a Colour Group box shall be ‘cgrp’ (0x6367 7270).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_COLOUR_GROUP
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        for info in child_boxes(reader, self.offset, self.length)? {
+            match info.box_type {
+                BOX_TYPE_COLOUR_SPECIFICATION => self.colour_specification_boxes.push(decode_box!(
+                    ColourSpecificationBox,
+                    reader,
+                    info
+                )),
+                _ => ignore(&info, self.identifier()),
+            }
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        for colour_specification_box in &self.colour_specification_boxes {
+            colour_specification_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+/// Opacity type of an Opacity box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpacityType {
+    /// The last channel is an opacity channel.
+    Opacity,
+    /// The last channel is a premultiplied opacity channel.
+    PremultipliedOpacity,
+    /// Samples matching the chroma key are transparent.
+    ChromaKey,
+    Reserved {
+        value: u8,
+    },
+}
+
+impl OpacityType {
+    fn new(value: u8) -> OpacityType {
+        match value {
+            0 => OpacityType::Opacity,
+            1 => OpacityType::PremultipliedOpacity,
+            2 => OpacityType::ChromaKey,
+            value => OpacityType::Reserved { value },
+        }
+    }
+}
+
+// M.11.8
+//
+// Opacity box
+//
+// This box specifies the opacity channel of a compositing layer, as a simpler
+// alternative to a Channel Definition box.
+#[derive(Debug, Default)]
+pub struct OpacityBox {
+    length: u64,
+    offset: u64,
+    opacity_type: u8,
+    channels_num: u8,
+    chroma_key: Vec<u8>,
+}
+
+impl OpacityBox {
+    /// Opacity type (OTyp).
+    pub fn opacity_type(&self) -> OpacityType {
+        OpacityType::new(self.opacity_type)
+    }
+
+    /// Number of channels (NCH), only present for chroma keys.
+    pub fn channels_num(&self) -> u8 {
+        self.channels_num
+    }
+
+    /// Chroma key values (CV) of each channel, each value is stored in the
+    /// number of bytes needed by the bit depth of the channel.
+    pub fn chroma_key(&self) -> &[u8] {
+        &self.chroma_key
+    }
+}
+
+impl JBox for OpacityBox {
+    // The type of an Opacity box shall be ‘opct’ (0x6F70 6374).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_OPACITY
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length == 0 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        let mut opacity_type: [u8; 1] = [0; 1];
+        reader.read_exact(&mut opacity_type).at(self.offset)?;
+        self.opacity_type = opacity_type[0];
+
+        if self.opacity_type() == OpacityType::ChromaKey {
+            if self.length < 2 {
+                return Err(malformed(self.identifier(), self.offset));
+            }
+            let mut channels_num: [u8; 1] = [0; 1];
+            reader.read_exact(&mut channels_num).at(self.offset)?;
+            self.channels_num = channels_num[0];
+            self.chroma_key = read_vec(reader, self.length as usize - 2).at(self.offset)?;
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![self.opacity_type];
+        if self.opacity_type() == OpacityType::ChromaKey {
+            contents.push(self.channels_num);
+            contents.extend(&self.chroma_key);
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+/// Registration of a codestream in a compositing layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodestreamRegistration {
+    codestream: u16,
+    horizontal_resolution: u8,
+    vertical_resolution: u8,
+    horizontal_offset: u8,
+    vertical_offset: u8,
+}
+
+impl CodestreamRegistration {
+    /// Codestream number (CDN).
+    pub fn codestream(&self) -> u16 {
+        self.codestream
+    }
+
+    /// Horizontal resolution (XR), in grid points per codestream sample.
+    pub fn horizontal_resolution(&self) -> u8 {
+        self.horizontal_resolution
+    }
+
+    /// Vertical resolution (YR), in grid points per codestream sample.
+    pub fn vertical_resolution(&self) -> u8 {
+        self.vertical_resolution
+    }
+
+    /// Horizontal offset (XO), in grid points.
+    pub fn horizontal_offset(&self) -> u8 {
+        self.horizontal_offset
+    }
+
+    /// Vertical offset (YO), in grid points.
+    pub fn vertical_offset(&self) -> u8 {
+        self.vertical_offset
+    }
+}
+
+// M.11.9
+//
+// Codestream Registration box
+//
+// This box specifies the registration of the codestreams used by a
+// compositing layer on a common grid.
+#[derive(Debug, Default)]
+pub struct CodestreamRegistrationBox {
+    length: u64,
+    offset: u64,
+    horizontal_grid_size: u16,
+    vertical_grid_size: u16,
+    registrations: Vec<CodestreamRegistration>,
+}
+
+impl CodestreamRegistrationBox {
+    /// Horizontal grid size (XS).
+    pub fn horizontal_grid_size(&self) -> u16 {
+        self.horizontal_grid_size
+    }
+
+    /// Vertical grid size (YS).
+    pub fn vertical_grid_size(&self) -> u16 {
+        self.vertical_grid_size
+    }
+
+    pub fn registrations(&self) -> &[CodestreamRegistration] {
+        &self.registrations
+    }
+}
+
+impl JBox for CodestreamRegistrationBox {
+    // The type of a Codestream Registration box shall be ‘creg’ (0x6372 6567).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_CODESTREAM_REGISTRATION
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length < 4 || !(self.length - 4).is_multiple_of(6) {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        self.horizontal_grid_size = read_u16(reader).at(self.offset)?;
+        self.vertical_grid_size = read_u16(reader).at(self.offset)?;
+
+        let registrations = (self.length - 4) / 6;
+        self.registrations = Vec::with_capacity(registrations as usize);
+        for _ in 0..registrations {
+            let codestream = read_u16(reader).at(self.offset)?;
+            let mut fields: [u8; 4] = [0; 4];
+            reader.read_exact(&mut fields).at(self.offset)?;
+            self.registrations.push(CodestreamRegistration {
+                codestream,
+                horizontal_resolution: fields[0],
+                vertical_resolution: fields[1],
+                horizontal_offset: fields[2],
+                vertical_offset: fields[3],
+            });
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        contents.extend(self.horizontal_grid_size.to_be_bytes());
+        contents.extend(self.vertical_grid_size.to_be_bytes());
+        for registration in &self.registrations {
+            contents.extend(registration.codestream.to_be_bytes());
+            contents.extend([
+                registration.horizontal_resolution,
+                registration.vertical_resolution,
+                registration.horizontal_offset,
+                registration.vertical_offset,
+            ]);
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.5
+//
+// Compositing Layer Header box (superbox)
+//
+// This box contains the header information of a single compositing layer, the
+// n-th Compositing Layer Header box is associated with the n-th compositing
+// layer of the file.
+#[derive(Debug, Default)]
+pub struct CompositingLayerHeaderSuperBox {
+    length: u64,
+    offset: u64,
+    pub label_box: Option<LabelBox>,
+    pub colour_group_boxes: Vec<ColourGroupSuperBox>,
+    pub opacity_box: Option<OpacityBox>,
+    pub channel_definition_box: Option<ChannelDefinitionBox>,
+    pub codestream_registration_box: Option<CodestreamRegistrationBox>,
+    pub resolution_box: Option<ResolutionSuperBox>,
+}
+
+impl JBox for CompositingLayerHeaderSuperBox {
+    // The type of a Compositing Layer Header box shall be ‘jplh’ (0x6A70 6C68).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_COMPOSITING_LAYER_HEADER
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        for info in child_boxes(reader, self.offset, self.length)? {
+            match info.box_type {
+                BOX_TYPE_LABEL if self.label_box.is_none() => {
+                    self.label_box = Some(decode_box!(LabelBox, reader, info));
+                }
+                BOX_TYPE_COLOUR_GROUP => {
+                    self.colour_group_boxes
+                        .push(decode_box!(ColourGroupSuperBox, reader, info))
+                }
+                BOX_TYPE_OPACITY if self.opacity_box.is_none() => {
+                    self.opacity_box = Some(decode_box!(OpacityBox, reader, info));
+                }
+                BOX_TYPE_CHANNEL_DEFINITION if self.channel_definition_box.is_none() => {
+                    self.channel_definition_box =
+                        Some(decode_box!(ChannelDefinitionBox, reader, info));
+                }
+                BOX_TYPE_CODESTREAM_REGISTRATION if self.codestream_registration_box.is_none() => {
+                    self.codestream_registration_box =
+                        Some(decode_box!(CodestreamRegistrationBox, reader, info));
+                }
+                BOX_TYPE_RESOLUTION if self.resolution_box.is_none() => {
+                    self.resolution_box = Some(decode_resolution(reader, &info)?);
+                }
+                BOX_TYPE_LABEL
+                | BOX_TYPE_OPACITY
+                | BOX_TYPE_CHANNEL_DEFINITION
+                | BOX_TYPE_CODESTREAM_REGISTRATION
+                | BOX_TYPE_RESOLUTION => return Err(duplicate(&info)),
+                _ => ignore(&info, self.identifier()),
+            }
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        if let Some(label_box) = &self.label_box {
+            label_box.encode(&mut contents)?;
+        }
+        for colour_group_box in &self.colour_group_boxes {
+            colour_group_box.encode(&mut contents)?;
+        }
+        if let Some(opacity_box) = &self.opacity_box {
+            opacity_box.encode(&mut contents)?;
+        }
+        if let Some(channel_definition_box) = &self.channel_definition_box {
+            channel_definition_box.encode(&mut contents)?;
+        }
+        if let Some(codestream_registration_box) = &self.codestream_registration_box {
+            codestream_registration_box.encode(&mut contents)?;
+        }
+        if let Some(resolution_box) = &self.resolution_box {
+            resolution_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// Decode a Resolution box within the bounds of its box, as it may be the last
+// box of its superbox.
+fn decode_resolution<R: io::Read + io::Seek>(
+    reader: &mut R,
+    info: &BoxInfo,
+) -> Result<ResolutionSuperBox, JP2Error> {
+    let mut resolution_box = ResolutionSuperBox {
+        length: info.length,
+        offset: info.offset,
+        ..Default::default()
+    };
+    for child in child_boxes(reader, info.offset, info.length)? {
+        match child.box_type {
+            BOX_TYPE_CAPTURE_RESOLUTION if resolution_box.capture_resolution_box.is_none() => {
+                resolution_box.capture_resolution_box =
+                    Some(decode_box!(CaptureResolutionBox, reader, child));
+            }
+            BOX_TYPE_DEFAULT_DISPLAY_RESOLUTION
+                if resolution_box.default_display_resolution_box.is_none() =>
+            {
+                resolution_box.default_display_resolution_box =
+                    Some(decode_box!(DefaultDisplayResolutionBox, reader, child));
+            }
+            BOX_TYPE_CAPTURE_RESOLUTION | BOX_TYPE_DEFAULT_DISPLAY_RESOLUTION => {
+                return Err(duplicate(&child))
+            }
+            _ => ignore(&child, BOX_TYPE_RESOLUTION),
+        }
+    }
+    if resolution_box.capture_resolution_box.is_none()
+        && resolution_box.default_display_resolution_box.is_none()
+    {
+        return Err(malformed(BOX_TYPE_RESOLUTION, info.offset));
+    }
+    reader
+        .seek(io::SeekFrom::Start(info.end()))
+        .at(info.offset)?;
+    Ok(resolution_box)
+}
+
+// M.11.10.1
+//
+// Composition Options box
+//
+// This box specifies the size of the render area and the number of times an
+// animation is repeated.
+#[derive(Debug, Default)]
+pub struct CompositionOptionsBox {
+    length: u64,
+    offset: u64,
+    height: [u8; 4],
+    width: [u8; 4],
+    loop_count: [u8; 1],
+}
+
+impl CompositionOptionsBox {
+    /// Height of the render area (HEIGHT).
+    pub fn height(&self) -> u32 {
+        u32::from_be_bytes(self.height)
+    }
+
+    /// Width of the render area (WIDTH).
+    pub fn width(&self) -> u32 {
+        u32::from_be_bytes(self.width)
+    }
+
+    /// Number of times the animation is repeated (LOOP), 255 to repeat
+    /// indefinitely.
+    pub fn loop_count(&self) -> u8 {
+        self.loop_count[0]
+    }
+}
+
+impl JBox for CompositionOptionsBox {
+    // The type of a Composition Options box shall be ‘copt’ (0x636F 7074).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_COMPOSITION_OPTIONS
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length != 9 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        reader.read_exact(&mut self.height).at(self.offset)?;
+        reader.read_exact(&mut self.width).at(self.offset)?;
+        reader.read_exact(&mut self.loop_count).at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 9).at(self.offset)?;
+        writer.write_all(&self.height).at(self.offset)?;
+        writer.write_all(&self.width).at(self.offset)?;
+        writer.write_all(&self.loop_count).at(self.offset)?;
+        Ok(())
+    }
+}
+
+// Instruction type (ITYP) flags of an Instruction Set box.
+const INSTRUCTION_OFFSET: u16 = 1;
+const INSTRUCTION_SIZE: u16 = 1 << 1;
+const INSTRUCTION_ANIMATION: u16 = 1 << 2;
+const INSTRUCTION_CROP: u16 = 1 << 5;
+
+/// Compositing instruction of an Instruction Set box, the fields present are
+/// given by the instruction type of the box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    offset: Option<(u32, u32)>,
+    size: Option<(u32, u32)>,
+    life: Option<u32>,
+    next_use: Option<u32>,
+    crop: Option<(u32, u32, u32, u32)>,
+}
+
+impl Instruction {
+    /// Horizontal and vertical offset (XO, YO) of the layer in the render
+    /// area.
+    pub fn offset(&self) -> Option<(u32, u32)> {
+        self.offset
+    }
+
+    /// Width and height (WIDTH, HEIGHT) the layer is scaled to.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    /// Duration of the instruction in ticks (LIFE), without the persistence
+    /// flag.
+    pub fn life(&self) -> Option<u32> {
+        self.life.map(|life| life & 0x7FFF_FFFF)
+    }
+
+    /// Whether the result of the instruction persists in the render area.
+    pub fn persistent(&self) -> Option<bool> {
+        self.life.map(|life| life & 0x8000_0000 != 0)
+    }
+
+    /// Number of instructions before the layer is reused (N).
+    pub fn next_use(&self) -> Option<u32> {
+        self.next_use
+    }
+
+    /// Horizontal and vertical offset, width and height (XC, YC, WC, HC) of
+    /// the part of the layer used.
+    pub fn crop(&self) -> Option<(u32, u32, u32, u32)> {
+        self.crop
+    }
+}
+
+// M.11.10.2
+//
+// Instruction Set box
+//
+// This box specifies how compositing layers are placed in the render area.
+#[derive(Debug, Default)]
+pub struct InstructionSetBox {
+    length: u64,
+    offset: u64,
+    instruction_type: u16,
+    repeat: u16,
+    tick: u32,
+    instructions: Vec<Instruction>,
+}
+
+impl InstructionSetBox {
+    /// Instruction type (ITYP).
+    pub fn instruction_type(&self) -> u16 {
+        self.instruction_type
+    }
+
+    /// Number of times the instructions are repeated (REPT).
+    pub fn repeat(&self) -> u16 {
+        self.repeat
+    }
+
+    /// Duration of a tick in milliseconds (TICK).
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    fn instruction_length(&self) -> u64 {
+        let mut length = 0;
+        if self.instruction_type & INSTRUCTION_OFFSET != 0 {
+            length += 8;
+        }
+        if self.instruction_type & INSTRUCTION_SIZE != 0 {
+            length += 8;
+        }
+        if self.instruction_type & INSTRUCTION_ANIMATION != 0 {
+            length += 8;
+        }
+        if self.instruction_type & INSTRUCTION_CROP != 0 {
+            length += 16;
+        }
+        length
+    }
+}
+
+impl JBox for InstructionSetBox {
+    // The type of an Instruction Set box shall be ‘inst’ (0x696E 7374).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_INSTRUCTION_SET
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length < 8 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        self.instruction_type = read_u16(reader).at(self.offset)?;
+        self.repeat = read_u16(reader).at(self.offset)?;
+        self.tick = read_u32(reader).at(self.offset)?;
+
+        let instruction_length = self.instruction_length();
+        if instruction_length == 0 {
+            if self.length != 8 {
+                return Err(malformed(self.identifier(), self.offset));
+            }
+            return Ok(());
+        }
+        if !(self.length - 8).is_multiple_of(instruction_length) {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+
+        for _ in 0..(self.length - 8) / instruction_length {
+            let mut instruction = Instruction {
+                offset: None,
+                size: None,
+                life: None,
+                next_use: None,
+                crop: None,
+            };
+            if self.instruction_type & INSTRUCTION_OFFSET != 0 {
+                instruction.offset = Some((
+                    read_u32(reader).at(self.offset)?,
+                    read_u32(reader).at(self.offset)?,
+                ));
+            }
+            if self.instruction_type & INSTRUCTION_SIZE != 0 {
+                instruction.size = Some((
+                    read_u32(reader).at(self.offset)?,
+                    read_u32(reader).at(self.offset)?,
+                ));
+            }
+            if self.instruction_type & INSTRUCTION_ANIMATION != 0 {
+                instruction.life = Some(read_u32(reader).at(self.offset)?);
+                instruction.next_use = Some(read_u32(reader).at(self.offset)?);
+            }
+            if self.instruction_type & INSTRUCTION_CROP != 0 {
+                instruction.crop = Some((
+                    read_u32(reader).at(self.offset)?,
+                    read_u32(reader).at(self.offset)?,
+                    read_u32(reader).at(self.offset)?,
+                    read_u32(reader).at(self.offset)?,
+                ));
+            }
+            self.instructions.push(instruction);
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        contents.extend(self.instruction_type.to_be_bytes());
+        contents.extend(self.repeat.to_be_bytes());
+        contents.extend(self.tick.to_be_bytes());
+        for instruction in &self.instructions {
+            let mut fields: Vec<u32> = vec![];
+            if let Some((horizontal_offset, vertical_offset)) = instruction.offset {
+                fields.extend([horizontal_offset, vertical_offset]);
+            }
+            if let Some((width, height)) = instruction.size {
+                fields.extend([width, height]);
+            }
+            if let (Some(life), Some(next_use)) = (instruction.life, instruction.next_use) {
+                fields.extend([life, next_use]);
+            }
+            if let Some((x, y, width, height)) = instruction.crop {
+                fields.extend([x, y, width, height]);
+            }
+            contents.extend(fields.iter().flat_map(|field| field.to_be_bytes()));
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.10
+//
+// Composition box (superbox)
+//
+// This box specifies how the compositing layers are combined into the
+// rendered result, it contains a Composition Options box followed by
+// Instruction Set boxes.
+#[derive(Debug, Default)]
+pub struct CompositionSuperBox {
+    length: u64,
+    offset: u64,
+    pub composition_options_box: CompositionOptionsBox,
+    pub instruction_set_boxes: Vec<InstructionSetBox>,
+}
+
+impl JBox for CompositionSuperBox {
+    // The type of a Composition box shall be ‘comp’ (0x636F 6D70).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_COMPOSITION
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        let children = child_boxes(reader, self.offset, self.length)?;
+
+        // The Composition Options box shall be the first box
+        match children.first() {
+            Some(info) if info.box_type == BOX_TYPE_COMPOSITION_OPTIONS => {
+                self.composition_options_box = decode_box!(CompositionOptionsBox, reader, info);
+            }
+            _ => {
+                return Err(JP2Error::BoxMissing {
+                    box_type: BOX_TYPE_COMPOSITION_OPTIONS,
+                    offset: self.offset,
+                })
+            }
+        }
+        for info in &children[1..] {
+            match info.box_type {
+                BOX_TYPE_INSTRUCTION_SET => {
+                    self.instruction_set_boxes
+                        .push(decode_box!(InstructionSetBox, reader, info))
+                }
+                _ => ignore(info, self.identifier()),
+            }
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        self.composition_options_box.encode(&mut contents)?;
+        for instruction_set_box in &self.instruction_set_boxes {
+            instruction_set_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+/// Box contained in an Association box.
+#[derive(Debug)]
+pub enum AssociatedBox {
+    Label(LabelBox),
+    NumberList(NumberListBox),
+    Xml(XMLBox),
+    ROIDescription(ROIDescriptionBox),
+    Association(AssociationSuperBox),
+    /// Boxes of other types, with their contents.
+    Other(UnknownBox),
+}
+
+impl AssociatedBox {
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        match self {
+            AssociatedBox::Label(label_box) => label_box.encode(writer),
+            AssociatedBox::NumberList(number_list_box) => number_list_box.encode(writer),
+            AssociatedBox::Xml(xml_box) => xml_box.encode(writer),
+            AssociatedBox::ROIDescription(roi_description_box) => {
+                roi_description_box.encode(writer)
+            }
+            AssociatedBox::Association(association_box) => association_box.encode(writer),
+            AssociatedBox::Other(unknown_box) => unknown_box.encode(writer),
+        }
+    }
+}
+
+// M.11.11
+//
+// Association box (superbox)
+//
+// This box associates the boxes it contains with each other, the first box
+// is usually described by the boxes following it, such as a Number List box
+// followed by Label or XML boxes describing codestreams or compositing layers.
+#[derive(Debug, Default)]
+pub struct AssociationSuperBox {
+    length: u64,
+    offset: u64,
+    boxes: Vec<AssociatedBox>,
+}
+
+impl AssociationSuperBox {
+    /// Create an Association box for encoding.
+    pub fn new(boxes: Vec<AssociatedBox>) -> AssociationSuperBox {
+        AssociationSuperBox {
+            boxes,
+            ..Default::default()
+        }
+    }
+
+    /// Contained boxes, in the order they were found.
+    pub fn boxes(&self) -> &[AssociatedBox] {
+        &self.boxes
+    }
+
+    /// The label of the first Label box, if any.
+    pub fn label(&self) -> Option<String> {
+        self.boxes
+            .iter()
+            .find_map(|associated_box| match associated_box {
+                AssociatedBox::Label(label_box) => Some(label_box.label()),
+                _ => None,
+            })
+    }
+
+    /// The associations of the first Number List box, if any.
+    pub fn associations(&self) -> Option<Vec<AssociationNumber>> {
+        self.boxes
+            .iter()
+            .find_map(|associated_box| match associated_box {
+                AssociatedBox::NumberList(number_list_box) => Some(number_list_box.associations()),
+                _ => None,
+            })
+    }
+
+    // Decode the contained boxes, which are nested in depth superboxes.
+    fn decode_nested<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+        limits: &Limits,
+        depth: u32,
+    ) -> Result<(), JP2Error> {
+        limits.check_nesting_depth(depth).at(self.offset)?;
+        for info in child_boxes(reader, self.offset, self.length)? {
+            let associated_box = match info.box_type {
+                BOX_TYPE_LABEL => AssociatedBox::Label(decode_box!(LabelBox, reader, info)),
+                BOX_TYPE_NUMBER_LIST => {
+                    AssociatedBox::NumberList(decode_box!(NumberListBox, reader, info))
+                }
+                BOX_TYPE_XML => AssociatedBox::Xml(decode_box!(XMLBox, reader, info)),
+                BOX_TYPE_ROI_DESCRIPTION => {
+                    AssociatedBox::ROIDescription(decode_box!(ROIDescriptionBox, reader, info))
+                }
+                BOX_TYPE_ASSOCIATION => {
+                    let mut association_box = AssociationSuperBox {
+                        length: info.length,
+                        offset: info.offset,
+                        boxes: vec![],
+                    };
+                    association_box.decode_nested(reader, limits, depth + 1)?;
+                    reader
+                        .seek(io::SeekFrom::Start(info.end()))
+                        .at(info.offset)?;
+                    AssociatedBox::Association(association_box)
+                }
+                box_type => {
+                    let mut unknown_box = UnknownBox {
+                        length: info.length,
+                        offset: info.offset,
+                        box_type,
+                        data: None,
+                    };
+                    unknown_box.data = Some(unknown_box.read_data(reader)?);
+                    AssociatedBox::Other(unknown_box)
+                }
+            };
+            self.boxes.push(associated_box);
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+}
+
+impl JBox for AssociationSuperBox {
+    // The type of an Association box shall be ‘asoc’ (0x6173 6F63).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_ASSOCIATION
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.decode_nested(reader, &Limits::default(), 1)
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        for associated_box in &self.boxes {
+            associated_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// Decode a top-level Association box of a JP2 file, with contents of the
+// length and at the offset given.
+pub(crate) fn decode_association_box<R: io::Read + io::Seek>(
+    reader: &mut R,
+    offset: u64,
+    length: u64,
+    limits: &Limits,
+) -> Result<AssociationSuperBox, JP2Error> {
+    let mut association_box = AssociationSuperBox {
+        length,
+        offset,
+        boxes: vec![],
+    };
+    association_box.decode_nested(reader, limits, 1)?;
+    Ok(association_box)
+}
+
+/// Fragment of a Fragment List box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragment {
+    offset: u64,
+    length: u32,
+    data_reference: u16,
+}
+
+impl Fragment {
+    /// Offset of the fragment (OFF) in the file it is found in.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Length of the fragment (LEN).
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Data reference (DR), 0 for the file containing the fragment list, or
+    /// the index plus one of a URL in the Data Reference box.
+    pub fn data_reference(&self) -> u16 {
+        self.data_reference
+    }
+}
+
+// M.11.17
+//
+// Fragment List box
+//
+// This box specifies the location of the fragments making up a codestream or
+// a box.
+#[derive(Debug, Default)]
+pub struct FragmentListBox {
+    length: u64,
+    offset: u64,
+    fragments: Vec<Fragment>,
+}
+
+impl FragmentListBox {
+    pub fn fragments(&self) -> &[Fragment] {
+        &self.fragments
+    }
+}
+
+impl JBox for FragmentListBox {
+    // The type of a Fragment List box shall be ‘flst’ (0x666C 7374).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_FRAGMENT_LIST
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length < 2 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        let fragments = read_u16(reader).at(self.offset)?;
+        if self.length != 2 + 14 * fragments as u64 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        self.fragments = Vec::with_capacity(fragments as usize);
+        for _ in 0..fragments {
+            self.fragments.push(Fragment {
+                offset: read_u64(reader).at(self.offset)?,
+                length: read_u32(reader).at(self.offset)?,
+                data_reference: read_u16(reader).at(self.offset)?,
+            });
+        }
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        contents.extend((self.fragments.len() as u16).to_be_bytes());
+        for fragment in &self.fragments {
+            contents.extend(fragment.offset.to_be_bytes());
+            contents.extend(fragment.length.to_be_bytes());
+            contents.extend(fragment.data_reference.to_be_bytes());
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// Decode the Fragment List box which shall be the only box of a superbox.
+fn decode_fragment_list<R: io::Read + io::Seek>(
+    reader: &mut R,
+    box_type: BoxType,
+    offset: u64,
+    length: u64,
+) -> Result<FragmentListBox, JP2Error> {
+    let children = child_boxes(reader, offset, length)?;
+    match children.as_slice() {
+        [info] if info.box_type == BOX_TYPE_FRAGMENT_LIST => {
+            Ok(decode_box!(FragmentListBox, reader, info))
+        }
+        [] => Err(JP2Error::BoxMissing {
+            box_type: BOX_TYPE_FRAGMENT_LIST,
+            offset,
+        }),
+        _ => Err(malformed(box_type, offset)),
+    }
+}
+
+// M.11.16
+//
+// Fragment Table box (superbox)
+//
+// This box specifies a codestream stored in fragments, it is used in place of
+// a Contiguous Codestream box.
+#[derive(Debug, Default)]
+pub struct FragmentTableSuperBox {
+    length: u64,
+    offset: u64,
+    pub fragment_list_box: FragmentListBox,
+}
+
+impl JBox for FragmentTableSuperBox {
+    // The type of a Fragment Table box shall be ‘ftbl’ (0x6674 626C).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_FRAGMENT_TABLE
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.fragment_list_box =
+            decode_fragment_list(reader, self.identifier(), self.offset, self.length)?;
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        self.fragment_list_box.encode(&mut contents)?;
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.15
+//
+// Cross-Reference box
+//
+// This box specifies a box stored in fragments, such as a box shared by
+// several files.
+#[derive(Debug, Default)]
+pub struct CrossReferenceBox {
+    length: u64,
+    offset: u64,
+    box_type: BoxType,
+    pub fragment_list_box: FragmentListBox,
+}
+
+impl CrossReferenceBox {
+    /// Type of the referenced box (Cr).
+    pub fn box_type(&self) -> [u8; 4] {
+        self.box_type
+    }
+}
+
+impl JBox for CrossReferenceBox {
+    // The type of a Cross-Reference box shall be ‘cref’ (0x6372 6566).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_CROSS_REFERENCE
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length < 4 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        reader.read_exact(&mut self.box_type).at(self.offset)?;
+        self.fragment_list_box =
+            decode_fragment_list(reader, self.identifier(), self.offset + 4, self.length - 4)?;
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = self.box_type.to_vec();
+        self.fragment_list_box.encode(&mut contents)?;
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+// M.11.18
+//
+// Data Reference box
+//
+// This box contains the URLs of the files referenced by Fragment List boxes,
+// there shall be at most one Data Reference box in a file.
+#[derive(Debug, Default)]
+pub struct DataReferenceBox {
+    length: u64,
+    offset: u64,
+    data_entry_url_boxes: Vec<DataEntryURLBox>,
+}
+
+impl DataReferenceBox {
+    /// Data Entry URL boxes, a data reference of n refers to the n-th box.
+    pub fn data_entry_url_boxes(&self) -> &[DataEntryURLBox] {
+        &self.data_entry_url_boxes
+    }
+}
+
+impl JBox for DataReferenceBox {
+    // The type of a Data Reference box shall be ‘dtbl’ (0x6474 626C).
+    fn identifier(&self) -> BoxType {
+        BOX_TYPE_DATA_REFERENCE
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length < 2 {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        let references = read_u16(reader).at(self.offset)?;
+        let children = child_boxes(reader, self.offset + 2, self.length - 2)?;
+        if children.len() != references as usize {
+            return Err(malformed(self.identifier(), self.offset));
+        }
+        for info in children {
+            if info.box_type != BOX_TYPE_DATA_ENTRY_URL || info.length < 4 {
+                return Err(malformed(self.identifier(), self.offset));
+            }
+            self.data_entry_url_boxes
+                .push(decode_box!(DataEntryURLBox, reader, info));
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        contents.extend((self.data_entry_url_boxes.len() as u16).to_be_bytes());
+        for data_entry_url_box in &self.data_entry_url_boxes {
+            data_entry_url_box.encode(&mut contents)?;
+        }
+        encode_superbox(writer, self.identifier(), &contents).at(self.offset)
+    }
+}
+
+/// JPX file, ISO/IEC 15444-2 Annex M.
+///
+/// Codestreams are stored in Contiguous Codestream boxes or Fragment Table
+/// boxes, the n-th Codestream Header box describes the n-th codestream.
+#[derive(Debug, Default)]
+pub struct JPXFile {
+    length: u64,
+    signature: Option<SignatureBox>,
+    file_type: Option<FileTypeBox>,
+    reader_requirements: Option<ReaderRequirementsBox>,
+    header: Option<HeaderSuperBox>,
+    codestream_headers: Vec<CodestreamHeaderSuperBox>,
+    compositing_layer_headers: Vec<CompositingLayerHeaderSuperBox>,
+    composition: Option<CompositionSuperBox>,
+    contiguous_codestreams: Vec<ContiguousCodestreamBox>,
+    fragment_tables: Vec<FragmentTableSuperBox>,
+    data_reference: Option<DataReferenceBox>,
+    cross_references: Vec<CrossReferenceBox>,
+    associations: Vec<AssociationSuperBox>,
+    intellectual_property: Option<IntellectualPropertyBox>,
+    xml: Vec<XMLBox>,
+    uuid: Vec<UUIDBox>,
+    uuid_info: Vec<UUIDInfoSuperBox>,
+    unknown: Vec<UnknownBox>,
+}
+
+impl JPXFile {
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+    pub fn signature_box(&self) -> &Option<SignatureBox> {
+        &self.signature
+    }
+    pub fn file_type_box(&self) -> &Option<FileTypeBox> {
+        &self.file_type
+    }
+    pub fn reader_requirements_box(&self) -> &Option<ReaderRequirementsBox> {
+        &self.reader_requirements
+    }
+    pub fn header_box(&self) -> &Option<HeaderSuperBox> {
+        &self.header
+    }
+    pub fn codestream_header_boxes(&self) -> &Vec<CodestreamHeaderSuperBox> {
+        &self.codestream_headers
+    }
+    pub fn compositing_layer_header_boxes(&self) -> &Vec<CompositingLayerHeaderSuperBox> {
+        &self.compositing_layer_headers
+    }
+    pub fn composition_box(&self) -> &Option<CompositionSuperBox> {
+        &self.composition
+    }
+    pub fn contiguous_codestreams_boxes(&self) -> &Vec<ContiguousCodestreamBox> {
+        &self.contiguous_codestreams
+    }
+    pub fn fragment_table_boxes(&self) -> &Vec<FragmentTableSuperBox> {
+        &self.fragment_tables
+    }
+    pub fn data_reference_box(&self) -> &Option<DataReferenceBox> {
+        &self.data_reference
+    }
+    pub fn cross_reference_boxes(&self) -> &Vec<CrossReferenceBox> {
+        &self.cross_references
+    }
+    pub fn association_boxes(&self) -> &Vec<AssociationSuperBox> {
+        &self.associations
+    }
+    pub fn intellectual_property_box(&self) -> &Option<IntellectualPropertyBox> {
+        &self.intellectual_property
+    }
+    pub fn xml_boxes(&self) -> &Vec<XMLBox> {
+        &self.xml
+    }
+    pub fn uuid_boxes(&self) -> &Vec<UUIDBox> {
+        &self.uuid
+    }
+    pub fn uuid_info_boxes(&self) -> &Vec<UUIDInfoSuperBox> {
+        &self.uuid_info
+    }
+
+    /// Top-level boxes of types not defined by ISO/IEC 15444-1 or 15444-2, in
+    /// the order they were found in the file.
+    pub fn unknown_boxes(&self) -> &Vec<UnknownBox> {
+        &self.unknown
+    }
+}
+
+// Compatibility list entries of files a JPX reader can interpret.
+const BRAND_JPX_BASELINE: [u8; 4] = [106, 112, 120, 98];
+
+/// Decode a JPX file, ISO/IEC 15444-2 Annex M.
+///
+/// JP2 files are also JPX files, the File Type box shall list ‘jp2\040’,
+/// ‘jpx\040’ or ‘jpxb’ in its Compatibility list.
+pub fn decode_jpx<R: io::Read + io::Seek>(reader: &mut R) -> Result<JPXFile, JP2Error> {
+    let start = reader.stream_position().at(0)?;
+    let end = reader.seek(io::SeekFrom::End(0)).at(start)?;
+    let boxes = child_boxes(reader, start, end - start)?;
+
+    let mut jpx = JPXFile {
+        length: end - start,
+        ..Default::default()
+    };
+
+    // The Signature box shall be the first box, immediately followed by the
+    // File Type box.
+    match boxes.first() {
+        Some(info) if info.box_type == BOX_TYPE_SIGNATURE => {
+            jpx.signature = Some(decode_box!(SignatureBox, reader, info));
+        }
+        Some(info) => {
+            return Err(JP2Error::BoxUnexpected {
+                box_type: info.box_type,
+                offset: info.offset,
+            })
+        }
+        None => {
+            return Err(JP2Error::BoxMissing {
+                box_type: BOX_TYPE_SIGNATURE,
+                offset: 0,
+            })
+        }
+    }
+    match boxes.get(1) {
+        Some(info) if info.box_type == BOX_TYPE_FILE_TYPE => {
+            let mut file_type_box = FileTypeBox {
+                length: info.length,
+                offset: info.offset,
+                ..Default::default()
+            };
+            reader
+                .seek(io::SeekFrom::Start(info.offset))
+                .at(info.offset)?;
+            file_type_box.decode_fields(reader)?;
+            let compatibility_list = &file_type_box.compatibility_list;
+            if !compatibility_list.contains(&BRAND_JP2)
+                && !compatibility_list.contains(&BRAND_JPX)
+                && !compatibility_list.contains(&BRAND_JPX_BASELINE)
+            {
+                return Err(JP2Error::NotCompatible {
+                    compatibility_list: file_type_box.compatibility_list(),
+                    offset: info.offset,
+                });
+            }
+            jpx.file_type = Some(file_type_box);
+        }
+        Some(info) => {
+            return Err(JP2Error::BoxUnexpected {
+                box_type: info.box_type,
+                offset: info.offset,
+            })
+        }
+        None => {
+            return Err(JP2Error::BoxMissing {
+                box_type: BOX_TYPE_FILE_TYPE,
+                offset: boxes[0].end(),
+            })
+        }
+    }
+
+    for (index, info) in boxes.iter().enumerate().skip(2) {
+        info!(
+            "{:?} start at {:?}",
+            str::from_utf8(&info.box_type),
+            info.offset
+        );
+        match info.box_type {
+            BOX_TYPE_READER_REQUIREMENTS if jpx.reader_requirements.is_none() => {
+                // The Reader Requirements box shall immediately follow the
+                // File Type box.
+                if index != 2 {
+                    warn!("ReaderRequirementsBox not after the FileTypeBox");
+                }
+                jpx.reader_requirements = Some(decode_box!(ReaderRequirementsBox, reader, info));
+            }
+            BOX_TYPE_HEADER if jpx.header.is_none() => {
+                jpx.header = Some(decode_box!(HeaderSuperBox, reader, info));
+            }
+            BOX_TYPE_CODESTREAM_HEADER => {
+                jpx.codestream_headers
+                    .push(decode_box!(CodestreamHeaderSuperBox, reader, info))
+            }
+            BOX_TYPE_COMPOSITING_LAYER_HEADER => jpx.compositing_layer_headers.push(decode_box!(
+                CompositingLayerHeaderSuperBox,
+                reader,
+                info
+            )),
+            BOX_TYPE_COMPOSITION if jpx.composition.is_none() => {
+                jpx.composition = Some(decode_box!(CompositionSuperBox, reader, info));
+            }
+            BOX_TYPE_CONTIGUOUS_CODESTREAM => {
+                jpx.contiguous_codestreams
+                    .push(decode_box!(ContiguousCodestreamBox, reader, info))
+            }
+            BOX_TYPE_FRAGMENT_TABLE => {
+                jpx.fragment_tables
+                    .push(decode_box!(FragmentTableSuperBox, reader, info))
+            }
+            BOX_TYPE_DATA_REFERENCE if jpx.data_reference.is_none() => {
+                jpx.data_reference = Some(decode_box!(DataReferenceBox, reader, info));
+            }
+            BOX_TYPE_CROSS_REFERENCE => {
+                jpx.cross_references
+                    .push(decode_box!(CrossReferenceBox, reader, info))
+            }
+            BOX_TYPE_ASSOCIATION => {
+                jpx.associations
+                    .push(decode_box!(AssociationSuperBox, reader, info))
+            }
+            BOX_TYPE_INTELLECTUAL_PROPERTY if jpx.intellectual_property.is_none() => {
+                jpx.intellectual_property =
+                    Some(decode_box!(IntellectualPropertyBox, reader, info));
+            }
+            BOX_TYPE_XML => jpx.xml.push(decode_box!(XMLBox, reader, info)),
+            BOX_TYPE_UUID => jpx.uuid.push(decode_box!(UUIDBox, reader, info)),
+            BOX_TYPE_UUID_INFO => {
+                let mut uuid_info_box = decode_box!(UUIDInfoSuperBox, reader, info);
+                for child in child_boxes(reader, info.offset, info.length)? {
+                    match child.box_type {
+                        BOX_TYPE_UUID_LIST => {
+                            uuid_info_box
+                                .uuid_list
+                                .push(decode_box!(UUIDListBox, reader, child))
+                        }
+                        BOX_TYPE_DATA_ENTRY_URL if child.length >= 4 => uuid_info_box
+                            .data_entry_url_box
+                            .push(decode_box!(DataEntryURLBox, reader, child)),
+                        _ => return Err(malformed(BOX_TYPE_UUID_INFO, info.offset)),
+                    }
+                }
+                jpx.uuid_info.push(uuid_info_box);
+            }
+            BOX_TYPE_SIGNATURE
+            | BOX_TYPE_FILE_TYPE
+            | BOX_TYPE_READER_REQUIREMENTS
+            | BOX_TYPE_HEADER
+            | BOX_TYPE_COMPOSITION
+            | BOX_TYPE_DATA_REFERENCE
+            | BOX_TYPE_INTELLECTUAL_PROPERTY => return Err(duplicate(info)),
+            box_type => {
+                warn!("Unknown box type {:?} at {:?}", box_type, info.offset);
+                jpx.unknown.push(decode_box!(UnknownBox, reader, info));
+            }
+        }
+    }
+
+    if jpx.contiguous_codestreams.is_empty() && jpx.fragment_tables.is_empty() {
+        return Err(JP2Error::BoxMissing {
+            box_type: BOX_TYPE_CONTIGUOUS_CODESTREAM,
+            offset: boxes.last().map(BoxInfo::end).unwrap_or_default(),
+        });
+    }
+
+    Ok(jpx)
+}
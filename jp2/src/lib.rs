@@ -1,24 +1,194 @@
 #![allow(dead_code)]
 
 use log::{debug, info, warn};
+use std::convert::TryFrom;
 use std::error;
 use std::fmt;
 use std::io;
 use std::str;
 
+mod boxes;
+mod channels;
+mod colour;
+mod conformance;
+mod fragments;
+mod geojp2;
+mod gmljp2;
+mod jpx;
+mod metadata;
+mod requirements;
+mod tiff;
+mod validate;
+
+pub use boxes::{is_superbox, BoxInfo, BoxIterator};
+pub use channels::{map_channels, ComponentSamples, ImageChannel};
+pub use colour::{convert_to_srgb, srgb_colour_specification};
+pub use conformance::{DecodeOptions, DecodeWarning, Nonconformance};
+pub use fragments::FragmentReader;
+pub use geojp2::{
+    GeoJP2, GeoKey, GeoKeyValue, GEO_KEY_CITATION, GEO_KEY_GEOGRAPHIC_CITATION,
+    GEO_KEY_GEOGRAPHIC_TYPE, GEO_KEY_MODEL_TYPE, GEO_KEY_PROJECTED_CITATION,
+    GEO_KEY_PROJECTED_CS_TYPE, GEO_KEY_RASTER_TYPE, UUID_GEOJP2,
+};
+pub use gmljp2::{
+    decode_gmljp2, gmljp2_association_box, RectifiedGrid, GMLJP2, LABEL_GML_DATA,
+    LABEL_GML_ROOT_INSTANCE,
+};
+pub use icc::{RestrictedProfileError, RestrictedProfileType, Violation};
+pub use jpc::{CodestreamError, LimitError, Limits};
+pub use jpx::{
+    decode_jpx, AssociatedBox, AssociationNumber, AssociationSuperBox, CodestreamHeaderSuperBox,
+    CodestreamRegistration, CodestreamRegistrationBox, ColourGroupSuperBox,
+    CompositingLayerHeaderSuperBox, CompositionOptionsBox, CompositionSuperBox, CrossReferenceBox,
+    DataReferenceBox, Fragment, FragmentListBox, FragmentTableSuperBox, Instruction,
+    InstructionSetBox, JPXFile, LabelBox, NumberListBox, OpacityBox, OpacityType,
+    ROIDescriptionBox, ReaderRequirementsBox, Region, StandardFeature, VendorFeature,
+};
+pub use metadata::{
+    ExifIfd, ExifTag, ExifValue, IptcDataSet, Metadata, UUID_EXIF, UUID_IPTC, UUID_XMP,
+};
+pub use requirements::{Feature, FeatureRequirement, RequirementsReport};
+pub use validate::validate_jp2;
+
+/// An error decoding, encoding or validating a file, the offset of each
+/// variant is the byte offset in the file of the box or value in error, or 0
+/// for an error which has no position in the file.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum JP2Error {
-    InvalidSignature { signature: [u8; 4], offset: u64 },
-    InvalidBrand { brand: [u8; 4], offset: u64 },
-    Unsupported,
-    NotCompatible { compatibility_list: Vec<String> },
-    BoxUnexpected { box_type: BoxType, offset: u64 },
-    BoxDuplicate { box_type: BoxType, offset: u64 },
-    BoxMalformed { box_type: BoxType, offset: u64 },
-    BoxMissing { box_type: BoxType },
+    InvalidSignature {
+        signature: [u8; 4],
+        offset: u64,
+    },
+    InvalidBrand {
+        brand: [u8; 4],
+        offset: u64,
+    },
+    Unsupported {
+        offset: u64,
+    },
+    NotCompatible {
+        compatibility_list: Vec<String>,
+        offset: u64,
+    },
+    BoxUnexpected {
+        box_type: BoxType,
+        offset: u64,
+    },
+    BoxDuplicate {
+        box_type: BoxType,
+        offset: u64,
+    },
+    BoxMalformed {
+        box_type: BoxType,
+        offset: u64,
+    },
+    /// A required box missing, the offset is of the box requiring it or of
+    /// the end of the superbox or file it is missing from.
+    BoxMissing {
+        box_type: BoxType,
+        offset: u64,
+    },
+    UnsupportedURL {
+        url: String,
+        offset: u64,
+    },
+    UnresolvedURL {
+        url: String,
+        offset: u64,
+    },
+    /// A URL of a file outside of the base directory files are read from.
+    ForbiddenURL {
+        url: String,
+        offset: u64,
+    },
+    UnsupportedColourSpace {
+        offset: u64,
+    },
+    ColourChannelsMismatch {
+        expected: usize,
+        found: usize,
+        offset: u64,
+    },
+    Nonconforming {
+        warning: DecodeWarning,
+        offset: u64,
+    },
+    /// An error of a codestream, the offset is of the start of the
+    /// codestream, the offset of the source is from there.
+    Codestream {
+        offset: u64,
+        source: CodestreamError,
+    },
+    Limit {
+        offset: u64,
+        source: LimitError,
+    },
+    Io {
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl JP2Error {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::InvalidSignature { offset, .. }
+            | Self::InvalidBrand { offset, .. }
+            | Self::Unsupported { offset }
+            | Self::NotCompatible { offset, .. }
+            | Self::BoxUnexpected { offset, .. }
+            | Self::BoxDuplicate { offset, .. }
+            | Self::BoxMalformed { offset, .. }
+            | Self::BoxMissing { offset, .. }
+            | Self::UnsupportedURL { offset, .. }
+            | Self::UnresolvedURL { offset, .. }
+            | Self::ForbiddenURL { offset, .. }
+            | Self::UnsupportedColourSpace { offset }
+            | Self::ColourChannelsMismatch { offset, .. }
+            | Self::Nonconforming { offset, .. }
+            | Self::Codestream { offset, .. }
+            | Self::Limit { offset, .. }
+            | Self::Io { offset, .. } => *offset,
+        }
+    }
+}
+
+// Errors of other crates and of the reader or writer, reported at the offset
+// of the box or field they occurred in.
+pub(crate) trait AtOffset<T> {
+    fn at(self, offset: u64) -> Result<T, JP2Error>;
+}
+
+impl<T> AtOffset<T> for io::Result<T> {
+    fn at(self, offset: u64) -> Result<T, JP2Error> {
+        self.map_err(|source| JP2Error::Io { offset, source })
+    }
+}
+
+impl<T> AtOffset<T> for Result<T, CodestreamError> {
+    fn at(self, offset: u64) -> Result<T, JP2Error> {
+        self.map_err(|source| JP2Error::Codestream { offset, source })
+    }
+}
+
+impl<T> AtOffset<T> for Result<T, LimitError> {
+    fn at(self, offset: u64) -> Result<T, JP2Error> {
+        self.map_err(|source| JP2Error::Limit { offset, source })
+    }
+}
+
+impl error::Error for JP2Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Codestream { source, .. } => Some(source),
+            Self::Limit { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
-impl error::Error for JP2Error {}
 impl fmt::Display for JP2Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -26,7 +196,7 @@ impl fmt::Display for JP2Error {
                 write!(
                     f,
                     "invalid signature {:?} at offset {}",
-                    str::from_utf8(signature).unwrap(),
+                    String::from_utf8_lossy(signature),
                     offset
                 )
             }
@@ -34,15 +204,19 @@ impl fmt::Display for JP2Error {
                 write!(
                     f,
                     "invalid brand {:?} at offset {}",
-                    str::from_utf8(brand).unwrap(),
+                    String::from_utf8_lossy(brand),
                     offset
                 )
             }
-            Self::NotCompatible { compatibility_list } => {
+            Self::NotCompatible {
+                compatibility_list,
+                offset,
+            } => {
                 write!(
                     f,
-                    "'jp2 ' not found in compatibility list '{}'",
-                    compatibility_list.join(", ")
+                    "'jp2 ' not found in compatibility list '{}' at offset {}",
+                    compatibility_list.join(", "),
+                    offset
                 )
             }
             Self::BoxDuplicate { box_type, offset } => {
@@ -58,15 +232,61 @@ impl fmt::Display for JP2Error {
             Self::BoxMalformed { box_type, offset } => {
                 write!(f, "malformed box type {:?} at offset {}", box_type, offset)
             }
-            Self::BoxMissing { box_type } => {
-                write!(f, "box type {:?} missing", box_type)
+            Self::BoxMissing { box_type, offset } => {
+                write!(f, "box type {:?} missing at offset {}", box_type, offset)
+            }
+            Self::UnsupportedURL { url, offset } => {
+                write!(
+                    f,
+                    "unsupported URL {:?} at offset {}, only local file URLs are supported",
+                    url, offset
+                )
+            }
+            Self::UnresolvedURL { url, offset } => {
+                write!(
+                    f,
+                    "URL {:?} at offset {} cannot be resolved without a base directory",
+                    url, offset
+                )
+            }
+            Self::ForbiddenURL { url, offset } => {
+                write!(
+                    f,
+                    "URL {:?} at offset {} is outside of the base directory",
+                    url, offset
+                )
+            }
+            Self::UnsupportedColourSpace { offset } => {
+                write!(
+                    f,
+                    "no Colour Specification box at offset {} can be converted to sRGB",
+                    offset
+                )
+            }
+            Self::ColourChannelsMismatch {
+                expected,
+                found,
+                offset,
+            } => {
+                write!(
+                    f,
+                    "expected {} colour channels of the same length, found {} at offset {}",
+                    expected, found, offset
+                )
             }
-            Self::Unsupported => {
+            Self::Unsupported { offset } => {
                 write!(
                     f,
-                    "only JPEG 2000 part-1 (ISO 15444-1 / T.800) is supported",
+                    "only JPEG 2000 part-1 (ISO 15444-1 / T.800) is supported, at offset {}",
+                    offset
                 )
             }
+            Self::Nonconforming { warning, .. } => write!(f, "nonconforming {}", warning),
+            Self::Codestream { offset, source } => {
+                write!(f, "codestream at offset {}: {}", offset, source)
+            }
+            Self::Limit { offset, source } => write!(f, "{} at offset {}", source, offset),
+            Self::Io { offset, source } => write!(f, "{} at offset {}", source, offset),
         }
     }
 }
@@ -92,6 +312,26 @@ const BOX_TYPE_UUID_INFO: BoxType = [117, 105, 110, 102];
 const BOX_TYPE_UUID_LIST: BoxType = [117, 108, 115, 116];
 const BOX_TYPE_DATA_ENTRY_URL: BoxType = [117, 114, 108, 32];
 
+// Box types of the JPX file format, ISO/IEC 15444-2 Table M.2
+const BOX_TYPE_READER_REQUIREMENTS: BoxType = [114, 114, 101, 113];
+const BOX_TYPE_CODESTREAM_HEADER: BoxType = [106, 112, 99, 104];
+const BOX_TYPE_COMPOSITING_LAYER_HEADER: BoxType = [106, 112, 108, 104];
+const BOX_TYPE_COLOUR_GROUP: BoxType = [99, 103, 114, 112];
+const BOX_TYPE_OPACITY: BoxType = [111, 112, 99, 116];
+const BOX_TYPE_CODESTREAM_REGISTRATION: BoxType = [99, 114, 101, 103];
+const BOX_TYPE_COMPOSITION: BoxType = [99, 111, 109, 112];
+const BOX_TYPE_COMPOSITION_OPTIONS: BoxType = [99, 111, 112, 116];
+const BOX_TYPE_INSTRUCTION_SET: BoxType = [105, 110, 115, 116];
+const BOX_TYPE_ASSOCIATION: BoxType = [97, 115, 111, 99];
+const BOX_TYPE_NUMBER_LIST: BoxType = [110, 108, 115, 116];
+const BOX_TYPE_LABEL: BoxType = [108, 98, 108, 32];
+const BOX_TYPE_ROI_DESCRIPTION: BoxType = [114, 111, 105, 100];
+const BOX_TYPE_CROSS_REFERENCE: BoxType = [99, 114, 101, 102];
+const BOX_TYPE_FRAGMENT_TABLE: BoxType = [102, 116, 98, 108];
+const BOX_TYPE_FRAGMENT_LIST: BoxType = [102, 108, 115, 116];
+const BOX_TYPE_DATA_REFERENCE: BoxType = [100, 116, 98, 108];
+const BOX_TYPE_DESIRED_REPRODUCTIONS: BoxType = [100, 114, 101, 112];
+
 // jp2\040
 const BRAND_JP2: [u8; 4] = [106, 112, 50, 32];
 
@@ -122,6 +362,7 @@ enum BoxTypes {
     UUIDInfo,
     UUIDList,
     DataEntryURL,
+    Association,
     Unknown,
 }
 
@@ -156,6 +397,7 @@ impl BoxTypes {
             BOX_TYPE_UUID_INFO => BoxTypes::UUIDInfo,
             BOX_TYPE_UUID_LIST => BoxTypes::UUIDList,
             BOX_TYPE_DATA_ENTRY_URL => BoxTypes::DataEntryURL,
+            BOX_TYPE_ASSOCIATION => BoxTypes::Association,
             _ => BoxTypes::Unknown,
         }
     }
@@ -180,10 +422,13 @@ pub trait JBox {
     fn length(&self) -> u64;
     fn offset(&self) -> u64;
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>>;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error>;
+
+    /// Encode the box, including the box header.
+    ///
+    /// The length of the box is derived from its contents, the length and
+    /// offset recorded when the box was decoded are not used.
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error>;
 }
 
 /// JPEG 2000 Signature box.
@@ -237,26 +482,29 @@ impl JBox for SignatureBox {
     }
 
     // The contents of this box shall be the 4-byte character string ‘<CR><LF><0x87><LF>’ (0x0D0A 870A).
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
         self.length = 12;
 
         let mut buffer: [u8; 4] = [0; 4];
 
-        reader.read_exact(&mut buffer)?;
+        reader.read_exact(&mut buffer).at(self.offset)?;
 
         if buffer != SIGNATURE_MAGIC {
             return Err(JP2Error::InvalidSignature {
                 signature: buffer,
-                offset: reader.stream_position()?,
-            }
-            .into());
+                offset: reader.stream_position().at(self.offset)?,
+            });
         };
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), SIGNATURE_MAGIC.len() as u64)
+            .at(self.offset)?;
+        writer.write_all(&SIGNATURE_MAGIC).at(self.offset)?;
+        Ok(())
+    }
 }
 
 type CompatibilityList = Vec<[u8; 4]>;
@@ -286,6 +534,20 @@ pub struct FileTypeBox {
 }
 
 impl FileTypeBox {
+    /// Create a File Type box for encoding.
+    ///
+    /// The compatibility list is written in the given order, it should
+    /// contain ‘jp2\040’ for the file to be readable as a JP2 file.
+    pub fn new(brand: [u8; 4], min_version: u32, compatibility_list: Vec<[u8; 4]>) -> FileTypeBox {
+        FileTypeBox {
+            length: 8 + 4 * compatibility_list.len() as u64,
+            offset: 0,
+            brand,
+            min_version: min_version.to_be_bytes(),
+            compatibility_list,
+        }
+    }
+
     /// Brand.
     ///
     /// This field specifies the Recommendation | International Standard which
@@ -330,9 +592,35 @@ impl FileTypeBox {
     pub fn compatibility_list(&self) -> Vec<String> {
         self.compatibility_list
             .iter()
-            .map(|c| str::from_utf8(c).unwrap().to_owned())
+            .map(|c| String::from_utf8_lossy(c).into_owned())
             .collect()
     }
+
+    // Read the fields of the box, the Compatibility list is checked by the
+    // file format being decoded.
+    fn decode_fields<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader.read_exact(&mut self.brand).at(self.offset)?;
+        if self.brand != BRAND_JP2 && self.brand != BRAND_JPX {
+            return Err(JP2Error::InvalidBrand {
+                brand: self.brand,
+                offset: reader.stream_position().at(self.offset)?,
+            });
+        }
+
+        reader.read_exact(&mut self.min_version).at(self.offset)?;
+
+        let mut buffer: [u8; 4] = [0; 4];
+
+        // The number of CL fields is determined by the length of this box
+        let mut size = length_after(self, 8)? / 4;
+        while size > 0 {
+            reader.read_exact(&mut buffer).at(self.offset)?;
+            self.compatibility_list.extend_from_slice(&[buffer]);
+            size -= 1;
+        }
+
+        Ok(())
+    }
 }
 
 impl JBox for FileTypeBox {
@@ -349,44 +637,31 @@ impl JBox for FileTypeBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.brand)?;
-        if self.brand == BRAND_JPX {
-            return Err(JP2Error::Unsupported {}.into());
-        } else if self.brand != BRAND_JP2 {
-            return Err(JP2Error::InvalidBrand {
-                brand: self.brand,
-                offset: reader.stream_position()?,
-            }
-            .into());
-        }
-
-        reader.read_exact(&mut self.min_version)?;
-
-        let mut buffer: [u8; 4] = [0; 4];
-
-        // The number of CL fields is determined by the length of this box
-        let mut size = (self.length() - 8) / 4;
-        while size > 0 {
-            reader.read_exact(&mut buffer)?;
-            self.compatibility_list.extend_from_slice(&[buffer]);
-            size -= 1;
-        }
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.decode_fields(reader)?;
 
         // A file shall have at least one CL field in the File Type box, and shall contain the value‘jp2\040’ in one of the CL fields in the File Type box, and all conforming readers shall properly interpret all files with ‘jp2\040’ in one of the CL fields.
         // Other values of the Compatibility list field are reserved for ISO use.
         if !self.compatibility_list.contains(&BRAND_JP2) {
             return Err(JP2Error::NotCompatible {
                 compatibility_list: self.compatibility_list().clone(),
-            }
-            .into());
+                offset: self.offset,
+            });
         }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let length = 8 + 4 * self.compatibility_list.len() as u64;
+        encode_box_header(writer, self.identifier(), length).at(self.offset)?;
+        writer.write_all(&self.brand).at(self.offset)?;
+        writer.write_all(&self.min_version).at(self.offset)?;
+        for compatibility in &self.compatibility_list {
+            writer.write_all(compatibility).at(self.offset)?;
+        }
+        Ok(())
+    }
 }
 
 // I.5.3
@@ -447,6 +722,13 @@ pub struct HeaderSuperBox {
     pub component_mapping_box: Option<ComponentMappingBox>,
     pub channel_definition_box: Option<ChannelDefinitionBox>,
     pub resolution_box: Option<ResolutionSuperBox>,
+
+    // Boxes of other types within the JP2 Header box, kept so they can be
+    // written back.
+    pub unknown_boxes: Vec<UnknownBox>,
+
+    // Offsets of Image Header boxes after the first, which are ignored.
+    ignored_image_headers: Vec<u64>,
 }
 
 impl JBox for HeaderSuperBox {
@@ -463,50 +745,53 @@ impl JBox for HeaderSuperBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
         let BoxHeader {
             box_length,
             box_type,
-            header_length: _,
+            offset,
+            ..
         } = decode_box_header(reader)?;
 
         if box_type != self.image_header_box.identifier() {
-            return Err(JP2Error::BoxUnexpected {
-                box_type,
-                offset: reader.stream_position()?,
-            }
-            .into());
+            return Err(JP2Error::BoxUnexpected { box_type, offset });
         }
         self.image_header_box.length = box_length;
-        self.image_header_box.offset = reader.stream_position()?;
+        self.image_header_box.offset = offset;
         info!("ImageHeaderBox start at {:?}", self.image_header_box.offset);
         self.image_header_box.decode(reader)?;
-        info!("ImageHeaderBox finish at {:?}", reader.stream_position()?);
+        info!(
+            "ImageHeaderBox finish at {:?}",
+            reader.stream_position().at(offset)?
+        );
 
         loop {
             let BoxHeader {
                 box_length,
                 box_type,
                 header_length,
+                offset,
+                ..
             } = decode_box_header(reader)?;
 
             match BoxTypes::new(box_type) {
                 BoxTypes::ImageHeader => {
                     // Instances of Image Header box in other places in the file shall be ignored.
-                    warn!("ImageHeaderBox found in other place, ignoring");
+                    self.ignored_image_headers.push(offset);
+                    reader
+                        .seek(io::SeekFrom::Current(box_length as i64))
+                        .at(offset)?;
                 }
                 BoxTypes::ColourSpecification => {
                     let mut colour_specification_box = ColourSpecificationBox {
                         length: box_length,
-                        offset: reader.stream_position()?,
+                        offset,
                         method: [0; 1],
                         precedence: [0; 1],
                         colourspace_approximation: [0; 1],
                         enumerated_colour_space: ENUMERATED_COLOUR_SPACE_UNKNOWN,
                         restricted_icc_profile: vec![],
+                        reserved: vec![],
                     };
                     info!(
                         "ColourSpecificationBox start at {:?}",
@@ -517,7 +802,7 @@ impl JBox for HeaderSuperBox {
                         .push(colour_specification_box);
                     info!(
                         "ColourSpecificationBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                 }
                 BoxTypes::BitsPerComponent => {
@@ -525,16 +810,15 @@ impl JBox for HeaderSuperBox {
                     if self.bits_per_component_box.is_some() {
                         return Err(JP2Error::BoxDuplicate {
                             box_type: BOX_TYPE_BITS_PER_COMPONENT,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
                     let components_num = self.image_header_box.components_num();
                     let mut bits_per_component_box = BitsPerComponentBox {
                         components_num,
                         bits_per_component: vec![0; components_num as usize],
                         length: box_length,
-                        offset: reader.stream_position()?,
+                        offset,
                     };
                     info!(
                         "BitsPerComponentBox start at {:?}",
@@ -544,7 +828,7 @@ impl JBox for HeaderSuperBox {
                     self.bits_per_component_box = Some(bits_per_component_box);
                     info!(
                         "BitsPerComponentBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                 }
                 BoxTypes::Palette => {
@@ -552,31 +836,32 @@ impl JBox for HeaderSuperBox {
                     if self.palette_box.is_some() {
                         return Err(JP2Error::BoxDuplicate {
                             box_type: BOX_TYPE_PALETTE,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
                     let mut palette_box = PaletteBox::default();
                     palette_box.length = box_length;
-                    palette_box.offset = reader.stream_position()?;
+                    palette_box.offset = offset;
                     info!("PaletteBox start at {:?}", palette_box.offset);
                     palette_box.decode(reader)?;
                     self.palette_box = Some(palette_box);
-                    info!("PaletteBox finish at {:?}", reader.stream_position()?);
+                    info!(
+                        "PaletteBox finish at {:?}",
+                        reader.stream_position().at(offset)?
+                    );
                 }
                 BoxTypes::ComponentMapping => {
                     // There shall be at most one Component Mapping box inside a JP2 Header box.
                     if self.component_mapping_box.is_some() {
                         return Err(JP2Error::BoxDuplicate {
                             box_type: BOX_TYPE_COMPONENT_MAPPING,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
 
                     let mut component_mapping_box = ComponentMappingBox {
                         length: box_length,
-                        offset: reader.stream_position()?,
+                        offset,
                         mapping: vec![],
                     };
                     info!(
@@ -586,7 +871,7 @@ impl JBox for HeaderSuperBox {
                     component_mapping_box.decode(reader)?;
                     info!(
                         "ComponentMappingBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                     self.component_mapping_box = Some(component_mapping_box);
                 }
@@ -595,14 +880,13 @@ impl JBox for HeaderSuperBox {
                     if self.channel_definition_box.is_some() {
                         return Err(JP2Error::BoxDuplicate {
                             box_type: BOX_TYPE_CHANNEL_DEFINITION,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
 
                     let mut channel_definition_box = ChannelDefinitionBox::default();
                     channel_definition_box.length = box_length;
-                    channel_definition_box.offset = reader.stream_position()?;
+                    channel_definition_box.offset = offset;
                     info!(
                         "ChannelDefinitionBox start at {:?}",
                         channel_definition_box.offset
@@ -610,7 +894,7 @@ impl JBox for HeaderSuperBox {
                     channel_definition_box.decode(reader)?;
                     info!(
                         "ChannelDefinitionBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                     self.channel_definition_box = Some(channel_definition_box);
                 }
@@ -619,32 +903,48 @@ impl JBox for HeaderSuperBox {
                     if self.resolution_box.is_some() {
                         return Err(JP2Error::BoxDuplicate {
                             box_type: BOX_TYPE_RESOLUTION,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
 
                     let mut resolution_box = ResolutionSuperBox::default();
                     resolution_box.length = box_length;
-                    resolution_box.offset = reader.stream_position()?;
+                    resolution_box.offset = offset;
                     info!("ResolutionBox start at {:?}", resolution_box.offset);
                     resolution_box.decode(reader)?;
-                    info!("ResolutionBox finish at {:?}", reader.stream_position()?);
+                    info!(
+                        "ResolutionBox finish at {:?}",
+                        reader.stream_position().at(offset)?
+                    );
                     self.resolution_box = Some(resolution_box);
                 }
 
                 BoxTypes::Unknown => {
-                    warn!(
-                        "Unknown box type 2 {:?} {:?}",
-                        reader.stream_position(),
-                        box_type
-                    );
-                    break;
+                    // Boxes of other types within the JP2 Header box shall be
+                    // ignored, they are kept so they can be written back.
+                    // Those after it are left for decode_jp2.
+                    if offset.saturating_add(box_length) > self.offset + self.length {
+                        reader
+                            .seek(io::SeekFrom::Current(-(header_length as i64)))
+                            .at(offset)?;
+                        break;
+                    }
+                    warn!("Unknown box type {:?} at {:?}", box_type, offset);
+                    let mut unknown_box = UnknownBox {
+                        length: box_length,
+                        offset,
+                        box_type,
+                        data: None,
+                    };
+                    unknown_box.data = Some(unknown_box.read_data(reader)?);
+                    self.unknown_boxes.push(unknown_box);
                 }
 
                 // End of header but recognised new box type
                 _ => {
-                    reader.seek(io::SeekFrom::Current(-(header_length as i64)))?;
+                    reader
+                        .seek(io::SeekFrom::Current(-(header_length as i64)))
+                        .at(offset)?;
                     break;
                 }
             }
@@ -655,9 +955,8 @@ impl JBox for HeaderSuperBox {
         if self.colour_specification_boxes.is_empty() {
             return Err(JP2Error::BoxMalformed {
                 box_type: BOX_TYPE_IMAGE_HEADER,
-                offset: reader.stream_position()?,
-            }
-            .into());
+                offset: self.offset,
+            });
         }
 
         // TODO
@@ -665,6 +964,56 @@ impl JBox for HeaderSuperBox {
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        // The contents of the JP2 Header box shall start with an Image Header box.
+        let mut contents: Vec<u8> = vec![];
+        self.image_header_box.encode(&mut contents)?;
+
+        let mut boxes: Vec<(u64, Vec<u8>)> = vec![];
+        if let Some(bits_per_component_box) = &self.bits_per_component_box {
+            boxes.push(encode_to_vec(bits_per_component_box)?);
+        }
+        for colour_specification_box in &self.colour_specification_boxes {
+            boxes.push(encode_to_vec(colour_specification_box)?);
+        }
+        if let Some(palette_box) = &self.palette_box {
+            boxes.push(encode_to_vec(palette_box)?);
+        }
+        if let Some(component_mapping_box) = &self.component_mapping_box {
+            boxes.push(encode_to_vec(component_mapping_box)?);
+        }
+        if let Some(channel_definition_box) = &self.channel_definition_box {
+            boxes.push(encode_to_vec(channel_definition_box)?);
+        }
+        if let Some(resolution_box) = &self.resolution_box {
+            boxes.push(encode_to_vec(resolution_box)?);
+        }
+        for unknown_box in &self.unknown_boxes {
+            boxes.push(encode_to_vec(unknown_box)?);
+        }
+        sort_encoded_boxes(&mut boxes);
+        for (_, encoded) in boxes {
+            contents.extend_from_slice(&encoded);
+        }
+
+        encode_box_header(writer, self.identifier(), contents.len() as u64).at(self.offset)?;
+        writer.write_all(&contents).at(self.offset)?;
+        Ok(())
+    }
+}
+
+impl HeaderSuperBox {
+    /// Create a JP2 Header box for encoding.
+    ///
+    /// The optional boxes can be set through the public fields, there shall be
+    /// at least one Colour Specification box.
+    pub fn new(image_header_box: ImageHeaderBox) -> HeaderSuperBox {
+        HeaderSuperBox {
+            image_header_box,
+            ..Default::default()
+        }
+    }
 }
 
 const COMPRESSION_TYPE_WAVELET: u8 = 7;
@@ -706,6 +1055,32 @@ pub struct ImageHeaderBox {
 }
 
 impl ImageHeaderBox {
+    /// Create an Image Header box for encoding.
+    ///
+    /// The `components_bits` value is encoded as the BPC field, the bit depth
+    /// minus 1 with the high-bit set for signed components, or 255 if the
+    /// components vary in bit depth.
+    pub fn new(
+        height: u32,
+        width: u32,
+        components_num: u16,
+        components_bits: u8,
+        colourspace_unknown: u8,
+        intellectual_property: u8,
+    ) -> ImageHeaderBox {
+        ImageHeaderBox {
+            length: 14,
+            offset: 0,
+            height: height.to_be_bytes(),
+            width: width.to_be_bytes(),
+            components_num: components_num.to_be_bytes(),
+            components_bits: [components_bits],
+            compression_type: [COMPRESSION_TYPE_WAVELET],
+            colourspace_unknown: [colourspace_unknown],
+            intellectual_property: [intellectual_property],
+        }
+    }
+
     // Image area height.
     //
     // The value of this parameter indicates the height of the image area.
@@ -779,13 +1154,9 @@ impl ImageHeaderBox {
             // x000 0000 — x010 0101 Component bit depth = value + 1. From 1 bit
             // deep through 38 bits deep respectively (counting the sign bit, if
             // appropriate)
-            let low_bits = self.components_bits[0] & 0b0111_1111;
-            if low_bits <= 37 {
-                low_bits + 1
-            } else {
-                // All other values reserved for ISO use.
-                todo!("reserved");
-            }
+            // appropriate), all other values are reserved for ISO use and
+            // rejected by decode.
+            (self.components_bits[0] & 0b0111_1111) + 1
         }
     }
 
@@ -870,20 +1241,57 @@ impl JBox for ImageHeaderBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.height)?;
-        reader.read_exact(&mut self.width)?;
-        reader.read_exact(&mut self.components_num)?;
-        reader.read_exact(&mut self.components_bits)?;
-        reader.read_exact(&mut self.compression_type)?;
-        reader.read_exact(&mut self.colourspace_unknown)?;
-        reader.read_exact(&mut self.intellectual_property)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        if self.length != 14 {
+            return Err(JP2Error::BoxMalformed {
+                box_type: self.identifier(),
+                offset: self.offset,
+            });
+        }
+        reader.read_exact(&mut self.height).at(self.offset)?;
+        reader.read_exact(&mut self.width).at(self.offset)?;
+        reader
+            .read_exact(&mut self.components_num)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.components_bits)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.compression_type)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.colourspace_unknown)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.intellectual_property)
+            .at(self.offset)?;
+
+        // Bit depths above 38 bits are reserved for ISO use
+        if self.components_bits[0] != 255 && self.components_bits[0] & 0b0111_1111 > 37 {
+            return Err(JP2Error::BoxMalformed {
+                box_type: self.identifier(),
+                offset: self.offset,
+            });
+        }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 14).at(self.offset)?;
+        writer.write_all(&self.height).at(self.offset)?;
+        writer.write_all(&self.width).at(self.offset)?;
+        writer.write_all(&self.components_num).at(self.offset)?;
+        writer.write_all(&self.components_bits).at(self.offset)?;
+        writer.write_all(&self.compression_type).at(self.offset)?;
+        writer
+            .write_all(&self.colourspace_unknown)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.intellectual_property)
+            .at(self.offset)?;
+        Ok(())
+    }
 }
 
 // I.5.3.6
@@ -922,6 +1330,15 @@ pub struct ChannelDefinitionBox {
 }
 
 impl ChannelDefinitionBox {
+    /// Create a Channel Definition box for encoding.
+    pub fn new(channels: Vec<Channel>) -> ChannelDefinitionBox {
+        ChannelDefinitionBox {
+            length: 2 + 6 * channels.len() as u64,
+            offset: 0,
+            channels,
+        }
+    }
+
     pub fn channels(&self) -> &Vec<Channel> {
         &self.channels
     }
@@ -962,6 +1379,14 @@ pub struct Channel {
 }
 
 impl Channel {
+    pub fn new(channel_index: u16, channel_type: u16, channel_association: u16) -> Channel {
+        Channel {
+            channel_index: channel_index.to_be_bytes(),
+            channel_type: channel_type.to_be_bytes(),
+            channel_association: channel_association.to_be_bytes(),
+        }
+    }
+
     pub fn channel_index(&self) -> u16 {
         u16::from_be_bytes(self.channel_index)
     }
@@ -986,9 +1411,9 @@ impl Channel {
 
 const CHANNEL_TYPE_COLOUR_IMAGE_DATA: u16 = 0;
 const CHANNEL_TYPE_OPACITY_DATA: u16 = 1;
-const CHANNEL_TYPE_PREMULTIPLIED_OPACITY: u16 = 3;
+const CHANNEL_TYPE_PREMULTIPLIED_OPACITY: u16 = 2;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ChannelTypes {
     ColourImageData,
     Opacity,
@@ -1032,16 +1457,15 @@ impl JBox for ChannelDefinitionBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
         // Number of channel descriptions. This field specifies the number of
         // channel descriptions in this box. This field is encoded as a 2-byte
         // big endian unsigned integer.
         let mut no_channel_descriptions: [u8; 2] = [0; 2];
 
-        reader.read_exact(&mut no_channel_descriptions)?;
+        reader
+            .read_exact(&mut no_channel_descriptions)
+            .at(self.offset)?;
 
         let mut size = u16::from_be_bytes(no_channel_descriptions);
 
@@ -1049,9 +1473,15 @@ impl JBox for ChannelDefinitionBox {
 
         while size > 0 {
             let mut channel = Channel::default();
-            reader.read_exact(&mut channel.channel_index)?;
-            reader.read_exact(&mut channel.channel_type)?;
-            reader.read_exact(&mut channel.channel_association)?;
+            reader
+                .read_exact(&mut channel.channel_index)
+                .at(self.offset)?;
+            reader
+                .read_exact(&mut channel.channel_type)
+                .at(self.offset)?;
+            reader
+                .read_exact(&mut channel.channel_association)
+                .at(self.offset)?;
 
             debug!(
                 "Found channel at index {:?} of type {:?} and association {:?}",
@@ -1069,10 +1499,31 @@ impl JBox for ChannelDefinitionBox {
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let num_channels =
+            u16::try_from(self.channels.len()).map_err(|_| JP2Error::BoxMalformed {
+                box_type: self.identifier(),
+                offset: self.offset,
+            })?;
+        let length = 2 + 6 * self.channels.len() as u64;
+        encode_box_header(writer, self.identifier(), length).at(self.offset)?;
+        writer
+            .write_all(&num_channels.to_be_bytes())
+            .at(self.offset)?;
+        for channel in &self.channels {
+            writer.write_all(&channel.channel_index).at(self.offset)?;
+            writer.write_all(&channel.channel_type).at(self.offset)?;
+            writer
+                .write_all(&channel.channel_association)
+                .at(self.offset)?;
+        }
+        Ok(())
+    }
 }
 
-const COMPONENT_MAP_TYPE_DIRECT: [u8; 1] = [1];
-const COMPONENT_MAP_TYPE_PALETTE: [u8; 1] = [2];
+const COMPONENT_MAP_TYPE_DIRECT: [u8; 1] = [0];
+const COMPONENT_MAP_TYPE_PALETTE: [u8; 1] = [1];
 
 #[derive(Debug)]
 pub enum ComponentMapType {
@@ -1132,6 +1583,14 @@ pub struct ComponentMap {
 }
 
 impl ComponentMap {
+    pub fn new(component: u16, mapping_type: u8, palette: u8) -> ComponentMap {
+        ComponentMap {
+            component: component.to_be_bytes(),
+            mapping_type: ComponentMapType::new([mapping_type]),
+            palette: [palette],
+        }
+    }
+
     pub fn component(&self) -> u16 {
         u16::from_be_bytes(self.component)
     }
@@ -1145,6 +1604,12 @@ impl ComponentMap {
     pub fn palette(&self) -> u8 {
         self.palette[0]
     }
+
+    /// Whether the channel is created by applying the palette to the
+    /// component, rather than using the component directly.
+    pub fn is_palette_mapping(&self) -> bool {
+        matches!(self.mapping_type, ComponentMapType::Palette)
+    }
 }
 
 /// Component Mapping Box.
@@ -1182,6 +1647,15 @@ pub struct ComponentMappingBox {
 }
 
 impl ComponentMappingBox {
+    /// Create a Component Mapping box for encoding.
+    pub fn new(mapping: Vec<ComponentMap>) -> ComponentMappingBox {
+        ComponentMappingBox {
+            length: 4 * mapping.len() as u64,
+            offset: 0,
+            mapping,
+        }
+    }
+
     pub fn component_map(&self) -> &Vec<ComponentMap> {
         &self.mapping
     }
@@ -1200,10 +1674,7 @@ impl JBox for ComponentMappingBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
         let mut index = 0;
         while index < self.length {
             let mut component_map = ComponentMap {
@@ -1211,13 +1682,17 @@ impl JBox for ComponentMappingBox {
                 palette: [0; 1],
                 mapping_type: ComponentMapType::new([255]),
             };
-            reader.read_exact(&mut component_map.component)?;
+            reader
+                .read_exact(&mut component_map.component)
+                .at(self.offset)?;
 
             let mut mapping_type: [u8; 1] = [0; 1];
-            reader.read_exact(&mut mapping_type)?;
+            reader.read_exact(&mut mapping_type).at(self.offset)?;
             component_map.mapping_type = ComponentMapType::new(mapping_type);
 
-            reader.read_exact(&mut component_map.palette)?;
+            reader
+                .read_exact(&mut component_map.palette)
+                .at(self.offset)?;
 
             self.mapping.push(component_map);
             index += 4;
@@ -1225,6 +1700,19 @@ impl JBox for ComponentMappingBox {
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 4 * self.mapping.len() as u64)
+            .at(self.offset)?;
+        for component_map in &self.mapping {
+            writer.write_all(&component_map.component).at(self.offset)?;
+            writer
+                .write_all(&[component_map.mapping_type()])
+                .at(self.offset)?;
+            writer.write_all(&component_map.palette).at(self.offset)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -1260,6 +1748,9 @@ pub struct GeneratedComponent {
     //
     // For example, if the value of Bi is 10 bits, then the individual Cji
     // values shall be stored in the low 10 bits of a 16 bit field.
+    //
+    // The padded values of this component are stored one after the other, in
+    // big endian order.
     values: Vec<u8>,
 }
 
@@ -1296,6 +1787,15 @@ impl BitDepth {
 }
 
 impl GeneratedComponent {
+    /// Create a generated component from its B<sup>i</sup> bit depth byte and
+    /// palette entries.
+    pub fn new(bit_depth: u8, values: Vec<u8>) -> GeneratedComponent {
+        GeneratedComponent {
+            bit_depth: [bit_depth],
+            values,
+        }
+    }
+
     pub fn bit_depth(&self) -> BitDepth {
         BitDepth::new(self.bit_depth[0])
     }
@@ -1303,6 +1803,36 @@ impl GeneratedComponent {
     pub fn values(&self) -> &Vec<u8> {
         &self.values
     }
+
+    /// Number of bytes of each padded value.
+    fn value_length(&self) -> usize {
+        (self.bit_depth().value() as usize).div_ceil(8)
+    }
+
+    /// Number of entries of the component.
+    pub fn num_entries(&self) -> usize {
+        self.values.len() / self.value_length()
+    }
+
+    /// The value of entry j, sign extended for signed components.
+    pub fn entry(&self, j: usize) -> Option<i32> {
+        let value_length = self.value_length();
+        let bytes = self.values.get(j * value_length..(j + 1) * value_length)?;
+        let value = bytes
+            .iter()
+            .fold(0u64, |value, byte| value << 8 | *byte as u64);
+        let bit_depth = self.bit_depth();
+        if bit_depth.value() > 32 {
+            return None;
+        }
+        let value = value & ((1u64 << bit_depth.value()) - 1);
+        match bit_depth {
+            BitDepth::Signed { value: bits } if value >> (bits - 1) & 1 == 1 => {
+                Some((value as i64 - (1i64 << bits)) as i32)
+            }
+            _ => Some(value as i32),
+        }
+    }
 }
 
 /// Palette box.
@@ -1347,6 +1877,49 @@ pub struct PaletteBox {
 }
 
 impl PaletteBox {
+    /// Create a Palette box for encoding.
+    ///
+    /// The number of entries is taken from the first generated component, all
+    /// generated components shall have the same number of entries.
+    ///
+    /// Fails if the generated components differ in their number of entries,
+    /// or there are more than 1024 entries or 255 generated components.
+    pub fn new(generated_components: Vec<GeneratedComponent>) -> Result<PaletteBox, JP2Error> {
+        let malformed = JP2Error::BoxMalformed {
+            box_type: BOX_TYPE_PALETTE,
+            offset: 0,
+        };
+        let num_entries = generated_components
+            .first()
+            .map_or(0, |generated_component| generated_component.num_entries());
+        if generated_components
+            .iter()
+            .any(|generated_component| generated_component.num_entries() != num_entries)
+        {
+            return Err(malformed);
+        }
+        let num_entries = match u16::try_from(num_entries) {
+            Ok(num_entries) if num_entries <= 1024 => num_entries,
+            _ => return Err(malformed),
+        };
+        let num_components = match u8::try_from(generated_components.len()) {
+            Ok(num_components) => num_components,
+            Err(_) => return Err(malformed),
+        };
+        Ok(PaletteBox {
+            length: 3
+                + generated_components.len() as u64
+                + generated_components
+                    .iter()
+                    .map(|generated_component| generated_component.values.len() as u64)
+                    .sum::<u64>(),
+            offset: 0,
+            num_entries: num_entries.to_be_bytes(),
+            num_components: [num_components],
+            generated_components,
+        })
+    }
+
     pub fn num_entries(&self) -> u16 {
         u16::from_be_bytes(self.num_entries)
     }
@@ -1373,12 +1946,11 @@ impl JBox for PaletteBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.num_entries)?;
-        reader.read_exact(&mut self.num_components)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader.read_exact(&mut self.num_entries).at(self.offset)?;
+        reader
+            .read_exact(&mut self.num_components)
+            .at(self.offset)?;
 
         let num_entries = self.num_entries() as usize;
         self.generated_components = vec![
@@ -1389,21 +1961,58 @@ impl JBox for PaletteBox {
             self.num_components() as usize
         ];
         for generated_component in &mut self.generated_components {
-            reader.read_exact(&mut generated_component.bit_depth)?;
+            reader
+                .read_exact(&mut generated_component.bit_depth)
+                .at(self.offset)?;
         }
 
-        for generated_component in &mut self.generated_components {
-            let mut j = 0;
-            while j < num_entries {
-                let mut entry: [u8; 1] = [0; 1];
-                reader.read_exact(&mut entry)?;
-                generated_component.values.push(entry[0]);
-                j += 1;
+        // The values of all generated components for entry j are grouped
+        // together, followed by those of entry j+1.
+        let mut j = 0;
+        while j < num_entries {
+            for generated_component in &mut self.generated_components {
+                let mut entry: Vec<u8> = vec![0; generated_component.value_length()];
+                reader.read_exact(&mut entry).at(self.offset)?;
+                generated_component.values.extend_from_slice(&entry);
             }
+            j += 1;
         }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let length = 3 + self
+            .generated_components
+            .iter()
+            .map(|generated_component| 1 + generated_component.values.len() as u64)
+            .sum::<u64>();
+        encode_box_header(writer, self.identifier(), length).at(self.offset)?;
+        writer.write_all(&self.num_entries).at(self.offset)?;
+        writer.write_all(&self.num_components).at(self.offset)?;
+        for generated_component in &self.generated_components {
+            writer
+                .write_all(&generated_component.bit_depth)
+                .at(self.offset)?;
+        }
+        for j in 0..self.num_entries() as usize {
+            for generated_component in &self.generated_components {
+                let value_length = generated_component.value_length();
+                let value = generated_component
+                    .values
+                    .get(j * value_length..(j + 1) * value_length)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "generated components have different numbers of entries",
+                        )
+                    })
+                    .at(self.offset)?;
+                writer.write_all(value).at(self.offset)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 // I.5.3.2
@@ -1441,6 +2050,17 @@ pub struct BitsPerComponentBox {
     bits_per_component: Vec<u8>,
 }
 impl BitsPerComponentBox {
+    /// Create a Bits Per Component box for encoding from the BPC<sup>i</sup>
+    /// bytes of each component.
+    pub fn new(bits_per_component: Vec<u8>) -> BitsPerComponentBox {
+        BitsPerComponentBox {
+            length: bits_per_component.len() as u64,
+            offset: 0,
+            components_num: bits_per_component.len() as u16,
+            bits_per_component,
+        }
+    }
+
     pub fn bits_per_component(&self) -> Vec<BitDepth> {
         self.bits_per_component
             .iter()
@@ -1462,11 +2082,21 @@ impl JBox for BitsPerComponentBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.bits_per_component)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader
+            .read_exact(&mut self.bits_per_component)
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(
+            writer,
+            self.identifier(),
+            self.bits_per_component.len() as u64,
+        )
+        .at(self.offset)?;
+        writer.write_all(&self.bits_per_component).at(self.offset)?;
         Ok(())
     }
 }
@@ -1557,9 +2187,43 @@ pub struct ColourSpecificationBox {
     colourspace_approximation: [u8; 1],
     enumerated_colour_space: EnumeratedColourSpace,
     restricted_icc_profile: Vec<u8>,
+
+    // Fields following the APPROX field for other methods, kept so the box
+    // can be written back unchanged.
+    reserved: Vec<u8>,
 }
 
 impl ColourSpecificationBox {
+    /// Create a Colour Specification box for encoding, using an enumerated
+    /// colourspace.
+    pub fn new_enumerated_colour_space(enumerated_colour_space: u32) -> ColourSpecificationBox {
+        ColourSpecificationBox {
+            length: 7,
+            offset: 0,
+            method: METHOD_ENUMERATED_COLOUR_SPACE,
+            precedence: [0],
+            colourspace_approximation: [0],
+            enumerated_colour_space: enumerated_colour_space.to_be_bytes(),
+            restricted_icc_profile: vec![],
+            reserved: vec![],
+        }
+    }
+
+    /// Create a Colour Specification box for encoding, using a restricted ICC
+    /// profile.
+    pub fn new_restricted_icc_profile(restricted_icc_profile: Vec<u8>) -> ColourSpecificationBox {
+        ColourSpecificationBox {
+            length: 3 + restricted_icc_profile.len() as u64,
+            offset: 0,
+            method: METHOD_ENUMERATED_RESTRICTED_ICC_PROFILE,
+            precedence: [0],
+            colourspace_approximation: [0],
+            enumerated_colour_space: ENUMERATED_COLOUR_SPACE_UNKNOWN,
+            restricted_icc_profile,
+            reserved: vec![],
+        }
+    }
+
     // Specification method.
     //
     // This field specifies the method used by this Colour Specification box to
@@ -1611,6 +2275,25 @@ impl ColourSpecificationBox {
     pub fn enumerated_colour_space(&self) -> Option<u32> {
         Some(u32::from_be_bytes(self.enumerated_colour_space))
     }
+
+    /// The ICC profile of the PROFILE field, when the value of the METH field
+    /// is 2.
+    pub fn restricted_icc_profile(&self) -> Option<&[u8]> {
+        match self.method() {
+            ColourSpecificationMethods::RestrictedICCProfile => Some(&self.restricted_icc_profile),
+            _ => None,
+        }
+    }
+
+    /// Check that the ICC profile of the PROFILE field conforms to either the
+    /// Monochrome Input or Three-Component Matrix-Based Input profile class,
+    /// see I.3.2, when the value of the METH field is 2.
+    pub fn check_restricted_icc_profile(
+        &self,
+    ) -> Option<Result<RestrictedProfileType, RestrictedProfileError>> {
+        self.restricted_icc_profile()
+            .map(icc::check_restricted_profile)
+    }
 }
 
 impl JBox for ColourSpecificationBox {
@@ -1627,23 +2310,12 @@ impl JBox for ColourSpecificationBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.method)?;
-        reader.read_exact(&mut self.precedence)?;
-        reader.read_exact(&mut self.colourspace_approximation)?;
-
-        if self.precedence() != 0 {
-            warn!("Precedence {:?} Unexpected", self.precedence());
-        }
-        if self.colourspace_approximation() != 0 {
-            warn!(
-                "Colourspace Approximation {:?} unexpected",
-                self.colourspace_approximation()
-            );
-        }
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader.read_exact(&mut self.method).at(self.offset)?;
+        reader.read_exact(&mut self.precedence).at(self.offset)?;
+        reader
+            .read_exact(&mut self.colourspace_approximation)
+            .at(self.offset)?;
 
         debug!("Method {:?}", self.method());
         debug!("Precedence {:?}", self.precedence());
@@ -1665,7 +2337,9 @@ impl JBox for ColourSpecificationBox {
             ColourSpecificationMethods::EnumeratedColourSpace => {
                 // TODO: Validate this box exists if METH field is 1 and is
                 // immediately following the APPROX field and the last field.
-                reader.read_exact(&mut self.enumerated_colour_space)?;
+                reader
+                    .read_exact(&mut self.enumerated_colour_space)
+                    .at(self.offset)?;
                 debug!("Enumerated Colour Space {:?}", self.enumerated_colour_space);
             }
 
@@ -1688,9 +2362,10 @@ impl JBox for ColourSpecificationBox {
             //
             // If the value of METH is 2, then the PROFILE field shall immediately follow the APPROX field and the PROFILE field shall be the last field in the box.
             ColourSpecificationMethods::RestrictedICCProfile => {
-                let mut restricted_icc_profile: Vec<u8> = vec![0; self.length as usize - 3];
-
-                reader.read_exact(&mut restricted_icc_profile)?;
+                // After METH, PREC and APPROX
+                let profile_length = length_after(self, 3)?;
+                self.restricted_icc_profile =
+                    read_vec(reader, profile_length as usize).at(self.offset)?;
                 debug!("Restricted ICC Profile");
             }
 
@@ -1698,11 +2373,29 @@ impl JBox for ColourSpecificationBox {
             // entire Colour Specification box.
             ColourSpecificationMethods::Reserved { value } => {
                 debug!("Reserved method {}", value[0]);
+                let reserved_length = length_after(self, 3)?;
+                self.reserved = read_vec(reader, reserved_length as usize).at(self.offset)?;
             }
         }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let contents: &[u8] = match self.method() {
+            ColourSpecificationMethods::EnumeratedColourSpace => &self.enumerated_colour_space,
+            ColourSpecificationMethods::RestrictedICCProfile => &self.restricted_icc_profile,
+            ColourSpecificationMethods::Reserved { value: _ } => &self.reserved,
+        };
+        encode_box_header(writer, self.identifier(), 3 + contents.len() as u64).at(self.offset)?;
+        writer.write_all(&self.method).at(self.offset)?;
+        writer.write_all(&self.precedence).at(self.offset)?;
+        writer
+            .write_all(&self.colourspace_approximation)
+            .at(self.offset)?;
+        writer.write_all(contents).at(self.offset)?;
+        Ok(())
+    }
 }
 
 // I.5.3.7
@@ -1728,6 +2421,22 @@ pub struct ResolutionSuperBox {
     default_display_resolution_box: Option<DefaultDisplayResolutionBox>,
 }
 impl ResolutionSuperBox {
+    /// Create a Resolution box for encoding.
+    ///
+    /// At least one of the Capture Resolution box or the Default Display
+    /// Resolution box shall be given.
+    pub fn new(
+        capture_resolution_box: Option<CaptureResolutionBox>,
+        default_display_resolution_box: Option<DefaultDisplayResolutionBox>,
+    ) -> ResolutionSuperBox {
+        ResolutionSuperBox {
+            length: 0,
+            offset: 0,
+            capture_resolution_box,
+            default_display_resolution_box,
+        }
+    }
+
     pub fn capture_resolution_box(&self) -> &Option<CaptureResolutionBox> {
         &self.capture_resolution_box
     }
@@ -1735,6 +2444,20 @@ impl ResolutionSuperBox {
     pub fn default_display_resolution_box(&self) -> &Option<DefaultDisplayResolutionBox> {
         &self.default_display_resolution_box
     }
+
+    pub fn set_capture_resolution_box(
+        &mut self,
+        capture_resolution_box: Option<CaptureResolutionBox>,
+    ) {
+        self.capture_resolution_box = capture_resolution_box;
+    }
+
+    pub fn set_default_display_resolution_box(
+        &mut self,
+        default_display_resolution_box: Option<DefaultDisplayResolutionBox>,
+    ) {
+        self.default_display_resolution_box = default_display_resolution_box;
+    }
 }
 
 impl JBox for ResolutionSuperBox {
@@ -1751,15 +2474,14 @@ impl JBox for ResolutionSuperBox {
     }
 
     // The type of a Resolution box shall be ‘res\040’ (0x7265 7320).
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
         loop {
             let BoxHeader {
                 box_length,
                 box_type,
                 header_length,
+                offset,
+                ..
             } = decode_box_header(reader)?;
 
             match BoxTypes::new(box_type) {
@@ -1767,13 +2489,12 @@ impl JBox for ResolutionSuperBox {
                     if self.capture_resolution_box.is_some() {
                         return Err(JP2Error::BoxUnexpected {
                             box_type: BOX_TYPE_CAPTURE_RESOLUTION,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
                     let mut capture_resolution_box = CaptureResolutionBox::default();
                     capture_resolution_box.length = box_length;
-                    capture_resolution_box.offset = reader.stream_position()?;
+                    capture_resolution_box.offset = offset;
                     info!(
                         "CaptureResolutionBox start at {:?}",
                         capture_resolution_box.offset
@@ -1781,7 +2502,7 @@ impl JBox for ResolutionSuperBox {
                     capture_resolution_box.decode(reader)?;
                     info!(
                         "CaptureResolutionBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                     self.capture_resolution_box = Some(capture_resolution_box);
                 }
@@ -1789,14 +2510,13 @@ impl JBox for ResolutionSuperBox {
                     if self.default_display_resolution_box.is_some() {
                         return Err(JP2Error::BoxUnexpected {
                             box_type: BOX_TYPE_DEFAULT_DISPLAY_RESOLUTION,
-                            offset: reader.stream_position()?,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
 
                     let mut default_display_resolution_box = DefaultDisplayResolutionBox::default();
                     default_display_resolution_box.length = box_length;
-                    default_display_resolution_box.offset = reader.stream_position()?;
+                    default_display_resolution_box.offset = offset;
                     info!(
                         "DisplayResolutionBox start at {:?}",
                         default_display_resolution_box.offset
@@ -1804,14 +2524,16 @@ impl JBox for ResolutionSuperBox {
                     default_display_resolution_box.decode(reader)?;
                     info!(
                         "DisplayResolutionBox finish at {:?}",
-                        reader.stream_position()?
+                        reader.stream_position().at(offset)?
                     );
                     self.default_display_resolution_box = Some(default_display_resolution_box);
                 }
 
                 // End of capture resolution but recognised new box type
                 _ => {
-                    reader.seek(io::SeekFrom::Current(-(header_length as i64)))?;
+                    reader
+                        .seek(io::SeekFrom::Current(-(header_length as i64)))
+                        .at(offset)?;
                     break;
                 }
             }
@@ -1823,10 +2545,25 @@ impl JBox for ResolutionSuperBox {
             return Err(JP2Error::BoxMalformed {
                 box_type: BOX_TYPE_RESOLUTION,
                 offset: self.offset,
-            }
-            .into());
+            });
+        }
+
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut boxes: Vec<(u64, Vec<u8>)> = vec![];
+        if let Some(capture_resolution_box) = &self.capture_resolution_box {
+            boxes.push(encode_to_vec(capture_resolution_box)?);
         }
+        if let Some(default_display_resolution_box) = &self.default_display_resolution_box {
+            boxes.push(encode_to_vec(default_display_resolution_box)?);
+        }
+        sort_encoded_boxes(&mut boxes);
 
+        let contents: Vec<u8> = boxes.into_iter().flat_map(|(_, encoded)| encoded).collect();
+        encode_box_header(writer, self.identifier(), contents.len() as u64).at(self.offset)?;
+        writer.write_all(&contents).at(self.offset)?;
         Ok(())
     }
 }
@@ -1854,9 +2591,24 @@ pub struct IntellectualPropertyBox {
 }
 
 impl IntellectualPropertyBox {
+    /// Create an Intellectual Property box for encoding.
+    pub fn new(data: Vec<u8>) -> IntellectualPropertyBox {
+        IntellectualPropertyBox {
+            length: data.len() as u64,
+            offset: 0,
+            data,
+        }
+    }
+
     /// Get the XML body as a UTF-8 string.
     pub fn format(&self) -> String {
-        str::from_utf8(&self.data).unwrap().to_string()
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+
+    /// Replace the contents of the box, keeping its position in the file.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.length = data.len() as u64;
+        self.data = data;
     }
 }
 
@@ -1874,12 +2626,14 @@ impl JBox for IntellectualPropertyBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        self.data = vec![0; self.length as usize];
-        reader.read_exact(&mut self.data)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.data = read_vec(reader, self.length as usize).at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), self.data.len() as u64).at(self.offset)?;
+        writer.write_all(&self.data).at(self.offset)?;
         Ok(())
     }
 }
@@ -1903,9 +2657,24 @@ pub struct XMLBox {
 }
 
 impl XMLBox {
+    /// Create an XML box for encoding.
+    pub fn new(xml: Vec<u8>) -> XMLBox {
+        XMLBox {
+            length: xml.len() as u64,
+            offset: 0,
+            xml,
+        }
+    }
+
     /// Get the XML body as a UTF-8 string.
     pub fn format(&self) -> String {
-        str::from_utf8(&self.xml).unwrap().to_string()
+        String::from_utf8_lossy(&self.xml).into_owned()
+    }
+
+    /// Replace the XML body, keeping the position of the box in the file.
+    pub fn set_xml(&mut self, xml: Vec<u8>) {
+        self.length = xml.len() as u64;
+        self.xml = xml;
     }
 }
 
@@ -1923,12 +2692,14 @@ impl JBox for XMLBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        self.xml = vec![0; self.length as usize];
-        reader.read_exact(&mut self.xml)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        self.xml = read_vec(reader, self.length as usize).at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), self.xml.len() as u64).at(self.offset)?;
+        writer.write_all(&self.xml).at(self.offset)?;
         Ok(())
     }
 }
@@ -1951,6 +2722,16 @@ pub struct UUIDBox {
 }
 
 impl UUIDBox {
+    /// Create a UUID box for encoding.
+    pub fn new(uuid: [u8; 16], data: Vec<u8>) -> UUIDBox {
+        UUIDBox {
+            length: (uuid.len() + data.len()) as u64,
+            offset: 0,
+            uuid,
+            data,
+        }
+    }
+
     /// Get the UUID for the box.
     ///
     /// This field contains a 16-byte UUID as specified by ISO/IEC 11578. The
@@ -1968,6 +2749,13 @@ impl UUIDBox {
     pub fn data(&self) -> &Vec<u8> {
         &self.data
     }
+
+    /// Replace the vendor-specific information, keeping the UUID and the
+    /// position of the box in the file.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.length = (self.uuid.len() + data.len()) as u64;
+        self.data = data;
+    }
 }
 
 impl JBox for UUIDBox {
@@ -1984,16 +2772,21 @@ impl JBox for UUIDBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.uuid)?;
-        self.data = vec![0; self.length as usize - self.uuid.len()];
-        reader.read_exact(&mut self.data)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        let data_length = length_after(self, self.uuid.len() as u64)?;
+        reader.read_exact(&mut self.uuid).at(self.offset)?;
+        self.data = read_vec(reader, data_length as usize).at(self.offset)?;
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let length = (self.uuid.len() + self.data.len()) as u64;
+        encode_box_header(writer, self.identifier(), length).at(self.offset)?;
+        writer.write_all(&self.uuid).at(self.offset)?;
+        writer.write_all(&self.data).at(self.offset)?;
+        Ok(())
+    }
 }
 
 // I.7.3
@@ -2036,14 +2829,56 @@ impl JBox for UUIDInfoSuperBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        _reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, _reader: &mut R) -> Result<(), JP2Error> {
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let mut contents: Vec<u8> = vec![];
+        for uuid_list_box in &self.uuid_list {
+            uuid_list_box.encode(&mut contents)?;
+        }
+        for data_entry_url_box in &self.data_entry_url_box {
+            data_entry_url_box.encode(&mut contents)?;
+        }
+        encode_box_header(writer, self.identifier(), contents.len() as u64).at(self.offset)?;
+        writer.write_all(&contents).at(self.offset)?;
         Ok(())
     }
 }
 
+impl UUIDInfoSuperBox {
+    /// Create a UUID Info box for encoding.
+    pub fn new(uuid_list: UUIDListBox, data_entry_url_box: DataEntryURLBox) -> UUIDInfoSuperBox {
+        UUIDInfoSuperBox {
+            length: 0,
+            offset: 0,
+            uuid_list: vec![uuid_list],
+            data_entry_url_box: vec![data_entry_url_box],
+        }
+    }
+
+    /// UUID List boxes, one in a conforming file.
+    pub fn uuid_list_boxes(&self) -> &[UUIDListBox] {
+        &self.uuid_list
+    }
+
+    /// Data Entry URL boxes, one in a conforming file.
+    pub fn data_entry_url_boxes(&self) -> &[DataEntryURLBox] {
+        &self.data_entry_url_box
+    }
+
+    /// The UUIDs of all UUID List boxes.
+    pub fn ids(&self) -> impl Iterator<Item = &[u8; 16]> {
+        self.uuid_list.iter().flat_map(|uuid_list| uuid_list.ids())
+    }
+
+    /// Whether the UUID is listed in the box.
+    pub fn contains(&self, uuid: &[u8; 16]) -> bool {
+        self.ids().any(|id| id == uuid)
+    }
+}
+
 // I.7.3.1
 //
 // UUID List box
@@ -2075,14 +2910,30 @@ pub struct UUIDListBox {
 }
 
 impl UUIDListBox {
-    fn ids(&self) -> Vec<&str> {
-        self.ids
-            .iter()
-            .map(|id| str::from_utf8(id).unwrap())
-            .collect()
+    /// Create a UUID List box for encoding.
+    ///
+    /// Fails if there are more than 65535 UUIDs.
+    pub fn new(ids: Vec<[u8; 16]>) -> Result<UUIDListBox, JP2Error> {
+        let number_of_uuids = u16::try_from(ids.len()).map_err(|_| JP2Error::BoxMalformed {
+            box_type: BOX_TYPE_UUID_LIST,
+            offset: 0,
+        })?;
+        Ok(UUIDListBox {
+            length: 2 + 16 * ids.len() as u64,
+            offset: 0,
+            number_of_uuids: number_of_uuids.to_be_bytes(),
+            ids,
+        })
+    }
+
+    /// The UUIDs of the list, each associated with the URL of the Data Entry
+    /// URL box in the same UUID Info box.
+    pub fn ids(&self) -> &[[u8; 16]] {
+        &self.ids
     }
-    fn number_of_uuids(&self) -> i16 {
-        i16::from_be_bytes(self.number_of_uuids)
+
+    fn number_of_uuids(&self) -> u16 {
+        u16::from_be_bytes(self.number_of_uuids)
     }
 }
 
@@ -2100,11 +2951,10 @@ impl JBox for UUIDListBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.number_of_uuids)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader
+            .read_exact(&mut self.number_of_uuids)
+            .at(self.offset)?;
 
         let mut size = self.number_of_uuids() as usize;
 
@@ -2112,13 +2962,30 @@ impl JBox for UUIDListBox {
 
         let mut buffer: [u8; 16] = [0; 16];
         while size > 0 {
-            reader.read_exact(&mut buffer)?;
+            reader.read_exact(&mut buffer).at(self.offset)?;
             self.ids.extend_from_slice(&[buffer]);
             size -= 1;
         }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        let number_of_uuids =
+            u16::try_from(self.ids.len()).map_err(|_| JP2Error::BoxMalformed {
+                box_type: self.identifier(),
+                offset: self.offset,
+            })?;
+        encode_box_header(writer, self.identifier(), 2 + 16 * self.ids.len() as u64)
+            .at(self.offset)?;
+        writer
+            .write_all(&number_of_uuids.to_be_bytes())
+            .at(self.offset)?;
+        for id in &self.ids {
+            writer.write_all(id).at(self.offset)?;
+        }
+        Ok(())
+    }
 }
 
 // I.7.3.2
@@ -2168,8 +3035,38 @@ pub struct DataEntryURLBox {
 }
 
 impl DataEntryURLBox {
-    fn location(&self) -> Result<&str, str::Utf8Error> {
-        str::from_utf8(&self.location)
+    /// Create a Data Entry URL box for encoding, the location is written as a
+    /// null terminated string.
+    pub fn new(location: &str) -> DataEntryURLBox {
+        let mut location = location.as_bytes().to_vec();
+        location.push(0);
+        DataEntryURLBox {
+            length: 4 + location.len() as u64,
+            offset: 0,
+            version: [0],
+            flags: [0; 3],
+            location,
+        }
+    }
+
+    /// The version number of the format of the box, 0 in conforming files.
+    pub fn version(&self) -> u8 {
+        self.version[0]
+    }
+
+    /// The flags of the box, a 3-byte unsigned integer which is 0 in
+    /// conforming files.
+    pub fn flags(&self) -> u32 {
+        u32::from_be_bytes([0, self.flags[0], self.flags[1], self.flags[2]])
+    }
+
+    /// The URL, without its null terminator.
+    pub fn location(&self) -> Result<&str, str::Utf8Error> {
+        let location = match self.location.split_last() {
+            Some((0, location)) => location,
+            _ => &self.location,
+        };
+        str::from_utf8(location)
     }
 }
 
@@ -2187,25 +3084,31 @@ impl JBox for DataEntryURLBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.version)?;
-        reader.read_exact(&mut self.flags)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader.read_exact(&mut self.version).at(self.offset)?;
+        reader.read_exact(&mut self.flags).at(self.offset)?;
 
         // location
-        let mut size = self.length() - 4;
+        let mut size = length_after(self, 4)?;
 
         let mut buffer: [u8; 1] = [0; 1];
         while size > 0 {
-            reader.read_exact(&mut buffer)?;
+            reader.read_exact(&mut buffer).at(self.offset)?;
             self.location.extend_from_slice(&buffer);
             size -= 1;
         }
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 4 + self.location.len() as u64)
+            .at(self.offset)?;
+        writer.write_all(&self.version).at(self.offset)?;
+        writer.write_all(&self.flags).at(self.offset)?;
+        writer.write_all(&self.location).at(self.offset)?;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -2329,6 +3232,22 @@ impl QuantizationDefaultMarkerSegment {
 pub struct ContiguousCodestreamBox {
     length: u64,
     pub offset: u64,
+
+    // Codestream held in memory for encoding, boxes read by decode_jp2 leave
+    // the codestream in the source.
+    codestream: Vec<u8>,
+}
+
+impl ContiguousCodestreamBox {
+    /// Create a Contiguous Codestream box for encoding from a complete
+    /// codestream.
+    pub fn new(codestream: Vec<u8>) -> ContiguousCodestreamBox {
+        ContiguousCodestreamBox {
+            length: codestream.len() as u64,
+            offset: 0,
+            codestream,
+        }
+    }
 }
 
 impl JBox for ContiguousCodestreamBox {
@@ -2345,17 +3264,17 @@ impl JBox for ContiguousCodestreamBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        if self.length == 0 {
-            reader.seek(io::SeekFrom::End(0))?;
-            self.length = reader.stream_position()? - self.offset;
-        } else {
-            reader.seek(io::SeekFrom::Current(self.length as i64))?;
-        }
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader
+            .seek(io::SeekFrom::Current(self.length as i64))
+            .at(self.offset)?;
+        Ok(())
+    }
 
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), self.codestream.len() as u64)
+            .at(self.offset)?;
+        writer.write_all(&self.codestream).at(self.offset)?;
         Ok(())
     }
 }
@@ -2396,6 +3315,30 @@ pub struct DefaultDisplayResolutionBox {
 }
 
 impl DefaultDisplayResolutionBox {
+    /// Create a Default Display Resolution box for encoding.
+    ///
+    /// The resolution in each direction is (numerator / denominator) *
+    /// 10^exponent reference grid points per meter.
+    pub fn new(
+        vertical_numerator: u16,
+        vertical_denominator: u16,
+        horizontal_numerator: u16,
+        horizontal_denominator: u16,
+        vertical_exponent: i8,
+        horizontal_exponent: i8,
+    ) -> DefaultDisplayResolutionBox {
+        DefaultDisplayResolutionBox {
+            length: 10,
+            offset: 0,
+            vertical_display_grid_resolution_numerator: vertical_numerator.to_be_bytes(),
+            vertical_display_grid_resolution_denominator: vertical_denominator.to_be_bytes(),
+            horizontal_display_grid_resolution_numerator: horizontal_numerator.to_be_bytes(),
+            horizontal_display_grid_resolution_denominator: horizontal_denominator.to_be_bytes(),
+            vertical_display_grid_resolution_exponent: vertical_exponent.to_be_bytes(),
+            horizontal_display_grid_resolution_exponent: horizontal_exponent.to_be_bytes(),
+        }
+    }
+
     pub fn vertical_display_grid_resolution_numerator(&self) -> u16 {
         u16::from_be_bytes(self.vertical_display_grid_resolution_numerator)
     }
@@ -2443,19 +3386,51 @@ impl JBox for DefaultDisplayResolutionBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.vertical_display_grid_resolution_numerator)?;
-        reader.read_exact(&mut self.vertical_display_grid_resolution_denominator)?;
-
-        reader.read_exact(&mut self.horizontal_display_grid_resolution_numerator)?;
-        reader.read_exact(&mut self.horizontal_display_grid_resolution_denominator)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader
+            .read_exact(&mut self.vertical_display_grid_resolution_numerator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.vertical_display_grid_resolution_denominator)
+            .at(self.offset)?;
+
+        reader
+            .read_exact(&mut self.horizontal_display_grid_resolution_numerator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.horizontal_display_grid_resolution_denominator)
+            .at(self.offset)?;
+
+        reader
+            .read_exact(&mut self.vertical_display_grid_resolution_exponent)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.horizontal_display_grid_resolution_exponent)
+            .at(self.offset)?;
 
-        reader.read_exact(&mut self.vertical_display_grid_resolution_exponent)?;
-        reader.read_exact(&mut self.horizontal_display_grid_resolution_exponent)?;
+        Ok(())
+    }
 
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 10).at(self.offset)?;
+        writer
+            .write_all(&self.vertical_display_grid_resolution_numerator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.vertical_display_grid_resolution_denominator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_display_grid_resolution_numerator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_display_grid_resolution_denominator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.vertical_display_grid_resolution_exponent)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_display_grid_resolution_exponent)
+            .at(self.offset)?;
         Ok(())
     }
 }
@@ -2523,6 +3498,30 @@ pub struct CaptureResolutionBox {
 }
 
 impl CaptureResolutionBox {
+    /// Create a Capture Resolution box for encoding.
+    ///
+    /// The resolution in each direction is (numerator / denominator) *
+    /// 10^exponent reference grid points per meter.
+    pub fn new(
+        vertical_numerator: u16,
+        vertical_denominator: u16,
+        horizontal_numerator: u16,
+        horizontal_denominator: u16,
+        vertical_exponent: i8,
+        horizontal_exponent: i8,
+    ) -> CaptureResolutionBox {
+        CaptureResolutionBox {
+            length: 10,
+            offset: 0,
+            vertical_capture_grid_resolution_numerator: vertical_numerator.to_be_bytes(),
+            vertical_capture_grid_resolution_denominator: vertical_denominator.to_be_bytes(),
+            horizontal_capture_grid_resolution_numerator: horizontal_numerator.to_be_bytes(),
+            horizontal_capture_grid_resolution_denominator: horizontal_denominator.to_be_bytes(),
+            vertical_capture_grid_resolution_exponent: vertical_exponent.to_be_bytes(),
+            horizontal_capture_grid_resolution_exponent: horizontal_exponent.to_be_bytes(),
+        }
+    }
+
     pub fn vertical_capture_grid_resolution_numerator(&self) -> u16 {
         u16::from_be_bytes(self.vertical_capture_grid_resolution_numerator)
     }
@@ -2572,7 +3571,7 @@ impl CaptureResolutionBox {
 impl JBox for CaptureResolutionBox {
     // The type of a Capture Resolution box shall be ‘resc’ (0x7265 7363).
     fn identifier(&self) -> BoxType {
-        BOX_TYPE_DEFAULT_DISPLAY_RESOLUTION
+        BOX_TYPE_CAPTURE_RESOLUTION
     }
 
     fn length(&self) -> u64 {
@@ -2583,19 +3582,155 @@ impl JBox for CaptureResolutionBox {
         self.offset
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
-        reader.read_exact(&mut self.vertical_capture_grid_resolution_numerator)?;
-        reader.read_exact(&mut self.vertical_capture_grid_resolution_denominator)?;
-        reader.read_exact(&mut self.horizontal_capture_grid_resolution_numerator)?;
-        reader.read_exact(&mut self.horizontal_capture_grid_resolution_denominator)?;
-        reader.read_exact(&mut self.vertical_capture_grid_resolution_exponent)?;
-        reader.read_exact(&mut self.horizontal_capture_grid_resolution_exponent)?;
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        reader
+            .read_exact(&mut self.vertical_capture_grid_resolution_numerator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.vertical_capture_grid_resolution_denominator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.horizontal_capture_grid_resolution_numerator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.horizontal_capture_grid_resolution_denominator)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.vertical_capture_grid_resolution_exponent)
+            .at(self.offset)?;
+        reader
+            .read_exact(&mut self.horizontal_capture_grid_resolution_exponent)
+            .at(self.offset)?;
 
         Ok(())
     }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        encode_box_header(writer, self.identifier(), 10).at(self.offset)?;
+        writer
+            .write_all(&self.vertical_capture_grid_resolution_numerator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.vertical_capture_grid_resolution_denominator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_capture_grid_resolution_numerator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_capture_grid_resolution_denominator)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.vertical_capture_grid_resolution_exponent)
+            .at(self.offset)?;
+        writer
+            .write_all(&self.horizontal_capture_grid_resolution_exponent)
+            .at(self.offset)?;
+        Ok(())
+    }
+}
+
+/// Unknown box
+///
+/// A top-level box whose type is not defined by ISO/IEC 15444-1, such as a
+/// vendor specific box. Conforming readers shall ignore such boxes, they are
+/// skipped by their length when decoding and recorded so they can be written
+/// back unchanged.
+///
+/// The contents of a decoded box are not read, `data` can be used to read them
+/// from the source the file was decoded from.
+#[derive(Debug, Default)]
+pub struct UnknownBox {
+    length: u64,
+    offset: u64,
+    box_type: BoxType,
+    data: Option<Vec<u8>>,
+}
+
+impl UnknownBox {
+    /// Create an unknown box with the given contents for encoding.
+    pub fn new(box_type: [u8; 4], data: Vec<u8>) -> UnknownBox {
+        UnknownBox {
+            length: data.len() as u64,
+            offset: 0,
+            box_type,
+            data: Some(data),
+        }
+    }
+
+    /// Type of the box.
+    pub fn box_type(&self) -> [u8; 4] {
+        self.box_type
+    }
+
+    /// Contents of the box, if they are held by the box.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    /// Read the contents of a decoded box from the source it was decoded from.
+    pub fn read_data<R: io::Read + io::Seek>(&self, reader: &mut R) -> Result<Vec<u8>, JP2Error> {
+        if let Some(data) = &self.data {
+            return Ok(data.clone());
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset))
+            .at(self.offset)?;
+        read_vec(reader, self.length as usize).at(self.offset)
+    }
+
+    /// Replace the contents of the box, keeping its position in the file.
+    pub fn set_data(&mut self, data: Vec<u8>) {
+        self.length = data.len() as u64;
+        self.data = Some(data);
+    }
+}
+
+impl JBox for UnknownBox {
+    fn identifier(&self) -> BoxType {
+        self.box_type
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), JP2Error> {
+        let end = reader.seek(io::SeekFrom::End(0)).at(self.offset)?;
+        if self
+            .offset
+            .checked_add(self.length)
+            .is_none_or(|box_end| box_end > end)
+        {
+            return Err(JP2Error::BoxMalformed {
+                box_type: self.box_type,
+                offset: self.offset,
+            });
+        }
+        reader
+            .seek(io::SeekFrom::Start(self.offset + self.length))
+            .at(self.offset)?;
+        Ok(())
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> Result<(), JP2Error> {
+        match &self.data {
+            Some(data) => {
+                encode_box_header(writer, self.identifier(), data.len() as u64).at(self.offset)?;
+                writer.write_all(data).at(self.offset)?;
+                Ok(())
+            }
+            // The contents of a decoded box are copied by encode_jp2
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unknown box contents were not read",
+            ))
+            .at(self.offset),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -2608,12 +3743,83 @@ pub struct JP2File {
     intellectual_property: Option<IntellectualPropertyBox>,
     xml: Vec<XMLBox>,
     uuid: Vec<UUIDBox>,
+    uuid_info: Vec<UUIDInfoSuperBox>,
+    associations: Vec<AssociationSuperBox>,
+    unknown: Vec<UnknownBox>,
+    warnings: Vec<DecodeWarning>,
+
+    // Header length of each top-level box read by decode_jp2, keyed by the
+    // offset of the box contents, used to copy unchanged boxes.
+    header_lengths: Vec<(u64, u8)>,
+
+    // Encoding of each top-level box as it was decoded, keyed by the offset
+    // of the box contents, a box still encoded the same way was not modified.
+    decoded_encodings: Vec<(u64, Vec<u8>)>,
 }
 
 impl JP2File {
+    /// Create a JP2 file for encoding.
+    ///
+    /// The Signature box is added when the file is encoded. Codestreams and
+    /// any optional boxes can be added through the mutable accessors.
+    pub fn new(file_type_box: FileTypeBox, header_box: HeaderSuperBox) -> JP2File {
+        JP2File {
+            length: 0,
+            signature: Some(SignatureBox::default()),
+            file_type: Some(file_type_box),
+            header: Some(header_box),
+            contiguous_codestreams: vec![],
+            intellectual_property: None,
+            xml: vec![],
+            uuid: vec![],
+            uuid_info: vec![],
+            associations: vec![],
+            unknown: vec![],
+            warnings: vec![],
+            header_lengths: vec![],
+            decoded_encodings: vec![],
+        }
+    }
+
     pub fn length(&self) -> u64 {
         self.length
     }
+
+    // Encode the top-level boxes other than unknown and Contiguous Codestream
+    // boxes, which do not hold their contents, keyed by their offset.
+    fn encode_boxes(&self) -> Vec<(u64, Vec<u8>)> {
+        let mut encoded: Vec<(u64, Vec<u8>)> = vec![];
+        if let Some(file_type_box) = &self.file_type {
+            encoded.extend(encode_to_vec(file_type_box).ok());
+        }
+        if let Some(header_box) = &self.header {
+            encoded.extend(encode_to_vec(header_box).ok());
+        }
+        if let Some(intellectual_property_box) = &self.intellectual_property {
+            encoded.extend(encode_to_vec(intellectual_property_box).ok());
+        }
+        encoded.extend(
+            self.xml
+                .iter()
+                .filter_map(|xml_box| encode_to_vec(xml_box).ok()),
+        );
+        encoded.extend(
+            self.uuid
+                .iter()
+                .filter_map(|uuid_box| encode_to_vec(uuid_box).ok()),
+        );
+        encoded.extend(
+            self.uuid_info
+                .iter()
+                .filter_map(|uuid_info_box| encode_to_vec(uuid_info_box).ok()),
+        );
+        encoded.extend(
+            self.associations
+                .iter()
+                .filter_map(|association_box| encode_to_vec(association_box).ok()),
+        );
+        encoded
+    }
     pub fn signature_box(&self) -> &Option<SignatureBox> {
         &self.signature
     }
@@ -2648,9 +3854,75 @@ impl JP2File {
     pub fn uuid_boxes(&self) -> &Vec<UUIDBox> {
         &self.uuid
     }
+    pub fn uuid_info_boxes(&self) -> &Vec<UUIDInfoSuperBox> {
+        &self.uuid_info
+    }
+
+    /// UUID Info boxes listing the UUID of the UUID box, with the URLs of
+    /// information about its format.
+    pub fn uuid_info_boxes_for(&self, uuid_box: &UUIDBox) -> Vec<&UUIDInfoSuperBox> {
+        self.uuid_info
+            .iter()
+            .filter(|uuid_info_box| uuid_info_box.contains(&uuid_box.uuid))
+            .collect()
+    }
+
+    /// UUID boxes of the file with a UUID listed in the UUID Info box.
+    pub fn uuid_boxes_for(&self, uuid_info_box: &UUIDInfoSuperBox) -> Vec<&UUIDBox> {
+        self.uuid
+            .iter()
+            .filter(|uuid_box| uuid_info_box.contains(&uuid_box.uuid))
+            .collect()
+    }
+
+    /// Top-level Association boxes of ISO/IEC 15444-2 M.11.11, such as the
+    /// GML of GMLJP2 files.
+    pub fn association_boxes(&self) -> &Vec<AssociationSuperBox> {
+        &self.associations
+    }
+
+    /// Top-level boxes of types not defined by ISO/IEC 15444-1, in the order
+    /// they were found in the file.
+    pub fn unknown_boxes(&self) -> &Vec<UnknownBox> {
+        &self.unknown
+    }
+
+    /// Requirements of ISO/IEC 15444-1 Annex I not met by a file decoded
+    /// with lenient options, in the order they were found.
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.warnings
+    }
+
+    pub fn header_box_mut(&mut self) -> &mut Option<HeaderSuperBox> {
+        &mut self.header
+    }
+    pub fn contiguous_codestreams_boxes_mut(&mut self) -> &mut Vec<ContiguousCodestreamBox> {
+        &mut self.contiguous_codestreams
+    }
+    pub fn intellectual_property_box_mut(&mut self) -> &mut Option<IntellectualPropertyBox> {
+        &mut self.intellectual_property
+    }
+    pub fn xml_boxes_mut(&mut self) -> &mut Vec<XMLBox> {
+        &mut self.xml
+    }
+    pub fn uuid_boxes_mut(&mut self) -> &mut Vec<UUIDBox> {
+        &mut self.uuid
+    }
+    pub fn uuid_info_boxes_mut(&mut self) -> &mut Vec<UUIDInfoSuperBox> {
+        &mut self.uuid_info
+    }
+    pub fn association_boxes_mut(&mut self) -> &mut Vec<AssociationSuperBox> {
+        &mut self.associations
+    }
+    pub fn unknown_boxes_mut(&mut self) -> &mut Vec<UnknownBox> {
+        &mut self.unknown
+    }
 }
 
 struct BoxHeader {
+    // Offset of the box contents (DBox)
+    offset: u64,
+
     // Box Length
     //
     // This field specifies the length of the box, stored as a 4-byte big
@@ -2678,101 +3950,476 @@ struct BoxHeader {
     box_type: [u8; 4],
 
     header_length: u8,
+
+    // Whether LBox is 0, the box extends to the end of the file and its
+    // length is that of the rest of the file.
+    extends_to_end: bool,
 }
 
-fn decode_box_header<R: io::Read + io::Seek>(
-    reader: &mut R,
-) -> Result<BoxHeader, Box<dyn error::Error>> {
+// Read the given number of bytes, the buffer grows as they are read so that a
+// length beyond the end of the file is not allocated.
+fn read_vec<R: io::Read>(reader: &mut R, length: usize) -> io::Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = vec![];
+    io::Read::read_to_end(&mut io::Read::take(reader, length as u64), &mut buffer)?;
+    if buffer.len() != length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(buffer)
+}
+
+// Length of the contents of a box after its fields of the given length, the
+// box is malformed if it is shorter.
+fn length_after<B: JBox>(jbox: &B, fields_length: u64) -> Result<u64, JP2Error> {
+    jbox.length()
+        .checked_sub(fields_length)
+        .ok_or_else(|| JP2Error::BoxMalformed {
+            box_type: jbox.identifier(),
+            offset: jbox.offset(),
+        })
+}
+
+fn decode_box_header<R: io::Read + io::Seek>(reader: &mut R) -> Result<BoxHeader, JP2Error> {
+    let offset = reader.stream_position().at(0)?;
     let mut header_length = 8;
     let mut box_length: [u8; 4] = [0; 4];
     let mut box_type: [u8; 4] = [0; 4];
 
-    reader.read_exact(&mut box_length)?;
+    reader.read_exact(&mut box_length).at(offset)?;
 
     let mut box_length_value = u32::from_be_bytes(box_length) as u64;
+    let mut extends_to_end = false;
     if box_length_value == 0 {
         // If the value of this field is 0, then the length of the box was not known when the LBox field was written. In this case, this box contains all bytes up to the end of the file. If a box of length 0 is contained with in another box (its superbox), then the length of that superbox shall also be 0. This means that this box is the last box in the file.
-        reader.read_exact(&mut box_type)?;
+        reader.read_exact(&mut box_type).at(offset)?;
+
+        let end = reader.seek(io::SeekFrom::End(0)).at(offset)?;
+        reader.seek(io::SeekFrom::Start(offset + 8)).at(offset)?;
+        box_length_value = end.saturating_sub(offset + 8);
+        extends_to_end = true;
     } else if box_length_value == 1 {
         // If the value of this field is 1, then the XLBox field shall exist and the value of that field shall be the actual length of the box.
-        reader.read_exact(&mut box_type)?;
+        reader.read_exact(&mut box_type).at(offset)?;
 
         let mut xl_length: [u8; 8] = [0; 8];
         // This field specifies the actual length of the box if the value of the LBox field is 1.
         // This field is stored as an 8-byte big endian unsigned integer. The value includes all of the fields of the box, including the LBox, TBox and XLBox fields
-        reader.read_exact(&mut xl_length)?;
+        reader.read_exact(&mut xl_length).at(offset)?;
 
-        box_length_value = u64::from_be_bytes(xl_length) - 16;
+        box_length_value = u64::from_be_bytes(xl_length);
+        if box_length_value < 16 {
+            return Err(JP2Error::BoxMalformed { box_type, offset });
+        }
+        box_length_value -= 16;
         header_length = 16;
     } else if box_length_value <= 7 {
         // The values 2–7 are reserved for ISO use.
-        panic!("unsupported reserved box length {:?}", box_length_value);
+        reader.read_exact(&mut box_type).at(offset)?;
+        return Err(JP2Error::BoxMalformed { box_type, offset });
     } else {
-        reader.read_exact(&mut box_type)?;
+        reader.read_exact(&mut box_type).at(offset)?;
 
         // Subtract LBox and TBox from length
         box_length_value -= 8;
     }
 
     Ok(BoxHeader {
+        offset: offset + header_length as u64,
         box_length: box_length_value,
         box_type,
         header_length,
+        extends_to_end,
     })
 }
 
+// Encode a box header for a box with contents of the given length.
+//
+// The XLBox field is only used when the length of the box does not fit in the
+// LBox field.
+fn encode_box_header<W: io::Write>(
+    writer: &mut W,
+    box_type: BoxType,
+    contents_length: u64,
+) -> io::Result<()> {
+    if contents_length + 8 <= u32::MAX as u64 {
+        writer.write_all(&(contents_length as u32 + 8).to_be_bytes())?;
+        writer.write_all(&box_type)?;
+    } else {
+        writer.write_all(&1_u32.to_be_bytes())?;
+        writer.write_all(&box_type)?;
+        writer.write_all(&(contents_length + 16).to_be_bytes())?;
+    }
+    Ok(())
+}
+
+// Encode a box to a buffer, returning the offset it was decoded from so boxes
+// can be written back in their original order.
+fn encode_to_vec<B: JBox>(jbox: &B) -> Result<(u64, Vec<u8>), JP2Error> {
+    let mut encoded: Vec<u8> = vec![];
+    jbox.encode(&mut encoded)?;
+    Ok((jbox.offset(), encoded))
+}
+
+// Order encoded boxes of a superbox, boxes read from a file keep their order
+// and boxes created for encoding follow them in the order they were added.
+fn sort_encoded_boxes(boxes: &mut [(u64, Vec<u8>)]) {
+    boxes.sort_by_key(|(offset, _)| if *offset == 0 { u64::MAX } else { *offset });
+}
+
+// A top-level box ready to be written by encode_jp2.
+enum EncodedBox {
+    Encoded(Vec<u8>),
+
+    // Box copied from the source, the range includes the box header.
+    Copied { start: u64, length: u64 },
+
+    // Contiguous Codestream box, with the codestream copied from the source.
+    Codestream { offset: u64, length: u64 },
+}
+
+// Encode a top-level box, unless copy is false a box read by decode_jp2 which
+// was not modified or whose contents are unchanged is copied from the source
+// including its original header.
+fn encode_or_copy<B: JBox, R: io::Read + io::Seek>(
+    jbox: &B,
+    jp2: &JP2File,
+    reader: &mut R,
+    copy: bool,
+) -> Result<EncodedBox, JP2Error> {
+    let (offset, encoded) = encode_to_vec(jbox)?;
+    if !copy {
+        return Ok(EncodedBox::Encoded(encoded));
+    }
+    let header_length = match jp2
+        .header_lengths
+        .iter()
+        .find(|(header_offset, _)| *header_offset == offset && offset != 0)
+    {
+        Some((_, header_length)) => *header_length as u64,
+        None => return Ok(EncodedBox::Encoded(encoded)),
+    };
+
+    reader
+        .seek(io::SeekFrom::Start(offset - header_length))
+        .at(offset)?;
+    let BoxHeader {
+        box_length,
+        box_type,
+        extends_to_end,
+        ..
+    } = decode_box_header(reader)?;
+    if box_type != jbox.identifier() {
+        return Ok(EncodedBox::Encoded(encoded));
+    }
+
+    // A box which was not modified is copied even when encoding it gives
+    // other bytes, such as an XLBox where LBox would do, so that nothing of
+    // the source is lost.
+    let is_modified = !jp2
+        .decoded_encodings
+        .iter()
+        .any(|(decoded_offset, decoded)| *decoded_offset == offset && *decoded == encoded);
+    if !is_modified && !extends_to_end {
+        return Ok(EncodedBox::Copied {
+            start: offset - header_length,
+            length: header_length + box_length,
+        });
+    }
+
+    let encoded_header_length = if encoded[0..4] == [0, 0, 0, 1] { 16 } else { 8 };
+    let encoded_contents = &encoded[encoded_header_length..];
+    if box_length != encoded_contents.len() as u64 {
+        return Ok(EncodedBox::Encoded(encoded));
+    }
+
+    let contents = read_vec(reader, box_length as usize).at(offset)?;
+    if contents != encoded_contents {
+        return Ok(EncodedBox::Encoded(encoded));
+    }
+
+    Ok(EncodedBox::Copied {
+        start: offset - header_length,
+        length: header_length + box_length,
+    })
+}
+
+// Copy a range of the source to the writer.
+fn copy_range<W: io::Write, R: io::Read + io::Seek>(
+    writer: &mut W,
+    reader: &mut R,
+    start: u64,
+    length: u64,
+) -> io::Result<()> {
+    reader.seek(io::SeekFrom::Start(start))?;
+    let copied = io::copy(&mut io::Read::take(&mut *reader, length), writer)?;
+    if copied != length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(())
+}
+
+/// Encode a JP2 file.
+///
+/// Boxes read by `decode_jp2` are written in the order they were decoded in,
+/// those which were not modified or whose contents are unchanged are copied
+/// byte-for-byte from `reader`, which shall be the source the file was decoded
+/// from. Contiguous Codestream boxes
+/// read by `decode_jp2` do not hold their codestream, it is also copied from
+/// `reader`.
+///
+/// This allows metadata to be edited without re-encoding the image, by
+/// changing the boxes of a decoded file before encoding it. Boxes which are
+/// removed are not written, boxes edited in place (such as with
+/// `XMLBox::set_xml`) keep their position and new boxes are written before the
+/// first Contiguous Codestream box. A new JP2 Header box is written first and
+/// new Contiguous Codestream boxes are written last.
+///
+/// The offsets of errors are of `reader`, such as the position it was read
+/// from when writing failed, and 0 for boxes missing from `jp2`.
+pub fn encode_jp2<W: io::Write, R: io::Read + io::Seek>(
+    writer: &mut W,
+    jp2: &JP2File,
+    reader: &mut R,
+) -> Result<(), JP2Error> {
+    encode_file(writer, jp2, reader, true)
+}
+
+/// Encode a JP2 file as `encode_jp2` does, with every box encoded from its
+/// decoded fields instead of copied from `reader`. Only the codestreams and
+/// the contents of unknown boxes are read from `reader`.
+///
+/// A file whose boxes are all encoded as they were found in the source, such
+/// as without XLBox fields where LBox would do, is encoded to the same bytes
+/// as the source.
+pub fn reencode_jp2<W: io::Write, R: io::Read + io::Seek>(
+    writer: &mut W,
+    jp2: &JP2File,
+    reader: &mut R,
+) -> Result<(), JP2Error> {
+    encode_file(writer, jp2, reader, false)
+}
+
+fn encode_file<W: io::Write, R: io::Read + io::Seek>(
+    writer: &mut W,
+    jp2: &JP2File,
+    reader: &mut R,
+    copy: bool,
+) -> Result<(), JP2Error> {
+    let missing = |box_type: BoxType| JP2Error::BoxMissing {
+        box_type,
+        offset: 0,
+    };
+    let file_type_box = match &jp2.file_type {
+        Some(file_type_box) => file_type_box,
+        None => return Err(missing(BOX_TYPE_FILE_TYPE)),
+    };
+    let header_box = match &jp2.header {
+        Some(header_box) => header_box,
+        None => return Err(missing(BOX_TYPE_HEADER)),
+    };
+    if jp2.contiguous_codestreams.is_empty() {
+        return Err(missing(BOX_TYPE_CONTIGUOUS_CODESTREAM));
+    }
+
+    // New boxes other than the JP2 Header box and Contiguous Codestream boxes
+    // are placed before the first Contiguous Codestream box.
+    let new_box_offset = jp2
+        .contiguous_codestreams
+        .iter()
+        .map(|contiguous_codestream_box| contiguous_codestream_box.offset)
+        .find(|offset| *offset != 0)
+        .unwrap_or(u64::MAX);
+    let position = |offset: u64| if offset == 0 { new_box_offset } else { offset };
+
+    let mut boxes: Vec<(u64, EncodedBox)> = vec![(
+        header_box.offset,
+        encode_or_copy(header_box, jp2, reader, copy)?,
+    )];
+    if let Some(intellectual_property_box) = &jp2.intellectual_property {
+        boxes.push((
+            position(intellectual_property_box.offset),
+            encode_or_copy(intellectual_property_box, jp2, reader, copy)?,
+        ));
+    }
+    for xml_box in &jp2.xml {
+        boxes.push((
+            position(xml_box.offset),
+            encode_or_copy(xml_box, jp2, reader, copy)?,
+        ));
+    }
+    for uuid_box in &jp2.uuid {
+        boxes.push((
+            position(uuid_box.offset),
+            encode_or_copy(uuid_box, jp2, reader, copy)?,
+        ));
+    }
+    for uuid_info_box in &jp2.uuid_info {
+        boxes.push((
+            position(uuid_info_box.offset),
+            encode_or_copy(uuid_info_box, jp2, reader, copy)?,
+        ));
+    }
+    for association_box in &jp2.associations {
+        boxes.push((
+            position(association_box.offset()),
+            encode_or_copy(association_box, jp2, reader, copy)?,
+        ));
+    }
+    for unknown_box in &jp2.unknown {
+        let encoded_box = match (&unknown_box.data, unknown_box.offset) {
+            (None, offset) if offset != 0 => {
+                let header_length = match jp2
+                    .header_lengths
+                    .iter()
+                    .find(|(header_offset, _)| *header_offset == offset)
+                {
+                    Some((_, header_length)) => *header_length as u64,
+                    None => {
+                        return Err(JP2Error::BoxMalformed {
+                            box_type: unknown_box.box_type,
+                            offset,
+                        })
+                    }
+                };
+                // A box with a length of 0 is recorded with its length, as
+                // boxes may follow it in the encoded file.
+                let mut box_length: [u8; 4] = [0; 4];
+                reader
+                    .seek(io::SeekFrom::Start(offset - header_length))
+                    .at(offset)?;
+                reader.read_exact(&mut box_length).at(offset)?;
+                if box_length == [0; 4] || !copy {
+                    let mut encoded: Vec<u8> = vec![];
+                    encode_box_header(&mut encoded, unknown_box.box_type, unknown_box.length)
+                        .at(offset)?;
+                    encoded.extend(unknown_box.read_data(reader)?);
+                    EncodedBox::Encoded(encoded)
+                } else {
+                    EncodedBox::Copied {
+                        start: offset - header_length,
+                        length: header_length + unknown_box.length,
+                    }
+                }
+            }
+            _ => encode_or_copy(unknown_box, jp2, reader, copy)?,
+        };
+        boxes.push((position(unknown_box.offset), encoded_box));
+    }
+    for contiguous_codestream_box in &jp2.contiguous_codestreams {
+        if contiguous_codestream_box.offset == 0 {
+            boxes.push((
+                u64::MAX,
+                EncodedBox::Encoded(encode_to_vec(contiguous_codestream_box)?.1),
+            ));
+        } else {
+            boxes.push((
+                contiguous_codestream_box.offset,
+                EncodedBox::Codestream {
+                    offset: contiguous_codestream_box.offset,
+                    length: contiguous_codestream_box.length,
+                },
+            ));
+        }
+    }
+
+    // The stable sort keeps boxes at the same position in the order above.
+    boxes.sort_by_key(|(position, _)| *position);
+
+    // The Signature box shall be the first box, immediately followed by the
+    // File Type box.
+    SignatureBox::default().encode(writer)?;
+    match encode_or_copy(file_type_box, jp2, reader, copy)? {
+        EncodedBox::Copied { start, length } => {
+            copy_range(writer, reader, start, length).at(file_type_box.offset)?
+        }
+        _ => file_type_box.encode(writer)?,
+    }
+
+    for (position, encoded_box) in boxes {
+        // Boxes created for encoding have no offset in the source
+        let offset = if position == u64::MAX { 0 } else { position };
+        match encoded_box {
+            EncodedBox::Encoded(encoded) => writer.write_all(&encoded).at(offset)?,
+            EncodedBox::Copied { start, length } => {
+                copy_range(writer, reader, start, length).at(offset)?
+            }
+            EncodedBox::Codestream { offset, length } => {
+                // The length of the codestream is always recorded, as boxes
+                // may follow it in the encoded file.
+                encode_box_header(writer, BOX_TYPE_CONTIGUOUS_CODESTREAM, length).at(offset)?;
+                copy_range(writer, reader, offset, length).at(offset)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a JP2 file with lenient options, requirements of Annex I not met
+/// by the file are available through `JP2File::warnings`.
+pub fn decode_jp2<R: io::Read + io::Seek>(reader: &mut R) -> Result<JP2File, JP2Error> {
+    decode_jp2_with_options(reader, &DecodeOptions::default())
+}
+
+/// Decode a JP2 file, a strict decode fails with `JP2Error::Nonconforming`
+/// on the first requirement of Annex I not met by the file.
 // TODO: Consider lazy parsing where possible
-pub fn decode_jp2<R: io::Read + io::Seek>(
+pub fn decode_jp2_with_options<R: io::Read + io::Seek>(
+    reader: &mut R,
+    options: &DecodeOptions,
+) -> Result<JP2File, JP2Error> {
+    decode_file(reader, options)
+}
+
+fn decode_file<R: io::Read + io::Seek>(
     reader: &mut R,
-) -> Result<JP2File, Box<dyn error::Error>> {
+    options: &DecodeOptions,
+) -> Result<JP2File, JP2Error> {
     let BoxHeader {
+        offset,
         box_length,
         box_type,
-        header_length: _,
+        ..
     } = decode_box_header(reader)?;
 
     // TODO: Enforce the following
-    // Check Image Headerbox (header, width) with codestream and allow user to read it otherwise
     // If resolution box is not present, then a header shall assume that reference grid points are square.
 
     let mut signature_box = SignatureBox::default();
     // The Signature box shall be the first box
     if box_type != signature_box.identifier() {
-        return Err(JP2Error::BoxUnexpected {
-            box_type,
-            offset: reader.stream_position()?,
-        }
-        .into());
+        return Err(JP2Error::BoxUnexpected { box_type, offset });
     }
     signature_box.length = box_length;
-    signature_box.offset = reader.stream_position().unwrap();
+    signature_box.offset = offset;
     info!("SignatureBox start at {:?}", signature_box.length);
     signature_box.decode(reader)?;
-    info!("SignatureBox finish at {:?}", reader.stream_position()?);
+    info!("SignatureBox finish at {:?}", offset + box_length);
 
     let BoxHeader {
+        offset,
         box_length,
         box_type,
-        header_length: _,
+        header_length,
+        ..
     } = decode_box_header(reader)?;
+    let mut header_lengths: Vec<(u64, u8)> = vec![(offset, header_length)];
     // The File Type box shall immediately follow the Signature box
     let mut file_type_box = FileTypeBox {
         length: box_length,
-        offset: reader.stream_position().unwrap(),
+        offset,
         brand: [0; 4],
         min_version: [0; 4],
         compatibility_list: vec![],
     };
     if box_type != file_type_box.identifier() {
-        return Err(JP2Error::BoxUnexpected {
-            box_type,
-            offset: reader.stream_position()?,
-        }
-        .into());
+        return Err(JP2Error::BoxUnexpected { box_type, offset });
     }
     info!("FileTypeBox start at {:?}", file_type_box.offset);
     file_type_box.decode(reader)?;
-    info!("FileTypeBox finish at {:?}", reader.stream_position()?);
+    info!("FileTypeBox finish at {:?}", offset + box_length);
+
+    let mut warnings = conformance::Warnings::default();
+    warnings.check_min_version(file_type_box.offset, file_type_box.min_version());
 
     let mut header_box_option: Option<HeaderSuperBox> = None;
     let mut contiguous_codestream_boxes: Vec<ContiguousCodestreamBox> = vec![];
@@ -2782,43 +4429,86 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
     let mut uuid_boxes: Vec<UUIDBox> = vec![];
     let mut uuid_info_boxes: Vec<UUIDInfoSuperBox> = vec![];
     let mut current_uuid_info_box: Option<UUIDInfoSuperBox> = None;
+    let mut association_boxes: Vec<AssociationSuperBox> = vec![];
+    let mut unknown_boxes: Vec<UnknownBox> = vec![];
+
+    let limits = options.limits();
+    // Bytes of the boxes read into memory
+    let mut allocated = 0;
 
     loop {
         let BoxHeader {
+            offset,
             box_length,
             box_type,
-            header_length: _,
+            header_length,
+            ..
         } = match decode_box_header(reader) {
             Ok(value) => value,
-            Err(derr) => {
-                // TODO: Improve check for EOF
-                if let Some(e) = derr.downcast_ref::<io::Error>() {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        break;
-                    }
-                }
-                return Err(derr);
+            // TODO: Improve check for EOF
+            Err(JP2Error::Io { source, .. }) if source.kind() == io::ErrorKind::UnexpectedEof => {
+                break;
             }
+            Err(error) => return Err(error),
         };
+        header_lengths.push((offset, header_length));
+
+        // The contents of these boxes are read into memory, codestreams and
+        // other boxes are skipped. The UUID List and Data Entry URL boxes of
+        // a UUID Info box are read as top-level boxes, so only they are
+        // counted.
+        if matches!(
+            BoxTypes::new(box_type),
+            BoxTypes::Header
+                | BoxTypes::IntellectualProperty
+                | BoxTypes::Xml
+                | BoxTypes::Uuid
+                | BoxTypes::UUIDList
+                | BoxTypes::DataEntryURL
+                | BoxTypes::Association
+        ) {
+            limits.check_box_size(box_length).at(offset)?;
+            limits.allocate(&mut allocated, box_length).at(offset)?;
+        }
 
         match BoxTypes::new(box_type) {
             BoxTypes::Header => {
                 // The header box must be at the same level as the Signature
                 // and File Type boxes it shall not be inside any other
                 // superbox within the file)
-                info!("HeaderSuperBox start at {:?}", reader.stream_position()?);
+                info!("HeaderSuperBox start at {:?}", offset);
                 let mut header_box = HeaderSuperBox::default();
                 header_box.length = box_length;
-                header_box.offset = reader.stream_position()?;
+                header_box.offset = offset;
+                // There shall be one and only one JP2 Header box, the last
+                // one is kept.
+                if header_box_option.is_some() {
+                    warnings.push(
+                        box_type,
+                        Some(header_box.offset),
+                        Nonconformance::BoxDuplicate,
+                    );
+                }
                 header_box.decode(reader)?;
+                let image_header_box = &header_box.image_header_box;
+                limits
+                    .check_components(image_header_box.components_num())
+                    .at(image_header_box.offset)?;
+                limits
+                    .check_image_area(image_header_box.width(), image_header_box.height())
+                    .at(image_header_box.offset)?;
+                warnings.check_header(&header_box);
                 header_box_option = Some(header_box);
-                info!("HeaderSuperBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "HeaderSuperBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::IntellectualProperty => {
                 let mut intellectual_property_box = IntellectualPropertyBox {
                     length: box_length,
-                    offset: reader.stream_position()?,
-                    data: vec![0; box_length as usize],
+                    offset,
+                    data: vec![],
                 };
                 info!(
                     "IntellectualPropertyBox start at {:?}",
@@ -2834,40 +4524,50 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
             BoxTypes::Xml => {
                 let mut xml_box = XMLBox {
                     length: box_length,
-                    offset: reader.stream_position()?,
-                    xml: Vec::with_capacity(box_length as usize).to_owned(),
+                    offset,
+                    xml: vec![],
                 };
                 info!("XMLBox start at {:?}", xml_box.offset);
                 xml_box.decode(reader)?;
                 xml_boxes.push(xml_box);
-                info!("XMLBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "XMLBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::Uuid => {
                 let mut uuid_box = UUIDBox::default();
                 uuid_box.length = box_length;
-                uuid_box.offset = reader.stream_position()?;
+                uuid_box.offset = offset;
                 info!("UUIDBox start at {:?}", uuid_box.offset);
                 uuid_box.decode(reader)?;
                 uuid_boxes.push(uuid_box);
-                info!("UUIDBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "UUIDBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::UUIDInfo => {
                 let mut uuid_info_box = UUIDInfoSuperBox::default();
                 uuid_info_box.length = box_length;
-                uuid_info_box.offset = reader.stream_position()?;
+                uuid_info_box.offset = offset;
                 info!("UUIDInfoBox start at {:?}", uuid_info_box.offset);
                 uuid_info_box.decode(reader)?;
 
                 if let Some(info_box) = current_uuid_info_box {
+                    warnings.check_uuid_info(&info_box);
                     uuid_info_boxes.push(info_box);
                 }
                 current_uuid_info_box = Some(uuid_info_box);
-                info!("UUIDInfoBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "UUIDInfoBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::UUIDList => {
                 let mut uuid_list_box = UUIDListBox::default();
                 uuid_list_box.length = box_length;
-                uuid_list_box.offset = reader.stream_position()?;
+                uuid_list_box.offset = offset;
                 info!("UUIDListBox start at {:?}", uuid_list_box.offset);
                 uuid_list_box.decode(reader)?;
                 match &mut current_uuid_info_box {
@@ -2877,23 +4577,26 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
                     None => {
                         return Err(JP2Error::BoxMissing {
                             box_type: BOX_TYPE_UUID_INFO,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
                 }
-                info!("UUIDListBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "UUIDListBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::DataEntryURL => {
                 let mut data_entry_url_box = DataEntryURLBox {
                     length: box_length,
-                    offset: reader.stream_position()?,
+                    offset,
                     version: [0; 1],
                     flags: [0; 3],
-                    location: Vec::with_capacity(box_length as usize - 4).to_owned(),
+                    location: vec![],
                 };
 
                 data_entry_url_box.length = box_length;
-                data_entry_url_box.offset = reader.stream_position()?;
+                data_entry_url_box.offset = offset;
                 info!("DataEntryURLBox start at {:?}", data_entry_url_box.offset);
                 data_entry_url_box.decode(reader)?;
                 match &mut current_uuid_info_box {
@@ -2903,54 +4606,125 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
                     None => {
                         return Err(JP2Error::BoxMissing {
                             box_type: BOX_TYPE_UUID_INFO,
-                        }
-                        .into());
+                            offset,
+                        });
                     }
                 }
-                info!("DataEntryURLBox finish at {:?}", reader.stream_position()?);
+                info!(
+                    "DataEntryURLBox finish at {:?}",
+                    reader.stream_position().at(offset)?
+                );
             }
             BoxTypes::ContiguousCodestream => {
                 // The Header box shall fall before the Contiguous Codestream box
                 if header_box_option.is_none() {
-                    return Err(JP2Error::BoxUnexpected {
-                        box_type,
-                        offset: reader.stream_position()?,
-                    }
-                    .into());
+                    return Err(JP2Error::BoxUnexpected { box_type, offset });
                 }
 
                 let mut continuous_codestream_box = ContiguousCodestreamBox {
                     length: box_length,
-                    offset: reader.stream_position()?,
+                    offset,
+                    codestream: vec![],
                 };
                 info!(
                     "ContiguousCodestreamBox start at {:?}",
                     continuous_codestream_box.offset
                 );
+                // The Image Header box describes the first codestream, which
+                // is read again from its start.
+                if let (Some(header_box), true) =
+                    (&header_box_option, contiguous_codestream_boxes.is_empty())
+                {
+                    let siz = jpc::decode_image_and_tile_size(reader);
+                    warnings.check_codestream(header_box, offset, siz);
+                    reader.seek(io::SeekFrom::Start(offset)).at(offset)?;
+                }
                 continuous_codestream_box.decode(reader)?;
                 info!(
                     "ContiguousCodestreamBox finish at {:?}",
-                    reader.stream_position()?
+                    reader.stream_position().at(offset)?
                 );
                 contiguous_codestream_boxes.push(continuous_codestream_box);
             }
 
-            _ => {
-                panic!(
-                    "Unexpected box type {:?} {:?}",
-                    reader.stream_position(),
-                    box_type
+            BoxTypes::Association => {
+                info!("AssociationSuperBox start at {:?}", offset);
+                association_boxes.push(jpx::decode_association_box(
+                    reader, offset, box_length, limits,
+                )?);
+                info!(
+                    "AssociationSuperBox finish at {:?}",
+                    reader.stream_position().at(offset)?
                 );
             }
+
+            // Boxes of other types shall be ignored by conforming readers,
+            // they are skipped and kept so they can be written back.
+            _ => {
+                let mut unknown_box = UnknownBox {
+                    length: box_length,
+                    offset,
+                    box_type,
+                    data: None,
+                };
+                // The Signature and File Type boxes shall occur once, at the
+                // start of the file.
+                if box_type == BOX_TYPE_SIGNATURE || box_type == BOX_TYPE_FILE_TYPE {
+                    warnings.push(
+                        box_type,
+                        Some(unknown_box.offset),
+                        Nonconformance::BoxDuplicate,
+                    );
+                } else {
+                    warn!(
+                        "Unknown box type {:?} at {:?}",
+                        box_type, unknown_box.offset
+                    );
+                }
+                unknown_box.decode(reader)?;
+                unknown_boxes.push(unknown_box);
+            }
         }
     }
 
     if let Some(uuid_box) = current_uuid_info_box {
+        warnings.check_uuid_info(&uuid_box);
         uuid_info_boxes.push(uuid_box);
     }
 
-    let result = JP2File {
-        length: reader.stream_position()?,
+    // A JP2 file contains a JP2 Header box and at least one Contiguous
+    // Codestream box.
+    match &header_box_option {
+        Some(header_box) => warnings.check_intellectual_property(
+            header_box,
+            intellectual_property_option
+                .as_ref()
+                .map(|intellectual_property_box| intellectual_property_box.offset),
+        ),
+        None => warnings.push(BOX_TYPE_HEADER, None, Nonconformance::BoxMissing),
+    }
+    if contiguous_codestream_boxes.is_empty() {
+        warnings.push(
+            BOX_TYPE_CONTIGUOUS_CODESTREAM,
+            None,
+            Nonconformance::BoxMissing,
+        );
+    }
+    if options.is_strict() {
+        if let Some(warning) = warnings.0.first() {
+            let offset = match warning.offset {
+                Some(offset) => offset,
+                None => reader.stream_position().at(0)?,
+            };
+            return Err(JP2Error::Nonconforming {
+                warning: warning.clone(),
+                offset,
+            });
+        }
+    }
+
+    let mut result = JP2File {
+        length: reader.stream_position().at(0)?,
         signature: Some(signature_box),
         file_type: Some(file_type_box),
         header: header_box_option,
@@ -2958,7 +4732,14 @@ pub fn decode_jp2<R: io::Read + io::Seek>(
         intellectual_property: intellectual_property_option,
         xml: xml_boxes,
         uuid: uuid_boxes,
+        uuid_info: uuid_info_boxes,
+        associations: association_boxes,
+        unknown: unknown_boxes,
+        warnings: warnings.0,
+        header_lengths,
+        decoded_encodings: vec![],
     };
+    result.decoded_encodings = result.encode_boxes();
 
     Ok(result)
 }
@@ -0,0 +1,348 @@
+// Photographic metadata of UUID boxes, XMP, Exif and IPTC.
+//
+// The UUIDs are those used by Adobe XMP Specification Part 3 (XMP), by the
+// 'JpgTiffExif->JP2' convention of digital cameras and ExifTool (Exif) and by
+// Adobe for IPTC-NAA Information Interchange Model records (IPTC).
+
+use log::warn;
+use std::str;
+
+use super::tiff::{
+    Field, Tiff, FIELD_TYPE_ASCII, FIELD_TYPE_BYTE, FIELD_TYPE_DOUBLE, FIELD_TYPE_FLOAT,
+    FIELD_TYPE_LONG, FIELD_TYPE_RATIONAL, FIELD_TYPE_SBYTE, FIELD_TYPE_SHORT, FIELD_TYPE_SLONG,
+    FIELD_TYPE_SRATIONAL, FIELD_TYPE_SSHORT, FIELD_TYPE_UNDEFINED,
+};
+use super::{JBox, JP2Error, JP2File, UUIDBox, BOX_TYPE_UUID};
+
+/// UUID of XMP boxes, be7acfcb-97a9-42e8-9c71-999491e3afac.
+pub const UUID_XMP: [u8; 16] = [
+    0xbe, 0x7a, 0xcf, 0xcb, 0x97, 0xa9, 0x42, 0xe8, 0x9c, 0x71, 0x99, 0x94, 0x91, 0xe3, 0xaf, 0xac,
+];
+
+/// UUID of Exif boxes, 'JpgTiffExif->JP2'.
+pub const UUID_EXIF: [u8; 16] = *b"JpgTiffExif->JP2";
+
+/// UUID of IPTC boxes, 33c7a4d2-b81d-4723-a0ba-f1a3e097ad38.
+pub const UUID_IPTC: [u8; 16] = [
+    0x33, 0xc7, 0xa4, 0xd2, 0xb8, 0x1d, 0x47, 0x23, 0xa0, 0xba, 0xf1, 0xa3, 0xe0, 0x97, 0xad, 0x38,
+];
+
+// Pointers to the Exif, GPS and Interoperability IFDs
+const TAG_EXIF_IFD: u16 = 34665;
+const TAG_GPS_IFD: u16 = 34853;
+const TAG_INTEROPERABILITY_IFD: u16 = 40965;
+
+// The start of an IPTC dataset
+const IPTC_TAG_MARKER: u8 = 0x1c;
+
+/// IFD of an Exif tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExifIfd {
+    /// 0th IFD, of the primary image.
+    Primary,
+
+    /// 1st IFD, of the thumbnail.
+    Thumbnail,
+
+    Exif,
+    Gps,
+    Interoperability,
+}
+
+/// Value of an Exif tag, by its TIFF field type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExifValue {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SShort(Vec<i16>),
+    SLong(Vec<i32>),
+    SRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl ExifValue {
+    /// The string of an ASCII value.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ExifValue::Ascii(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The first value of an unsigned integer value.
+    pub fn unsigned(&self) -> Option<u32> {
+        match self {
+            ExifValue::Byte(values) => values.first().map(|value| *value as u32),
+            ExifValue::Short(values) => values.first().map(|value| *value as u32),
+            ExifValue::Long(values) => values.first().copied(),
+            _ => None,
+        }
+    }
+}
+
+/// An Exif tag and its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExifTag {
+    ifd: ExifIfd,
+    tag: u16,
+    value: ExifValue,
+}
+
+impl ExifTag {
+    pub fn ifd(&self) -> ExifIfd {
+        self.ifd
+    }
+
+    pub fn tag(&self) -> u16 {
+        self.tag
+    }
+
+    pub fn value(&self) -> &ExifValue {
+        &self.value
+    }
+}
+
+/// A dataset of an IPTC-NAA Information Interchange Model record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IptcDataSet {
+    record: u8,
+    dataset: u8,
+    data: Vec<u8>,
+}
+
+impl IptcDataSet {
+    /// Record number, 2 for the application record.
+    pub fn record(&self) -> u8 {
+        self.record
+    }
+
+    /// Dataset number, such as 2:80 for the by-line or 2:120 for the
+    /// caption.
+    pub fn dataset(&self) -> u8 {
+        self.dataset
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The data as text, UTF-8 or otherwise ISO 8859-1.
+    pub fn text(&self) -> String {
+        match str::from_utf8(&self.data) {
+            Ok(text) => text.to_owned(),
+            Err(_) => self.data.iter().map(|byte| *byte as char).collect(),
+        }
+    }
+}
+
+/// XMP, Exif and IPTC metadata of the UUID boxes of a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    xmp: Option<String>,
+    exif: Vec<ExifTag>,
+    iptc: Vec<IptcDataSet>,
+}
+
+impl Metadata {
+    /// The XMP packet.
+    pub fn xmp(&self) -> Option<&str> {
+        self.xmp.as_deref()
+    }
+
+    /// Exif tags, in the order of the IFDs.
+    pub fn exif(&self) -> &[ExifTag] {
+        &self.exif
+    }
+
+    pub fn exif_tag(&self, ifd: ExifIfd, tag: u16) -> Option<&ExifValue> {
+        self.exif
+            .iter()
+            .find(|exif_tag| exif_tag.ifd == ifd && exif_tag.tag == tag)
+            .map(|exif_tag| &exif_tag.value)
+    }
+
+    /// IPTC datasets, in the order they were found.
+    pub fn iptc(&self) -> &[IptcDataSet] {
+        &self.iptc
+    }
+
+    /// The datasets of a record and dataset number, repeatable datasets such
+    /// as keywords (2:25) may occur several times.
+    pub fn iptc_datasets(&self, record: u8, dataset: u8) -> Vec<&IptcDataSet> {
+        self.iptc
+            .iter()
+            .filter(|iptc_dataset| iptc_dataset.record == record && iptc_dataset.dataset == dataset)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.xmp.is_none() && self.exif.is_empty() && self.iptc.is_empty()
+    }
+}
+
+fn exif_value(tiff: &Tiff, field: &Field) -> Option<ExifValue> {
+    let rational = |tiff: &Tiff, offset: usize| Some((tiff.u32(offset)?, tiff.u32(offset + 4)?));
+    let value = match field.field_type {
+        FIELD_TYPE_BYTE => ExifValue::Byte(tiff.raw(field)?.to_vec()),
+        FIELD_TYPE_ASCII => ExifValue::Ascii(tiff.ascii(field)?),
+        FIELD_TYPE_SHORT => ExifValue::Short(tiff.values(field, Tiff::u16)?),
+        FIELD_TYPE_LONG => ExifValue::Long(tiff.values(field, Tiff::u32)?),
+        FIELD_TYPE_RATIONAL => ExifValue::Rational(tiff.values(field, rational)?),
+        FIELD_TYPE_SBYTE => {
+            ExifValue::SByte(tiff.raw(field)?.iter().map(|byte| *byte as i8).collect())
+        }
+        FIELD_TYPE_UNDEFINED => ExifValue::Undefined(tiff.raw(field)?.to_vec()),
+        FIELD_TYPE_SSHORT => ExifValue::SShort(tiff.values(field, |tiff, offset| {
+            tiff.u16(offset).map(|value| value as i16)
+        })?),
+        FIELD_TYPE_SLONG => ExifValue::SLong(tiff.values(field, |tiff, offset| {
+            tiff.u32(offset).map(|value| value as i32)
+        })?),
+        FIELD_TYPE_SRATIONAL => ExifValue::SRational(tiff.values(field, |tiff, offset| {
+            rational(tiff, offset)
+                .map(|(numerator, denominator)| (numerator as i32, denominator as i32))
+        })?),
+        FIELD_TYPE_FLOAT => ExifValue::Float(tiff.values(field, Tiff::f32)?),
+        FIELD_TYPE_DOUBLE => ExifValue::Double(tiff.doubles(field)?),
+        _ => return None,
+    };
+    Some(value)
+}
+
+// Exif tags of a TIFF, optionally preceded by the 'Exif\0\0' header of the
+// APP1 segment of JPEG files.
+fn decode_exif(data: &[u8]) -> Option<Vec<ExifTag>> {
+    let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+    let tiff = Tiff::new(data)?;
+
+    let mut tags: Vec<ExifTag> = vec![];
+    let mut visited: Vec<usize> = vec![];
+    let mut ifds: Vec<(ExifIfd, usize)> = vec![(ExifIfd::Primary, tiff.first_ifd()?)];
+    let mut index = 0;
+    while let Some((ifd, offset)) = ifds.get(index).copied() {
+        index += 1;
+        // IFDs shall not be read twice, such as by a loop of next IFDs
+        if offset == 0 || visited.contains(&offset) {
+            continue;
+        }
+        visited.push(offset);
+
+        let (fields, next) = tiff.ifd(offset)?;
+        if ifd == ExifIfd::Primary {
+            ifds.push((ExifIfd::Thumbnail, next));
+        }
+        for field in &fields {
+            let value = match exif_value(&tiff, field) {
+                Some(value) => value,
+                None => {
+                    warn!(
+                        "Exif tag {} of type {} ignored",
+                        field.tag, field.field_type
+                    );
+                    continue;
+                }
+            };
+            let sub_ifd = match field.tag {
+                TAG_EXIF_IFD => Some(ExifIfd::Exif),
+                TAG_GPS_IFD => Some(ExifIfd::Gps),
+                TAG_INTEROPERABILITY_IFD => Some(ExifIfd::Interoperability),
+                _ => None,
+            };
+            if let (Some(sub_ifd), Some(offset)) = (sub_ifd, value.unsigned()) {
+                ifds.push((sub_ifd, offset as usize));
+            }
+            tags.push(ExifTag {
+                ifd,
+                tag: field.tag,
+                value,
+            });
+        }
+    }
+    Some(tags)
+}
+
+// Datasets of IPTC records, which may be followed by padding.
+fn decode_iptc(data: &[u8]) -> Option<Vec<IptcDataSet>> {
+    let mut datasets: Vec<IptcDataSet> = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        if data[offset] != IPTC_TAG_MARKER {
+            if data[offset..].iter().all(|byte| *byte == 0) {
+                break;
+            }
+            return None;
+        }
+        let header = data.get(offset..offset + 5)?;
+        let (record, dataset) = (header[1], header[2]);
+        let length = u16::from_be_bytes([header[3], header[4]]) as usize;
+        offset += 5;
+
+        // Extended datasets give the number of bytes of their length
+        let length = if length & 0x8000 != 0 {
+            let size = length & 0x7fff;
+            if size > 8 {
+                return None;
+            }
+            let bytes = data.get(offset..offset + size)?;
+            offset += size;
+            bytes
+                .iter()
+                .fold(0u64, |length, byte| length << 8 | *byte as u64) as usize
+        } else {
+            length
+        };
+        datasets.push(IptcDataSet {
+            record,
+            dataset,
+            data: data.get(offset..offset.checked_add(length)?)?.to_vec(),
+        });
+        offset += length;
+    }
+    Some(datasets)
+}
+
+impl UUIDBox {
+    /// Whether the box is an XMP, Exif or IPTC box.
+    pub fn is_metadata(&self) -> bool {
+        [UUID_XMP, UUID_EXIF, UUID_IPTC].contains(&self.uuid)
+    }
+}
+
+impl JP2File {
+    /// Decode the XMP, Exif and IPTC metadata of the UUID boxes of the file.
+    ///
+    /// The XMP packet is returned as text, Exif IFDs as tag and value pairs
+    /// and IPTC records as datasets. Of several boxes of the same kind the
+    /// first is used.
+    pub fn metadata(&self) -> Result<Metadata, JP2Error> {
+        let mut metadata = Metadata::default();
+        let mut decoded: Vec<[u8; 16]> = vec![];
+        for uuid_box in self.uuid.iter().filter(|uuid_box| uuid_box.is_metadata()) {
+            if decoded.contains(&uuid_box.uuid) {
+                warn!("Duplicate metadata UUID box at {}", uuid_box.offset());
+                continue;
+            }
+            decoded.push(uuid_box.uuid);
+
+            let malformed = || JP2Error::BoxMalformed {
+                box_type: BOX_TYPE_UUID,
+                offset: uuid_box.offset(),
+            };
+            match uuid_box.uuid {
+                UUID_XMP => {
+                    let xmp = str::from_utf8(&uuid_box.data).map_err(|_| malformed())?;
+                    metadata.xmp = Some(xmp.trim_end_matches('\0').to_owned());
+                }
+                UUID_EXIF => metadata.exif = decode_exif(&uuid_box.data).ok_or_else(malformed)?,
+                _ => metadata.iptc = decode_iptc(&uuid_box.data).ok_or_else(malformed)?,
+            }
+        }
+        Ok(metadata)
+    }
+}
@@ -0,0 +1,326 @@
+// Evaluation of the Reader Requirements box, ISO/IEC 15444-2 M.11.1.
+//
+// Each bit of the masks is a term, the features whose mask has the bit set
+// are all needed to satisfy the term. The file is fully understood when any of
+// the terms selected by the Fully Understand Aspects mask is satisfied, and
+// decoded completely when any of the terms selected by the Decode Completely
+// mask is satisfied.
+
+use std::fmt;
+
+use super::jpx::{JPXFile, ReaderRequirementsBox};
+
+// Description of a standard feature, ISO/IEC 15444-2 Table M.14 and its
+// amendments.
+fn standard_feature_description(feature: u16) -> Option<&'static str> {
+    let description = match feature {
+        0 => "File not completely understood",
+        1 => "Codestream contains no extensions (deprecated)",
+        2 => "Contains multiple composition layers",
+        3 => "JPEG 2000 Part 1 Profile 0 codestream (deprecated)",
+        4 => "JPEG 2000 Part 1 Profile 1 codestream",
+        5 => "Unrestricted JPEG 2000 Part 1 codestream",
+        6 => "Unrestricted JPEG 2000 Part 2 codestream",
+        7 => "JPEG codestream as defined in ISO/IEC 10918-1",
+        8 => "Does not contain opacity (deprecated)",
+        9 => "Non-premultiplied opacity channel",
+        10 => "Premultiplied opacity channel",
+        11 => "Chroma-key based opacity",
+        12 => "Codestream is contiguous (deprecated)",
+        13 => "Fragmented codestream, all fragments in file and in order",
+        14 => "Fragmented codestream, all fragments in file but out of order (deprecated)",
+        15 => "Fragmented codestream, fragments in locally accessible files",
+        16 => "Fragmented codestream, fragments accessible through a network URL",
+        17 => "Compositing required to produce the rendered result",
+        18 => "Support for compositing is not required (deprecated)",
+        19 => "Multiple discrete layers not combined (deprecated)",
+        20 => "Compositing layers contain a single codestream (deprecated)",
+        21 => "Compositing layers consist of multiple codestreams (deprecated)",
+        22 => "Compositing layers in the same colourspace (deprecated)",
+        23 => "Compositing layers in different colourspaces (deprecated)",
+        24 => "Rendered result created without animation (deprecated)",
+        25 => "Animated, first layer covers the entire area and is opaque (deprecated)",
+        26 => "Animated, first layer does not cover the entire area (deprecated)",
+        27 => "Animated, no layer is reused (deprecated)",
+        28 => "Animated, layers are reused",
+        29 => "Animated with persistent frames only (deprecated)",
+        30 => "Animated with non-persistent frames",
+        31 => "Rendered result created without scaling (deprecated)",
+        32 => "Rendered result involves scaling within a layer",
+        33 => "Rendered result involves scaling between layers",
+        34 => "ROI metadata",
+        35 => "IPR metadata",
+        36 => "Content metadata",
+        37 => "History metadata",
+        38 => "Creation metadata",
+        39 => "JPX digital signatures",
+        40 => "JPX checksums",
+        41 => "Desired Graphics Arts Reproduction specified",
+        42 => "Compositing layer uses palettised colour (deprecated)",
+        43 => "Compositing layer uses restricted ICC profile (deprecated)",
+        44 => "Compositing layer uses Any ICC profile",
+        45 => "Compositing layer uses sRGB enumerated colourspace (deprecated)",
+        46 => "Compositing layer uses sRGB-grey enumerated colourspace (deprecated)",
+        47 => "BiLevel 1 enumerated colourspace",
+        48 => "BiLevel 2 enumerated colourspace",
+        49 => "YCbCr 1 enumerated colourspace",
+        50 => "YCbCr 2 enumerated colourspace",
+        51 => "YCbCr 3 enumerated colourspace",
+        52 => "PhotoYCC enumerated colourspace",
+        53 => "YCCK enumerated colourspace",
+        54 => "CMY enumerated colourspace",
+        55 => "CMYK enumerated colourspace",
+        56 => "CIELab enumerated colourspace with default parameters",
+        57 => "CIELab enumerated colourspace with non-default parameters",
+        58 => "CIEJab enumerated colourspace with default parameters",
+        59 => "CIEJab enumerated colourspace with non-default parameters",
+        60 => "e-sRGB enumerated colourspace",
+        61 => "ROMM-RGB enumerated colourspace",
+        62 => "Non-square samples",
+        63 => "Layers have labels (deprecated)",
+        64 => "Codestreams have labels (deprecated)",
+        65 => "Compositing layers have different colour spaces (deprecated)",
+        66 => "Compositing layers have different metadata (deprecated)",
+        67 => "GIS metadata XML box",
+        68 => "JPSEC extensions in codestream",
+        69 => "JP3D extensions in codestream",
+        70 => "Compositing layer uses sYCC enumerated colourspace (deprecated)",
+        71 => "e-sYCC enumerated colourspace",
+        72 => "JPEG 2000 Part 2 codestream restricted by baseline conformance",
+        73 => "YPbPr(1125/60) enumerated colourspace",
+        74 => "YPbPr(1250/50) enumerated colourspace",
+        _ => return None,
+    };
+    Some(description)
+}
+
+// Standard features read and interpreted by this crate: codestreams in
+// Contiguous Codestream boxes or in fragments of local files, opacity
+// signalled by Channel Definition or Opacity boxes, single layer files without
+// animation or scaling, metadata boxes and the colourspaces of the JP2 file
+// format. Features of the codestream itself need a codestream decoder, which
+// this crate does not provide.
+const SUPPORTED_STANDARD_FEATURES: [u16; 23] = [
+    8, 9, 10, 12, 13, 14, 15, 18, 20, 22, 24, 31, 34, 35, 36, 37, 38, 42, 43, 45, 46, 63, 64,
+];
+
+/// A feature listed by a Reader Requirements box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Standard feature, ISO/IEC 15444-2 Table M.14.
+    Standard(u16),
+    /// Vendor feature, identified by a UUID.
+    Vendor([u8; 16]),
+}
+
+impl Feature {
+    /// Description of a standard feature, None for vendor features and
+    /// reserved standard features.
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            Feature::Standard(feature) => standard_feature_description(*feature),
+            Feature::Vendor(_) => None,
+        }
+    }
+
+    /// Whether this crate reads and interprets the feature, vendor features
+    /// are not supported.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            Feature::Standard(feature) => SUPPORTED_STANDARD_FEATURES.contains(feature),
+            Feature::Vendor(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Feature::Standard(feature) => match self.description() {
+                Some(description) => write!(f, "{} ({})", feature, description),
+                None => write!(f, "{} (reserved)", feature),
+            },
+            Feature::Vendor(uuid) => {
+                for byte in uuid {
+                    write!(f, "{:02x}", byte)?;
+                }
+                write!(f, " (vendor)")
+            }
+        }
+    }
+}
+
+/// A feature listed by a Reader Requirements box, with what it is needed for.
+///
+/// A feature is needed to fully understand or to decode the file when it is
+/// part of a term selected by the Fully Understand Aspects or the Decode
+/// Completely mask, another term may be satisfied without it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureRequirement {
+    feature: Feature,
+    fully_understand: bool,
+    decode_completely: bool,
+}
+
+impl FeatureRequirement {
+    pub fn feature(&self) -> Feature {
+        self.feature
+    }
+
+    /// Whether the feature is part of a term needed to fully understand the
+    /// file.
+    pub fn is_required_to_understand(&self) -> bool {
+        self.fully_understand
+    }
+
+    /// Whether the feature is part of a term needed to decode the file
+    /// completely.
+    pub fn is_required_to_decode(&self) -> bool {
+        self.decode_completely
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.feature.is_supported()
+    }
+}
+
+/// Evaluation of the Reader Requirements box of a file against the features
+/// supported by this crate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequirementsReport {
+    requirements: Vec<FeatureRequirement>,
+
+    // Terms selected by FUAM and DCM, the features needed by each term.
+    fully_understand_terms: Vec<Vec<Feature>>,
+    decode_completely_terms: Vec<Vec<Feature>>,
+}
+
+impl RequirementsReport {
+    /// Every feature listed by the box, in the order of the box.
+    pub fn requirements(&self) -> &[FeatureRequirement] {
+        &self.requirements
+    }
+
+    /// Features needed to fully understand or to decode the file.
+    pub fn required_features(&self) -> Vec<Feature> {
+        self.requirements
+            .iter()
+            .filter(|requirement| requirement.fully_understand || requirement.decode_completely)
+            .map(|requirement| requirement.feature)
+            .collect()
+    }
+
+    /// Features needed to fully understand or to decode the file which are
+    /// not supported.
+    pub fn unsupported_features(&self) -> Vec<Feature> {
+        self.required_features()
+            .into_iter()
+            .filter(|feature| !feature.is_supported())
+            .collect()
+    }
+
+    /// Whether the features supported by this crate satisfy a term needed to
+    /// fully understand the file.
+    pub fn is_fully_understood(&self) -> bool {
+        self.is_fully_understood_with(&[])
+    }
+
+    /// Whether the features supported by this crate satisfy a term needed to
+    /// decode the file completely.
+    pub fn is_decoded_completely(&self) -> bool {
+        self.is_decoded_completely_with(&[])
+    }
+
+    /// Whether the features supported by this crate, together with the given
+    /// features, such as those of a codestream decoder, satisfy a term needed
+    /// to fully understand the file.
+    pub fn is_fully_understood_with(&self, features: &[Feature]) -> bool {
+        is_satisfied(&self.fully_understand_terms, features)
+    }
+
+    /// Whether the features supported by this crate, together with the given
+    /// features, such as those of a codestream decoder, satisfy a term needed
+    /// to decode the file completely.
+    pub fn is_decoded_completely_with(&self, features: &[Feature]) -> bool {
+        is_satisfied(&self.decode_completely_terms, features)
+    }
+}
+
+// Whether any of the terms has all its features supported, a mask without any
+// bit set has no terms to satisfy.
+fn is_satisfied(terms: &[Vec<Feature>], features: &[Feature]) -> bool {
+    terms.is_empty()
+        || terms.iter().any(|term| {
+            term.iter()
+                .all(|feature| feature.is_supported() || features.contains(feature))
+        })
+}
+
+// Whether a bit, counted from the MSB of the first byte, is set in a mask.
+fn is_bit_set(mask: &[u8], bit: usize) -> bool {
+    mask.get(bit / 8)
+        .is_some_and(|byte| byte & (0x80 >> (bit % 8)) != 0)
+}
+
+// Whether two masks of the same length have a bit set in common.
+fn intersects(mask: &[u8], other: &[u8]) -> bool {
+    mask.iter().zip(other).any(|(a, b)| a & b != 0)
+}
+
+// The terms selected by a mask, each term is the features whose mask has the
+// selecting bit set.
+fn terms(selecting_mask: &[u8], features: &[(Feature, &[u8])]) -> Vec<Vec<Feature>> {
+    (0..selecting_mask.len() * 8)
+        .filter(|bit| is_bit_set(selecting_mask, *bit))
+        .map(|bit| {
+            features
+                .iter()
+                .filter(|(_, mask)| is_bit_set(mask, bit))
+                .map(|(feature, _)| *feature)
+                .collect()
+        })
+        .collect()
+}
+
+impl ReaderRequirementsBox {
+    /// Evaluate the features listed by the box against the features supported
+    /// by this crate.
+    pub fn evaluate(&self) -> RequirementsReport {
+        let fully_understand_aspects_mask = self.fully_understand_aspects_mask();
+        let decode_completely_mask = self.decode_completely_mask();
+
+        let standard_features = self
+            .standard_features()
+            .iter()
+            .map(|feature| (Feature::Standard(feature.feature()), feature.mask()));
+        let vendor_features = self
+            .vendor_features()
+            .iter()
+            .map(|feature| (Feature::Vendor(feature.feature()), feature.mask()));
+        let features: Vec<(Feature, &[u8])> = standard_features.chain(vendor_features).collect();
+
+        RequirementsReport {
+            requirements: features
+                .iter()
+                .map(|(feature, mask)| FeatureRequirement {
+                    feature: *feature,
+                    fully_understand: intersects(mask, fully_understand_aspects_mask),
+                    decode_completely: intersects(mask, decode_completely_mask),
+                })
+                .collect(),
+            fully_understand_terms: terms(fully_understand_aspects_mask, &features),
+            decode_completely_terms: terms(decode_completely_mask, &features),
+        }
+    }
+}
+
+impl JPXFile {
+    /// Evaluate the Reader Requirements box of the file, a file without the
+    /// box has no requirements beyond the JP2 file format.
+    pub fn reader_requirements(&self) -> RequirementsReport {
+        match self.reader_requirements_box() {
+            Some(reader_requirements_box) => reader_requirements_box.evaluate(),
+            None => RequirementsReport::default(),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Image file directories of TIFF 6.0, as embedded in the UUID boxes of
+// GeoJP2 (a degenerate GeoTIFF) and of Exif metadata.
+
+pub(crate) const FIELD_TYPE_BYTE: u16 = 1;
+pub(crate) const FIELD_TYPE_ASCII: u16 = 2;
+pub(crate) const FIELD_TYPE_SHORT: u16 = 3;
+pub(crate) const FIELD_TYPE_LONG: u16 = 4;
+pub(crate) const FIELD_TYPE_RATIONAL: u16 = 5;
+pub(crate) const FIELD_TYPE_SBYTE: u16 = 6;
+pub(crate) const FIELD_TYPE_UNDEFINED: u16 = 7;
+pub(crate) const FIELD_TYPE_SSHORT: u16 = 8;
+pub(crate) const FIELD_TYPE_SLONG: u16 = 9;
+pub(crate) const FIELD_TYPE_SRATIONAL: u16 = 10;
+pub(crate) const FIELD_TYPE_FLOAT: u16 = 11;
+pub(crate) const FIELD_TYPE_DOUBLE: u16 = 12;
+
+// Size in bytes of a value of the field type, None for unknown types.
+fn field_type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        FIELD_TYPE_BYTE | FIELD_TYPE_ASCII | FIELD_TYPE_SBYTE | FIELD_TYPE_UNDEFINED => Some(1),
+        FIELD_TYPE_SHORT | FIELD_TYPE_SSHORT => Some(2),
+        FIELD_TYPE_LONG | FIELD_TYPE_SLONG | FIELD_TYPE_FLOAT => Some(4),
+        FIELD_TYPE_RATIONAL | FIELD_TYPE_SRATIONAL | FIELD_TYPE_DOUBLE => Some(8),
+        _ => None,
+    }
+}
+
+// A TIFF file of either byte order.
+pub(crate) struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+// A field of an IFD, with the offset of its values, which are stored in the
+// entry when they fit in 4 bytes. Fields of unknown types have no values.
+pub(crate) struct Field {
+    pub(crate) tag: u16,
+    pub(crate) field_type: u16,
+    pub(crate) count: u32,
+    value_offset: usize,
+}
+
+impl<'a> Tiff<'a> {
+    // The TIFF of the data, None if the header is invalid.
+    pub(crate) fn new(data: &'a [u8]) -> Option<Tiff<'a>> {
+        let little_endian = match data.get(0..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Tiff {
+            data,
+            little_endian,
+        })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let bytes = self.data.get(offset..offset.checked_add(N)?)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        if self.little_endian {
+            array.reverse();
+        }
+        Some(array)
+    }
+
+    // Values are read from big endian bytes, little endian bytes are
+    // reversed first.
+    pub(crate) fn u16(&self, offset: usize) -> Option<u16> {
+        self.bytes(offset).map(u16::from_be_bytes)
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Option<u32> {
+        self.bytes(offset).map(u32::from_be_bytes)
+    }
+
+    pub(crate) fn f32(&self, offset: usize) -> Option<f32> {
+        self.bytes(offset).map(f32::from_be_bytes)
+    }
+
+    pub(crate) fn f64(&self, offset: usize) -> Option<f64> {
+        self.bytes(offset).map(f64::from_be_bytes)
+    }
+
+    // Offset of the first IFD.
+    pub(crate) fn first_ifd(&self) -> Option<usize> {
+        Some(self.u32(4)? as usize)
+    }
+
+    // Fields of the IFD at the offset, and the offset of the next IFD, 0 for
+    // the last IFD.
+    pub(crate) fn ifd(&self, offset: usize) -> Option<(Vec<Field>, usize)> {
+        let count = self.u16(offset)? as usize;
+        let fields = (0..count)
+            .map(|index| {
+                let entry = offset + 2 + index * 12;
+                let field_type = self.u16(entry + 2)?;
+                let count = self.u32(entry + 4)?;
+                let size = field_type_size(field_type).unwrap_or(0);
+                let value_offset = if size.checked_mul(count as usize)? <= 4 {
+                    entry + 8
+                } else {
+                    self.u32(entry + 8)? as usize
+                };
+                Some(Field {
+                    tag: self.u16(entry)?,
+                    field_type,
+                    count,
+                    value_offset,
+                })
+            })
+            .collect::<Option<_>>()?;
+        let next = self.u32(offset + 2 + count * 12)? as usize;
+        Some((fields, next))
+    }
+
+    // The values of a field of known type, each read by the function from
+    // its offset.
+    pub(crate) fn values<T, F: Fn(&Self, usize) -> Option<T>>(
+        &self,
+        field: &Field,
+        read: F,
+    ) -> Option<Vec<T>> {
+        let size = field_type_size(field.field_type)?;
+        let end = field
+            .value_offset
+            .checked_add(size.checked_mul(field.count as usize)?)?;
+        if end > self.data.len() {
+            return None;
+        }
+        (0..field.count as usize)
+            .map(|index| read(self, field.value_offset + index * size))
+            .collect()
+    }
+
+    pub(crate) fn raw(&self, field: &Field) -> Option<&'a [u8]> {
+        let size = field_type_size(field.field_type)?;
+        let length = size.checked_mul(field.count as usize)?;
+        self.data
+            .get(field.value_offset..field.value_offset.checked_add(length)?)
+    }
+
+    pub(crate) fn shorts(&self, field: &Field) -> Option<Vec<u16>> {
+        if field.field_type != FIELD_TYPE_SHORT {
+            return None;
+        }
+        self.values(field, Tiff::u16)
+    }
+
+    pub(crate) fn doubles(&self, field: &Field) -> Option<Vec<f64>> {
+        if field.field_type != FIELD_TYPE_DOUBLE {
+            return None;
+        }
+        self.values(field, Tiff::f64)
+    }
+
+    // An ASCII field, up to its first NUL.
+    pub(crate) fn ascii(&self, field: &Field) -> Option<String> {
+        if field.field_type != FIELD_TYPE_ASCII {
+            return None;
+        }
+        let bytes = self.raw(field)?;
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
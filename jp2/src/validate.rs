@@ -0,0 +1,569 @@
+// Validation of JP2 files, reporting each requirement of ISO/IEC 15444-1
+// Annex I checked on a file as a test which passes or fails, with the
+// properties of the boxes, in the structure of the reports of jpylyzer.
+//
+// The boxes are located with BoxIterator and their contents are read from
+// the file rather than decoded into the boxes of decode_jp2, which rejects
+// files it cannot represent, so that any file is reported. The first
+// codestream is validated by jpc.
+
+use std::io::{self, Read};
+
+use jpc::ValidationReport;
+
+use super::conformance::codestream_mismatches;
+use super::{
+    AtOffset, BoxInfo, BoxIterator, BoxType, JP2Error, Nonconformance, BOX_TYPE_BITS_PER_COMPONENT,
+    BOX_TYPE_CHANNEL_DEFINITION, BOX_TYPE_COLOUR_SPECIFICATION, BOX_TYPE_COMPONENT_MAPPING,
+    BOX_TYPE_CONTIGUOUS_CODESTREAM, BOX_TYPE_DATA_ENTRY_URL, BOX_TYPE_FILE_TYPE, BOX_TYPE_HEADER,
+    BOX_TYPE_IMAGE_HEADER, BOX_TYPE_INTELLECTUAL_PROPERTY, BOX_TYPE_PALETTE, BOX_TYPE_RESOLUTION,
+    BOX_TYPE_SIGNATURE, BOX_TYPE_UUID, BOX_TYPE_UUID_INFO, BOX_TYPE_UUID_LIST, BRAND_JP2,
+    COMPRESSION_TYPE_WAVELET, SIGNATURE_MAGIC,
+};
+
+// The fields of the Image Header box the file is checked against.
+struct ImageHeader {
+    height: u32,
+    width: u32,
+    components: u16,
+    bits: u8,
+    intellectual_property: u8,
+}
+
+fn read_contents<R: io::Read + io::Seek>(
+    reader: &mut R,
+    info: &BoxInfo,
+) -> Result<Vec<u8>, JP2Error> {
+    reader
+        .seek(io::SeekFrom::Start(info.offset))
+        .at(info.offset)?;
+    let mut contents: Vec<u8> = vec![];
+    reader
+        .by_ref()
+        .take(info.length)
+        .read_to_end(&mut contents)
+        .at(info.offset)?;
+    Ok(contents)
+}
+
+// The boxes directly contained by a superbox.
+fn children<'a>(boxes: &'a [BoxInfo], superbox: &BoxInfo) -> Vec<&'a BoxInfo> {
+    boxes
+        .iter()
+        .filter(|info| {
+            info.depth == superbox.depth + 1
+                && info.start() >= superbox.offset
+                && info.end() <= superbox.end()
+        })
+        .collect()
+}
+
+fn count(boxes: &[&BoxInfo], box_type: BoxType) -> usize {
+    boxes
+        .iter()
+        .filter(|info| info.box_type == box_type)
+        .count()
+}
+
+fn find<'a>(boxes: &[&'a BoxInfo], box_type: BoxType) -> Option<&'a BoxInfo> {
+    boxes.iter().find(|info| info.box_type == box_type).copied()
+}
+
+fn position(boxes: &[&BoxInfo], box_type: BoxType) -> Option<usize> {
+    boxes.iter().position(|info| info.box_type == box_type)
+}
+
+// A bit depth as encoded by BPC, the Bits Per Component box and Ssiz.
+fn add_bit_depth(report: &mut ValidationReport, bits: u8) {
+    let signedness = if bits & 0x80 != 0 {
+        "signed"
+    } else {
+        "unsigned"
+    };
+    report.add_property("bPCSign", signedness);
+    report.add_property("bPCDepth", (bits & 0x7f) + 1);
+}
+
+/// Validate a JP2 file into a report named `jp2`, with the tests and
+/// properties of its boxes and of its first codestream. Only errors reading
+/// the file and boxes nested beyond the default `Limits` are returned, a file
+/// which is not a valid JP2 file fails tests of the report.
+pub fn validate_jp2<R: io::Read + io::Seek>(reader: &mut R) -> Result<ValidationReport, JP2Error> {
+    validate_file(reader)
+}
+
+fn validate_file<R: io::Read + io::Seek>(reader: &mut R) -> Result<ValidationReport, JP2Error> {
+    let mut report = ValidationReport::new("jp2");
+
+    // Boxes are found up to the first box which extends beyond its superbox
+    // or the file.
+    let mut boxes: Vec<BoxInfo> = vec![];
+    let mut box_lengths_are_valid = true;
+    for info in BoxIterator::new(reader) {
+        match info {
+            Ok(info) => boxes.push(info),
+            Err(error @ JP2Error::Limit { .. }) => return Err(error),
+            Err(_) => {
+                box_lengths_are_valid = false;
+                break;
+            }
+        }
+    }
+    let top_level: Vec<&BoxInfo> = boxes.iter().filter(|info| info.depth == 0).collect();
+
+    // I.4 - Signature and File Type boxes first, the JP2 Header box before
+    // the Contiguous Codestream box
+    report.add_test("boxLengthsAreValid", box_lengths_are_valid);
+    for (test, box_type) in [
+        ("containsSignatureBox", BOX_TYPE_SIGNATURE),
+        ("containsFileTypeBox", BOX_TYPE_FILE_TYPE),
+        ("containsJP2HeaderBox", BOX_TYPE_HEADER),
+        (
+            "containsContiguousCodestreamBox",
+            BOX_TYPE_CONTIGUOUS_CODESTREAM,
+        ),
+    ] {
+        report.add_test(test, count(&top_level, box_type) > 0);
+    }
+    report.add_test(
+        "firstBoxIsSignatureBox",
+        position(&top_level, BOX_TYPE_SIGNATURE) == Some(0),
+    );
+    report.add_test(
+        "secondBoxIsFileTypeBox",
+        position(&top_level, BOX_TYPE_FILE_TYPE) == Some(1),
+    );
+    report.add_test(
+        "locationJP2HeaderBoxIsValid",
+        match (
+            position(&top_level, BOX_TYPE_HEADER),
+            position(&top_level, BOX_TYPE_CONTIGUOUS_CODESTREAM),
+        ) {
+            (Some(header), Some(codestream)) => header < codestream,
+            _ => false,
+        },
+    );
+    for (test, box_type) in [
+        ("noMoreThanOneSignatureBox", BOX_TYPE_SIGNATURE),
+        ("noMoreThanOneFileTypeBox", BOX_TYPE_FILE_TYPE),
+        ("noMoreThanOneJP2HeaderBox", BOX_TYPE_HEADER),
+    ] {
+        report.add_test(test, count(&top_level, box_type) <= 1);
+    }
+
+    if let Some(info) = find(&top_level, BOX_TYPE_SIGNATURE) {
+        report.add_child(validate_signature_box(&read_contents(reader, info)?));
+    }
+    if let Some(info) = find(&top_level, BOX_TYPE_FILE_TYPE) {
+        report.add_child(validate_file_type_box(&read_contents(reader, info)?));
+    }
+    let mut image_header = None;
+    let mut bits_per_component = None;
+    if let Some(info) = find(&top_level, BOX_TYPE_HEADER) {
+        let header_boxes = children(&boxes, info);
+        let mut header_report = validate_header_box(&header_boxes);
+        if let Some(info) = find(&header_boxes, BOX_TYPE_IMAGE_HEADER) {
+            let (image_header_report, header) =
+                validate_image_header_box(&read_contents(reader, info)?);
+            header_report.add_child(image_header_report);
+            image_header = header;
+        }
+        if let Some(info) = find(&header_boxes, BOX_TYPE_BITS_PER_COMPONENT) {
+            let contents = read_contents(reader, info)?;
+            header_report.add_child(validate_bits_per_component_box(
+                &contents,
+                image_header.as_ref().map(|header| header.components),
+            ));
+            bits_per_component = Some(contents);
+        }
+        for (index, info) in header_boxes
+            .iter()
+            .filter(|info| info.box_type == BOX_TYPE_COLOUR_SPECIFICATION)
+            .enumerate()
+        {
+            header_report.add_child(validate_colour_specification_box(
+                &read_contents(reader, info)?,
+                index == 0,
+            ));
+        }
+        // The Bits Per Component box shall be present if and only if the bit
+        // depths of the components vary, a BPC of 255.
+        match &image_header {
+            Some(header) if header.bits == 255 => {
+                header_report.add_test("containsBitsPerComponentBox", bits_per_component.is_some())
+            }
+            Some(_) => {
+                header_report.add_test("noBitsPerComponentBox", bits_per_component.is_none())
+            }
+            None => {}
+        }
+        report.add_child(header_report);
+    }
+
+    if let Some(info) = find(&top_level, BOX_TYPE_CONTIGUOUS_CODESTREAM) {
+        reader
+            .seek(io::SeekFrom::Start(info.offset))
+            .at(info.offset)?;
+        let mut codestream_report = ValidationReport::new("contiguousCodestreamBox");
+        let codestream =
+            jpc::validate_jpc(&mut reader.by_ref().take(info.length)).map_err(|source| {
+                JP2Error::Codestream {
+                    offset: info.offset,
+                    source,
+                }
+            })?;
+        codestream_report.append(codestream);
+        report.add_child(codestream_report);
+
+        // I.5.3.1 - The Image Header box repeats the SIZ marker segment of
+        // the first codestream, checked as by decode_jp2
+        if let Some(header) = &image_header {
+            reader
+                .seek(io::SeekFrom::Start(info.offset))
+                .at(info.offset)?;
+            let siz = jpc::decode_image_and_tile_size(reader).ok();
+            report_codestream_consistency(
+                &mut report,
+                header,
+                bits_per_component.as_deref(),
+                siz.as_ref(),
+            );
+        }
+    }
+
+    // I.6 - The Intellectual Property box is present if the IPR field is 1
+    if let Some(header) = &image_header {
+        if header.intellectual_property == 1 {
+            report.add_test(
+                "containsIntellectualPropertyBox",
+                count(&top_level, BOX_TYPE_INTELLECTUAL_PROPERTY) > 0,
+            );
+        }
+    }
+
+    for info in top_level
+        .iter()
+        .filter(|info| info.box_type == BOX_TYPE_UUID)
+    {
+        report.add_child(validate_uuid_box(reader, info)?);
+    }
+    for info in top_level
+        .iter()
+        .filter(|info| info.box_type == BOX_TYPE_UUID_INFO)
+    {
+        report.add_child(validate_uuid_info_box(&children(&boxes, info)));
+    }
+
+    Ok(report)
+}
+
+// I.5.1 - Signature box
+fn validate_signature_box(contents: &[u8]) -> ValidationReport {
+    let mut report = ValidationReport::new("signatureBox");
+    report.add_test("boxLengthIsValid", contents.len() == 4);
+    report.add_test("signatureIsValid", contents == SIGNATURE_MAGIC);
+    report
+}
+
+// I.5.2 - File Type box
+fn validate_file_type_box(contents: &[u8]) -> ValidationReport {
+    let mut report = ValidationReport::new("fileTypeBox");
+    let length_is_valid = contents.len() >= 8 && contents.len().is_multiple_of(4);
+    report.add_test("boxLengthIsValid", length_is_valid);
+    if contents.len() < 8 {
+        return report;
+    }
+    let brand = &contents[0..4];
+    let min_version = u32::from_be_bytes([contents[4], contents[5], contents[6], contents[7]]);
+    let compatibility_list: Vec<&[u8]> = contents[8..].chunks_exact(4).collect();
+
+    report.add_property("br", String::from_utf8_lossy(brand));
+    report.add_property("minV", min_version);
+    for compatibility in &compatibility_list {
+        report.add_property("cL", String::from_utf8_lossy(compatibility));
+    }
+    report.add_test("brandIsValid", brand == BRAND_JP2);
+    report.add_test("minorVersionIsValid", min_version == 0);
+    report.add_test(
+        "compatibilityListIsValid",
+        compatibility_list.contains(&&BRAND_JP2[..]),
+    );
+    report
+}
+
+// I.5.3 - JP2 Header box, the order and number of the boxes it contains
+fn validate_header_box(boxes: &[&BoxInfo]) -> ValidationReport {
+    let mut report = ValidationReport::new("jp2HeaderBox");
+    report.add_test(
+        "containsImageHeaderBox",
+        count(boxes, BOX_TYPE_IMAGE_HEADER) > 0,
+    );
+    report.add_test(
+        "containsColourSpecificationBox",
+        count(boxes, BOX_TYPE_COLOUR_SPECIFICATION) > 0,
+    );
+    report.add_test(
+        "firstJP2HeaderBoxIsImageHeaderBox",
+        position(boxes, BOX_TYPE_IMAGE_HEADER) == Some(0),
+    );
+    for (test, box_type) in [
+        ("noMoreThanOneImageHeaderBox", BOX_TYPE_IMAGE_HEADER),
+        (
+            "noMoreThanOneBitsPerComponentBox",
+            BOX_TYPE_BITS_PER_COMPONENT,
+        ),
+        ("noMoreThanOnePaletteBox", BOX_TYPE_PALETTE),
+        (
+            "noMoreThanOneComponentMappingBox",
+            BOX_TYPE_COMPONENT_MAPPING,
+        ),
+        (
+            "noMoreThanOneChannelDefinitionBox",
+            BOX_TYPE_CHANNEL_DEFINITION,
+        ),
+        ("noMoreThanOneResolutionBox", BOX_TYPE_RESOLUTION),
+    ] {
+        report.add_test(test, count(boxes, box_type) <= 1);
+    }
+
+    // Colour Specification boxes follow each other
+    let colour_specifications: Vec<usize> = boxes
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| info.box_type == BOX_TYPE_COLOUR_SPECIFICATION)
+        .map(|(index, _)| index)
+        .collect();
+    report.add_test(
+        "colourSpecificationBoxesAreContiguous",
+        colour_specifications
+            .windows(2)
+            .all(|indices| indices[1] == indices[0] + 1),
+    );
+    // I.5.3.5 - The Component Mapping box is present if and only if the
+    // Palette box is
+    report.add_test(
+        "paletteAndComponentMappingBoxesOnlyTogether",
+        (count(boxes, BOX_TYPE_PALETTE) > 0) == (count(boxes, BOX_TYPE_COMPONENT_MAPPING) > 0),
+    );
+    report
+}
+
+// I.5.3.1 - Image Header box
+fn validate_image_header_box(contents: &[u8]) -> (ValidationReport, Option<ImageHeader>) {
+    let mut report = ValidationReport::new("imageHeaderBox");
+    report.add_test("boxLengthIsValid", contents.len() == 14);
+    if contents.len() < 14 {
+        return (report, None);
+    }
+    let header = ImageHeader {
+        height: u32::from_be_bytes([contents[0], contents[1], contents[2], contents[3]]),
+        width: u32::from_be_bytes([contents[4], contents[5], contents[6], contents[7]]),
+        components: u16::from_be_bytes([contents[8], contents[9]]),
+        bits: contents[10],
+        intellectual_property: contents[13],
+    };
+    let (compression_type, colourspace_unknown) = (contents[11], contents[12]);
+
+    report.add_property("height", header.height);
+    report.add_property("width", header.width);
+    report.add_property("nC", header.components);
+    if header.bits == 255 {
+        report.add_property("bPCSign", "variable");
+        report.add_property("bPCDepth", "variable");
+    } else {
+        add_bit_depth(&mut report, header.bits);
+    }
+    report.add_property("c", compression_type);
+    report.add_property("unkC", colourspace_unknown);
+    report.add_property("iPR", header.intellectual_property);
+
+    report.add_test("heightIsValid", header.height > 0);
+    report.add_test("widthIsValid", header.width > 0);
+    report.add_test("nCIsValid", (1..=16384).contains(&header.components));
+    report.add_test("bPCIsValid", header.bits == 255 || header.bits & 0x7f < 38);
+    report.add_test("cIsValid", compression_type == COMPRESSION_TYPE_WAVELET);
+    report.add_test("unkCIsValid", colourspace_unknown <= 1);
+    report.add_test("iPRIsValid", header.intellectual_property <= 1);
+
+    (report, Some(header))
+}
+
+// I.5.3.2 - Bits Per Component box
+fn validate_bits_per_component_box(contents: &[u8], components: Option<u16>) -> ValidationReport {
+    let mut report = ValidationReport::new("bitsPerComponentBox");
+    for bits in contents {
+        add_bit_depth(&mut report, *bits);
+    }
+    report.add_test(
+        "boxLengthIsValid",
+        components.is_none_or(|components| contents.len() == components as usize),
+    );
+    report.add_test("bPCIsValid", contents.iter().all(|bits| bits & 0x7f < 38));
+    report
+}
+
+// I.5.3.3 - Colour Specification box
+//
+// The approximation of the values of ISO/IEC 15444-2 up to 4 is accepted as
+// used by the conformance files of ISO/IEC 15444-4. Colour Specification
+// boxes after the first are ignored by JP2 readers, and may be meant for JPX
+// readers with the methods and colourspaces of ISO/IEC 15444-2, so only the
+// first is required to be a JP2 colour specification.
+fn validate_colour_specification_box(contents: &[u8], first: bool) -> ValidationReport {
+    let mut report = ValidationReport::new("colourSpecificationBox");
+    if contents.len() < 3 {
+        report.add_test("boxLengthIsValid", false);
+        return report;
+    }
+    let (method, precedence, approximation) = (contents[0], contents[1] as i8, contents[2]);
+    report.add_property(
+        "meth",
+        match method {
+            1 => "Enumerated",
+            2 => "Restricted ICC",
+            _ => "other",
+        },
+    );
+    report.add_property("prec", precedence);
+    report.add_property("approx", approximation);
+    let colourspace = match contents {
+        [1, _, _, a, b, c, d] => Some(u32::from_be_bytes([*a, *b, *c, *d])),
+        _ => None,
+    };
+    if let Some(colourspace) = colourspace {
+        report.add_property(
+            "enumCS",
+            match colourspace {
+                16 => "sRGB".to_owned(),
+                17 => "greyscale".to_owned(),
+                18 => "sYCC".to_owned(),
+                _ => colourspace.to_string(),
+            },
+        );
+    }
+    if method == 2 {
+        report.add_property("iccSize", contents.len() - 3);
+    }
+
+    report.add_test("approxIsValid", approximation <= 4);
+    if !first {
+        return report;
+    }
+    report.add_test("methIsValid", method == 1 || method == 2);
+    report.add_test("precIsValid", precedence == 0);
+    match method {
+        1 => {
+            report.add_test("boxLengthIsValid", colourspace.is_some());
+            report.add_test(
+                "enumCSIsValid",
+                colourspace.is_some_and(|colourspace| (16..=18).contains(&colourspace)),
+            );
+        }
+        2 => {
+            report.add_test("boxLengthIsValid", contents.len() > 3);
+            report.add_test(
+                "iccProfileIsRestricted",
+                icc::check_restricted_profile(&contents[3..]).is_ok(),
+            );
+        }
+        _ => {}
+    }
+    report
+}
+
+fn report_codestream_consistency(
+    report: &mut ValidationReport,
+    header: &ImageHeader,
+    bits_per_component: Option<&[u8]>,
+    siz: Option<&jpc::ImageAndTileSizeMarkerSegment>,
+) {
+    let siz = match siz {
+        Some(siz) => siz,
+        None => {
+            for test in [
+                "heightConsistentWithSIZ",
+                "widthConsistentWithSIZ",
+                "nCConsistentWithSIZ",
+                "bPCConsistentWithSIZ",
+            ] {
+                report.add_test(test, false);
+            }
+            return;
+        }
+    };
+    let mismatches = codestream_mismatches(
+        header.height,
+        header.width,
+        header.components,
+        header.bits,
+        bits_per_component,
+        siz,
+    );
+    let passes = |test: fn(&Nonconformance) -> bool| !mismatches.iter().any(test);
+    report.add_test(
+        "heightConsistentWithSIZ",
+        passes(|mismatch| matches!(mismatch, Nonconformance::HeightMismatch { .. })),
+    );
+    report.add_test(
+        "widthConsistentWithSIZ",
+        passes(|mismatch| matches!(mismatch, Nonconformance::WidthMismatch { .. })),
+    );
+    report.add_test(
+        "nCConsistentWithSIZ",
+        passes(|mismatch| matches!(mismatch, Nonconformance::ComponentsMismatch { .. })),
+    );
+
+    // The bit depths are not compared without the Bits Per Component box
+    // for a BPC of 255, or for a number of components which differs.
+    report.add_test(
+        "bPCConsistentWithSIZ",
+        (header.bits != 255 || bits_per_component.is_some())
+            && passes(|mismatch| {
+                matches!(
+                    mismatch,
+                    Nonconformance::ComponentsMismatch { .. }
+                        | Nonconformance::BitDepthMismatch { .. }
+                )
+            }),
+    );
+}
+
+// I.7.2 - UUID box
+fn validate_uuid_box<R: io::Read + io::Seek>(
+    reader: &mut R,
+    info: &BoxInfo,
+) -> Result<ValidationReport, JP2Error> {
+    let mut report = ValidationReport::new("uuidBox");
+    report.add_test("boxLengthIsValid", info.length >= 16);
+    if info.length >= 16 {
+        let mut uuid = [0; 16];
+        reader
+            .seek(io::SeekFrom::Start(info.offset))
+            .at(info.offset)?;
+        reader.read_exact(&mut uuid).at(info.offset)?;
+        let hex: Vec<String> = uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+        report.add_property(
+            "uuid",
+            [
+                hex[0..4].concat(),
+                hex[4..6].concat(),
+                hex[6..8].concat(),
+                hex[8..10].concat(),
+                hex[10..16].concat(),
+            ]
+            .join("-"),
+        );
+    }
+    Ok(report)
+}
+
+// I.7.3 - UUID Info box
+fn validate_uuid_info_box(boxes: &[&BoxInfo]) -> ValidationReport {
+    let mut report = ValidationReport::new("uuidInfoBox");
+    report.add_test("containsOneListBox", count(boxes, BOX_TYPE_UUID_LIST) == 1);
+    report.add_test(
+        "containsOneURLBox",
+        count(boxes, BOX_TYPE_DATA_ENTRY_URL) == 1,
+    );
+    report
+}
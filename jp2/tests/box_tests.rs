@@ -0,0 +1,189 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Cursor, Read},
+    path::Path,
+};
+
+use jp2::{decode_jp2, encode_jp2, BoxInfo, BoxIterator, JBox, JP2Error};
+
+#[test]
+fn test_iterate_sample_file1() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let boxes: Vec<BoxInfo> = BoxIterator::new(&mut reader)
+        .collect::<Result<_, _>>()
+        .expect("iteration should succeed");
+
+    assert_eq!(
+        summary(&boxes),
+        vec![
+            ("jP  ".into(), 0, 8, 4),
+            ("ftyp".into(), 0, 20, 16),
+            ("xml ".into(), 0, 44, 431),
+            ("jp2h".into(), 0, 483, 37),
+            ("ihdr".into(), 1, 491, 14),
+            ("colr".into(), 1, 513, 7),
+            ("xml ".into(), 0, 528, 741),
+            ("jp2c".into(), 0, 1277, 649401),
+        ]
+    );
+    assert!(boxes.iter().all(|info| info.header_length == 8));
+    assert!(boxes[3].is_superbox());
+    assert_eq!(boxes[3].start(), 475);
+    assert_eq!(boxes[3].end(), boxes[5].end());
+}
+
+#[test]
+fn test_iterate_length_to_end_of_file() {
+    // The Contiguous Codestream box has a length of 0
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("subsampling_1.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let boxes: Vec<BoxInfo> = BoxIterator::new(&mut reader)
+        .collect::<Result<_, _>>()
+        .expect("iteration should succeed");
+
+    let contiguous_codestream_box = boxes.last().unwrap();
+    assert_eq!(contiguous_codestream_box.box_type, *b"jp2c");
+    assert_eq!(contiguous_codestream_box.offset, 113);
+    assert_eq!(contiguous_codestream_box.length, 666297);
+}
+
+#[test]
+fn test_iterate_nested_superboxes() {
+    // JP2 Header box with a Resolution box, a UUID Info box and a box using
+    // the XLBox field
+    let data: Vec<u8> = [
+        &[0, 0, 0, 46][..],
+        b"jp2h",
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0; 14],
+        &[0, 0, 0, 16],
+        b"res ",
+        &[0, 0, 0, 8],
+        b"resc",
+        &[0, 0, 0, 33],
+        b"uinf",
+        &[0, 0, 0, 25],
+        b"url ",
+        &[0; 4],
+        b"file:///a.j2c",
+        &[0, 0, 0, 1],
+        b"xml ",
+        &[0, 0, 0, 0, 0, 0, 0, 20],
+        b"<a/>",
+        b"\x00\x00\x00\x00jp2c",
+        b"\xff\x4f\xff\xd9",
+    ]
+    .concat();
+
+    let mut reader = Cursor::new(&data);
+    let mut iterator = BoxIterator::new(&mut reader);
+    let mut boxes: Vec<BoxInfo> = vec![];
+    while let Some(info) = iterator.next() {
+        let info = info.expect("iteration should succeed");
+        if info.box_type == *b"xml " {
+            // The reader can be used between boxes
+            let mut xml = vec![0; info.length as usize];
+            let reader = iterator.reader();
+            reader.set_position(info.offset);
+            reader.read_exact(&mut xml).unwrap();
+            assert_eq!(xml, b"<a/>");
+        }
+        boxes.push(info);
+    }
+
+    assert_eq!(
+        summary(&boxes),
+        vec![
+            ("jp2h".into(), 0, 8, 38),
+            ("ihdr".into(), 1, 16, 14),
+            ("res ".into(), 1, 38, 8),
+            ("resc".into(), 2, 46, 0),
+            ("uinf".into(), 0, 54, 25),
+            ("url ".into(), 1, 62, 17),
+            ("xml ".into(), 0, 95, 4),
+            ("jp2c".into(), 0, 107, 4),
+        ]
+    );
+    assert_eq!(boxes[6].header_length, 16);
+}
+
+#[test]
+fn test_iterate_box_beyond_superbox() {
+    // The Image Header box extends beyond the JP2 Header box
+    let data: Vec<u8> = [
+        &[0, 0, 0, 20][..],
+        b"jp2h",
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0; 14],
+    ]
+    .concat();
+
+    let mut reader = Cursor::new(&data);
+    let mut iterator = BoxIterator::new(&mut reader);
+    assert_eq!(iterator.next().unwrap().unwrap().box_type, *b"jp2h");
+    let error = iterator.next().unwrap().expect_err("iteration should fail");
+    match &error {
+        JP2Error::BoxMalformed { box_type, offset } => {
+            assert_eq!(*box_type, *b"ihdr");
+            assert_eq!(*offset, 8);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert!(iterator.next().is_none());
+}
+
+#[test]
+fn test_empty_box() {
+    // An empty box, with a LBox of 8, after the File Type box of file1.jp2
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let mut data = fs::read(path).expect("file should exist");
+    data.splice(36..36, [&[0, 0, 0, 8][..], b"abcd"].concat());
+
+    let boxes: Vec<BoxInfo> = BoxIterator::new(&mut Cursor::new(&data))
+        .collect::<Result<_, _>>()
+        .expect("iteration should succeed");
+    assert_eq!(
+        summary(&boxes)[1..4],
+        [
+            ("ftyp".into(), 0, 20, 16),
+            ("abcd".into(), 0, 44, 0),
+            ("xml ".into(), 0, 52, 431),
+        ]
+    );
+    assert_eq!(boxes.last().unwrap().length, 649401);
+
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    assert!(jp2.header_box().is_some());
+    assert_eq!(jp2.contiguous_codestreams_boxes().len(), 1);
+    assert_eq!(jp2.contiguous_codestreams_boxes()[0].length(), 649401);
+    assert_eq!(jp2.unknown_boxes().len(), 1);
+    assert_eq!(jp2.unknown_boxes()[0].identifier(), *b"abcd");
+    assert_eq!(jp2.unknown_boxes()[0].length(), 0);
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut Cursor::new(&data)).expect("encoding should succeed");
+    assert_eq!(encoded, data);
+}
+
+fn summary(boxes: &[BoxInfo]) -> Vec<(String, usize, u64, u64)> {
+    boxes
+        .iter()
+        .map(|info| {
+            (
+                String::from_utf8_lossy(&info.box_type).to_string(),
+                info.depth,
+                info.offset,
+                info.length,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,232 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use jp2::{
+    decode_jp2, map_channels, Channel, ChannelDefinitionBox, ChannelTypes, ColourSpecificationBox,
+    ComponentMap, ComponentMappingBox, ComponentSamples, GeneratedComponent, HeaderSuperBox,
+    ImageHeaderBox, JBox, JP2Error, PaletteBox,
+};
+
+fn unsigned(bit_depth: u8, samples: Vec<i32>) -> ComponentSamples {
+    ComponentSamples {
+        bit_depth,
+        signed: false,
+        samples,
+    }
+}
+
+#[test]
+fn test_palette_sample_file9() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file9.jp2");
+    let file = File::open(path).expect("file should exist");
+    let mut reader = BufReader::new(file);
+    let jp2 = decode_jp2(&mut reader).expect("decoding should succeed");
+
+    // The single component of file9 is mapped through the palette to three
+    // sRGB channels
+    let channels = jp2
+        .header_box()
+        .as_ref()
+        .unwrap()
+        .map_channels(&[unsigned(8, vec![1, 2, 0])])
+        .expect("mapping should succeed");
+    assert_eq!(channels.len(), 3);
+    for (index, channel) in channels.iter().enumerate() {
+        assert_eq!(channel.index(), index as u16);
+        assert_eq!(channel.channel_type(), ChannelTypes::ColourImageData);
+        assert_eq!(channel.colour(), Some(index as u16 + 1));
+        assert_eq!(channel.bit_depth(), 8);
+        assert!(!channel.signed());
+    }
+    assert_eq!(channels[0].samples()[..2], [0xff, 0x17]);
+    assert_eq!(channels[1].samples()[..2], [0xff, 0x0c]);
+    assert_eq!(channels[2].samples()[..2], [0xff, 0x15]);
+}
+
+#[test]
+fn test_channel_definition_order() {
+    // Components are stored as opacity, blue, green and red
+    let components = [
+        unsigned(8, vec![255, 128]),
+        unsigned(8, vec![3, 4]),
+        unsigned(8, vec![5, 6]),
+        unsigned(8, vec![7, 8]),
+    ];
+    let channel_definition_box = ChannelDefinitionBox::new(vec![
+        Channel::new(0, 1, 0),
+        Channel::new(1, 0, 3),
+        Channel::new(2, 0, 2),
+        Channel::new(3, 0, 1),
+    ]);
+
+    let channels = map_channels(&components, None, None, Some(&channel_definition_box), 3)
+        .expect("mapping should succeed");
+    let indices: Vec<u16> = channels.iter().map(|channel| channel.index()).collect();
+    assert_eq!(indices, [3, 2, 1, 0]);
+    assert_eq!(channels[0].samples(), [7, 8]);
+    assert_eq!(channels[3].channel_type(), ChannelTypes::Opacity);
+    assert!(channels[3].is_opacity());
+    assert!(!channels[3].is_premultiplied());
+    assert_eq!(channels[3].colour(), None);
+    assert_eq!(channels[3].association(), 0);
+}
+
+#[test]
+fn test_premultiplied_opacity_and_undefined_channels() {
+    let components = [
+        unsigned(8, vec![1]),
+        unsigned(8, vec![2]),
+        unsigned(8, vec![3]),
+    ];
+    // The second component is not described, the third is premultiplied
+    // opacity of the only colour
+    let channel_definition_box =
+        ChannelDefinitionBox::new(vec![Channel::new(2, 2, 1), Channel::new(0, 0, 1)]);
+
+    let channels = map_channels(&components, None, None, Some(&channel_definition_box), 1)
+        .expect("mapping should succeed");
+    let indices: Vec<u16> = channels.iter().map(|channel| channel.index()).collect();
+    assert_eq!(indices, [0, 2, 1]);
+    assert_eq!(
+        channels[1].channel_type(),
+        ChannelTypes::PremultipliedOpacity
+    );
+    assert!(channels[1].is_premultiplied());
+    assert_eq!(
+        channels[2].channel_type(),
+        ChannelTypes::Unspecified { value: 65535 }
+    );
+    assert_eq!(channels[2].association(), 65535);
+}
+
+#[test]
+fn test_colourspace_channels_without_definition() {
+    let mut header_box = HeaderSuperBox::new(ImageHeaderBox::new(1, 1, 2, 7, 0, 0));
+    header_box
+        .colour_specification_boxes
+        .push(ColourSpecificationBox::new_enumerated_colour_space(17));
+
+    // Greyscale with an extra channel
+    let channels = header_box
+        .map_channels(&[unsigned(8, vec![1]), unsigned(8, vec![2])])
+        .expect("mapping should succeed");
+    assert_eq!(channels[0].colour(), Some(1));
+    assert_eq!(
+        channels[1].channel_type(),
+        ChannelTypes::Unspecified { value: 65535 }
+    );
+}
+
+#[test]
+fn test_mixed_component_mapping() {
+    // A 4-bit signed palette of two entries, and a direct mapping of the
+    // second component
+    let palette_box = PaletteBox::new(vec![GeneratedComponent::new(0x83, vec![0x07, 0x0f])])
+        .expect("palette should be valid");
+    let component_mapping_box =
+        ComponentMappingBox::new(vec![ComponentMap::new(1, 0, 0), ComponentMap::new(0, 1, 0)]);
+    let components = [
+        unsigned(1, vec![0, 1, 5]),
+        ComponentSamples {
+            bit_depth: 12,
+            signed: true,
+            samples: vec![-2048, 2047, 0],
+        },
+    ];
+
+    let channels = map_channels(
+        &components,
+        Some(&palette_box),
+        Some(&component_mapping_box),
+        None,
+        2,
+    )
+    .expect("mapping should succeed");
+    assert_eq!(channels[0].samples(), [-2048, 2047, 0]);
+    assert_eq!(channels[0].bit_depth(), 12);
+    // Indices outside of the palette use its last entry
+    assert_eq!(channels[1].samples(), [7, -1, -1]);
+    assert_eq!(channels[1].bit_depth(), 4);
+    assert!(channels[1].signed());
+}
+
+#[test]
+fn test_component_mapping_errors() {
+    let palette_box = PaletteBox::new(vec![GeneratedComponent::new(7, vec![0, 255])])
+        .expect("palette should be valid");
+    let components = [unsigned(1, vec![0, 1])];
+
+    // A palette requires a Component Mapping box
+    let error = map_channels(&components, Some(&palette_box), None, None, 1)
+        .expect_err("mapping should fail");
+    match &error {
+        JP2Error::BoxMissing { box_type, .. } => assert_eq!(*box_type, *b"cmap"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // A palette mapping requires a Palette box
+    let component_mapping_box = ComponentMappingBox::new(vec![ComponentMap::new(0, 1, 0)]);
+    let error = map_channels(&components, None, Some(&component_mapping_box), None, 1)
+        .expect_err("mapping should fail");
+    match &error {
+        JP2Error::BoxMissing { box_type, .. } => assert_eq!(*box_type, *b"pclr"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // The mapped component does not exist
+    let component_mapping_box = ComponentMappingBox::new(vec![ComponentMap::new(1, 0, 0)]);
+    let error = map_channels(&components, None, Some(&component_mapping_box), None, 1)
+        .expect_err("mapping should fail");
+    match &error {
+        JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"cmap"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // The described channel does not exist
+    let channel_definition_box = ChannelDefinitionBox::new(vec![Channel::new(1, 0, 1)]);
+    let error = map_channels(&components, None, None, Some(&channel_definition_box), 1)
+        .expect_err("mapping should fail");
+    match &error {
+        JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"cdef"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_palette_limits() {
+    // Generated components shall have the same number of entries
+    let error = PaletteBox::new(vec![
+        GeneratedComponent::new(7, vec![0, 255]),
+        GeneratedComponent::new(7, vec![0]),
+    ])
+    .expect_err("palette should be invalid");
+    match &error {
+        JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"pclr"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // At most 1024 entries
+    PaletteBox::new(vec![GeneratedComponent::new(7, vec![0; 1024])])
+        .expect("palette should be valid");
+    PaletteBox::new(vec![GeneratedComponent::new(7, vec![0; 1025])])
+        .expect_err("palette should be invalid");
+
+    // At most 255 generated components
+    PaletteBox::new(vec![GeneratedComponent::new(7, vec![0]); 255])
+        .expect("palette should be valid");
+    PaletteBox::new(vec![GeneratedComponent::new(7, vec![0]); 256])
+        .expect_err("palette should be invalid");
+}
+
+#[test]
+fn test_channel_definition_limits() {
+    // At most 65535 channels, nothing is written for more
+    let channel_definition_box =
+        ChannelDefinitionBox::new((0..65536).map(|_| Channel::new(0, 0, 1)).collect());
+    let mut encoded: Vec<u8> = vec![];
+    let error = channel_definition_box
+        .encode(&mut encoded)
+        .expect_err("encoding should fail");
+    assert!(encoded.is_empty(), "{}", error);
+}
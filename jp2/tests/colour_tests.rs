@@ -0,0 +1,260 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    convert_to_srgb, decode_jpx, map_channels, srgb_colour_specification, ColourSpecificationBox,
+    ColourSpecificationMethods, ComponentSamples, ImageChannel, JBox, JP2Error, JPXFile,
+};
+
+fn decode_sample(name: &str) -> JPXFile {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join(name);
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    decode_jpx(&mut reader).expect("decoding should succeed")
+}
+
+fn channels(components: &[Vec<i32>]) -> Vec<ImageChannel> {
+    let components: Vec<ComponentSamples> = components
+        .iter()
+        .map(|samples| ComponentSamples {
+            bit_depth: 8,
+            signed: false,
+            samples: samples.clone(),
+        })
+        .collect();
+    map_channels(&components, None, None, None, components.len()).unwrap()
+}
+
+// An enumerated colourspace box with the precedence and approximation.
+fn enumerated(colour_space: u32, precedence: i8, approximation: u8) -> ColourSpecificationBox {
+    let mut data = vec![1, precedence as u8, approximation];
+    data.extend_from_slice(&colour_space.to_be_bytes());
+    let mut colour_specification_box = ColourSpecificationBox::default();
+    colour_specification_box
+        .decode(&mut Cursor::new(data))
+        .unwrap();
+    colour_specification_box
+}
+
+fn tag(signature: &[u8; 4], data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    (signature.to_vec(), data.to_vec())
+}
+
+fn s15_fixed16(values: &[f64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| ((value * 65536.0).round() as i32).to_be_bytes())
+        .collect()
+}
+
+// A restricted ICC profile with the colour space and tags.
+fn icc_profile(colour_space: &[u8; 4], tags: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut header = vec![0; 128];
+    header[12..16].copy_from_slice(b"scnr");
+    header[16..20].copy_from_slice(colour_space);
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data: Vec<u8> = vec![];
+    let start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in tags {
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&((start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+    }
+    let mut profile = [header, table, data].concat();
+    let length = profile.len() as u32;
+    profile[0..4].copy_from_slice(&length.to_be_bytes());
+    profile
+}
+
+// A three-component matrix-based profile of sRGB, with the colorants adapted
+// to D50 and the sRGB transfer function as a parametric curve.
+fn srgb_icc_profile() -> Vec<u8> {
+    let xyz = |values: &[f64]| [&b"XYZ \0\0\0\0"[..], &s15_fixed16(values)].concat();
+    let curve = [
+        &b"para\0\0\0\0\0\x03\0\0"[..],
+        &s15_fixed16(&[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045]),
+    ]
+    .concat();
+    icc_profile(
+        b"RGB ",
+        &[
+            tag(b"rXYZ", &xyz(&[0.4361, 0.2225, 0.0139])),
+            tag(b"gXYZ", &xyz(&[0.3851, 0.7169, 0.0971])),
+            tag(b"bXYZ", &xyz(&[0.1431, 0.0606, 0.7141])),
+            tag(b"rTRC", &curve),
+            tag(b"gTRC", &curve),
+            tag(b"bTRC", &curve),
+        ],
+    )
+}
+
+#[test]
+fn test_srgb_sample_file1() {
+    let jpx = decode_sample("file1.jp2");
+    let header_box = jpx.header_box().as_ref().unwrap();
+
+    let channels = channels(&[vec![0, 10, 255], vec![1, 20, 255], vec![2, 30, 255]]);
+    let pixels = header_box.convert_to_srgb(&channels).unwrap();
+    assert_eq!(pixels, [[0, 1, 2], [10, 20, 30], [255, 255, 255]]);
+}
+
+#[test]
+fn test_sycc_sample_file2() {
+    let jpx = decode_sample("file2.jp2");
+    let header_box = jpx.header_box().as_ref().unwrap();
+
+    // Grey, and the red and blue of IEC 61966-2-1/Amd 1
+    let channels = channels(&[vec![128, 76, 29], vec![128, 85, 255], vec![128, 255, 107]]);
+    let pixels = header_box.convert_to_srgb(&channels).unwrap();
+    assert_eq!(pixels, [[128, 128, 128], [254, 0, 0], [0, 0, 254]]);
+}
+
+#[test]
+fn test_greyscale_sample_file4() {
+    let jpx = decode_sample("file4.jp2");
+    let header_box = jpx.header_box().as_ref().unwrap();
+
+    let channels = channels(&[vec![0, 100, 255]]);
+    let pixels = header_box.convert_to_srgb(&channels).unwrap();
+    assert_eq!(pixels, [[0, 0, 0], [100, 100, 100], [255, 255, 255]]);
+}
+
+#[test]
+fn test_monochrome_icc_sample_file8() {
+    let jpx = decode_sample("file8.jp2");
+    let header_box = jpx.header_box().as_ref().unwrap();
+
+    let channels = channels(&[vec![0, 64, 128, 255]]);
+    let pixels = header_box.convert_to_srgb(&channels).unwrap();
+    assert_eq!(
+        pixels,
+        [[0, 0, 0], [81, 81, 81], [146, 146, 146], [255, 255, 255]]
+    );
+}
+
+#[test]
+fn test_matrix_icc_sample_file5() {
+    let jpx = decode_sample("file5.jp2");
+    let header_box = jpx.header_box().as_ref().unwrap();
+
+    // The enumerated ROMM-RGB colourspace has the higher precedence, but it
+    // cannot be converted, so the restricted ICC profile is used
+    let colour_specification_box =
+        srgb_colour_specification(&header_box.colour_specification_boxes).unwrap();
+    assert_eq!(
+        colour_specification_box.method(),
+        ColourSpecificationMethods::RestrictedICCProfile
+    );
+    assert!(!header_box.colour_specification_boxes[1].is_srgb_convertible());
+
+    let channels = channels(&[vec![0, 255, 255], vec![0, 255, 0], vec![0, 255, 0]]);
+    let pixels = header_box.convert_to_srgb(&channels).unwrap();
+    assert_eq!(pixels, [[0, 0, 0], [255, 255, 255], [255, 0, 0]]);
+}
+
+#[test]
+fn test_matrix_icc_srgb_profile() {
+    let colour_specification_box =
+        ColourSpecificationBox::new_restricted_icc_profile(srgb_icc_profile());
+    assert!(colour_specification_box.is_srgb_convertible());
+
+    // The profile of sRGB leaves the values unchanged
+    let values: Vec<i32> = (0..=255).step_by(15).collect();
+    let reversed: Vec<i32> = values.iter().rev().copied().collect();
+    let channels = channels(&[values.clone(), reversed.clone(), values.clone()]);
+    let pixels = convert_to_srgb(&[colour_specification_box], &channels).unwrap();
+    for (index, pixel) in pixels.iter().enumerate() {
+        let expected = [values[index], reversed[index], values[index]];
+        for (value, expected) in pixel.iter().zip(expected) {
+            assert!(
+                (*value as i32 - expected).abs() <= 1,
+                "{:?} differs from {:?}",
+                pixel,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn test_monochrome_icc_gamma_profile() {
+    // A linear curve as a gamma of 1.0
+    let profile = icc_profile(b"GRAY", &[tag(b"kTRC", b"curv\0\0\0\0\0\0\0\x01\x01\x00")]);
+    let colour_specification_box = ColourSpecificationBox::new_restricted_icc_profile(profile);
+
+    let channels = channels(&[vec![0, 128, 255]]);
+    let pixels = convert_to_srgb(&[colour_specification_box], &channels).unwrap();
+    assert_eq!(pixels, [[0, 0, 0], [188, 188, 188], [255, 255, 255]]);
+}
+
+#[test]
+fn test_precedence_and_approximation() {
+    // The highest precedence is used
+    let boxes = [enumerated(16, 0, 1), enumerated(17, 2, 3)];
+    let selected = srgb_colour_specification(&boxes).unwrap();
+    assert_eq!(selected.enumerated_colour_space(), Some(17));
+    let channels = channels(&[vec![10], vec![20], vec![30]]);
+    assert_eq!(
+        convert_to_srgb(&boxes, &channels[..1]).unwrap(),
+        [[10, 10, 10]]
+    );
+
+    // Then the most accurate approximation, an unspecified approximation is
+    // the least accurate
+    let boxes = [
+        enumerated(16, 1, 0),
+        enumerated(18, 1, 3),
+        enumerated(17, 1, 2),
+    ];
+    let selected = srgb_colour_specification(&boxes).unwrap();
+    assert_eq!(selected.enumerated_colour_space(), Some(17));
+
+    // Then the first box
+    let boxes = [enumerated(18, 0, 0), enumerated(16, 0, 0)];
+    let selected = srgb_colour_specification(&boxes).unwrap();
+    assert_eq!(selected.enumerated_colour_space(), Some(18));
+
+    // Boxes which cannot be converted are ignored
+    let boxes = [enumerated(12, 5, 1), enumerated(16, -1, 4)];
+    let selected = srgb_colour_specification(&boxes).unwrap();
+    assert_eq!(selected.enumerated_colour_space(), Some(16));
+}
+
+#[test]
+fn test_conversion_errors() {
+    let channels = channels(&[vec![0, 1], vec![0, 1]]);
+
+    let error = convert_to_srgb(&[], &channels).expect_err("conversion should fail");
+    match &error {
+        JP2Error::BoxMissing { box_type, offset } => {
+            assert_eq!(*box_type, *b"colr");
+            assert_eq!(*offset, 0);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // CMYK is not converted
+    let error =
+        convert_to_srgb(&[enumerated(12, 0, 0)], &channels).expect_err("conversion should fail");
+    assert!(matches!(error, JP2Error::UnsupportedColourSpace { .. }));
+
+    // sRGB needs three colour channels
+    let error =
+        convert_to_srgb(&[enumerated(16, 0, 0)], &channels).expect_err("conversion should fail");
+    match &error {
+        JP2Error::ColourChannelsMismatch {
+            expected, found, ..
+        } => {
+            assert_eq!((*expected, *found), (3, 2))
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
@@ -0,0 +1,20 @@
+// Helpers shared by the tests, each test crate uses some of them.
+#![allow(dead_code)]
+
+// Offset of the contents of the first box of the type.
+pub fn contents(data: &[u8], box_type: &[u8; 4]) -> usize {
+    data.windows(4)
+        .position(|window| window == box_type)
+        .expect("box should exist")
+        + 4
+}
+
+// A box with the contents given.
+pub fn jbox(box_type: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    [
+        &(8 + contents.len() as u32).to_be_bytes()[..],
+        box_type,
+        contents,
+    ]
+    .concat()
+}
@@ -0,0 +1,238 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, decode_jp2_with_options, DecodeOptions, DecodeWarning, JBox, JP2Error,
+    Nonconformance,
+};
+
+mod common;
+use common::contents;
+
+#[test]
+fn test_strict_samples() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let samples = (1..=9)
+        .map(|index| format!("../samples/file{}.jp2", index))
+        .chain(
+            [
+                "../samples/subsampling_1.jp2",
+                "../samples/zoo2.jp2",
+                "tests/geojp2.jp2",
+                "tests/j2pi.jp2",
+            ]
+            .iter()
+            .map(|name| name.to_string()),
+        );
+    for name in samples {
+        let path = directory.join(&name);
+        let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+        let jp2 = decode_jp2_with_options(&mut reader, &DecodeOptions::strict())
+            .expect("decoding should succeed");
+        assert!(jp2.warnings().is_empty(), "{}", name);
+    }
+}
+
+#[test]
+fn test_nonconforming() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let mut data = fs::read(path).expect("file should exist");
+
+    // Minor version of the File Type box
+    let file_type = contents(&data, b"ftyp");
+    data[file_type + 7] = 1;
+    // Intellectual Property of the Image Header box without an IPR box
+    let image_header = contents(&data, b"ihdr");
+    data[image_header + 13] = 1;
+    // Precedence and approximation of the Colour Specification box
+    let colour_specification = contents(&data, b"colr");
+    data[colour_specification + 1] = 2;
+    data[colour_specification + 2] = 5;
+
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let colour_specification_box = &jp2
+        .header_box()
+        .as_ref()
+        .unwrap()
+        .colour_specification_boxes[0];
+    assert_eq!(colour_specification_box.precedence(), 2);
+    assert_eq!(
+        jp2.warnings(),
+        [
+            DecodeWarning {
+                box_type: *b"ftyp",
+                offset: Some(file_type as u64),
+                nonconformance: Nonconformance::MinorVersion { min_version: 1 },
+            },
+            DecodeWarning {
+                box_type: *b"colr",
+                offset: Some(colour_specification_box.offset()),
+                nonconformance: Nonconformance::Precedence { precedence: 2 },
+            },
+            DecodeWarning {
+                box_type: *b"colr",
+                offset: Some(colour_specification_box.offset()),
+                nonconformance: Nonconformance::Approximation { approximation: 5 },
+            },
+            DecodeWarning {
+                box_type: *b"jp2i",
+                offset: None,
+                nonconformance: Nonconformance::BoxMissing,
+            },
+        ]
+    );
+
+    let error = decode_jp2_with_options(&mut Cursor::new(&data), &DecodeOptions::strict())
+        .expect_err("decoding should fail");
+    match &error {
+        JP2Error::Nonconforming { warning, .. } => assert_eq!(*warning, jp2.warnings()[0]),
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_nonconforming_boxes() {
+    // A repeated Image Header box in the JP2 Header box, a repeated File Type
+    // box and no codestream
+    let data: Vec<u8> = [
+        &[0, 0, 0, 12][..],
+        b"jP  \r\n\x87\n",
+        &[0, 0, 0, 20],
+        b"ftypjp2 \0\0\0\0jp2 ",
+        &[0, 0, 0, 67],
+        b"jp2h",
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0, 0, 0, 1, 0, 0, 0, 1, 0, 1, 7, 7, 0, 0],
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0; 14],
+        &[0, 0, 0, 15],
+        b"colr",
+        &[1, 0, 0, 0, 0, 0, 17],
+        &[0, 0, 0, 20],
+        b"ftypjp2 \0\0\0\0jp2 ",
+    ]
+    .concat();
+
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let warnings: Vec<(&[u8; 4], Option<u64>, &Nonconformance)> = jp2
+        .warnings()
+        .iter()
+        .map(|warning| (&warning.box_type, warning.offset, &warning.nonconformance))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            (b"ihdr", Some(70), &Nonconformance::BoxDuplicate),
+            (b"ftyp", Some(107), &Nonconformance::BoxDuplicate),
+            (b"jp2c", None, &Nonconformance::BoxMissing),
+        ]
+    );
+    // The first Image Header box is used
+    assert_eq!(
+        jp2.header_box()
+            .as_ref()
+            .unwrap()
+            .image_header_box
+            .components_num(),
+        1
+    );
+}
+
+#[test]
+fn test_codestream_mismatch() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let data = fs::read(path).expect("file should exist");
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let image_header_box = &jp2.header_box().as_ref().unwrap().image_header_box;
+    let (height, width) = (image_header_box.height(), image_header_box.width());
+    let codestream = jp2.contiguous_codestreams_boxes()[0].offset();
+
+    // Height, width and bit depth of the Image Header box
+    let mut patched = data.clone();
+    let image_header = contents(&patched, b"ihdr");
+    patched[image_header..image_header + 4].copy_from_slice(&(height + 1).to_be_bytes());
+    patched[image_header + 4..image_header + 8].copy_from_slice(&(width - 1).to_be_bytes());
+    let bits = patched[image_header + 10];
+    patched[image_header + 10] = 0x80 | 11;
+
+    let jp2 = decode_jp2(&mut Cursor::new(&patched)).expect("decoding should succeed");
+    let mut expected = vec![
+        DecodeWarning {
+            box_type: *b"jp2c",
+            offset: Some(codestream),
+            nonconformance: Nonconformance::HeightMismatch {
+                height: height + 1,
+                codestream_height: height,
+            },
+        },
+        DecodeWarning {
+            box_type: *b"jp2c",
+            offset: Some(codestream),
+            nonconformance: Nonconformance::WidthMismatch {
+                width: width - 1,
+                codestream_width: width,
+            },
+        },
+    ];
+    for component in 0..image_header_box.components_num() {
+        expected.push(DecodeWarning {
+            box_type: *b"jp2c",
+            offset: Some(codestream),
+            nonconformance: Nonconformance::BitDepthMismatch {
+                component,
+                bits: 0x80 | 11,
+                codestream_bits: bits,
+            },
+        });
+    }
+    assert_eq!(jp2.warnings(), expected);
+    assert_eq!(
+        jp2.warnings()[2].to_string(),
+        format!(
+            "box type [106, 112, 50, 99] at offset {}: component 0 of 12 bit signed differs from {} bit unsigned of the codestream",
+            codestream,
+            bits + 1
+        )
+    );
+
+    let error = decode_jp2_with_options(&mut Cursor::new(&patched), &DecodeOptions::strict())
+        .expect_err("decoding should fail");
+    match &error {
+        JP2Error::Nonconforming { warning, .. } => assert_eq!(*warning, expected[0]),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // Number of components of the Image Header box
+    let mut patched = data.clone();
+    patched[image_header + 9] += 1;
+    let jp2 = decode_jp2(&mut Cursor::new(&patched)).expect("decoding should succeed");
+    let components = image_header_box.components_num();
+    assert_eq!(
+        jp2.warnings()
+            .iter()
+            .map(|warning| &warning.nonconformance)
+            .collect::<Vec<_>>(),
+        [&Nonconformance::ComponentsMismatch {
+            components: components + 1,
+            codestream_components: components,
+        }]
+    );
+
+    // Start of codestream marker
+    let mut patched = data;
+    patched[codestream as usize + 1] = 0;
+    let jp2 = decode_jp2(&mut Cursor::new(&patched)).expect("decoding should succeed");
+    assert_eq!(
+        jp2.warnings(),
+        [DecodeWarning {
+            box_type: *b"jp2c",
+            offset: Some(codestream),
+            nonconformance: Nonconformance::CodestreamMalformed,
+        }]
+    );
+}
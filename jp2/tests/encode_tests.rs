@@ -0,0 +1,431 @@
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use jp2::{
+    decode_jp2, encode_jp2, reencode_jp2, ColourSpecificationBox, ContiguousCodestreamBox,
+    DefaultDisplayResolutionBox, FileTypeBox, HeaderSuperBox, ImageHeaderBox,
+    IntellectualPropertyBox, JBox as _, JP2File, ResolutionSuperBox, UUIDBox, UnknownBox, XMLBox,
+};
+
+#[test]
+fn test_round_trip_samples() {
+    for filename in [
+        "file1.jp2",
+        "file2.jp2",
+        "file3.jp2",
+        "file4.jp2",
+        "file5.jp2",
+        "file6.jp2",
+        "file7.jp2",
+        "file8.jp2",
+        "file9.jp2",
+    ] {
+        test_round_trip(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../samples")
+                .join(filename),
+        );
+    }
+}
+
+#[test]
+fn test_round_trip_codestream_to_end_of_file() {
+    // The Contiguous Codestream box in these files has a length of 0, the
+    // encoded box records its length.
+    for filename in ["subsampling_1.jp2", "subsampling_2.jp2", "zoo2.jp2"] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../samples")
+            .join(filename);
+        let mut original = read_file(&path);
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+        let contiguous_codestream_box = &jp2.contiguous_codestreams_boxes()[0];
+        let header_offset = contiguous_codestream_box.offset as usize - 8;
+        assert_eq!(original[header_offset..header_offset + 4], [0, 0, 0, 0]);
+        original[header_offset..header_offset + 4]
+            .copy_from_slice(&(contiguous_codestream_box.length() as u32 + 8).to_be_bytes());
+
+        let mut encoded: Vec<u8> = vec![];
+        encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+        assert!(encoded == original, "{:?}", path);
+    }
+}
+
+#[test]
+fn test_round_trip_tests() {
+    for filename in ["geojp2.jp2", "hazard.jp2", "j2pi.jp2"] {
+        test_round_trip(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join(filename),
+        );
+    }
+}
+
+#[test]
+fn test_encode_new_file() {
+    // The container does not decode the codestream, SOC and EOC are enough.
+    let codestream: Vec<u8> = vec![0xFF, 0x4F, 0xFF, 0xD9];
+
+    let mut header_box = HeaderSuperBox::new(ImageHeaderBox::new(128, 64, 3, 7, 0, 0));
+    header_box
+        .colour_specification_boxes
+        .push(ColourSpecificationBox::new_enumerated_colour_space(16));
+    header_box.resolution_box = Some(ResolutionSuperBox::new(
+        None,
+        Some(DefaultDisplayResolutionBox::new(72, 1, 72, 1, 0, 0)),
+    ));
+
+    let mut jp2 = JP2File::new(FileTypeBox::new(*b"jp2 ", 0, vec![*b"jp2 "]), header_box);
+    jp2.xml_boxes_mut()
+        .push(XMLBox::new(b"<xml>test</xml>".to_vec()));
+    jp2.contiguous_codestreams_boxes_mut()
+        .push(ContiguousCodestreamBox::new(codestream.clone()));
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut Cursor::new(vec![])).expect("encode should succeed");
+
+    let mut reader = Cursor::new(&encoded);
+    let decoded = decode_jp2(&mut reader).expect("decode should succeed");
+
+    let file_type_box = decoded.file_type_box().as_ref().unwrap();
+    assert_eq!(file_type_box.brand(), "jp2 ");
+    assert_eq!(file_type_box.compatibility_list(), vec!["jp2 "]);
+
+    let header_box = decoded.header_box().as_ref().unwrap();
+    assert_eq!(header_box.image_header_box.height(), 128);
+    assert_eq!(header_box.image_header_box.width(), 64);
+    assert_eq!(header_box.image_header_box.components_num(), 3);
+    assert_eq!(header_box.colour_specification_boxes.len(), 1);
+    assert_eq!(
+        header_box.colour_specification_boxes[0]
+            .enumerated_colour_space()
+            .unwrap(),
+        16
+    );
+    let resolution_box = header_box.resolution_box.as_ref().unwrap();
+    assert!(resolution_box.capture_resolution_box().is_none());
+    let default_display_resolution_box = resolution_box
+        .default_display_resolution_box()
+        .as_ref()
+        .unwrap();
+    assert_eq!(
+        default_display_resolution_box.vertical_display_grid_resolution(),
+        72
+    );
+
+    assert_eq!(decoded.xml_boxes().len(), 1);
+    assert_eq!(decoded.xml_boxes()[0].format(), "<xml>test</xml>");
+
+    assert_eq!(decoded.contiguous_codestreams_boxes().len(), 1);
+    let contiguous_codestream_box = &decoded.contiguous_codestreams_boxes()[0];
+    assert_eq!(contiguous_codestream_box.length(), codestream.len() as u64);
+    let offset = contiguous_codestream_box.offset as usize;
+    assert_eq!(&encoded[offset..offset + codestream.len()], &codestream[..]);
+
+    // Encoding the decoded file copies the codestream from the source
+    let mut reencoded: Vec<u8> = vec![];
+    encode_jp2(&mut reencoded, &decoded, &mut reader).expect("encode should succeed");
+    assert_eq!(reencoded, encoded);
+}
+
+#[test]
+fn test_edit_metadata() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let original = read_file(&path);
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let mut jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    let codestream_offset = jp2.contiguous_codestreams_boxes()[0].offset as usize;
+    let codestream_length = jp2.contiguous_codestreams_boxes()[0].length() as usize;
+
+    // Remove the XML box before the header and replace the one after it
+    jp2.xml_boxes_mut().remove(0);
+    jp2.xml_boxes_mut()[0].set_xml(b"<xml>replaced</xml>".to_vec());
+    jp2.uuid_boxes_mut()
+        .push(UUIDBox::new([7; 16], b"vendor".to_vec()));
+    *jp2.intellectual_property_box_mut() = Some(IntellectualPropertyBox::new(b"<ipr/>".to_vec()));
+    jp2.header_box_mut().as_mut().unwrap().resolution_box = Some(ResolutionSuperBox::new(
+        None,
+        Some(DefaultDisplayResolutionBox::new(300, 1, 300, 1, 0, 0)),
+    ));
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+
+    assert_eq!(
+        box_types(&encoded),
+        vec!["jP  ", "ftyp", "jp2h", "xml ", "jp2i", "uuid", "jp2c"]
+    );
+
+    let mut reader = Cursor::new(&encoded);
+    let decoded = decode_jp2(&mut reader).expect("decode should succeed");
+    assert_eq!(decoded.xml_boxes().len(), 1);
+    assert_eq!(decoded.xml_boxes()[0].format(), "<xml>replaced</xml>");
+    assert_eq!(decoded.uuid_boxes().len(), 1);
+    assert_eq!(decoded.uuid_boxes()[0].uuid(), &[7; 16]);
+    assert_eq!(decoded.uuid_boxes()[0].data(), b"vendor");
+    assert_eq!(
+        decoded
+            .intellectual_property_box()
+            .as_ref()
+            .unwrap()
+            .format(),
+        "<ipr/>"
+    );
+    let header_box = decoded.header_box().as_ref().unwrap();
+    assert_eq!(header_box.image_header_box.width(), 768);
+    assert_eq!(header_box.colour_specification_boxes.len(), 1);
+    let resolution_box = header_box.resolution_box.as_ref().unwrap();
+    assert_eq!(
+        resolution_box
+            .default_display_resolution_box()
+            .as_ref()
+            .unwrap()
+            .horizontal_display_grid_resolution(),
+        300
+    );
+
+    let contiguous_codestream_box = &decoded.contiguous_codestreams_boxes()[0];
+    let offset = contiguous_codestream_box.offset as usize;
+    assert_eq!(
+        contiguous_codestream_box.length() as usize,
+        codestream_length
+    );
+    assert!(
+        encoded[offset..offset + codestream_length]
+            == original[codestream_offset..codestream_offset + codestream_length]
+    );
+}
+
+#[test]
+fn test_edit_copies_unchanged_boxes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("geojp2.jp2");
+    let original = read_file(&path);
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let mut jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    let xml_offset = jp2.xml_boxes()[0].offset() as usize;
+    let xml_length = jp2.xml_boxes()[0].length() as usize;
+
+    let xml = b"<GDALMultiDomainMetadata/>".to_vec();
+    jp2.xml_boxes_mut()[0].set_xml(xml.clone());
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+
+    // Everything before the XML box is unchanged, including the UUID box
+    assert_eq!(encoded[..xml_offset - 8], original[..xml_offset - 8]);
+    assert_eq!(
+        encoded[xml_offset - 8..xml_offset - 4],
+        (xml.len() as u32 + 8).to_be_bytes()
+    );
+    assert_eq!(encoded[xml_offset..xml_offset + xml.len()], xml[..]);
+
+    // The Contiguous Codestream box follows unchanged
+    assert_eq!(
+        encoded[xml_offset + xml.len()..],
+        original[xml_offset + xml_length..]
+    );
+
+    // Removing every optional box leaves the header and the codestream
+    jp2.xml_boxes_mut().clear();
+    jp2.uuid_boxes_mut().clear();
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(box_types(&encoded), vec!["jP  ", "ftyp", "jp2h", "jp2c"]);
+}
+
+#[test]
+fn test_round_trip_unknown_boxes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("geojp2.jp2");
+    let mut original = read_file(&path);
+
+    // Vendor box following the JP2 Header box, at the start of the UUID box
+    original.splice(77..77, [&[0, 0, 0, 13][..], b"vndr", b"hello"].concat());
+
+    let mut reader = Cursor::new(&original);
+    let mut jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    assert_eq!(jp2.unknown_boxes().len(), 1);
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert!(encoded == original);
+
+    // Edited unknown boxes keep their position, new ones are placed before
+    // the Contiguous Codestream box
+    jp2.unknown_boxes_mut()[0].set_data(b"world!".to_vec());
+    jp2.unknown_boxes_mut()
+        .push(UnknownBox::new(*b"vnds", b"new".to_vec()));
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(
+        box_types(&encoded),
+        vec!["jP  ", "ftyp", "jp2h", "vndr", "uuid", "xml ", "vnds", "jp2c"]
+    );
+    assert_eq!(encoded[85..91], *b"world!");
+
+    let decoded = decode_jp2(&mut Cursor::new(&encoded)).expect("decode should succeed");
+    assert_eq!(decoded.unknown_boxes().len(), 2);
+    assert_eq!(decoded.unknown_boxes()[1].box_type(), *b"vnds");
+
+    // A box with a length of 0 is written with its length
+    let mut original = read_file(&path);
+    original.extend([&[0, 0, 0, 0][..], b"vndz", b"last"].concat());
+    let mut reader = Cursor::new(&original);
+    let jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    let length = original.len();
+    original[length - 12..length - 8].copy_from_slice(&12_u32.to_be_bytes());
+    assert!(encoded == original);
+}
+
+#[test]
+fn test_round_trip_header_vendor_box() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let mut original = read_file(&path);
+    let jp2 = decode_jp2(&mut Cursor::new(&original)).expect("decode should succeed");
+    let header_box = jp2.header_box().as_ref().unwrap();
+    let start = header_box.offset() as usize - 8;
+    let end = (header_box.offset() + header_box.length()) as usize;
+
+    // 12-byte vendor box at the end of the JP2 Header box
+    original.splice(end..end, [&[0, 0, 0, 12][..], b"vndr", b"data"].concat());
+    let length = end - start + 12;
+    original[start..start + 4].copy_from_slice(&(length as u32).to_be_bytes());
+
+    let mut reader = Cursor::new(&original);
+    let jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(encoded.len(), 650690);
+    assert!(encoded == original);
+}
+
+#[test]
+fn test_round_trip_unmodified_box_with_xlbox() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("geojp2.jp2");
+    let mut original = read_file(&path);
+    let jp2 = decode_jp2(&mut Cursor::new(&original)).expect("decode should succeed");
+    let header_box = jp2.header_box().as_ref().unwrap();
+    let header_start = header_box.offset() as usize - 8;
+    let header_length = header_box.length() as usize;
+    let colour_specification_box = &header_box.colour_specification_boxes[0];
+    let start = colour_specification_box.offset() as usize - 8;
+    let length = colour_specification_box.length();
+
+    // Colour Specification box with an XLBox, which encoding the JP2 Header
+    // box would write as a LBox
+    let header: Vec<u8> = [&[0, 0, 0, 1][..], b"colr", &(length + 16).to_be_bytes()].concat();
+    original.splice(start..start + 8, header);
+    original[header_start..header_start + 4]
+        .copy_from_slice(&(header_length as u32 + 16).to_be_bytes());
+
+    // The JP2 Header box is copied as it was not modified
+    let mut reader = Cursor::new(&original);
+    let mut jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert!(encoded == original);
+
+    // A modified box is encoded
+    jp2.header_box_mut()
+        .as_mut()
+        .unwrap()
+        .unknown_boxes
+        .push(UnknownBox::new(*b"vndn", b"new".to_vec()));
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(
+        encoded[header_start..header_start + 4],
+        (header_length as u32 + 8 + 11).to_be_bytes()
+    );
+    assert_eq!(encoded[start..start + 8], *b"\0\0\0\x0fcolr");
+}
+
+#[test]
+fn test_encode_header_unknown_boxes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("geojp2.jp2");
+    let mut original = read_file(&path);
+
+    // Vendor box at the end of the JP2 Header box, which ends at 77
+    original.splice(77..77, [&[0, 0, 0, 10][..], b"vndh", b"hi"].concat());
+    original[32..36].copy_from_slice(&(45_u32 + 10).to_be_bytes());
+
+    let mut reader = Cursor::new(&original);
+    let mut jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+
+    // The header box is encoded with the kept and the added vendor boxes
+    jp2.header_box_mut()
+        .as_mut()
+        .unwrap()
+        .unknown_boxes
+        .push(UnknownBox::new(*b"vndn", b"new".to_vec()));
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(encoded[77..87], original[77..87]);
+    assert_eq!(encoded[87..98], *b"\0\0\0\x0bvndnnew");
+
+    let decoded = decode_jp2(&mut Cursor::new(&encoded)).expect("decode should succeed");
+    let header_box = decoded.header_box().as_ref().unwrap();
+    assert_eq!(header_box.unknown_boxes.len(), 2);
+    assert_eq!(header_box.unknown_boxes[0].data(), Some(&b"hi"[..]));
+    assert_eq!(header_box.unknown_boxes[1].box_type(), *b"vndn");
+}
+
+// List the types of the top-level boxes, the boxes are expected to have an
+// 8-byte header.
+fn box_types(data: &[u8]) -> Vec<String> {
+    let mut types: Vec<String> = vec![];
+    let mut position = 0;
+    while position < data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap());
+        types.push(String::from_utf8_lossy(&data[position + 4..position + 8]).to_string());
+        position += length as usize;
+    }
+    types
+}
+
+fn test_round_trip(path: PathBuf) {
+    let original = read_file(&path);
+
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encode should succeed");
+
+    assert_eq!(encoded.len(), original.len(), "{:?}", path);
+    assert!(encoded == original, "{:?}", path);
+
+    // Every box encoded instead of copied
+    let mut reencoded: Vec<u8> = vec![];
+    reencode_jp2(&mut reencoded, &jp2, &mut reader).expect("encode should succeed");
+    assert_eq!(reencoded.len(), original.len(), "{:?}", path);
+    assert!(reencoded == original, "{:?}", path);
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![];
+    File::open(path)
+        .expect("file should exist")
+        .read_to_end(&mut data)
+        .unwrap();
+    data
+}
@@ -0,0 +1,226 @@
+use std::{
+    env, fs,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process,
+};
+
+use jp2::{decode_jpx, JP2Error};
+
+mod common;
+use common::jbox;
+
+#[test]
+fn test_fragmented_codestream() {
+    let codestream = sample_codestream();
+    let (first, rest) = codestream.split_at(1000);
+    let (second, third) = rest.split_at(200_000);
+
+    // The first fragment is in the file, the others are in files referenced
+    // by a relative URL and by an absolute file URL, with an empty fragment
+    let directory = temporary_directory("fragmented");
+    fs::write(
+        directory.join("second.j2c"),
+        [&[0; 10][..], second].concat(),
+    )
+    .unwrap();
+    fs::write(directory.join("third part.j2c"), third).unwrap();
+    let third_url = format!(
+        "file://{}",
+        directory
+            .join("third%20part.j2c")
+            .to_str()
+            .unwrap()
+            .replace('\\', "/")
+    );
+
+    let data = jpx_file(
+        &[
+            (10, second.len() as u32, 1),
+            (0, 0, 1),
+            (0, third.len() as u32, 2),
+        ],
+        first,
+        &["second.j2c", &third_url],
+    );
+    let path = directory.join("fragmented.jpf");
+    fs::write(&path, &data).unwrap();
+
+    let mut reader = BufReader::new(File::open(&path).expect("file should exist"));
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+    let mut fragments = jpx
+        .fragmented_codestream(0, reader, Some(&directory))
+        .expect("fragments should be found");
+    assert_eq!(fragments.len(), codestream.len() as u64);
+
+    let mut reassembled: Vec<u8> = vec![];
+    fragments.read_to_end(&mut reassembled).unwrap();
+    assert!(reassembled == codestream);
+
+    // Reads across fragments
+    fragments.seek(SeekFrom::Start(990)).unwrap();
+    let mut buffer = [0; 20];
+    fragments.read_exact(&mut buffer).unwrap();
+    assert_eq!(buffer[..], codestream[990..1010]);
+    fragments.seek(SeekFrom::End(-2)).unwrap();
+    fragments.read_exact(&mut buffer[..2]).unwrap();
+    assert_eq!(buffer[..2], [0xff, 0xd9]);
+    assert!(fragments
+        .seek(SeekFrom::Current(-(codestream.len() as i64) - 1))
+        .is_err());
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_fragmented_codestream_network_url() {
+    let data = jpx_file(&[(0, 4, 1)], &[], &["http://example.com/a.j2c"]);
+    let mut reader = Cursor::new(&data);
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    let error = jpx
+        .fragmented_codestream(0, reader, Some(Path::new(".")))
+        .err()
+        .expect("network URLs should be rejected");
+    match &error {
+        JP2Error::UnsupportedURL { url, .. } => assert_eq!(url, "http://example.com/a.j2c"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // File URLs on other hosts are network URLs
+    let data = jpx_file(&[(0, 4, 1)], &[], &["file://example.com/a.j2c"]);
+    let mut reader = Cursor::new(&data);
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+    let error = jpx
+        .fragmented_codestream(0, reader, Some(Path::new(".")))
+        .err()
+        .expect("network URLs should be rejected");
+    assert!(matches!(error, JP2Error::UnsupportedURL { .. }));
+}
+
+#[test]
+fn test_fragmented_codestream_unresolved_url() {
+    // Files are only opened within a base directory
+    let data = jpx_file(&[(0, 4, 1)], &[], &["a.j2c"]);
+    let mut reader = Cursor::new(&data);
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    let error = jpx
+        .fragmented_codestream(0, reader, None)
+        .err()
+        .expect("the URL should not be resolved");
+    match &error {
+        JP2Error::UnresolvedURL { url, .. } => assert_eq!(url, "a.j2c"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_fragmented_codestream_forbidden_url() {
+    let directory = temporary_directory("forbidden");
+    let base_directory = directory.join("base");
+    fs::create_dir_all(base_directory.join("sub")).unwrap();
+    fs::write(directory.join("outside.j2c"), [0; 4]).unwrap();
+    fs::write(base_directory.join("inside.j2c"), [0; 4]).unwrap();
+    let outside = directory.join("outside.j2c");
+    let outside = outside.to_str().unwrap();
+
+    // Files outside of the base directory, by a parent component or an
+    // absolute path, are not opened
+    for url in ["../outside.j2c", "sub/../../outside.j2c", outside].iter() {
+        let data = jpx_file(&[(0, 4, 1)], &[], &[url]);
+        let mut reader = Cursor::new(&data);
+        let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+        let error = jpx
+            .fragmented_codestream(0, reader, Some(&base_directory))
+            .err()
+            .expect("the URL should be rejected");
+        match &error {
+            JP2Error::ForbiddenURL { url: forbidden, .. } => assert_eq!(forbidden, url),
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    // Within the base directory
+    for url in ["inside.j2c", "sub/../inside.j2c"].iter() {
+        let data = jpx_file(&[(0, 4, 1)], &[], &[url]);
+        let mut reader = Cursor::new(&data);
+        let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+        let fragments = jpx
+            .fragmented_codestream(0, reader, Some(&base_directory))
+            .expect("fragments should be found");
+        assert_eq!(fragments.len(), 4);
+    }
+
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn test_fragmented_codestream_missing_data_reference() {
+    let data = jpx_file(&[(0, 4, 2)], &[], &["a.j2c"]);
+    let mut reader = Cursor::new(&data);
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    let error = jpx
+        .fragmented_codestream(0, reader, None)
+        .err()
+        .expect("the data reference should not be found");
+    match &error {
+        JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"flst"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+fn sample_codestream() -> Vec<u8> {
+    // Contiguous Codestream box of file1.jp2
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let data = fs::read(path).expect("file should exist");
+    data[1277..1277 + 649401].to_vec()
+}
+
+fn temporary_directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("jp2-{}-{}", name, process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+// A JPX file with a Fragment Table box listing the external fragments after
+// a fragment stored in the file, and a Data Reference box with the URLs.
+fn jpx_file(external: &[(u64, u32, u16)], local: &[u8], urls: &[&str]) -> Vec<u8> {
+    let fragment_list = |local_offset: u64| -> Vec<u8> {
+        let mut fragments: Vec<(u64, u32, u16)> = vec![];
+        if !local.is_empty() {
+            fragments.push((local_offset, local.len() as u32, 0));
+        }
+        fragments.extend_from_slice(external);
+
+        let mut contents: Vec<u8> = (fragments.len() as u16).to_be_bytes().to_vec();
+        for (offset, length, data_reference) in fragments {
+            contents.extend_from_slice(&offset.to_be_bytes());
+            contents.extend_from_slice(&length.to_be_bytes());
+            contents.extend_from_slice(&data_reference.to_be_bytes());
+        }
+        jbox(b"ftbl", &jbox(b"flst", &contents))
+    };
+
+    let mut data_reference: Vec<u8> = (urls.len() as u16).to_be_bytes().to_vec();
+    for url in urls {
+        data_reference.extend(jbox(b"url ", &[&[0; 4][..], url.as_bytes(), &[0]].concat()));
+    }
+
+    let header: Vec<u8> = [
+        jbox(b"jP  ", b"\x0d\x0a\x87\x0a"),
+        jbox(b"ftyp", b"jpx \x00\x00\x00\x00jpx "),
+        jbox(b"dtbl", &data_reference),
+    ]
+    .concat();
+
+    // The local fragment is stored in a Media Data box after the Fragment
+    // Table box
+    let local_offset = (header.len() + fragment_list(0).len() + 8) as u64;
+    [header, fragment_list(local_offset), jbox(b"mdat", local)].concat()
+}
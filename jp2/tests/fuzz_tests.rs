@@ -0,0 +1,47 @@
+// Regressions of the inputs found by the fuzz targets, which panicked instead
+// of returning an error.
+use std::io::Cursor;
+
+use jp2::{decode_jp2, JP2Error};
+
+fn with_boxes(header_box: &[u8], jbox: &[u8]) -> Vec<u8> {
+    let header_length = 45 + header_box.len() as u32;
+    [
+        &[0, 0, 0, 12][..],
+        b"jP  \r\n\x87\n",
+        &[0, 0, 0, 20],
+        b"ftypjp2 \0\0\0\0jp2 ",
+        &header_length.to_be_bytes(),
+        b"jp2h",
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0, 0, 0, 1, 0, 0, 0, 1, 0, 1, 7, 7, 0, 0],
+        &[0, 0, 0, 15],
+        b"colr",
+        &[1, 0, 0, 0, 0, 0, 17],
+        header_box,
+        jbox,
+        &[0, 0, 0, 8],
+        b"jp2c",
+    ]
+    .concat()
+}
+
+#[test]
+fn test_extended_length_overflow() {
+    // An unknown box whose extended length overflows the end of the box
+    let data = with_boxes(&[], &[&[0, 0, 0, 1][..], b"free", &[0xFF; 8]].concat());
+    let error = decode_jp2(&mut Cursor::new(data)).expect_err("decoding should fail");
+    assert!(matches!(&error, JP2Error::BoxMalformed { .. }));
+
+    // The same box within the JP2 Header box is left for decode_jp2
+    let data = with_boxes(&[&[0, 0, 0, 1][..], b"free", &[0xFF; 8]].concat(), &[]);
+    decode_jp2(&mut Cursor::new(data)).expect_err("decoding should fail");
+}
+
+#[test]
+fn test_xml_invalid_utf8() {
+    let data = with_boxes(&[], &[&[0, 0, 0, 13][..], b"xml <a\xFF/>"].concat());
+    let jp2 = decode_jp2(&mut Cursor::new(data)).expect("decoding should succeed");
+    assert_eq!(jp2.xml_boxes()[0].format(), "<a\u{FFFD}/>");
+}
@@ -0,0 +1,174 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use jp2::{
+    decode_jp2, GeoKeyValue, JP2Error, UUIDBox, GEO_KEY_GEOGRAPHIC_TYPE, GEO_KEY_MODEL_TYPE,
+    UUID_GEOJP2,
+};
+
+// A big endian TIFF with a single IFD of the fields, given as tag, type,
+// count and value bytes, the values of more than 4 bytes follow the IFD.
+fn tiff(fields: &[(u16, u16, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut data: Vec<u8> = b"MM\0*\0\0\0\x08".to_vec();
+    let mut values: Vec<u8> = vec![];
+    let values_offset = 8 + 2 + fields.len() * 12 + 4;
+    data.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for (tag, field_type, count, value) in fields {
+        data.extend_from_slice(&tag.to_be_bytes());
+        data.extend_from_slice(&field_type.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            data.extend_from_slice(&inline);
+        } else {
+            data.extend_from_slice(&((values_offset + values.len()) as u32).to_be_bytes());
+            values.extend_from_slice(value);
+        }
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&values);
+    data
+}
+
+fn doubles(values: &[f64]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+fn shorts(values: &[u16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+#[test]
+fn test_geojp2_sample() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let file = File::open(path).expect("file should exist");
+    let jp2 = decode_jp2(&mut BufReader::new(file)).expect("decoding should succeed");
+
+    assert!(jp2.uuid_boxes()[0].is_geojp2());
+    let geojp2 = jp2
+        .geojp2()
+        .expect("GeoJP2 box should exist")
+        .expect("GeoJP2 box should decode");
+
+    assert_eq!(geojp2.tiepoints(), [[0.0, 0.0, 0.0, 135.2, -30.3, 0.0]]);
+    let scale = geojp2.pixel_scale().unwrap();
+    assert!((scale[0] - 0.004).abs() < 1e-12);
+    assert!(geojp2.transformation().is_none());
+
+    // Geographic WGS 84, PixelIsArea
+    assert_eq!(geojp2.model_type(), Some(2));
+    assert_eq!(geojp2.raster_type(), Some(1));
+    assert_eq!(geojp2.crs_code(), Some(4326));
+    assert_eq!(geojp2.citation(), Some("WGS 84"));
+    assert_eq!(geojp2.geo_keys().len(), 7);
+    assert_eq!(geojp2.geo_keys()[0].id(), GEO_KEY_MODEL_TYPE);
+    assert_eq!(
+        geojp2.geo_key(2057),
+        Some(&GeoKeyValue::Double(vec![6378137.0]))
+    );
+    assert_eq!(
+        geojp2.geo_key(GEO_KEY_GEOGRAPHIC_TYPE),
+        Some(&GeoKeyValue::Short(4326))
+    );
+
+    let geotransform = geojp2.geotransform().unwrap();
+    let expected = [135.2, 0.004, 0.0, -30.3, 0.0, -0.1 / 24.0];
+    for (value, expected) in geotransform.iter().zip(expected.iter()) {
+        assert!((value - expected).abs() < 1e-9, "{:?}", geotransform);
+    }
+}
+
+#[test]
+fn test_geojp2_transformation() {
+    // UTM zone 33N, PixelIsPoint, with a rotated transformation
+    let data = tiff(&[
+        (
+            34264,
+            12,
+            16,
+            doubles(&[
+                10.0, 1.0, 0.0, 500000.0, 2.0, -10.0, 0.0, 4000000.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+                0.0, 1.0,
+            ]),
+        ),
+        (
+            34735,
+            3,
+            16,
+            shorts(&[1, 1, 0, 3, 1024, 0, 1, 1, 1025, 0, 1, 2, 3072, 0, 1, 32633]),
+        ),
+    ]);
+    let uuid_box = UUIDBox::new(UUID_GEOJP2, data);
+    let geojp2 = uuid_box
+        .geojp2()
+        .unwrap()
+        .expect("GeoJP2 box should decode");
+
+    assert_eq!(geojp2.crs_code(), Some(32633));
+    assert_eq!(geojp2.model_type(), Some(1));
+    assert!(geojp2.citation().is_none());
+    // The origin is moved from the centre to the corner of the pixel
+    assert_eq!(
+        geojp2.geotransform(),
+        Some([499994.5, 10.0, 1.0, 4000004.0, 2.0, -10.0])
+    );
+}
+
+#[test]
+fn test_geojp2_user_defined_crs() {
+    // A user-defined projection falls back to the geographic type, several
+    // tie points are ground control points without a geotransform
+    let data = tiff(&[
+        (33550, 12, 3, doubles(&[1.0, 1.0, 0.0])),
+        (
+            33922,
+            12,
+            12,
+            doubles(&[
+                0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 10.0, 10.0, 0.0, 11.0, -8.0, 0.0,
+            ]),
+        ),
+        (
+            34735,
+            3,
+            12,
+            shorts(&[1, 1, 0, 2, 2048, 0, 1, 4269, 3072, 0, 1, 32767]),
+        ),
+    ]);
+    let geojp2 = UUIDBox::new(UUID_GEOJP2, data)
+        .geojp2()
+        .unwrap()
+        .expect("GeoJP2 box should decode");
+    assert_eq!(geojp2.tiepoints().len(), 2);
+    assert_eq!(geojp2.crs_code(), Some(4269));
+    assert!(geojp2.geotransform().is_none());
+}
+
+#[test]
+fn test_geojp2_errors() {
+    // Other UUID boxes are not GeoJP2 boxes
+    let uuid_box = UUIDBox::new([0; 16], vec![]);
+    assert!(!uuid_box.is_geojp2());
+    assert!(uuid_box.geojp2().is_none());
+
+    // The GeoKey directory exceeds the TIFF
+    let mut data = tiff(&[(34735, 3, 8, shorts(&[1, 1, 0, 1, 1024, 0, 1, 1]))]);
+    data.truncate(data.len() - 4);
+    let invalid = [b"GIF89a".to_vec(), data];
+    for data in invalid.iter() {
+        let error = UUIDBox::new(UUID_GEOJP2, data.clone())
+            .geojp2()
+            .unwrap()
+            .expect_err("decoding should fail");
+        match &error {
+            JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"uuid"),
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+}
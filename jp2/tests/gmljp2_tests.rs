@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_gmljp2, decode_jp2, decode_jpx, encode_jp2, gmljp2_association_box, AssociatedBox,
+    AssociationSuperBox, JP2Error, LabelBox, Limits, XMLBox, LABEL_GML_DATA,
+    LABEL_GML_ROOT_INSTANCE,
+};
+
+fn gml_data(xml: &str) -> AssociationSuperBox {
+    AssociationSuperBox::new(vec![
+        AssociatedBox::Label(LabelBox::new(LABEL_GML_DATA)),
+        AssociatedBox::Association(AssociationSuperBox::new(vec![
+            AssociatedBox::Label(LabelBox::new(LABEL_GML_ROOT_INSTANCE)),
+            AssociatedBox::Xml(XMLBox::new(xml.as_bytes().to_vec())),
+        ])),
+    ])
+}
+
+fn assert_geotransform(geotransform: [f64; 6], expected: [f64; 6]) {
+    for (value, expected) in geotransform.iter().zip(expected.iter()) {
+        assert!((value - expected).abs() < 1e-9, "{:?}", geotransform);
+    }
+}
+
+#[test]
+fn test_write_gmljp2() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let mut jp2 = decode_jp2(&mut reader).expect("decoding should succeed");
+    assert!(jp2.association_boxes().is_empty());
+    assert!(jp2.gmljp2().is_none());
+
+    let geotransform = [500000.0, 30.0, 0.0, 4200000.0, 0.0, -30.0];
+    jp2.association_boxes_mut().push(gmljp2_association_box(
+        100,
+        24,
+        geotransform,
+        "urn:ogc:def:crs:EPSG::32633",
+    ));
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encoding should succeed");
+
+    let jp2 = decode_jp2(&mut Cursor::new(&encoded)).expect("decoding should succeed");
+    assert_eq!(jp2.association_boxes().len(), 1);
+    assert!(jp2.unknown_boxes().is_empty());
+    let gmljp2 = jp2
+        .gmljp2()
+        .expect("GMLJP2 should exist")
+        .expect("GMLJP2 should decode");
+    assert!(gmljp2.root_instance().contains("gmljp2://codestream/0"));
+    assert_eq!(gmljp2.srs_name(), Some("urn:ogc:def:crs:EPSG::32633"));
+    assert_geotransform(gmljp2.geotransform().unwrap(), geotransform);
+
+    // The origin of the grid is the centre of the first pixel
+    let grid = gmljp2.rectified_grid().unwrap();
+    assert_eq!(grid.low(), [0, 0]);
+    assert_eq!(grid.high(), [99, 23]);
+    assert_eq!(grid.origin(), [500015.0, 4199985.0]);
+    assert_eq!(grid.offset_vectors(), [vec![30.0, 0.0], vec![0.0, -30.0]]);
+
+    // The association box is also read as a JPX file
+    let jpx = decode_jpx(&mut Cursor::new(&encoded)).expect("decoding should succeed");
+    let gmljp2 = jpx.gmljp2().unwrap().expect("GMLJP2 should decode");
+    assert_geotransform(gmljp2.geotransform().unwrap(), geotransform);
+}
+
+#[test]
+fn test_gmljp2_version_2() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- GMLJP2 version 2 coverage collection -->
+<gmljp2:GMLJP2CoverageCollection gml:id="JPEG2000_0" xmlns:gml="http://www.opengis.net/gml/3.2" xmlns:gmljp2="http://www.opengis.net/gmljp2/2.0" xmlns:gmlcov="http://www.opengis.net/gmlcov/1.0">
+  <gml:domainSet/>
+  <gml:rangeSet><gml:File><gml:rangeParameters/><gml:fileName>gmljp2://codestream/0</gml:fileName></gml:File></gml:rangeSet>
+  <gmljp2:featureMember>
+    <gmljp2:GMLJP2RectifiedGridCoverage gml:id="RGC_1">
+      <gml:domainSet>
+        <gml:RectifiedGrid gml:id="RGC_1_GRID" dimension="2" srsName='http://www.opengis.net/def/crs/EPSG/0/4326'>
+          <gml:limits><gml:GridEnvelope><gml:low>0 0</gml:low><gml:high>359 179</gml:high></gml:GridEnvelope></gml:limits>
+          <gml:axisLabels>i j</gml:axisLabels>
+          <gml:origin><gml:Point gml:id="RGC_1_ORIGIN"><gml:pos>89.75 -179.75</gml:pos></gml:Point></gml:origin>
+          <gml:offsetVector>0 0.5</gml:offsetVector>
+          <gml:offsetVector>-0.5 0</gml:offsetVector>
+        </gml:RectifiedGrid>
+      </gml:domainSet>
+      <gmlcov:rangeType><![CDATA[<not parsed>]]></gmlcov:rangeType>
+    </gmljp2:GMLJP2RectifiedGridCoverage>
+  </gmljp2:featureMember>
+</gmljp2:GMLJP2CoverageCollection>"#;
+    let gmljp2 = decode_gmljp2(&[gml_data(xml)])
+        .expect("GMLJP2 should exist")
+        .expect("GMLJP2 should decode");
+    assert_eq!(
+        gmljp2.srs_name(),
+        Some("http://www.opengis.net/def/crs/EPSG/0/4326")
+    );
+    // Latitude and longitude axes, in the order of the GML
+    assert_geotransform(
+        gmljp2.geotransform().unwrap(),
+        [90.0, 0.0, -0.5, -180.0, 0.5, 0.0],
+    );
+}
+
+#[test]
+fn test_gmljp2_errors() {
+    // Association boxes of other labels are not GMLJP2
+    let other = AssociationSuperBox::new(vec![AssociatedBox::Label(LabelBox::new("other"))]);
+    assert!(decode_gmljp2(&[other]).is_none());
+
+    // A coverage without a grid has no geotransform
+    let gmljp2 = decode_gmljp2(&[gml_data("<gml:FeatureCollection/>")])
+        .unwrap()
+        .expect("GMLJP2 should decode");
+    assert!(gmljp2.rectified_grid().is_none());
+    assert!(gmljp2.geotransform().is_none());
+
+    // Elements nested up to the default limits
+    let depth = Limits::default().max_nesting_depth as usize + 1;
+    let nested = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+    assert!(decode_gmljp2(&[gml_data(&nested)]).unwrap().is_ok());
+
+    let missing_root_instance =
+        AssociationSuperBox::new(vec![AssociatedBox::Label(LabelBox::new(LABEL_GML_DATA))]);
+    let invalid = [
+        missing_root_instance,
+        gml_data("<gml:FeatureCollection>"),
+        gml_data("<a><b></a></b>"),
+        gml_data("<gml:RectifiedGrid><gml:origin><gml:pos>1 x</gml:pos></gml:origin></gml:RectifiedGrid>"),
+        gml_data(&format!("<a>{}</a>", nested)),
+        gml_data(&format!("{}{}", "<a>".repeat(100_000), "</a>".repeat(100_000))),
+    ];
+    for association_box in invalid {
+        let error = decode_gmljp2(&[association_box])
+            .unwrap()
+            .expect_err("decoding should fail");
+        match &error {
+            JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"asoc"),
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+}
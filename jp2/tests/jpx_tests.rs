@@ -0,0 +1,503 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, decode_jpx, AssociatedBox, AssociationNumber, JBox, JP2Error, JPXFile, OpacityType,
+};
+
+mod common;
+use common::jbox;
+
+#[test]
+fn test_sample_file5() {
+    let jpx = test_sample_jpx_file("file5.jp2");
+
+    let reader_requirements = jpx.reader_requirements_box().as_ref().unwrap();
+    assert_eq!(
+        standard_features(&jpx),
+        vec![(5, vec![0x80]), (61, vec![0x60]), (43, vec![0x40])]
+    );
+    assert!(reader_requirements.vendor_features().is_empty());
+
+    let header_box = jpx.header_box().as_ref().unwrap();
+    assert_eq!(header_box.image_header_box.width(), 768);
+    assert_eq!(header_box.colour_specification_boxes.len(), 2);
+}
+
+#[test]
+fn test_sample_file7() {
+    let jpx = test_sample_jpx_file("file7.jp2");
+
+    assert_eq!(
+        standard_features(&jpx),
+        vec![(5, vec![0x80]), (60, vec![0x60]), (43, vec![0x40])]
+    );
+
+    let header_box = jpx.header_box().as_ref().unwrap();
+    assert_eq!(header_box.image_header_box.width(), 480);
+    assert_eq!(header_box.colour_specification_boxes.len(), 2);
+}
+
+#[test]
+fn test_sample_jp2_file() {
+    // JP2 files are JPX files without the Reader Requirements box
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    assert!(jpx.reader_requirements_box().is_none());
+    assert!(jpx.header_box().is_some());
+    assert_eq!(jpx.xml_boxes().len(), 2);
+    assert_eq!(jpx.contiguous_codestreams_boxes().len(), 1);
+    assert!(jpx.codestream_header_boxes().is_empty());
+}
+
+#[test]
+fn test_decode_jpx_boxes() {
+    let data = jpx_file();
+    let mut reader = Cursor::new(&data);
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    assert_eq!(jpx.length(), data.len() as u64);
+    assert_eq!(jpx.file_type_box().as_ref().unwrap().brand(), "jpx ");
+
+    // Reader Requirements
+    let reader_requirements = jpx.reader_requirements_box().as_ref().unwrap();
+    assert_eq!(reader_requirements.mask_length(), 1);
+    assert_eq!(reader_requirements.fully_understand_aspects_mask(), [0xc0]);
+    assert_eq!(reader_requirements.decode_completely_mask(), [0x80]);
+    assert_eq!(
+        standard_features(&jpx),
+        vec![(2, vec![0x80]), (12, vec![0x40])]
+    );
+    let vendor_feature = &reader_requirements.vendor_features()[0];
+    assert_eq!(vendor_feature.feature(), [0xab; 16]);
+    assert_eq!(vendor_feature.mask(), [0x40]);
+
+    // Codestream Header
+    assert_eq!(jpx.codestream_header_boxes().len(), 1);
+    let codestream_header = &jpx.codestream_header_boxes()[0];
+    assert_eq!(
+        codestream_header.label_box.as_ref().unwrap().label(),
+        "codestream"
+    );
+    assert_eq!(
+        codestream_header
+            .image_header_box
+            .as_ref()
+            .unwrap()
+            .components_num(),
+        3
+    );
+    assert_eq!(
+        codestream_header
+            .bits_per_component_box
+            .as_ref()
+            .unwrap()
+            .bits_per_component()
+            .len(),
+        3
+    );
+    let regions = codestream_header
+        .roi_description_box
+        .as_ref()
+        .unwrap()
+        .regions();
+    assert_eq!(regions.len(), 1);
+    assert!(regions[0].in_codestream());
+    assert_eq!(regions[0].region_type(), 1);
+    assert_eq!(regions[0].priority(), 2);
+    assert_eq!(regions[0].horizontal_offset(), 3);
+    assert_eq!(regions[0].vertical_offset(), 4);
+    assert_eq!(regions[0].width(), 5);
+    assert_eq!(regions[0].height(), 6);
+
+    // Compositing Layer Header
+    assert_eq!(jpx.compositing_layer_header_boxes().len(), 1);
+    let compositing_layer_header = &jpx.compositing_layer_header_boxes()[0];
+    assert_eq!(compositing_layer_header.colour_group_boxes.len(), 1);
+    let colour_group = &compositing_layer_header.colour_group_boxes[0];
+    assert_eq!(colour_group.colour_specification_boxes.len(), 2);
+    assert_eq!(
+        colour_group.colour_specification_boxes[1].enumerated_colour_space(),
+        Some(17)
+    );
+    let opacity = compositing_layer_header.opacity_box.as_ref().unwrap();
+    assert_eq!(opacity.opacity_type(), OpacityType::ChromaKey);
+    assert_eq!(opacity.channels_num(), 3);
+    assert_eq!(opacity.chroma_key(), [1, 2, 3]);
+    let codestream_registration = compositing_layer_header
+        .codestream_registration_box
+        .as_ref()
+        .unwrap();
+    assert_eq!(codestream_registration.horizontal_grid_size(), 2);
+    assert_eq!(codestream_registration.vertical_grid_size(), 1);
+    let registration = &codestream_registration.registrations()[0];
+    assert_eq!(registration.codestream(), 0);
+    assert_eq!(registration.horizontal_resolution(), 2);
+    assert_eq!(registration.vertical_resolution(), 1);
+    assert_eq!(registration.horizontal_offset(), 1);
+    assert_eq!(registration.vertical_offset(), 0);
+    let resolution = compositing_layer_header.resolution_box.as_ref().unwrap();
+    assert!(resolution.capture_resolution_box().is_some());
+    assert!(resolution.default_display_resolution_box().is_none());
+
+    // Composition
+    let composition = jpx.composition_box().as_ref().unwrap();
+    assert_eq!(composition.composition_options_box.height(), 2);
+    assert_eq!(composition.composition_options_box.width(), 4);
+    assert_eq!(composition.composition_options_box.loop_count(), 255);
+    assert_eq!(composition.instruction_set_boxes.len(), 1);
+    let instruction_set = &composition.instruction_set_boxes[0];
+    assert_eq!(instruction_set.instruction_type(), 0b10_0111);
+    assert_eq!(instruction_set.repeat(), 1);
+    assert_eq!(instruction_set.tick(), 40);
+    let instructions = instruction_set.instructions();
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[0].offset(), Some((0, 0)));
+    assert_eq!(instructions[0].size(), Some((2, 2)));
+    assert_eq!(instructions[0].life(), Some(10));
+    assert_eq!(instructions[0].persistent(), Some(true));
+    assert_eq!(instructions[0].next_use(), Some(1));
+    assert_eq!(instructions[0].crop(), Some((0, 0, 1, 1)));
+    assert_eq!(instructions[1].offset(), Some((2, 0)));
+    assert_eq!(instructions[1].persistent(), Some(false));
+
+    // Codestreams and fragments
+    assert_eq!(jpx.contiguous_codestreams_boxes().len(), 1);
+    assert_eq!(jpx.fragment_table_boxes().len(), 1);
+    let fragments = jpx.fragment_table_boxes()[0].fragment_list_box.fragments();
+    assert_eq!(fragments.len(), 2);
+    assert_eq!(fragments[0].offset(), 0);
+    assert_eq!(fragments[0].length(), 100);
+    assert_eq!(fragments[0].data_reference(), 1);
+    assert_eq!(fragments[1].offset(), 1 << 32);
+    assert_eq!(fragments[1].data_reference(), 0);
+    let data_reference = jpx.data_reference_box().as_ref().unwrap();
+    assert_eq!(data_reference.data_entry_url_boxes().len(), 1);
+    assert_eq!(
+        data_reference.data_entry_url_boxes()[0].location().unwrap(),
+        "file:///fragments.j2c"
+    );
+
+    // Cross-Reference
+    assert_eq!(jpx.cross_reference_boxes().len(), 1);
+    let cross_reference = &jpx.cross_reference_boxes()[0];
+    assert_eq!(cross_reference.box_type(), *b"xml ");
+    assert_eq!(cross_reference.fragment_list_box.fragments().len(), 1);
+
+    // Association
+    assert_eq!(jpx.association_boxes().len(), 1);
+    let association = &jpx.association_boxes()[0];
+    assert_eq!(
+        association.associations(),
+        Some(vec![
+            AssociationNumber::Codestream(0),
+            AssociationNumber::CompositingLayer(1),
+        ])
+    );
+    assert_eq!(association.label(), Some("layer".into()));
+    let boxes = association.boxes();
+    assert_eq!(boxes.len(), 4);
+    match &boxes[2] {
+        AssociatedBox::Association(nested) => {
+            assert_eq!(
+                nested.associations(),
+                Some(vec![AssociationNumber::RenderedResult])
+            );
+            match &nested.boxes()[1] {
+                AssociatedBox::Xml(xml) => assert_eq!(xml.format(), "<a/>"),
+                other => panic!("unexpected box {:?}", other),
+            }
+        }
+        other => panic!("unexpected box {:?}", other),
+    }
+    match &boxes[3] {
+        AssociatedBox::Other(unknown) => {
+            assert_eq!(unknown.box_type(), *b"free");
+            assert_eq!(unknown.data(), Some(&b"\x00\x01"[..]));
+        }
+        other => panic!("unexpected box {:?}", other),
+    }
+
+    // The Reader Requirements box is not part of the JP2 file format
+    assert!(decode_jp2(&mut Cursor::new(&data)).is_err());
+}
+
+#[test]
+fn test_encode_jpx_boxes() {
+    // Boxes are encoded as they were decoded
+    let data = jpx_file();
+    let jpx = decode_jpx(&mut Cursor::new(&data)).expect("decoding should succeed");
+
+    let mut encoded: Vec<u8> = vec![];
+    jpx.reader_requirements_box()
+        .as_ref()
+        .unwrap()
+        .encode(&mut encoded)
+        .unwrap();
+    jpx.codestream_header_boxes()[0]
+        .encode(&mut encoded)
+        .unwrap();
+    jpx.compositing_layer_header_boxes()[0]
+        .encode(&mut encoded)
+        .unwrap();
+    jpx.composition_box()
+        .as_ref()
+        .unwrap()
+        .encode(&mut encoded)
+        .unwrap();
+    jpx.data_reference_box()
+        .as_ref()
+        .unwrap()
+        .encode(&mut encoded)
+        .unwrap();
+    jpx.fragment_table_boxes()[0].encode(&mut encoded).unwrap();
+    jpx.cross_reference_boxes()[0].encode(&mut encoded).unwrap();
+    jpx.association_boxes()[0].encode(&mut encoded).unwrap();
+
+    let expected: Vec<u8> = [
+        reader_requirements(),
+        codestream_header(),
+        compositing_layer_header(),
+        composition(),
+        data_reference(),
+        fragment_table(),
+        cross_reference(),
+        association(),
+    ]
+    .concat();
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn test_decode_jpx_incompatible() {
+    let data: Vec<u8> = [
+        jbox(b"jP  ", b"\x0d\x0a\x87\x0a"),
+        jbox(b"ftyp", b"jpx \x00\x00\x00\x00jpm "),
+        jbox(b"jp2c", b"\xff\x4f\xff\xd9"),
+    ]
+    .concat();
+
+    let error = decode_jpx(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    match &error {
+        JP2Error::NotCompatible {
+            compatibility_list,
+            offset,
+        } => {
+            assert_eq!(*compatibility_list, vec!["jpm ".to_string()]);
+            assert_eq!(*offset, 20);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_decode_jpx_malformed_fragment_list() {
+    // NF does not match the length of the Fragment List box
+    let data: Vec<u8> = [
+        jbox(b"jP  ", b"\x0d\x0a\x87\x0a"),
+        jbox(b"ftyp", b"jpx \x00\x00\x00\x00jpx "),
+        jbox(
+            b"ftbl",
+            &jbox(b"flst", &[0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0]),
+        ),
+    ]
+    .concat();
+
+    let error = decode_jpx(&mut Cursor::new(&data)).expect_err("decoding should fail");
+    match &error {
+        JP2Error::BoxMalformed { box_type, .. } => assert_eq!(*box_type, *b"flst"),
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+fn test_sample_jpx_file(filename: &str) -> JPXFile {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join(filename);
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+
+    assert!(jpx.signature_box().is_some());
+    assert_eq!(jpx.file_type_box().as_ref().unwrap().brand(), "jpx ");
+
+    let reader_requirements = jpx.reader_requirements_box().as_ref().unwrap();
+    assert_eq!(reader_requirements.mask_length(), 1);
+    assert_eq!(reader_requirements.fully_understand_aspects_mask(), [0xa0]);
+    assert_eq!(reader_requirements.decode_completely_mask(), [0xc0]);
+
+    assert!(jpx.unknown_boxes().is_empty());
+    assert_eq!(jpx.contiguous_codestreams_boxes().len(), 1);
+
+    jpx
+}
+
+fn standard_features(jpx: &JPXFile) -> Vec<(u16, Vec<u8>)> {
+    jpx.reader_requirements_box()
+        .as_ref()
+        .unwrap()
+        .standard_features()
+        .iter()
+        .map(|feature| (feature.feature(), feature.mask().to_vec()))
+        .collect()
+}
+
+fn image_header() -> Vec<u8> {
+    jbox(b"ihdr", &[0, 0, 0, 2, 0, 0, 0, 2, 0, 3, 7, 7, 0, 0])
+}
+
+fn reader_requirements() -> Vec<u8> {
+    let contents: Vec<u8> = [
+        // ML, FUAM, DCM
+        &[1, 0xc0, 0x80][..],
+        // NSF, SF0, SM0, SF1, SM1
+        &[0, 2, 0, 2, 0x80, 0, 12, 0x40],
+        // NVF, VF0, VM0
+        &[0, 1],
+        &[0xab; 16],
+        &[0x40],
+    ]
+    .concat();
+    jbox(b"rreq", &contents)
+}
+
+fn codestream_header() -> Vec<u8> {
+    let roi: Vec<u8> = [
+        &[1, 1, 1, 2][..],
+        &3u32.to_be_bytes(),
+        &4u32.to_be_bytes(),
+        &5u32.to_be_bytes(),
+        &6u32.to_be_bytes(),
+    ]
+    .concat();
+    let contents: Vec<u8> = [
+        jbox(b"lbl ", b"codestream"),
+        image_header(),
+        jbox(b"bpcc", &[7, 7, 7]),
+        jbox(b"roid", &roi),
+    ]
+    .concat();
+    jbox(b"jpch", &contents)
+}
+
+fn compositing_layer_header() -> Vec<u8> {
+    let colour_group: Vec<u8> = [
+        jbox(b"colr", &[1, 0, 0, 0, 0, 0, 16]),
+        jbox(b"colr", &[1, 1, 0, 0, 0, 0, 17]),
+    ]
+    .concat();
+    let contents: Vec<u8> = [
+        jbox(b"cgrp", &colour_group),
+        jbox(b"opct", &[2, 3, 1, 2, 3]),
+        jbox(b"creg", &[0, 2, 0, 1, 0, 0, 2, 1, 1, 0]),
+        jbox(b"res ", &jbox(b"resc", &[0, 1, 0, 1, 0, 1, 0, 1, 0, 0])),
+    ]
+    .concat();
+    jbox(b"jplh", &contents)
+}
+
+fn composition() -> Vec<u8> {
+    let instructions: Vec<u32> = vec![
+        // XO, YO, W, H, LIFE, N, XC, YC, WC, HC
+        0,
+        0,
+        2,
+        2,
+        0x8000_000a,
+        1,
+        0,
+        0,
+        1,
+        1,
+        2,
+        0,
+        2,
+        2,
+        10,
+        0,
+        0,
+        0,
+        1,
+        1,
+    ];
+    let instruction_set: Vec<u8> = [
+        &[0, 0b10_0111, 0, 1, 0, 0, 0, 40][..],
+        &instructions
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect::<Vec<u8>>(),
+    ]
+    .concat();
+    let contents: Vec<u8> = [
+        jbox(b"copt", &[0, 0, 0, 2, 0, 0, 0, 4, 255]),
+        jbox(b"inst", &instruction_set),
+    ]
+    .concat();
+    jbox(b"comp", &contents)
+}
+
+fn data_reference() -> Vec<u8> {
+    let contents: Vec<u8> = [
+        &[0, 1][..],
+        &jbox(b"url ", b"\x00\x00\x00\x00file:///fragments.j2c\x00"),
+    ]
+    .concat();
+    jbox(b"dtbl", &contents)
+}
+
+fn fragment_list(fragments: &[(u64, u32, u16)]) -> Vec<u8> {
+    let mut contents: Vec<u8> = (fragments.len() as u16).to_be_bytes().to_vec();
+    for (offset, length, data_reference) in fragments {
+        contents.extend_from_slice(&offset.to_be_bytes());
+        contents.extend_from_slice(&length.to_be_bytes());
+        contents.extend_from_slice(&data_reference.to_be_bytes());
+    }
+    jbox(b"flst", &contents)
+}
+
+fn fragment_table() -> Vec<u8> {
+    jbox(b"ftbl", &fragment_list(&[(0, 100, 1), (1 << 32, 50, 0)]))
+}
+
+fn cross_reference() -> Vec<u8> {
+    let contents: Vec<u8> = [&b"xml "[..], &fragment_list(&[(0, 12, 0)])].concat();
+    jbox(b"cref", &contents)
+}
+
+fn association() -> Vec<u8> {
+    let nested: Vec<u8> = [jbox(b"nlst", &[0, 0, 0, 0]), jbox(b"xml ", b"<a/>")].concat();
+    let contents: Vec<u8> = [
+        jbox(b"nlst", &[1, 0, 0, 0, 2, 0, 0, 1]),
+        jbox(b"lbl ", b"layer"),
+        jbox(b"asoc", &nested),
+        jbox(b"free", &[0, 1]),
+    ]
+    .concat();
+    jbox(b"asoc", &contents)
+}
+
+// A JPX file using all boxes of ISO/IEC 15444-2 Annex M read by decode_jpx
+fn jpx_file() -> Vec<u8> {
+    let header: Vec<u8> = [image_header(), jbox(b"colr", &[1, 0, 0, 0, 0, 0, 16])].concat();
+    [
+        jbox(b"jP  ", b"\x0d\x0a\x87\x0a"),
+        jbox(b"ftyp", b"jpx \x00\x00\x00\x00jpx "),
+        reader_requirements(),
+        jbox(b"jp2h", &header),
+        codestream_header(),
+        compositing_layer_header(),
+        composition(),
+        jbox(b"jp2c", b"\xff\x4f\xff\xd9"),
+        data_reference(),
+        fragment_table(),
+        cross_reference(),
+        association(),
+    ]
+    .concat()
+}
@@ -0,0 +1,260 @@
+use std::{
+    convert::TryInto,
+    fs,
+    io::{self, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, decode_jp2_with_options, decode_jpx, validate_jp2, BoxIterator, DecodeOptions,
+    JP2Error, LimitError, Limits,
+};
+
+mod common;
+use common::contents;
+
+fn geojp2() -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    fs::read(path).expect("file should exist")
+}
+
+fn box_length(data: &[u8], box_type: &[u8; 4]) -> u64 {
+    let start = contents(data, box_type) - 8;
+    u32::from_be_bytes(data[start..start + 4].try_into().unwrap()) as u64 - 8
+}
+
+fn limit_error(data: &[u8], limits: Limits) -> LimitError {
+    let error = decode_jp2_with_options(
+        &mut Cursor::new(data),
+        &DecodeOptions::lenient().with_limits(limits),
+    )
+    .expect_err("decoding should fail");
+    match error {
+        JP2Error::Limit { source, .. } => source,
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_limits() {
+    let data = geojp2();
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let image_header_box = &jp2.header_box().as_ref().unwrap().image_header_box;
+    let area = image_header_box.width() as u64 * image_header_box.height() as u64;
+    let header_length = box_length(&data, b"jp2h");
+    let uuid_length = box_length(&data, b"uuid");
+
+    decode_jp2_with_options(
+        &mut Cursor::new(&data),
+        &DecodeOptions::strict().with_limits(Limits::unlimited()),
+    )
+    .expect("decoding should succeed");
+
+    assert_eq!(
+        limit_error(
+            &data,
+            Limits {
+                max_box_size: header_length - 1,
+                ..Limits::default()
+            }
+        ),
+        LimitError::BoxSize {
+            length: header_length,
+            max_box_size: header_length - 1
+        }
+    );
+    // The JP2 Header box and the UUID box
+    assert_eq!(
+        limit_error(
+            &data,
+            Limits {
+                max_memory: header_length,
+                ..Limits::default()
+            }
+        ),
+        LimitError::Memory {
+            allocated: header_length + uuid_length,
+            max_memory: header_length
+        }
+    );
+    assert_eq!(
+        limit_error(
+            &data,
+            Limits {
+                max_components: 0,
+                ..Limits::default()
+            }
+        ),
+        LimitError::Components {
+            components: image_header_box.components_num(),
+            max_components: 0
+        }
+    );
+    assert_eq!(
+        limit_error(
+            &data,
+            Limits {
+                max_image_area: area - 1,
+                ..Limits::default()
+            }
+        ),
+        LimitError::ImageArea {
+            area,
+            max_image_area: area - 1
+        }
+    );
+}
+
+// A JP2 file with the box between the JP2 Header box and an empty codestream.
+fn with_box(jbox: &[u8]) -> Vec<u8> {
+    [
+        &[0, 0, 0, 12][..],
+        b"jP  \r\n\x87\n",
+        &[0, 0, 0, 20],
+        b"ftypjp2 \0\0\0\0jp2 ",
+        &[0, 0, 0, 45],
+        b"jp2h",
+        &[0, 0, 0, 22],
+        b"ihdr",
+        &[0, 0, 0, 1, 0, 0, 0, 1, 0, 1, 7, 7, 0, 0],
+        &[0, 0, 0, 15],
+        b"colr",
+        &[1, 0, 0, 0, 0, 0, 17],
+        jbox,
+        &[0, 0, 0, 8],
+        b"jp2c",
+    ]
+    .concat()
+}
+
+#[test]
+fn test_hostile_lengths() {
+    decode_jp2(&mut Cursor::new(with_box(&[]))).expect("decoding should succeed");
+
+    // An XML box of 4 GB in a file of 100 bytes
+    let data = with_box(&[&[0xFF, 0xFF, 0xFF, 0xFF][..], b"xml ", b"<a/>"].concat());
+    assert_eq!(
+        limit_error(&data, Limits::default()),
+        LimitError::BoxSize {
+            length: 0xFFFF_FFFF - 8,
+            max_box_size: Limits::default().max_box_size
+        }
+    );
+    // The box is read up to the end of the file without allocating its length
+    let error = decode_jp2_with_options(
+        &mut Cursor::new(&data),
+        &DecodeOptions::lenient().with_limits(Limits::unlimited()),
+    )
+    .expect_err("decoding should fail");
+    match &error {
+        JP2Error::Io { source, .. } => assert_eq!(source.kind(), io::ErrorKind::UnexpectedEof),
+        _ => panic!("unexpected error {:?}", error),
+    }
+
+    // Boxes shorter than their fields
+    for (jbox, box_type) in [
+        (&[&[0, 0, 0, 12][..], b"uuid", &[0; 4]].concat(), *b"uuid"),
+        (
+            &[&[0, 0, 0, 12][..], b"uinf", &[0, 0, 0, 9], b"url ", &[0]].concat(),
+            *b"url ",
+        ),
+    ] {
+        let data = with_box(jbox);
+        let error = decode_jp2(&mut Cursor::new(&data)).expect_err("decoding should fail");
+        match &error {
+            JP2Error::BoxMalformed {
+                box_type: found, ..
+            } => assert_eq!(*found, box_type),
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    // A reserved bit depth and an Image Header box of another length
+    let mut data = with_box(&[]);
+    let image_header = contents(&data, b"ihdr");
+    data[image_header + 10] = 38;
+    assert!(decode_jp2(&mut Cursor::new(&data)).is_err());
+    let mut data = with_box(&[]);
+    data[image_header - 5] = 21;
+    assert!(decode_jp2(&mut Cursor::new(&data)).is_err());
+
+    // Every byte of the boxes replaced
+    let data = with_box(&[&[0, 0, 0, 12][..], b"xml ", b"<a/>"].concat());
+    for position in 0..data.len() {
+        for value in [0x00, 0x01, 0xFF] {
+            let mut patched = data.clone();
+            patched[position] = value;
+            let _ = decode_jp2(&mut Cursor::new(&patched));
+        }
+    }
+}
+
+// Association boxes nested in each other, the innermost one empty.
+fn nested_associations(depth: u32) -> Vec<u8> {
+    (0..depth)
+        .flat_map(|level| {
+            let length = (depth - level) * 8;
+            [&length.to_be_bytes()[..], b"asoc"].concat()
+        })
+        .collect()
+}
+
+#[test]
+fn test_nesting_depth() {
+    let max_nesting_depth = Limits::default().max_nesting_depth;
+    let data = with_box(&nested_associations(max_nesting_depth));
+    decode_jp2(&mut Cursor::new(&data)).expect("decoding should succeed");
+    decode_jpx(&mut Cursor::new(&data)).expect("decoding should succeed");
+    assert!(BoxIterator::new(&mut Cursor::new(&data)).all(|info| info.is_ok()));
+    validate_jp2(&mut Cursor::new(&data)).expect("validation should succeed");
+
+    // Far below the other limits, but deep enough to overflow the stack
+    let data = with_box(&nested_associations(100_000));
+    assert_eq!(
+        limit_error(&data, Limits::default()),
+        LimitError::NestingDepth {
+            depth: max_nesting_depth + 1,
+            max_nesting_depth
+        }
+    );
+    for error in [
+        decode_jpx(&mut Cursor::new(&data)).expect_err("decoding should fail"),
+        BoxIterator::new(&mut Cursor::new(&data))
+            .find_map(|info| info.err())
+            .expect("iterating should fail"),
+        validate_jp2(&mut Cursor::new(&data)).expect_err("validation should fail"),
+    ] {
+        match error {
+            JP2Error::Limit {
+                source: LimitError::NestingDepth { .. },
+                ..
+            } => {}
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+}
+
+#[test]
+fn test_uuid_info_memory() {
+    // The Data Entry URL box of a UUID Info box is counted once
+    let data = with_box(
+        &[
+            &[0, 0, 0, 22][..],
+            b"uinf",
+            &[0, 0, 0, 14],
+            b"url ",
+            &[0, 0, 0, 0],
+            b"a\0",
+        ]
+        .concat(),
+    );
+    let max_memory = box_length(&data, b"jp2h") + 6;
+    decode_jp2_with_options(
+        &mut Cursor::new(&data),
+        &DecodeOptions::lenient().with_limits(Limits {
+            max_memory,
+            ..Limits::default()
+        }),
+    )
+    .expect("decoding should succeed");
+}
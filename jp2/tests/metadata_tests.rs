@@ -0,0 +1,209 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, encode_jp2, ExifIfd, ExifValue, JBox, JP2Error, JP2File, UUIDBox, UUID_EXIF,
+    UUID_IPTC, UUID_XMP,
+};
+
+// An IFD at the offset of a little endian TIFF, with the fields given as
+// tag, type, count and value bytes, the values of more than 4 bytes follow
+// the IFD.
+fn ifd(fields: &[(u16, u16, u32, Vec<u8>)], offset: usize, next: u32) -> Vec<u8> {
+    let mut data: Vec<u8> = (fields.len() as u16).to_le_bytes().to_vec();
+    let mut values: Vec<u8> = vec![];
+    let values_offset = offset + 2 + fields.len() * 12 + 4;
+    for (tag, field_type, count, value) in fields {
+        data.extend_from_slice(&tag.to_le_bytes());
+        data.extend_from_slice(&field_type.to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            data.extend_from_slice(&inline);
+        } else {
+            data.extend_from_slice(&((values_offset + values.len()) as u32).to_le_bytes());
+            values.extend_from_slice(value);
+        }
+    }
+    data.extend_from_slice(&next.to_le_bytes());
+    data.extend_from_slice(&values);
+    data
+}
+
+fn rational(numerator: u32, denominator: u32) -> Vec<u8> {
+    [numerator.to_le_bytes(), denominator.to_le_bytes()].concat()
+}
+
+// Exif with the 0th IFD, the Exif IFD and the GPS IFD, the next IFD of the
+// 0th IFD is itself.
+fn exif() -> Vec<u8> {
+    let primary = |exif: u32, gps: u32| {
+        vec![
+            (271, 2, 6, b"Maker\0".to_vec()),
+            (274, 3, 1, 6u16.to_le_bytes().to_vec()),
+            (282, 5, 1, rational(300, 1)),
+            (34665, 4, 1, exif.to_le_bytes().to_vec()),
+            (34853, 4, 1, gps.to_le_bytes().to_vec()),
+        ]
+    };
+    let exif_offset = 8 + ifd(&primary(0, 0), 8, 8).len();
+    let exif = ifd(
+        &[
+            (33434, 5, 1, rational(1, 250)),
+            (34855, 3, 1, 400u16.to_le_bytes().to_vec()),
+            (36864, 7, 4, b"0230".to_vec()),
+            (
+                37380,
+                10,
+                1,
+                [(-1i32).to_le_bytes(), 3i32.to_le_bytes()].concat(),
+            ),
+        ],
+        exif_offset,
+        0,
+    );
+    let gps_offset = exif_offset + exif.len();
+    let gps = ifd(&[(1, 2, 2, b"N\0".to_vec())], gps_offset, 0);
+
+    [
+        &b"Exif\0\0II*\0\x08\0\0\0"[..],
+        &ifd(&primary(exif_offset as u32, gps_offset as u32), 8, 8),
+        &exif,
+        &gps,
+    ]
+    .concat()
+}
+
+fn dataset(record: u8, dataset: u8, data: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![0x1c, record, dataset];
+    encoded.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    encoded.extend_from_slice(data);
+    encoded
+}
+
+// A JP2 file with the UUID boxes added to the GeoJP2 sample.
+fn jp2_with(uuid_boxes: Vec<UUIDBox>) -> JP2File {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let mut jp2 = decode_jp2(&mut reader).expect("decoding should succeed");
+    jp2.uuid_boxes_mut().extend(uuid_boxes);
+
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encoding should succeed");
+    decode_jp2(&mut Cursor::new(encoded)).expect("decoding should succeed")
+}
+
+#[test]
+fn test_metadata() {
+    let xmp = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?><x:xmpmeta xmlns:x="adobe:ns:meta/"/><?xpacket end="w"?>"#;
+    let mut iptc = [
+        dataset(1, 90, b"\x1b%G"),
+        dataset(2, 25, b"harbour"),
+        dataset(2, 25, b"boats"),
+        dataset(2, 120, "Caf\u{e9} at dusk".as_bytes()),
+        // An extended dataset, its length of 4 bytes follows
+        [&[0x1c, 2, 80, 0x80, 0x04, 0, 0, 0, 5][..], b"Smith"].concat(),
+    ]
+    .concat();
+    iptc.extend_from_slice(&[0; 3]);
+
+    let jp2 = jp2_with(vec![
+        UUIDBox::new(UUID_XMP, [xmp.as_bytes(), b"\0"].concat()),
+        UUIDBox::new(UUID_EXIF, exif()),
+        UUIDBox::new(UUID_IPTC, iptc),
+    ]);
+    assert_eq!(jp2.uuid_boxes().len(), 4);
+    assert!(!jp2.uuid_boxes()[0].is_metadata());
+    assert!(jp2.uuid_boxes()[1].is_metadata());
+
+    let metadata = jp2.metadata().expect("metadata should decode");
+    assert!(!metadata.is_empty());
+    assert_eq!(metadata.xmp(), Some(xmp));
+
+    let ifds: Vec<ExifIfd> = metadata.exif().iter().map(|tag| tag.ifd()).collect();
+    assert_eq!(ifds.len(), 10);
+    assert_eq!(ifds[0], ExifIfd::Primary);
+    assert_eq!(ifds[9], ExifIfd::Gps);
+    assert_eq!(
+        metadata
+            .exif_tag(ExifIfd::Primary, 271)
+            .and_then(ExifValue::text),
+        Some("Maker")
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Primary, 274),
+        Some(&ExifValue::Short(vec![6]))
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Primary, 282),
+        Some(&ExifValue::Rational(vec![(300, 1)]))
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Exif, 33434),
+        Some(&ExifValue::Rational(vec![(1, 250)]))
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Exif, 34855).unwrap().unsigned(),
+        Some(400)
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Exif, 36864),
+        Some(&ExifValue::Undefined(b"0230".to_vec()))
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Exif, 37380),
+        Some(&ExifValue::SRational(vec![(-1, 3)]))
+    );
+    assert_eq!(
+        metadata.exif_tag(ExifIfd::Gps, 1).and_then(ExifValue::text),
+        Some("N")
+    );
+    // The next IFD is the 0th IFD, which is not read again
+    assert!(metadata.exif_tag(ExifIfd::Thumbnail, 271).is_none());
+
+    assert_eq!(metadata.iptc().len(), 5);
+    let keywords: Vec<String> = metadata
+        .iptc_datasets(2, 25)
+        .iter()
+        .map(|dataset| dataset.text())
+        .collect();
+    assert_eq!(keywords, ["harbour", "boats"]);
+    assert_eq!(
+        metadata.iptc_datasets(2, 120)[0].text(),
+        "Caf\u{e9} at dusk"
+    );
+    assert_eq!(metadata.iptc_datasets(2, 80)[0].data(), b"Smith");
+}
+
+#[test]
+fn test_metadata_absent() {
+    let jp2 = jp2_with(vec![]);
+    let metadata = jp2.metadata().expect("metadata should decode");
+    assert!(metadata.is_empty());
+    assert!(metadata.xmp().is_none());
+}
+
+#[test]
+fn test_metadata_errors() {
+    let invalid = [
+        UUIDBox::new(UUID_XMP, vec![0xff, 0xfe]),
+        UUIDBox::new(UUID_EXIF, b"Exif\0\0MM\0*\0\0\0\x40".to_vec()),
+        UUIDBox::new(UUID_IPTC, dataset(2, 5, b"title")[..6].to_vec()),
+        UUIDBox::new(UUID_IPTC, b"8BIM".to_vec()),
+    ];
+    for uuid_box in invalid {
+        let jp2 = jp2_with(vec![uuid_box]);
+        let error = jp2.metadata().expect_err("decoding should fail");
+        match &error {
+            JP2Error::BoxMalformed { box_type, offset } => {
+                assert_eq!(*box_type, *b"uuid");
+                assert_eq!(*offset, jp2.uuid_boxes()[1].offset());
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+    }
+}
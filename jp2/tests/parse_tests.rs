@@ -1,6 +1,14 @@
-use std::{fs::File, io::BufReader, path::Path};
-
-use jp2::{decode_jp2, BitDepth, ChannelTypes, ColourSpecificationMethods, JBox as _, JP2File};
+use std::{
+    error,
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, BitDepth, ChannelTypes, ColourSpecificationMethods, JBox as _, JP2Error, JP2File,
+    RestrictedProfileType,
+};
 
 struct ExpectedConfiguration {
     compatibility_list: Vec<String>,
@@ -176,29 +184,18 @@ fn test_sample_file4() {
     assert_eq!(boxes.uuid_boxes().len(), 0);
 }
 
-#[ignore = "uses unsupported Part 2 extensions"]
 #[test]
 fn test_sample_file5() {
-    let boxes = test_sample_jp2_file(
-        "file5.jp2",
-        ExpectedConfiguration {
-            compatibility_list: vec!["\0\0\0\u{1}".into(), "jp2 ".into()],
-            width: 640,
-            height: 480,
-            num_components: 3,
-            bit_depth: 16,
-            colourspace: 16,
-            colour_specification_method: ColourSpecificationMethods::EnumeratedColourSpace,
-            has_unexpected_approx_set: true,
-        },
-    );
+    // JPX branded file using a restricted ICC profile and the ROMM-RGB
+    // enumerated colour space
+    let boxes = test_sample_jpx_branded_file("file5.jp2", 768, 512, 8, 21);
 
     let header_box = boxes.header_box().as_ref().unwrap();
     assert!(header_box.channel_definition_box.is_none());
     assert!(header_box.palette_box.is_none());
     assert!(header_box.component_mapping_box.is_none());
 
-    assert_eq!(boxes.xml_boxes().len(), 2);
+    assert_eq!(boxes.xml_boxes().len(), 0);
 
     assert_eq!(boxes.uuid_boxes().len(), 0);
 }
@@ -229,29 +226,18 @@ fn test_sample_file6() {
     assert_eq!(boxes.uuid_boxes().len(), 0);
 }
 
-#[ignore = "uses unsupported Part 2 extensions"]
 #[test]
 fn test_sample_file7() {
-    let boxes = test_sample_jp2_file(
-        "file7.jp2",
-        ExpectedConfiguration {
-            compatibility_list: vec!["\0\0\0\u{1}".into(), "jp2 ".into()],
-            width: 640,
-            height: 480,
-            num_components: 3,
-            bit_depth: 16,
-            colourspace: 16,
-            colour_specification_method: ColourSpecificationMethods::EnumeratedColourSpace,
-            has_unexpected_approx_set: true,
-        },
-    );
+    // JPX branded file using a restricted ICC profile and the e-sRGB
+    // enumerated colour space
+    let boxes = test_sample_jpx_branded_file("file7.jp2", 480, 640, 16, 20);
 
     let header_box = boxes.header_box().as_ref().unwrap();
     assert!(header_box.channel_definition_box.is_none());
     assert!(header_box.palette_box.is_none());
     assert!(header_box.component_mapping_box.is_none());
 
-    assert_eq!(boxes.xml_boxes().len(), 2);
+    assert_eq!(boxes.xml_boxes().len(), 0);
 
     assert_eq!(boxes.uuid_boxes().len(), 0);
 }
@@ -579,29 +565,28 @@ fn test_sample_file9() {
     );
     assert_eq!(pclr.generated_components()[0].values().len(), 256);
     assert_eq!(pclr.generated_components()[0].values()[0], 0);
-    // TODO: fix these
-    // assert_eq!(pclr.generated_components()[0].values()[1], 0xff);
-    // assert_eq!(pclr.generated_components()[0].values()[2], 0x17);
-    // assert_eq!(pclr.generated_components()[0].values()[252], 0x16);
+    assert_eq!(pclr.generated_components()[0].values()[1], 0xff);
+    assert_eq!(pclr.generated_components()[0].values()[2], 0x17);
+    assert_eq!(pclr.generated_components()[0].values()[252], 0x16);
     assert_eq!(
         pclr.generated_components()[1].bit_depth(),
         BitDepth::Unsigned { value: 8 }
     );
     assert_eq!(pclr.generated_components()[1].values().len(), 256);
-    // assert_eq!(pclr.generated_components()[1].values()[0], 0);
-    // assert_eq!(pclr.generated_components()[1].values()[1], 0xff);
-    // assert_eq!(pclr.generated_components()[1].values()[2], 0x0c);
-    // assert_eq!(pclr.generated_components()[1].values()[252], 0x0b);
+    assert_eq!(pclr.generated_components()[1].values()[0], 0);
+    assert_eq!(pclr.generated_components()[1].values()[1], 0xff);
+    assert_eq!(pclr.generated_components()[1].values()[2], 0x0c);
+    assert_eq!(pclr.generated_components()[1].values()[252], 0x0b);
     assert_eq!(
         pclr.generated_components()[2].bit_depth(),
         BitDepth::Unsigned { value: 8 }
     );
     assert_eq!(pclr.generated_components()[2].values().len(), 256);
-    // assert_eq!(pclr.generated_components()[2].values()[0], 0);
-    // assert_eq!(pclr.generated_components()[2].values()[1], 0xff);
-    // assert_eq!(pclr.generated_components()[3].values()[2], 0x15);
-    // assert_eq!(pclr.generated_components()[2].values()[252], 0x09);
-    // assert_eq!(pclr.generated_components()[2].values()[255], 0xf5);
+    assert_eq!(pclr.generated_components()[2].values()[0], 0);
+    assert_eq!(pclr.generated_components()[2].values()[1], 0xff);
+    assert_eq!(pclr.generated_components()[2].values()[2], 0x15);
+    assert_eq!(pclr.generated_components()[2].values()[252], 0x09);
+    assert_eq!(pclr.generated_components()[2].values()[255], 0xf5);
 
     assert!(header_box.component_mapping_box.is_some());
     /* From the description text (file9.txt):
@@ -801,6 +786,63 @@ fn test_sample_jp2_file(filename: &str, expected: ExpectedConfiguration) -> JP2F
     boxes
 }
 
+// Files of the JPX brand which are compatible with JP2 readers, the Reader
+// Requirements box is not part of the JP2 file format.
+fn test_sample_jpx_branded_file(
+    filename: &str,
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    colourspace: u32,
+) -> JP2File {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join(filename);
+    let file = File::open(path).expect("file should exist");
+    let mut reader = BufReader::new(file);
+    let boxes = decode_jp2(&mut reader).expect("decoding should succeed");
+
+    let file_type = boxes.file_type_box().as_ref().unwrap();
+    assert_eq!(file_type.brand(), "jpx ");
+    assert_eq!(file_type.min_version(), 0);
+    // file5.jp2 lists ‘jpbx’ rather than ‘jpxb’
+    assert_eq!(&file_type.compatibility_list()[1..3], ["jp2 ", "jpx "]);
+
+    let header_box = boxes.header_box().as_ref().unwrap();
+    let image_header_box = &header_box.image_header_box;
+    assert_eq!(image_header_box.height(), height);
+    assert_eq!(image_header_box.width(), width);
+    assert_eq!(image_header_box.components_num(), 3);
+    assert_eq!(image_header_box.components_bits(), bit_depth);
+
+    assert_eq!(header_box.colour_specification_boxes.len(), 2);
+    let restricted = &header_box.colour_specification_boxes[0];
+    assert_eq!(
+        restricted.method(),
+        ColourSpecificationMethods::RestrictedICCProfile
+    );
+    assert_eq!(restricted.precedence(), 0);
+    assert_eq!(
+        restricted.check_restricted_icc_profile(),
+        Some(Ok(RestrictedProfileType::ThreeComponentMatrix))
+    );
+    let enumerated = &header_box.colour_specification_boxes[1];
+    assert_eq!(
+        enumerated.method(),
+        ColourSpecificationMethods::EnumeratedColourSpace
+    );
+    assert_eq!(enumerated.precedence(), 1);
+    assert_eq!(enumerated.enumerated_colour_space(), Some(colourspace));
+    assert!(enumerated.check_restricted_icc_profile().is_none());
+
+    assert_eq!(boxes.unknown_boxes().len(), 1);
+    assert_eq!(boxes.unknown_boxes()[0].box_type(), *b"rreq");
+
+    assert_eq!(boxes.contiguous_codestreams_boxes().len(), 1);
+
+    boxes
+}
+
 #[test]
 fn test_geojp2() {
     // GeoJP2, as implemented by GDAL
@@ -940,3 +982,103 @@ fn test_j2pi() {
 
     assert_eq!(boxes.uuid_boxes().len(), 0);
 }
+
+#[test]
+fn test_unknown_box() {
+    let mut data: Vec<u8> = vec![];
+    File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2"))
+        .expect("file should exist")
+        .read_to_end(&mut data)
+        .unwrap();
+
+    // The JP2 Header box starts at 32 and ends at 77, where the UUID box starts
+    assert_eq!(data[36..40], *b"jp2h");
+    assert_eq!(data[81..85], *b"uuid");
+
+    // Vendor box following the JP2 Header box
+    let vendor: Vec<u8> = [&[0, 0, 0, 13][..], b"vndr", b"hello"].concat();
+    data.splice(77..77, vendor);
+
+    // Vendor box inside the JP2 Header box, which is kept by the header box
+    let nested: Vec<u8> = [&[0, 0, 0, 10][..], b"vndh", b"hi"].concat();
+    data.splice(77..77, nested);
+    data[32..36].copy_from_slice(&(45_u32 + 10).to_be_bytes());
+
+    let mut reader = Cursor::new(&data);
+    let jp2 = decode_jp2(&mut reader).expect("decode should succeed");
+    assert_eq!(jp2.uuid_boxes().len(), 1);
+    assert_eq!(jp2.xml_boxes().len(), 1);
+    assert_eq!(jp2.contiguous_codestreams_boxes().len(), 1);
+
+    let header_box = jp2.header_box().as_ref().unwrap();
+    assert_eq!(header_box.unknown_boxes.len(), 1);
+    let nested = &header_box.unknown_boxes[0];
+    assert_eq!(nested.box_type(), *b"vndh");
+    assert_eq!(nested.offset(), 85);
+    assert_eq!(nested.length(), 2);
+    assert_eq!(nested.data(), Some(&b"hi"[..]));
+
+    assert_eq!(jp2.unknown_boxes().len(), 1);
+    let unknown = &jp2.unknown_boxes()[0];
+    assert_eq!(unknown.box_type(), *b"vndr");
+    assert_eq!(unknown.offset(), 95);
+    assert_eq!(unknown.length(), 5);
+    assert!(unknown.data().is_none());
+    assert_eq!(unknown.read_data(&mut reader).unwrap(), b"hello");
+
+    // An unknown box with a length of 0 extends to the end of the file
+    data.extend([&[0, 0, 0, 0][..], b"vndz", b"last"].concat());
+    let jp2 = decode_jp2(&mut Cursor::new(&data)).expect("decode should succeed");
+    assert_eq!(jp2.unknown_boxes().len(), 2);
+    assert_eq!(jp2.unknown_boxes()[1].box_type(), *b"vndz");
+    assert_eq!(jp2.unknown_boxes()[1].length(), 4);
+}
+
+#[test]
+fn test_reserved_box_length() {
+    let mut data: Vec<u8> = vec![];
+    File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2"))
+        .expect("file should exist")
+        .read_to_end(&mut data)
+        .unwrap();
+
+    // The values 2-7 of LBox are reserved
+    data.splice(77..77, [&[0, 0, 0, 3][..], b"vndr"].concat());
+
+    let error = decode_jp2(&mut Cursor::new(&data)).expect_err("decode should fail");
+    match &error {
+        JP2Error::BoxMalformed { box_type, offset } => {
+            assert_eq!(*box_type, *b"vndr");
+            assert_eq!(*offset, 77);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_error_offsets() {
+    let mut data: Vec<u8> = vec![];
+    File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2"))
+        .expect("file should exist")
+        .read_to_end(&mut data)
+        .unwrap();
+
+    // The Image Header box, whose contents start at offset 48, is truncated
+    let error = decode_jp2(&mut Cursor::new(&data[..50])).expect_err("decode should fail");
+    match &error {
+        JP2Error::Io { offset, source } => {
+            assert_eq!(*offset, 48);
+            assert_eq!(source.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+    let source = error::Error::source(&error).expect("error should have a source");
+    assert!(source.downcast_ref::<io::Error>().is_some());
+
+    // The compatibility list of the File Type box, whose contents start at
+    // offset 20, does not list 'jp2\040'
+    data[28..32].copy_from_slice(b"jpx ");
+    let error = decode_jp2(&mut Cursor::new(&data)).expect_err("decode should fail");
+    assert!(matches!(error, JP2Error::NotCompatible { offset: 20, .. }));
+    assert_eq!(error.offset(), 20);
+}
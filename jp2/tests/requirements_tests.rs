@@ -0,0 +1,127 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{decode_jpx, Feature};
+
+mod common;
+use common::jbox;
+
+#[test]
+fn test_sample_file5_requirements() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file5.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+    let report = jpx.reader_requirements();
+
+    // FUAM 0xa0 and DCM 0xc0, the restricted ICC profile (mask 0x40) is only
+    // part of a term needed to decode the file completely
+    let requirements = report.requirements();
+    assert_eq!(requirements.len(), 3);
+    assert_eq!(requirements[0].feature(), Feature::Standard(5));
+    assert!(requirements[0].is_required_to_understand());
+    assert!(requirements[0].is_required_to_decode());
+    assert!(!requirements[0].is_supported());
+    assert_eq!(requirements[1].feature(), Feature::Standard(61));
+    assert!(requirements[1].is_required_to_understand());
+    assert!(requirements[1].is_required_to_decode());
+    assert!(!requirements[1].is_supported());
+    assert_eq!(requirements[2].feature(), Feature::Standard(43));
+    assert!(!requirements[2].is_required_to_understand());
+    assert!(requirements[2].is_required_to_decode());
+    assert!(requirements[2].is_supported());
+
+    assert_eq!(
+        report.required_features(),
+        vec![
+            Feature::Standard(5),
+            Feature::Standard(61),
+            Feature::Standard(43)
+        ]
+    );
+    assert_eq!(
+        report.unsupported_features(),
+        vec![Feature::Standard(5), Feature::Standard(61)]
+    );
+
+    // Each bit is a term, the Part 1 codestream (mask 0x80) alone satisfies
+    // both FUAM and DCM even though ROMM-RGB is not supported
+    let codestream_decoder = [Feature::Standard(5)];
+    assert!(report.is_fully_understood_with(&codestream_decoder));
+    assert!(report.is_decoded_completely_with(&codestream_decoder));
+
+    // ROMM-RGB satisfies the term of bit 0x20 of FUAM, and with the
+    // restricted ICC profile the term of bit 0x40 of DCM
+    let romm_rgb = [Feature::Standard(61)];
+    assert!(report.is_fully_understood_with(&romm_rgb));
+    assert!(report.is_decoded_completely_with(&romm_rgb));
+
+    // Without a codestream decoder no term is satisfied
+    assert!(!report.is_fully_understood());
+    assert!(!report.is_decoded_completely());
+    assert_eq!(
+        Feature::Standard(61).to_string(),
+        "61 (ROMM-RGB enumerated colourspace)"
+    );
+}
+
+#[test]
+fn test_jp2_file_requirements() {
+    // A file without a Reader Requirements box has no requirements
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let jpx = decode_jpx(&mut reader).expect("decoding should succeed");
+    let report = jpx.reader_requirements();
+
+    assert!(report.requirements().is_empty());
+    assert!(report.is_fully_understood());
+    assert!(report.is_decoded_completely());
+}
+
+#[test]
+fn test_vendor_feature_requirements() {
+    let rreq: Vec<u8> = [
+        // ML, FUAM, DCM
+        &[2, 0x80, 0x00, 0x00, 0x01][..],
+        // NSF, SF0 (contiguous codestream), SM0, SF1 (JPX checksums), SM1
+        &[0, 2, 0, 12, 0x80, 0x00, 0, 40, 0x00, 0x02],
+        // NVF, VF0, VM0
+        &[0, 1],
+        &[0x12; 16],
+        &[0x00, 0x01],
+    ]
+    .concat();
+    let data: Vec<u8> = [
+        jbox(b"jP  ", b"\x0d\x0a\x87\x0a"),
+        jbox(b"ftyp", b"jpx \x00\x00\x00\x00jpx "),
+        jbox(b"rreq", &rreq),
+        jbox(b"jp2c", b"\xff\x4f\xff\xd9"),
+    ]
+    .concat();
+    let jpx = decode_jpx(&mut Cursor::new(&data)).expect("decoding should succeed");
+    let report = jpx.reader_requirements();
+
+    // The JPX checksums are neither needed to understand nor to decode the
+    // file, the vendor feature is needed to decode it
+    assert_eq!(
+        report.required_features(),
+        vec![Feature::Standard(12), Feature::Vendor([0x12; 16])]
+    );
+    assert_eq!(
+        report.unsupported_features(),
+        vec![Feature::Vendor([0x12; 16])]
+    );
+    assert!(report.is_fully_understood());
+    assert!(!report.is_decoded_completely());
+    assert_eq!(
+        Feature::Vendor([0x12; 16]).to_string(),
+        "12121212121212121212121212121212 (vendor)"
+    );
+    assert_eq!(Feature::Standard(100).description(), None);
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::{
+    decode_jp2, encode_jp2, DataEntryURLBox, UUIDInfoSuperBox, UUIDListBox, UUID_GEOJP2, UUID_XMP,
+};
+
+#[test]
+fn test_uuid_info() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+    let mut jp2 = decode_jp2(&mut reader).expect("decoding should succeed");
+    assert!(jp2.uuid_info_boxes().is_empty());
+
+    jp2.uuid_info_boxes_mut().push(UUIDInfoSuperBox::new(
+        UUIDListBox::new(vec![UUID_XMP, UUID_GEOJP2]).expect("list should be valid"),
+        DataEntryURLBox::new("https://example.com/geojp2"),
+    ));
+    let mut encoded: Vec<u8> = vec![];
+    encode_jp2(&mut encoded, &jp2, &mut reader).expect("encoding should succeed");
+
+    // Version and flags of the Data Entry URL box
+    let url = encoded
+        .windows(4)
+        .position(|window| window == b"url ")
+        .expect("Data Entry URL box should exist");
+    encoded[url + 4..url + 8].copy_from_slice(&[1, 0, 1, 2]);
+
+    let jp2 = decode_jp2(&mut Cursor::new(encoded)).expect("decoding should succeed");
+    assert_eq!(jp2.uuid_info_boxes().len(), 1);
+    let uuid_info_box = &jp2.uuid_info_boxes()[0];
+    assert_eq!(uuid_info_box.uuid_list_boxes().len(), 1);
+    assert_eq!(
+        uuid_info_box.uuid_list_boxes()[0].ids(),
+        [UUID_XMP, UUID_GEOJP2]
+    );
+    assert_eq!(uuid_info_box.ids().count(), 2);
+    assert!(uuid_info_box.contains(&UUID_GEOJP2));
+
+    let data_entry_url_box = &uuid_info_box.data_entry_url_boxes()[0];
+    assert_eq!(
+        data_entry_url_box.location().unwrap(),
+        "https://example.com/geojp2"
+    );
+    assert_eq!(data_entry_url_box.version(), 1);
+    assert_eq!(data_entry_url_box.flags(), 0x0102);
+
+    // The GeoJP2 box is listed, there is no XMP box
+    let uuid_boxes = jp2.uuid_boxes_for(uuid_info_box);
+    assert_eq!(uuid_boxes.len(), 1);
+    assert_eq!(*uuid_boxes[0].uuid(), UUID_GEOJP2);
+    assert_eq!(jp2.uuid_info_boxes_for(uuid_boxes[0]).len(), 1);
+}
+
+#[test]
+fn test_uuid_list_limit() {
+    UUIDListBox::new(vec![UUID_XMP; 65535]).expect("list should be valid");
+    UUIDListBox::new(vec![UUID_XMP; 65536]).expect_err("list should be invalid");
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use jp2::validate_jp2;
+use jpc::encode_validation_xml;
+
+mod common;
+use common::contents;
+
+#[test]
+fn test_validate_samples() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let samples = (1..=9)
+        .map(|index| format!("../samples/file{}.jp2", index))
+        .chain(
+            [
+                "../samples/subsampling_1.jp2",
+                "../samples/subsampling_2.jp2",
+                "../samples/zoo2.jp2",
+                "tests/geojp2.jp2",
+                "tests/hazard.jp2",
+                "tests/j2pi.jp2",
+            ]
+            .iter()
+            .map(|name| name.to_string()),
+        );
+    for name in samples {
+        let path = directory.join(&name);
+        let mut reader = BufReader::new(File::open(path).expect("file should exist"));
+        let report = validate_jp2(&mut reader).expect("validation should succeed");
+        assert_eq!(report.name(), "jp2");
+        // JPX files with a JP2 compatible brand
+        if name.ends_with("file5.jp2") || name.ends_with("file7.jp2") {
+            assert_eq!(
+                report.failed_tests(),
+                ["fileTypeBox/brandIsValid"],
+                "{}",
+                name
+            );
+        } else {
+            assert!(report.is_valid(), "{} {:?}", name, report.failed_tests());
+        }
+        let codestream = report.child("contiguousCodestreamBox").unwrap();
+        assert!(codestream.child("siz").is_some(), "{}", name);
+    }
+}
+
+#[test]
+fn test_validate_invalid() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let data = fs::read(path).expect("file should exist");
+
+    // Minor version of the File Type box and height of the Image Header box
+    let mut patched = data.clone();
+    let file_type = contents(&patched, b"ftyp");
+    patched[file_type + 7] = 1;
+    let image_header = contents(&patched, b"ihdr");
+    patched[image_header + 3] += 1;
+    let report = validate_jp2(&mut Cursor::new(&patched)).expect("validation should succeed");
+    assert!(!report.is_valid());
+    assert_eq!(report.test("heightConsistentWithSIZ"), Some(false));
+    assert_eq!(
+        report.failed_tests(),
+        ["heightConsistentWithSIZ", "fileTypeBox/minorVersionIsValid"]
+    );
+    let image_header_box = report
+        .child("jp2HeaderBox")
+        .and_then(|header| header.child("imageHeaderBox"))
+        .unwrap();
+    assert_eq!(
+        image_header_box.property("height"),
+        Some(
+            u32::from_be_bytes([
+                patched[image_header],
+                patched[image_header + 1],
+                patched[image_header + 2],
+                patched[image_header + 3]
+            ])
+            .to_string()
+            .as_str()
+        )
+    );
+
+    // Compression type of the Image Header box
+    let mut patched = data.clone();
+    patched[image_header + 11] = 8;
+    let report = validate_jp2(&mut Cursor::new(&patched)).expect("validation should succeed");
+    assert_eq!(
+        report.failed_tests(),
+        ["jp2HeaderBox/imageHeaderBox/cIsValid"]
+    );
+
+    // A JP2 Header box of another type is missing
+    let mut patched = data;
+    let header = contents(&patched, b"jp2h");
+    patched[header - 4..header].copy_from_slice(b"jp2x");
+    let report = validate_jp2(&mut Cursor::new(&patched)).expect("validation should succeed");
+    assert_eq!(report.test("containsJP2HeaderBox"), Some(false));
+    assert!(report.child("jp2HeaderBox").is_none());
+}
+
+#[test]
+fn test_validate_box_order() {
+    // The codestream precedes the JP2 Header box, which decode_jp2 rejects
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/geojp2.jp2");
+    let data = fs::read(path).expect("file should exist");
+    let header = contents(&data, b"jp2h") - 8;
+    let header_length = u32::from_be_bytes([
+        data[header],
+        data[header + 1],
+        data[header + 2],
+        data[header + 3],
+    ]) as usize;
+    let codestream = contents(&data, b"jp2c") - 8;
+    assert!(codestream > header + header_length);
+    let codestream_length = u32::from_be_bytes([
+        data[codestream],
+        data[codestream + 1],
+        data[codestream + 2],
+        data[codestream + 3],
+    ]) as usize;
+    let patched = [
+        &data[..header],
+        &data[codestream..codestream + codestream_length],
+        &data[header + header_length..codestream],
+        &data[header..header + header_length],
+        &data[codestream + codestream_length..],
+    ]
+    .concat();
+    assert_eq!(patched.len(), data.len());
+
+    let report = validate_jp2(&mut Cursor::new(&patched)).expect("validation should succeed");
+    assert_eq!(report.failed_tests(), ["locationJP2HeaderBoxIsValid"]);
+
+    let mut output: Vec<u8> = vec![];
+    encode_validation_xml(&mut output, &report, "geojp2.jp2").expect("encoding should succeed");
+    let xml = String::from_utf8(output).unwrap();
+    assert!(xml.contains("<isValid format=\"jp2\">False</isValid>"));
+    assert!(xml.contains("<locationJP2HeaderBoxIsValid>False</locationJP2HeaderBoxIsValid>"));
+    assert!(xml.contains("<jp2HeaderBox>"));
+    assert!(xml.contains("<contiguousCodestreamBox>"));
+}
@@ -5,25 +5,39 @@ use std::cmp;
 use std::error;
 use std::fmt;
 use std::io;
-use std::io::prelude::*;
 use std::str;
 
 mod coder;
-
+mod limits;
+mod packet;
+mod transcode;
+mod validate;
+
+pub use limits::{LimitError, Limits};
+pub use transcode::{transcode_jpc, TranscodeError, TranscodeOptions};
+pub use validate::{encode_validation_xml, validate_jpc, ValidationReport};
+
+/// An error decoding or transcoding a codestream, the offset of each variant
+/// is the byte offset from the start of the reader of the marker or the data
+/// in error.
 #[derive(Debug)]
-enum CodestreamError {
+#[non_exhaustive]
+pub enum CodestreamError {
     MarkerError {
         marker: MarkerSymbol,
+        offset: u64,
         error: String,
     },
     MarkerMissing {
         marker: MarkerSymbol,
+        offset: u64,
     },
     MarkerUnexpected {
         marker: MarkerSymbol,
         offset: u64,
     },
     TileSizeOverflow {
+        offset: u64,
         image_horizontal_offset: u32,
         image_vertical_offset: u32,
         tile_horizontal_offset: u32,
@@ -32,26 +46,124 @@ enum CodestreamError {
         reference_tile_height: u32,
     },
     TileGridOffsetOverflow {
+        offset: u64,
         tile_horizontal_offset: u32,
         tile_vertical_offset: u32,
         image_horizontal_offset: u32,
         image_vertical_offset: u32,
     },
+    /// A packet of a tile, the offset is in the data of the tile.
+    PacketError {
+        tile: u16,
+        offset: u64,
+        error: String,
+    },
+    Unsupported {
+        marker: MarkerSymbol,
+        offset: u64,
+    },
+    /// A codestream which cannot be transcoded with the options, the offset
+    /// is of the tile or marker segment concerned, or 0 for the codestream.
+    TranscodeError {
+        offset: u64,
+        source: TranscodeError,
+    },
+    Limit {
+        offset: u64,
+        source: LimitError,
+    },
+    Io {
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl CodestreamError {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::MarkerError { offset, .. }
+            | Self::MarkerMissing { offset, .. }
+            | Self::MarkerUnexpected { offset, .. }
+            | Self::TileSizeOverflow { offset, .. }
+            | Self::TileGridOffsetOverflow { offset, .. }
+            | Self::PacketError { offset, .. }
+            | Self::Unsupported { offset, .. }
+            | Self::TranscodeError { offset, .. }
+            | Self::Limit { offset, .. }
+            | Self::Io { offset, .. } => *offset,
+        }
+    }
+
+    // The error of a decoder given a copy of the marker segment at the
+    // offset, whose offsets are relative to the marker.
+    pub(crate) fn relative_to(self, base: u64) -> CodestreamError {
+        let mut error = self;
+        match &mut error {
+            Self::MarkerError { offset, .. }
+            | Self::MarkerMissing { offset, .. }
+            | Self::MarkerUnexpected { offset, .. }
+            | Self::TileSizeOverflow { offset, .. }
+            | Self::TileGridOffsetOverflow { offset, .. }
+            | Self::Unsupported { offset, .. }
+            | Self::TranscodeError { offset, .. }
+            | Self::Limit { offset, .. }
+            | Self::Io { offset, .. } => *offset += base,
+            // Offsets in the data of a tile
+            Self::PacketError { .. } => {}
+        }
+        error
+    }
+}
+
+// Errors of the reader and of the limits, reported at the offset of the marker
+// segment or data they occurred in.
+pub(crate) trait AtOffset<T> {
+    fn at(self, offset: u64) -> Result<T, CodestreamError>;
+}
+
+impl<T> AtOffset<T> for io::Result<T> {
+    fn at(self, offset: u64) -> Result<T, CodestreamError> {
+        self.map_err(|source| CodestreamError::Io { offset, source })
+    }
+}
+
+impl<T> AtOffset<T> for Result<T, LimitError> {
+    fn at(self, offset: u64) -> Result<T, CodestreamError> {
+        self.map_err(|source| CodestreamError::Limit { offset, source })
+    }
+}
+
+impl error::Error for CodestreamError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::TranscodeError { source, .. } => Some(source),
+            Self::Limit { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
-impl error::Error for CodestreamError {}
 impl fmt::Display for CodestreamError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::MarkerError { marker, error } => {
+            Self::MarkerError {
+                marker,
+                offset,
+                error,
+            } => {
                 write!(
                     f,
-                    "marker 0x{:0>2X?}{:0>2X?} error {:?}",
-                    marker[0], marker[1], error
+                    "marker 0x{:0>2X?}{:0>2X?} at byte offset {} error {:?}",
+                    marker[0], marker[1], offset, error
                 )
             }
-            Self::MarkerMissing { marker } => {
-                write!(f, "missing marker 0x{:0>2X?}{:0>2X?}", marker[0], marker[1])
+            Self::MarkerMissing { marker, offset } => {
+                write!(
+                    f,
+                    "missing marker 0x{:0>2X?}{:0>2X?} at byte offset {}",
+                    marker[0], marker[1], offset
+                )
             }
             Self::MarkerUnexpected { marker, offset } => {
                 write!(
@@ -61,6 +173,7 @@ impl fmt::Display for CodestreamError {
                 )
             }
             Self::TileGridOffsetOverflow {
+                offset,
                 image_horizontal_offset,
                 image_vertical_offset,
                 tile_horizontal_offset,
@@ -68,7 +181,8 @@ impl fmt::Display for CodestreamError {
             } => {
                 write!(
                     f,
-                    "tile grid offset overflow: XOSiz = {:?}, YOsiz = {:?}, XTOsiz = {:?}, YTOsiz = {:?}",
+                    "tile grid offset overflow at byte offset {}: XOSiz = {:?}, YOsiz = {:?}, XTOsiz = {:?}, YTOsiz = {:?}",
+                    offset,
                     image_horizontal_offset,
                     image_vertical_offset,
                     tile_horizontal_offset,
@@ -78,6 +192,7 @@ impl fmt::Display for CodestreamError {
             // XTsiz + XTOsiz > XOsiz
             // YTsiz + YTOsiz > YOsiz
             Self::TileSizeOverflow {
+                offset,
                 image_horizontal_offset,
                 image_vertical_offset,
                 tile_horizontal_offset,
@@ -87,7 +202,8 @@ impl fmt::Display for CodestreamError {
             } => {
                 write!(
                     f,
-                    "tile size overflow: XOSiz = {:?}, YOsiz = {:?}, XTOsiz = {:?}, YTOsiz = {:?}, XTsize = {:?}, YTsize = {:?}",
+                    "tile size overflow at byte offset {}: XOSiz = {:?}, YOsiz = {:?}, XTOsiz = {:?}, YTOsiz = {:?}, XTsize = {:?}, YTsize = {:?}",
+                    offset,
                     image_horizontal_offset,
                     image_vertical_offset,
                     tile_horizontal_offset,
@@ -96,6 +212,33 @@ impl fmt::Display for CodestreamError {
                     reference_tile_height,
                 )
             }
+            Self::PacketError {
+                tile,
+                offset,
+                error,
+            } => {
+                write!(
+                    f,
+                    "packet error in tile {} at byte offset {} of the tile data: {}",
+                    tile, offset, error
+                )
+            }
+            Self::Unsupported { marker, offset } => {
+                write!(
+                    f,
+                    "unsupported marker 0x{:0>2X?}{:0>2X?} at byte offset {}",
+                    marker[0], marker[1], offset
+                )
+            }
+            Self::TranscodeError { offset, source } => {
+                write!(f, "transcode error at byte offset {}: {}", offset, source)
+            }
+            Self::Limit { offset, source } => {
+                write!(f, "{} at byte offset {}", source, offset)
+            }
+            Self::Io { offset, source } => {
+                write!(f, "{} at byte offset {}", source, offset)
+            }
         }
     }
 }
@@ -112,6 +255,8 @@ const MARKER_SYMBOL_EOC: MarkerSymbol = [255, 217]; // End of codestream
 
 // Fixed information marker segments
 const MARKER_SYMBOL_SIZ: MarkerSymbol = [255, 81]; // Image and tile size
+const MARKER_SYMBOL_CAP: MarkerSymbol = [255, 80]; // Extended capabilities
+const MARKER_SYMBOL_CPF: MarkerSymbol = [255, 89]; // Corresponding profile
 
 // Functional marker segments
 const MARKER_SYMBOL_COD: MarkerSymbol = [255, 82]; // Coding style default
@@ -136,7 +281,7 @@ const MARKER_SYMBOL_EPH: MarkerSymbol = [255, 146]; // End of packet header
 const MARKER_SYMBOL_CRG: MarkerSymbol = [255, 99]; // Component registration
 const MARKER_SYMBOL_COM: MarkerSymbol = [255, 100]; // Comment
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ProgressionOrder {
     // 0000 0000 Layer-resolution level-component-position progression
     LRLCPP,
@@ -168,6 +313,17 @@ impl ProgressionOrder {
             _ => ProgressionOrder::Reserved { value },
         }
     }
+
+    fn value(&self) -> u8 {
+        match self {
+            ProgressionOrder::LRLCPP => 0b0000_0000,
+            ProgressionOrder::RLLCPP => 0b0000_0001,
+            ProgressionOrder::RLPCLP => 0b0000_0010,
+            ProgressionOrder::PCRLLP => 0b0000_0011,
+            ProgressionOrder::CPRLLP => 0b0000_0100,
+            ProgressionOrder::Reserved { value } => *value,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -208,6 +364,13 @@ pub enum CodingBlockStyle {
     // xx1x xxxx - Segmentation symbols are used
     SegmentationSymbolsAreUsed,
 
+    // 01xx xxxx - All code-blocks are HT code-blocks, ISO/IEC 15444-15
+    HighThroughputBlockCoding,
+
+    // 11xx xxxx - Code-blocks are either HT code-blocks or use the block
+    // coder of Annex D, ISO/IEC 15444-15
+    MixedBlockCoding,
+
     // All other values reserved
     Reserved { value: [u8; 1] },
 }
@@ -252,8 +415,34 @@ impl CodingBlockStyle {
             coding_block_styles.push(CodingBlockStyle::NoSegmentationSymbolsAreUsed);
         }
 
+        match value & 0b_1100_0000 {
+            0b_0100_0000 => coding_block_styles.push(CodingBlockStyle::HighThroughputBlockCoding),
+            0b_1100_0000 => coding_block_styles.push(CodingBlockStyle::MixedBlockCoding),
+            0b_0000_0000 => {}
+            _ => coding_block_styles.push(CodingBlockStyle::Reserved { value: [value] }),
+        }
+
         coding_block_styles
     }
+
+    /// The code-block style parameter of SPcod or SPcoc signalling the
+    /// styles, the styles not given are not used.
+    pub fn encode(coding_block_styles: &[CodingBlockStyle]) -> u8 {
+        coding_block_styles
+            .iter()
+            .fold(0, |value, coding_block_style| match coding_block_style {
+                CodingBlockStyle::SelectiveArithmeticCodingBypass => value | 0b_0000_0001,
+                CodingBlockStyle::ResetContextProbabilities => value | 0b_0000_0010,
+                CodingBlockStyle::TerminationOnEachCodingPass => value | 0b_0000_0100,
+                CodingBlockStyle::VerticallyCausalContext => value | 0b_0000_1000,
+                CodingBlockStyle::PredictableTermination => value | 0b_0001_0000,
+                CodingBlockStyle::SegmentationSymbolsAreUsed => value | 0b_0010_0000,
+                CodingBlockStyle::HighThroughputBlockCoding => value | 0b_0100_0000,
+                CodingBlockStyle::MixedBlockCoding => value | 0b_1100_0000,
+                CodingBlockStyle::Reserved { value: [reserved] } => value | reserved,
+                _ => value,
+            })
+    }
 }
 
 // A.13 – Coding style parameter values for the Scod parameter
@@ -543,13 +732,13 @@ pub struct CodingStyleParametersPrecinctSize {
 
 impl CodingStyleParametersPrecinctSize {
     pub fn height_exponent(&self) -> u8 {
-        // 4 LSBs are the precinct width exponent, PPx = value
-        self.value << 4 >> 4
+        // 4 MSBs are the precinct height exponent PPy = value
+        self.value >> 4
     }
 
     pub fn width_exponent(&self) -> u8 {
-        // 4 MSBs are the precinct height exponent PPy = value
-        self.value >> 4
+        // 4 LSBs are the precinct width exponent, PPx = value
+        self.value << 4 >> 4
     }
 }
 
@@ -725,16 +914,157 @@ impl CodingStyleComponentSegmentProgression {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum DecoderCapability {
     Part1,
+
+    // x1xx xxxx xxxx xxxx - Capabilities are specified in the CAP marker
+    // segment
+    ExtendedCapabilities,
+
     Reserved { value: [u8; 2] },
 }
 impl DecoderCapability {
     fn new(value: [u8; 2]) -> Vec<DecoderCapability> {
-        match value {
-            [0, 0] => vec![DecoderCapability::Part1],
-            _ => vec![DecoderCapability::Reserved { value }],
+        let mut decoder_capabilities = vec![];
+        let capabilities = u16::from_be_bytes(value);
+        match capabilities & !0x4000 {
+            0 => decoder_capabilities.push(DecoderCapability::Part1),
+            _ => decoder_capabilities.push(DecoderCapability::Reserved { value }),
+        }
+        if capabilities & 0x4000 != 0 {
+            decoder_capabilities.push(DecoderCapability::ExtendedCapabilities);
         }
+        decoder_capabilities
+    }
+}
+
+// A.5.2
+//
+// Extended capabilities (CAP)
+//
+// Function: Signals the capabilities required to decode the codestream that
+// are specified in other parts of ISO/IEC 15444.
+#[derive(Debug, Default)]
+pub struct ExtendedCapabilitiesSegment {
+    offset: u64,
+
+    // Lcap: Length of marker segment in bytes (not including the marker).
+    length: u16,
+
+    // Pcap: The MSB is Pcap^1, bit i is set if the ISO/IEC 15444-i
+    // capabilities are described by a Ccap parameter.
+    parts: [u8; 4],
+
+    // Ccap^i: Capabilities of ISO/IEC 15444-i, in the order of increasing i.
+    capabilities: Vec<[u8; 2]>,
+}
+
+impl ExtendedCapabilitiesSegment {
+    /// A CAP marker segment for encoding, signalling HT code-blocks with the
+    /// Ccap^15 capabilities of ISO/IEC 15444-15.
+    pub fn high_throughput(ccap15: u16) -> ExtendedCapabilitiesSegment {
+        ExtendedCapabilitiesSegment {
+            offset: 0,
+            length: 8,
+            parts: (1u32 << (32 - 15)).to_be_bytes(),
+            capabilities: vec![ccap15.to_be_bytes()],
+        }
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // Parts of ISO/IEC 15444 with capabilities, in increasing order
+    pub fn parts(&self) -> Vec<u8> {
+        let parts = u32::from_be_bytes(self.parts);
+        (1..=32u8)
+            .filter(|i| parts & (1 << (32 - *i as u32)) != 0)
+            .collect()
+    }
+
+    // Ccap^i of the ISO/IEC 15444-i capabilities
+    pub fn capabilities(&self, part: u8) -> Option<u16> {
+        let index = self.parts().iter().position(|i| *i == part)?;
+        self.capabilities
+            .get(index)
+            .map(|value| u16::from_be_bytes(*value))
+    }
+
+    /// Encode the marker segment, from its marker.
+    pub fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MARKER_SYMBOL_CAP)?;
+        writer.write_all(&(6 + 2 * self.capabilities.len() as u16).to_be_bytes())?;
+        writer.write_all(&self.parts)?;
+        for capabilities in &self.capabilities {
+            writer.write_all(capabilities)?;
+        }
+        Ok(())
+    }
+}
+
+// A.5.3
+//
+// Corresponding profile (CPF)
+//
+// Function: Signals the profiles of other parts of ISO/IEC 15444 the
+// codestream conforms to.
+#[derive(Debug, Default)]
+pub struct CorrespondingProfileSegment {
+    offset: u64,
+
+    // Lcpf: Length of marker segment in bytes (not including the marker).
+    length: u16,
+
+    // Pcpf^i: Profile values, see ISO/IEC 15444-1 Table A.12.
+    profiles: Vec<[u8; 2]>,
+}
+
+impl CorrespondingProfileSegment {
+    /// A CPF marker segment for encoding, None if there are more than 32766
+    /// profiles.
+    pub fn new(profiles: Vec<u16>) -> Option<CorrespondingProfileSegment> {
+        if profiles.len() > 32766 {
+            return None;
+        }
+        Some(CorrespondingProfileSegment {
+            offset: 0,
+            length: 2 + 2 * profiles.len() as u16,
+            profiles: profiles
+                .iter()
+                .map(|profile| profile.to_be_bytes())
+                .collect(),
+        })
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn profiles(&self) -> Vec<u16> {
+        self.profiles
+            .iter()
+            .map(|value| u16::from_be_bytes(*value))
+            .collect()
+    }
+
+    /// Encode the marker segment, from its marker.
+    pub fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MARKER_SYMBOL_CPF)?;
+        writer.write_all(&(2 + 2 * self.profiles.len() as u16).to_be_bytes())?;
+        for profile in &self.profiles {
+            writer.write_all(profile)?;
+        }
+        Ok(())
     }
 }
 
@@ -1103,6 +1433,10 @@ impl ImageAndTileSizeMarkerSegment {
         u16::from_be_bytes(self.decoder_capabilities)
     }
 
+    pub fn decoder_capability(&self) -> Vec<DecoderCapability> {
+        DecoderCapability::new(self.decoder_capabilities)
+    }
+
     pub fn reference_grid_width(&self) -> u32 {
         u32::from_be_bytes(self.reference_grid_width)
     }
@@ -1135,38 +1469,55 @@ impl ImageAndTileSizeMarkerSegment {
         u16::from_be_bytes(self.no_components)
     }
 
-    pub fn precision(&self, i: usize) -> Result<i16, Box<dyn error::Error>> {
-        let ssiz = self.precision.get(i).unwrap();
+    pub fn precision(&self, i: usize) -> Result<i16, CodestreamError> {
+        let ssiz = self.component_parameter(&self.precision, i)?;
         let precision = (u8::from_be_bytes(*ssiz) & 0x7f) as i16;
         // ISO/IEC 15444-1:2019 Table A.11, component bit depth is value + 1.
         Ok(precision + 1)
     }
 
-    pub fn values_are_signed(&self, i: usize) -> Result<bool, Box<dyn error::Error>> {
-        let ssiz = self.precision.get(i).unwrap();
+    pub fn values_are_signed(&self, i: usize) -> Result<bool, CodestreamError> {
+        let ssiz = self.component_parameter(&self.precision, i)?;
         let is_signed = (u8::from_be_bytes(*ssiz) & 0x80) == 0x80;
         Ok(is_signed)
     }
 
-    pub fn horizontal_separation(&self, i: usize) -> Result<u8, Box<dyn error::Error>> {
-        let horizontal_separation = self.horizontal_separation.get(i).unwrap();
+    pub fn horizontal_separation(&self, i: usize) -> Result<u8, CodestreamError> {
+        let horizontal_separation = self.component_parameter(&self.horizontal_separation, i)?;
         Ok(u8::from_be_bytes(*horizontal_separation))
     }
-    pub fn vertical_separation(&self, i: usize) -> Result<u8, Box<dyn error::Error>> {
-        let vertical_separation = self.vertical_separation.get(i).unwrap();
+    pub fn vertical_separation(&self, i: usize) -> Result<u8, CodestreamError> {
+        let vertical_separation = self.component_parameter(&self.vertical_separation, i)?;
         Ok(u8::from_be_bytes(*vertical_separation))
     }
 
+    // The parameter of component i, which the marker segment may not have.
+    fn component_parameter<'a>(
+        &self,
+        parameters: &'a [[u8; 1]],
+        i: usize,
+    ) -> Result<&'a [u8; 1], CodestreamError> {
+        parameters
+            .get(i)
+            .ok_or_else(|| CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: self.offset.saturating_sub(2),
+                error: format!("no component {} of {}", i, self.no_components()),
+            })
+    }
+
     // The number of tiles in the X direction (numXtiles) and the Y direction
     // (numYtiles) is the following
     //
-    // numXtiles = [(Xsiz - XTOsiz) / XTsiz]
-    // numYtiles = [(Ysiz - YTOsiz) / YTsiz]
+    // numXtiles = ⌈(Xsiz - XTOsiz) / XTsiz⌉
+    // numYtiles = ⌈(Ysiz - YTOsiz) / YTsiz⌉
     fn num_x_tiles(&self) -> u32 {
-        (self.reference_grid_width() - self.tile_horizontal_offset()) / self.reference_tile_width()
+        (self.reference_grid_width() - self.tile_horizontal_offset())
+            .div_ceil(self.reference_tile_width())
     }
     fn num_y_tiles(&self) -> u32 {
-        (self.reference_grid_height() - self.tile_vertical_offset()) / self.reference_tile_height()
+        (self.reference_grid_height() - self.tile_vertical_offset())
+            .div_ceil(self.reference_tile_height())
     }
 
     // Let p be the horizontal index of a tile, ranging from 0 to numXtiles -1
@@ -1204,29 +1555,63 @@ impl ImageAndTileSizeMarkerSegment {
     }
 
     // lower left x corner of the tile
-    // tx_1(p,q) = max(XTOsiz + (p + 1) · XTsiz, XOsiz)
+    // tx_1(p,q) = min(XTOsiz + (p + 1) · XTsiz, Xsiz)
     fn tile_x_lower(&self, t: u32) -> u32 {
         cmp::min(
             self.tile_horizontal_offset()
                 + ((self.tile_horizontal_index(t) + 1) * self.reference_tile_width()),
-            self.image_horizontal_offset(),
+            self.reference_grid_width(),
         ) - 1
     }
 
     // lower left y corner of the tile
-    // ty_1(p,q) = max(YTOsiz + (q + 1) · YTsiz, YOsiz)
+    // ty_1(p,q) = min(YTOsiz + (q + 1) · YTsiz, Ysiz)
     fn tile_y_lower(&self, t: u32) -> u32 {
         cmp::min(
             self.tile_vertical_offset()
                 + ((self.tile_vertical_index(t) + 1) * self.reference_tile_height()),
-            self.image_vertical_offset(),
+            self.reference_grid_height(),
         ) - 1
     }
 
-    fn tile_dimensions(&self, t: u32) -> (u32, u32) {
+    // Tile area on the reference grid, the upper left corner is included and
+    // the lower right corner is excluded, (tx_0, ty_0, tx_1, ty_1).
+    fn tile_area(&self, t: u32) -> (u32, u32, u32, u32) {
         (
-            self.tile_x_lower(t) - self.tile_x_upper(t),
-            self.tile_y_lower(t) - self.tile_y_upper(t),
+            self.tile_x_upper(t),
+            self.tile_y_upper(t),
+            self.tile_x_lower(t) + 1,
+            self.tile_y_lower(t) + 1,
+        )
+    }
+
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MARKER_SYMBOL_SIZ)?;
+        // Lsiz = 38 + 3 · Csiz
+        writer.write_all(&(38 + 3 * self.precision.len() as u16).to_be_bytes())?;
+        writer.write_all(&self.decoder_capabilities)?;
+        writer.write_all(&self.reference_grid_width)?;
+        writer.write_all(&self.reference_grid_height)?;
+        writer.write_all(&self.image_horizontal_offset)?;
+        writer.write_all(&self.image_vertical_offset)?;
+        writer.write_all(&self.reference_tile_width)?;
+        writer.write_all(&self.reference_tile_height)?;
+        writer.write_all(&self.tile_horizontal_offset)?;
+        writer.write_all(&self.tile_vertical_offset)?;
+        writer.write_all(&(self.precision.len() as u16).to_be_bytes())?;
+        for i in 0..self.precision.len() {
+            writer.write_all(&self.precision[i])?;
+            writer.write_all(&self.horizontal_separation[i])?;
+            writer.write_all(&self.vertical_separation[i])?;
+        }
+        Ok(())
+    }
+
+    /// Width and height on the reference grid of the tile with index t.
+    pub fn tile_dimensions(&self, t: u32) -> (u32, u32) {
+        (
+            self.tile_x_lower(t) + 1 - self.tile_x_upper(t),
+            self.tile_y_lower(t) + 1 - self.tile_y_upper(t),
         )
     }
 }
@@ -1448,6 +1833,10 @@ pub struct ContiguousCodestream {
     length: u16,
     header: Header,
     tiles: Vec<Tile>,
+
+    limits: Limits,
+    // Bytes allocated for the contents of marker segments
+    allocated: u64,
 }
 
 impl ContiguousCodestream {
@@ -1455,56 +1844,105 @@ impl ContiguousCodestream {
         &self.header
     }
 
+    // Buffer for contents of a marker segment, within the memory limit.
+    fn allocate(&mut self, length: usize) -> Result<Vec<u8>, LimitError> {
+        self.limits.allocate(&mut self.allocated, length as u64)?;
+        Ok(vec![0; length])
+    }
+
     // Length of marker segment in bytes (not including the marker).
-    fn decode_length<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<u16, Box<dyn error::Error>> {
+    fn decode_length<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> io::Result<u16> {
         let mut length: [u8; 2] = [0; 2];
         reader.read_exact(&mut length)?;
         Ok(u16::from_be_bytes(length))
     }
 
-    fn decode_siz<R: io::Read + io::Seek>(
+    // The SOC marker and SIZ marker segment which start the main header.
+    fn decode_soc_siz<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<ImageAndTileSizeMarkerSegment, Box<dyn error::Error>> {
-        info!("SIZ start at byte offset {}", reader.stream_position()? - 2);
-        let mut segment = ImageAndTileSizeMarkerSegment::default();
-
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
-
-        reader.read_exact(&mut segment.decoder_capabilities)?;
-        reader.read_exact(&mut segment.reference_grid_width)?;
-        reader.read_exact(&mut segment.reference_grid_height)?;
-        reader.read_exact(&mut segment.image_horizontal_offset)?;
-        reader.read_exact(&mut segment.image_vertical_offset)?;
-        reader.read_exact(&mut segment.reference_tile_width)?;
-        reader.read_exact(&mut segment.reference_tile_height)?;
-        reader.read_exact(&mut segment.tile_horizontal_offset)?;
-        reader.read_exact(&mut segment.tile_vertical_offset)?;
-        reader.read_exact(&mut segment.no_components)?;
+    ) -> Result<ImageAndTileSizeMarkerSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)?;
+        let mut marker_type: MarkerSymbol = [0; 2];
 
-        let no_components = segment.no_components();
+        // SOC (Required as the first marker)
+        reader.read_exact(&mut marker_type).at(start)?;
+        if marker_type != MARKER_SYMBOL_SOC {
+            return Err(CodestreamError::MarkerUnexpected {
+                marker: MARKER_SYMBOL_SOC,
+                offset: start,
+            });
+        }
+        info!("SOC start at byte offset {}", start);
 
-        segment.precision = Vec::with_capacity(no_components as usize);
-        segment.horizontal_separation = Vec::with_capacity(no_components as usize);
-        segment.vertical_separation = Vec::with_capacity(no_components as usize);
+        // SIZ (Required as the second marker segment)
+        reader.read_exact(&mut marker_type).at(start + 2)?;
+        if marker_type != MARKER_SYMBOL_SIZ {
+            return Err(CodestreamError::MarkerUnexpected {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: start + 2,
+            });
+        }
 
-        for _ in 0..no_components {
-            // TODO: Consider putting into struct
-            let mut precision: [u8; 1] = [0; 1];
-            reader.read_exact(&mut precision)?;
-            segment.precision.push(precision);
+        self.decode_siz(reader)
+    }
 
-            let mut horizontal_separation: [u8; 1] = [0; 1];
-            reader.read_exact(&mut horizontal_separation)?;
-            segment.horizontal_separation.push(horizontal_separation);
+    fn decode_siz<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<ImageAndTileSizeMarkerSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("SIZ start at byte offset {}", start);
+        let segment = self.decode_siz_parameters(reader)?;
 
-            let mut vertical_separation: [u8; 1] = [0; 1];
-            reader.read_exact(&mut vertical_separation)?;
-            segment.vertical_separation.push(vertical_separation);
+        // Lsiz = 38 + 3 · Csiz
+        if segment.length as u32 != 38 + 3 * segment.no_components() as u32 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: segment.offset - 2,
+                error: format!(
+                    "length {} does not match {} components",
+                    segment.length,
+                    segment.no_components()
+                ),
+            });
+        }
+
+        // XRsiz and YRsiz of 1 to 255
+        if segment
+            .horizontal_separation
+            .iter()
+            .chain(&segment.vertical_separation)
+            .any(|separation| separation[0] == 0)
+        {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: segment.offset - 2,
+                error: "component separation of 0".to_string(),
+            });
+        }
+
+        // The image area and the tiles shall not be empty, which the number
+        // of tiles is derived from
+        // XOsiz < Xsiz, YOsiz < Ysiz, 0 < XTsiz and 0 < YTsiz
+        if segment.image_horizontal_offset() >= segment.reference_grid_width()
+            || segment.image_vertical_offset() >= segment.reference_grid_height()
+            || segment.reference_tile_width() == 0
+            || segment.reference_tile_height() == 0
+        {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: segment.offset - 2,
+                error: format!(
+                    "empty image area or tiles: Xsiz = {}, Ysiz = {}, XOsiz = {}, YOsiz = {}, XTsiz = {}, YTsiz = {}",
+                    segment.reference_grid_width(),
+                    segment.reference_grid_height(),
+                    segment.image_horizontal_offset(),
+                    segment.image_vertical_offset(),
+                    segment.reference_tile_width(),
+                    segment.reference_tile_height(),
+                ),
+            });
         }
 
         // The tile grid offsets (XTOsiz, YTOsiz) are constrained to be no
@@ -1516,12 +1954,12 @@ impl ContiguousCodestream {
             || segment.tile_vertical_offset() > segment.image_vertical_offset()
         {
             return Err(CodestreamError::TileGridOffsetOverflow {
+                offset: segment.offset - 2,
                 tile_horizontal_offset: segment.tile_horizontal_offset(),
                 image_horizontal_offset: segment.image_horizontal_offset(),
                 tile_vertical_offset: segment.tile_vertical_offset(),
                 image_vertical_offset: segment.image_vertical_offset(),
-            }
-            .into());
+            });
         }
 
         // Also, the tile size plus the tile offset shall be greater than the image
@@ -1531,49 +1969,206 @@ impl ContiguousCodestream {
         //
         // XTsiz + XTOsiz > XOsiz
         // YTsiz + YTOsiz > YOsiz
-        if ((segment.reference_tile_width() + segment.tile_horizontal_offset())
-            < segment.image_horizontal_offset())
-            || ((segment.reference_tile_height() + segment.tile_vertical_offset())
-                < segment.image_vertical_offset())
+        if ((segment.reference_tile_width() as u64 + segment.tile_horizontal_offset() as u64)
+            < segment.image_horizontal_offset() as u64)
+            || ((segment.reference_tile_height() as u64 + segment.tile_vertical_offset() as u64)
+                < segment.image_vertical_offset() as u64)
         {
             return Err(CodestreamError::TileSizeOverflow {
+                offset: segment.offset - 2,
                 reference_tile_width: segment.reference_tile_width(),
                 tile_horizontal_offset: segment.tile_horizontal_offset(),
                 image_horizontal_offset: segment.image_horizontal_offset(),
                 reference_tile_height: segment.reference_tile_height(),
                 tile_vertical_offset: segment.tile_vertical_offset(),
                 image_vertical_offset: segment.image_vertical_offset(),
-            }
-            .into());
+            });
+        }
+        self.limits.check_image_and_tile_size(&segment).at(start)?;
+        info!(
+            "SIZ end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
+
+        Ok(segment)
+    }
+
+    // The parameters of the SIZ marker segment, without the constraints on
+    // the tile grid checked by decode_siz.
+    fn decode_siz_parameters<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<ImageAndTileSizeMarkerSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        let mut segment = ImageAndTileSizeMarkerSegment {
+            offset: reader.stream_position().at(start)?,
+            length: self.decode_length(reader).at(start)?,
+            ..Default::default()
+        };
+
+        reader
+            .read_exact(&mut segment.decoder_capabilities)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.reference_grid_width)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.reference_grid_height)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.image_horizontal_offset)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.image_vertical_offset)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.reference_tile_width)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.reference_tile_height)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.tile_horizontal_offset)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.tile_vertical_offset)
+            .at(start)?;
+        reader.read_exact(&mut segment.no_components).at(start)?;
+
+        let no_components = segment.no_components();
+        self.limits.check_components(no_components).at(start)?;
+
+        segment.precision = Vec::with_capacity(no_components as usize);
+        segment.horizontal_separation = Vec::with_capacity(no_components as usize);
+        segment.vertical_separation = Vec::with_capacity(no_components as usize);
+
+        for _ in 0..no_components {
+            // TODO: Consider putting into struct
+            let mut precision: [u8; 1] = [0; 1];
+            reader.read_exact(&mut precision).at(start)?;
+            segment.precision.push(precision);
+
+            let mut horizontal_separation: [u8; 1] = [0; 1];
+            reader.read_exact(&mut horizontal_separation).at(start)?;
+            segment.horizontal_separation.push(horizontal_separation);
+
+            let mut vertical_separation: [u8; 1] = [0; 1];
+            reader.read_exact(&mut vertical_separation).at(start)?;
+            segment.vertical_separation.push(vertical_separation);
         }
-        info!("SIZ end at byte offset {}", reader.stream_position()?);
 
         Ok(segment)
     }
+
     fn decode_sot<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<StartOfTileSegment, Box<dyn error::Error>> {
-        info!("SOT start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<StartOfTileSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("SOT start at byte offset {}", start);
         let mut segment = StartOfTileSegment::default();
 
         // LSot
         let mut marker_segment_length: [u8; 2] = [0; 2];
-        reader.read_exact(&mut marker_segment_length)?;
+        reader.read_exact(&mut marker_segment_length).at(start)?;
+        if u16::from_be_bytes(marker_segment_length) != 10 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SOT,
+                offset: reader.stream_position().at(start)? - 4,
+                error: format!(
+                    "invalid length {}",
+                    u16::from_be_bytes(marker_segment_length)
+                ),
+            });
+        }
 
         // ISot
-        reader.read_exact(&mut segment.tile_index)?;
+        reader.read_exact(&mut segment.tile_index).at(start)?;
 
         // PSot
-        reader.read_exact(&mut segment.tile_length)?;
+        reader.read_exact(&mut segment.tile_length).at(start)?;
 
         // TPSot
-        reader.read_exact(&mut segment.tile_part_index)?;
+        reader.read_exact(&mut segment.tile_part_index).at(start)?;
 
         // TNSot
-        reader.read_exact(&mut segment.no_tile_parts)?;
+        reader.read_exact(&mut segment.no_tile_parts).at(start)?;
 
-        info!("SOT end at byte offset {}", reader.stream_position()?);
+        info!(
+            "SOT end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
+
+        Ok(segment)
+    }
+
+    // A.5.2 - Extended capabilities (CAP)
+    fn decode_cap<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<ExtendedCapabilitiesSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("CAP start at byte offset {}", start);
+        let mut segment = ExtendedCapabilitiesSegment {
+            offset: reader.stream_position().at(start)?,
+            length: self.decode_length(reader).at(start)?,
+            ..Default::default()
+        };
+        reader.read_exact(&mut segment.parts).at(start)?;
+
+        // Lcap = 6 + 2 · number of parts
+        let no_parts = u32::from_be_bytes(segment.parts).count_ones() as usize;
+        if segment.length as usize != 6 + 2 * no_parts {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_CAP,
+                offset: segment.offset - 2,
+                error: format!(
+                    "length {} does not match {} capabilities",
+                    segment.length, no_parts
+                ),
+            });
+        }
+        segment.capabilities = vec![[0; 2]; no_parts];
+        for capabilities in segment.capabilities.iter_mut() {
+            reader.read_exact(capabilities).at(start)?;
+        }
+        info!(
+            "CAP end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
+
+        Ok(segment)
+    }
+
+    // A.5.3 - Corresponding profile (CPF)
+    fn decode_cpf<R: io::Read + io::Seek>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<CorrespondingProfileSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("CPF start at byte offset {}", start);
+        let mut segment = CorrespondingProfileSegment {
+            offset: reader.stream_position().at(start)?,
+            length: self.decode_length(reader).at(start)?,
+            ..Default::default()
+        };
+
+        // Lcpf = 2 + 2 · number of profiles
+        if segment.length < 2 || !segment.length.is_multiple_of(2) {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_CPF,
+                offset: segment.offset - 2,
+                error: format!("invalid length {}", segment.length),
+            });
+        }
+        segment.profiles = vec![[0; 2]; (segment.length as usize - 2) / 2];
+        for profile in segment.profiles.iter_mut() {
+            reader.read_exact(profile).at(start)?;
+        }
+        info!(
+            "CPF end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1582,24 +2177,34 @@ impl ContiguousCodestream {
     fn decode_cod<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<CodingStyleMarkerSegment, Box<dyn error::Error>> {
-        info!("COD start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<CodingStyleMarkerSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("COD start at byte offset {}", start);
         let mut segment = CodingStyleMarkerSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
-        reader.read_exact(&mut segment.coding_style)?;
-        reader.read_exact(&mut segment.progression_order)?;
-        reader.read_exact(&mut segment.no_layers)?;
-        reader.read_exact(&mut segment.multiple_component_transformation)?;
+        reader.read_exact(&mut segment.coding_style).at(start)?;
+        reader
+            .read_exact(&mut segment.progression_order)
+            .at(start)?;
+        reader.read_exact(&mut segment.no_layers).at(start)?;
+        reader
+            .read_exact(&mut segment.multiple_component_transformation)
+            .at(start)?;
 
         self.decode_coding_style_parameters(
             reader,
+            start,
+            MARKER_SYMBOL_COD,
             segment.coding_style[0],
             &mut segment.coding_style_parameters,
         )?;
-        info!("COD end at byte offset {}", reader.stream_position()?);
+        info!(
+            "COD end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1607,23 +2212,55 @@ impl ContiguousCodestream {
     fn decode_coding_style_parameters<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
+        offset: u64,
+        marker: MarkerSymbol,
         coding_style: u8,
         coding_style_parameters: &mut CodingStyleParameters,
-    ) -> Result<(), Box<dyn error::Error>> {
+    ) -> Result<(), CodestreamError> {
         coding_style_parameters.coding_style = [coding_style];
 
-        reader.read_exact(&mut coding_style_parameters.no_decomposition_levels)?;
-        reader.read_exact(&mut coding_style_parameters.code_block_width)?;
-        reader.read_exact(&mut coding_style_parameters.code_block_height)?;
-        reader.read_exact(&mut coding_style_parameters.code_block_style)?;
-        reader.read_exact(&mut coding_style_parameters.transformation)?;
+        reader
+            .read_exact(&mut coding_style_parameters.no_decomposition_levels)
+            .at(offset)?;
+        reader
+            .read_exact(&mut coding_style_parameters.code_block_width)
+            .at(offset)?;
+        reader
+            .read_exact(&mut coding_style_parameters.code_block_height)
+            .at(offset)?;
+        reader
+            .read_exact(&mut coding_style_parameters.code_block_style)
+            .at(offset)?;
+        reader
+            .read_exact(&mut coding_style_parameters.transformation)
+            .at(offset)?;
+
+        // Up to 32 decomposition levels and code-block width and height
+        // exponents up to 8, other values are reserved, A.6.1
+        if coding_style_parameters.no_decomposition_levels[0] > 32
+            || coding_style_parameters.code_block_width[0] > 8
+            || coding_style_parameters.code_block_height[0] > 8
+        {
+            return Err(CodestreamError::MarkerError {
+                marker,
+                offset: reader.stream_position().at(offset)? - 5,
+                error: format!(
+                    "reserved decomposition levels {} or code-block size {} x {}",
+                    coding_style_parameters.no_decomposition_levels[0],
+                    coding_style_parameters.code_block_width[0],
+                    coding_style_parameters.code_block_height[0]
+                ),
+            });
+        }
 
         if coding_style_parameters.has_defined_precinct_size() {
             // The first parameter (8 bits) corresponds to the N<sub>L</sub>LL sub-band.
             // Each successive parameter corresponds to each successive resolution level in order.
             coding_style_parameters.precinct_size =
                 vec![0; coding_style_parameters.no_decomposition_levels() as usize + 1];
-            reader.read_exact(&mut coding_style_parameters.precinct_size)?;
+            reader
+                .read_exact(&mut coding_style_parameters.precinct_size)
+                .at(offset)?;
         }
 
         Ok(())
@@ -1634,7 +2271,7 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<[u8; 2], Box<dyn error::Error>> {
+    ) -> io::Result<[u8; 2]> {
         // Either 8 or 16 bits depending on Csiz value.
         if no_components < 257 {
             let mut buffer: [u8; 1] = [0; 1];
@@ -1652,23 +2289,31 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<CodingStyleComponentSegment, Box<dyn error::Error>> {
-        info!("COC start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<CodingStyleComponentSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("COC start at byte offset {}", start);
         let mut segment = CodingStyleComponentSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
-        segment.index = self.decode_component_index(reader, no_components)?;
+        segment.index = self
+            .decode_component_index(reader, no_components)
+            .at(start)?;
 
-        reader.read_exact(&mut segment.coding_style)?;
+        reader.read_exact(&mut segment.coding_style).at(start)?;
 
         self.decode_coding_style_parameters(
             reader,
+            start,
+            MARKER_SYMBOL_COC,
             segment.coding_style[0],
             &mut segment.coding_style_parameters,
         )?;
-        info!("COC end at byte offset {}", reader.stream_position()?);
+        info!(
+            "COC end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1677,18 +2322,28 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<RegionOfInterestSegment, Box<dyn error::Error>> {
-        info!("RGN start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<RegionOfInterestSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("RGN start at byte offset {}", start);
         let mut segment = RegionOfInterestSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
-        segment.component_index = self.decode_component_index(reader, no_components)?;
+        segment.component_index = self
+            .decode_component_index(reader, no_components)
+            .at(start)?;
 
-        reader.read_exact(&mut segment.region_of_interest_style)?;
-        reader.read_exact(&mut segment.region_of_interest_style_parameter)?;
-        info!("RGN end at byte offset {}", reader.stream_position()?);
+        reader
+            .read_exact(&mut segment.region_of_interest_style)
+            .at(start)?;
+        reader
+            .read_exact(&mut segment.region_of_interest_style_parameter)
+            .at(start)?;
+        info!(
+            "RGN end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1697,19 +2352,30 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<ProgressionOrderChangeSegment, Box<dyn error::Error>> {
-        info!("POC start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<ProgressionOrderChangeSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("POC start at byte offset {}", start);
         let mut segment = ProgressionOrderChangeSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
         // The number of progression changes can be derived from the length of the
-        // marker segment.
-        let no_progression_order_change = match no_components < 256 {
-            true => segment.length - 2 - 7,
-            false => segment.length - 2 - 9,
+        // marker segment, Lpoc = 2 + 7 · changes or 2 + 9 · changes
+        let progression_size = match no_components < 257 {
+            true => 7,
+            false => 9,
         };
+        let progressions_length =
+            length_after(MARKER_SYMBOL_POC, segment.offset - 2, segment.length, 2)?;
+        if progressions_length == 0 || progressions_length % progression_size != 0 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_POC,
+                offset: segment.offset - 2,
+                error: format!("invalid length {}", segment.length),
+            });
+        }
+        let no_progression_order_change = progressions_length / progression_size;
 
         segment.progressions = Vec::with_capacity(no_progression_order_change as usize);
 
@@ -1717,24 +2383,38 @@ impl ContiguousCodestream {
         while index < no_progression_order_change {
             let mut progression = CodingStyleComponentSegmentProgression::default();
 
-            reader.read_exact(&mut progression.resolution_level_index_start)?;
+            reader
+                .read_exact(&mut progression.resolution_level_index_start)
+                .at(start)?;
 
-            progression.component_index_start =
-                self.decode_component_index(reader, no_components)?;
+            progression.component_index_start = self
+                .decode_component_index(reader, no_components)
+                .at(start)?;
 
-            reader.read_exact(&mut progression.layer_index_end)?;
+            reader
+                .read_exact(&mut progression.layer_index_end)
+                .at(start)?;
 
-            reader.read_exact(&mut progression.resolution_level_index_end)?;
+            reader
+                .read_exact(&mut progression.resolution_level_index_end)
+                .at(start)?;
 
-            progression.component_index_end = self.decode_component_index(reader, no_components)?;
+            progression.component_index_end = self
+                .decode_component_index(reader, no_components)
+                .at(start)?;
 
-            reader.read_exact(&mut progression.progression_order)?;
+            reader
+                .read_exact(&mut progression.progression_order)
+                .at(start)?;
 
             segment.progressions.push(progression);
 
             index += 1;
         }
-        info!("POC end at byte offset {}", reader.stream_position()?);
+        info!(
+            "POC end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1742,10 +2422,12 @@ impl ContiguousCodestream {
     fn decode_ppm<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<PackedPacketHeaderSegment, Box<dyn error::Error>> {
-        info!("PPM start at byte offset {}", reader.stream_position()? - 2);
-        let offset = reader.stream_position()?;
-        let length = self.decode_length(reader)?;
+    ) -> Result<PackedPacketHeaderSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("PPM start at byte offset {}", start);
+        let offset = reader.stream_position().at(start)?;
+        let length = self.decode_length(reader).at(start)?;
+        let data_length = length_after(MARKER_SYMBOL_PPM, offset - 2, length, 7)?;
         let mut segment = PackedPacketHeaderSegment {
             offset,
             length,
@@ -1754,13 +2436,16 @@ impl ContiguousCodestream {
             // TODO: It is possible that the next PPM marker segment will not
             // have an Nppm parameter after Zppm, but the continuation of the
             // Ippm series from the last PPM marker segment.
-            data: vec![0; (length as usize) - 7],
+            data: self.allocate(data_length as usize).at(start)?,
         };
 
-        reader.read_exact(&mut segment.index)?;
-        reader.read_exact(&mut segment.number_of_bytes)?;
-        reader.read_exact(&mut segment.data)?;
-        info!("PPM end at byte offset {}", reader.stream_position()?);
+        reader.read_exact(&mut segment.index).at(start)?;
+        reader.read_exact(&mut segment.number_of_bytes).at(start)?;
+        reader.read_exact(&mut segment.data).at(start)?;
+        info!(
+            "PPM end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1768,21 +2453,26 @@ impl ContiguousCodestream {
     fn decode_ppt<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<TilePackedPacketHeaderSegment, Box<dyn error::Error>> {
-        info!("PPT start at byte offset {}", reader.stream_position()? - 2);
-        let offset = reader.stream_position()?;
-        let length = self.decode_length(reader)?;
+    ) -> Result<TilePackedPacketHeaderSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("PPT start at byte offset {}", start);
+        let offset = reader.stream_position().at(start)?;
+        let length = self.decode_length(reader).at(start)?;
+        let data_length = length_after(MARKER_SYMBOL_PPT, offset - 2, length, 3)?;
         let mut segment = TilePackedPacketHeaderSegment {
             offset,
             length,
             index: [0],
-            data: vec![0; (length as usize) - 3],
+            data: self.allocate(data_length as usize).at(start)?,
         };
 
-        reader.read_exact(&mut segment.index)?;
-        reader.read_exact(&mut segment.data)?;
+        reader.read_exact(&mut segment.index).at(start)?;
+        reader.read_exact(&mut segment.data).at(start)?;
 
-        info!("PPT end at byte offset {}", reader.stream_position()?);
+        info!(
+            "PPT end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1790,12 +2480,14 @@ impl ContiguousCodestream {
     fn decode_tlm<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<TilePartLengthsSegment, Box<dyn error::Error>> {
-        info!("TLM start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<TilePartLengthsSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("TLM start at byte offset {}", start);
         let mut segment = TilePartLengthsSegment::default();
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
-        reader.read_exact(&mut segment.parameter_sizes)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
+        reader.read_exact(&mut segment.index).at(start)?;
+        reader.read_exact(&mut segment.parameter_sizes).at(start)?;
 
         let parameter_sizes = segment.parameter_sizes();
 
@@ -1812,45 +2504,51 @@ impl ContiguousCodestream {
         }
 
         // number of tile lengths
-        let no_tile_part_lengths = (segment.length - 4) / tile_part_size;
+        let no_tile_part_lengths =
+            length_after(MARKER_SYMBOL_TLM, segment.offset - 2, segment.length, 4)?
+                / tile_part_size;
 
         for _ in 0..no_tile_part_lengths {
             let mut tile_part_length = TilePartLength::default();
 
-            // Ttlm
+            // Ttlm, big endian in the low bytes of the tile index
             if parameter_sizes.contains(&TilePartParameterSize::Ttlm8Bit) {
                 reader
-                    .take(1)
-                    .read_exact(&mut tile_part_length.tile_index)?;
+                    .read_exact(&mut tile_part_length.tile_index[1..])
+                    .at(start)?;
             } else if parameter_sizes.contains(&TilePartParameterSize::Ttlm16Bit) {
                 reader
-                    .take(2)
-                    .read_exact(&mut tile_part_length.tile_index)?;
+                    .read_exact(&mut tile_part_length.tile_index)
+                    .at(start)?;
             }
 
             // Ptlm
             if parameter_sizes.contains(&TilePartParameterSize::Ptlm16Bit) {
                 reader
-                    .take(2)
-                    .read_exact(&mut tile_part_length.tile_length)?;
+                    .read_exact(&mut tile_part_length.tile_length[2..])
+                    .at(start)?;
             } else if parameter_sizes.contains(&TilePartParameterSize::Ptlm32Bit) {
                 reader
-                    .take(4)
-                    .read_exact(&mut tile_part_length.tile_length)?;
+                    .read_exact(&mut tile_part_length.tile_length)
+                    .at(start)?;
             }
             segment.tile_part_lengths.push(tile_part_length);
         }
 
-        info!("TLM end at byte offset {}", reader.stream_position()?);
+        info!(
+            "TLM end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
         Ok(segment)
     }
 
     fn decode_quantization_values<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
+        offset: u64,
         quantization_style: QuantizationStyle,
         no_decomposition_levels: u8,
-    ) -> Result<Vec<QuantizationValue>, Box<dyn error::Error>> {
+    ) -> Result<Vec<QuantizationValue>, CodestreamError> {
         // Decomposition levels are divided into subbands. These include the HL, LH, and HH subbands of the same two dimensional subband decomposition. For the last decomposition level the LL subband is also included.
         let no_subbands = no_decomposition_levels * 3 + 1;
 
@@ -1862,7 +2560,7 @@ impl ContiguousCodestream {
                 // Reversible transformation values
                 QuantizationStyle::No { guard: _ } => {
                     let mut value: [u8; 1] = [0; 1];
-                    reader.read_exact(&mut value)?;
+                    reader.read_exact(&mut value).at(offset)?;
 
                     let quantization_value = QuantizationValue::Reversible { value };
                     quantization_values.push(quantization_value);
@@ -1871,13 +2569,17 @@ impl ContiguousCodestream {
                 QuantizationStyle::ScalarExpounded { guard: _ }
                 | QuantizationStyle::ScalarDerived { guard: _ } => {
                     let mut value: [u8; 2] = [0; 2];
-                    reader.read_exact(&mut value)?;
+                    reader.read_exact(&mut value).at(offset)?;
 
                     let quantization_value = QuantizationValue::Irreversible { value };
                     quantization_values.push(quantization_value);
                 }
-                QuantizationStyle::Reserved { value: _value } => {
-                    todo!()
+                QuantizationStyle::Reserved { value } => {
+                    return Err(CodestreamError::MarkerError {
+                        marker: MARKER_SYMBOL_QCD,
+                        offset: reader.stream_position().at(offset)?,
+                        error: format!("reserved quantization style {}", value),
+                    });
                 }
             }
         }
@@ -1888,26 +2590,34 @@ impl ContiguousCodestream {
     fn decode_qcd<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<QuantizationDefaultMarkerSegment, Box<dyn error::Error>> {
-        info!("QCD start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<QuantizationDefaultMarkerSegment, CodestreamError> {
+        let offset = reader.stream_position().at(0)? - 2;
+        info!("QCD start at byte offset {}", offset);
         let mut segment = QuantizationDefaultMarkerSegment::default();
 
-        segment.length = self.decode_length(reader)?;
-        reader.read_exact(&mut segment.quantization_style)?;
+        segment.length = self.decode_length(reader).at(offset)?;
+        reader
+            .read_exact(&mut segment.quantization_style)
+            .at(offset)?;
 
-        let no_decomposition_levels = match segment.quantization_style() {
-            QuantizationStyle::No { guard: _ } => (segment.length() - 4) / 3,
-            QuantizationStyle::ScalarDerived { guard: _ } => 5,
-            QuantizationStyle::ScalarExpounded { guard: _ } => (segment.length() - 5) / 6,
-            _ => panic!(),
-        } as u8;
+        // Lqcd, Sqcd
+        let no_decomposition_levels = quantization_levels(
+            MARKER_SYMBOL_QCD,
+            offset,
+            segment.quantization_style(),
+            length_after(MARKER_SYMBOL_QCD, offset, segment.length, 3)?,
+        )?;
 
         segment.values = self.decode_quantization_values(
             reader,
+            offset,
             segment.quantization_style(),
             no_decomposition_levels,
         )?;
-        info!("QCD end at byte offset {}", reader.stream_position()?);
+        info!(
+            "QCD end at byte offset {}",
+            reader.stream_position().at(offset)?
+        );
 
         Ok(segment)
     }
@@ -1916,40 +2626,52 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<QuantizationComponentSegment, Box<dyn error::Error>> {
-        info!("QCC start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<QuantizationComponentSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("QCC start at byte offset {}", start);
         let mut segment = QuantizationComponentSegment::default();
 
-        segment.offset = reader.stream_position()?;
+        segment.offset = reader.stream_position().at(start)?;
 
         // Lqcc
-        segment.length = self.decode_length(reader)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
         // Cqcc
-        segment.component_index = self.decode_component_index(reader, no_components)?;
+        segment.component_index = self
+            .decode_component_index(reader, no_components)
+            .at(start)?;
 
         // Sqcc
-        reader.read_exact(&mut segment.quantization_style)?;
-
-        let mut no_decomposition_levels = match segment.quantization_style() {
-            QuantizationStyle::No { guard: _ } => (segment.length() - 5) / 3,
-            QuantizationStyle::ScalarDerived { guard: _ } => 6,
-            QuantizationStyle::ScalarExpounded { guard: _ } => (segment.length() - 6) / 6,
-            _ => panic!(),
-        } as u8;
-
-        if no_components >= 257 {
-            no_decomposition_levels += 1;
-        }
+        reader
+            .read_exact(&mut segment.quantization_style)
+            .at(start)?;
+
+        // Lqcc, Cqcc of 8 or 16 bits, Sqcc
+        let parameters_length = if no_components < 257 { 4 } else { 5 };
+        let no_decomposition_levels = quantization_levels(
+            MARKER_SYMBOL_QCC,
+            segment.offset - 2,
+            segment.quantization_style(),
+            length_after(
+                MARKER_SYMBOL_QCC,
+                segment.offset - 2,
+                segment.length,
+                parameters_length,
+            )?,
+        )?;
 
         // SPqcc
 
         segment.quantization_values = self.decode_quantization_values(
             reader,
+            start,
             segment.quantization_style(),
             no_decomposition_levels,
         )?;
-        info!("QCC end at byte offset {}", reader.stream_position()?);
+        info!(
+            "QCC end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1957,23 +2679,28 @@ impl ContiguousCodestream {
     fn decode_plm<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<PacketLengthSegment, Box<dyn error::Error>> {
-        info!("PLM start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<PacketLengthSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("PLM start at byte offset {}", start);
         let mut segment = PacketLengthSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
-        reader.read_exact(&mut segment.index)?;
-        reader.read_exact(&mut segment.no_bytes)?;
+        reader.read_exact(&mut segment.index).at(start)?;
+        reader.read_exact(&mut segment.no_bytes).at(start)?;
 
         segment.packet_length = Vec::with_capacity(segment.no_bytes() as usize);
 
         // TODO: Handle multiple PLM where the next PLM is missing
         // Nplm and is a continuation of previous Iplm
-        self.decode_packet_length(reader, &mut segment.packet_length)?;
+        self.decode_packet_length(reader, &mut segment.packet_length)
+            .at(start)?;
 
-        info!("PLM end at byte offset {}", reader.stream_position()?);
+        info!(
+            "PLM end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -1982,7 +2709,7 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         vec: &mut Vec<u8>,
-    ) -> Result<(), Box<dyn error::Error>> {
+    ) -> io::Result<()> {
         let mut packet_length: [u8; 1] = [0; 1];
         loop {
             reader.read_exact(&mut packet_length)?;
@@ -2014,18 +2741,23 @@ impl ContiguousCodestream {
     fn decode_plt<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<TilePacketLength, Box<dyn error::Error>> {
-        info!("PLT start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<TilePacketLength, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("PLT start at byte offset {}", start);
         let mut segment = TilePacketLength::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
-        reader.read_exact(&mut segment.index)?;
+        reader.read_exact(&mut segment.index).at(start)?;
 
-        self.decode_packet_length(reader, &mut segment.packet_length)?;
+        self.decode_packet_length(reader, &mut segment.packet_length)
+            .at(start)?;
 
-        info!("PLT end at byte offset {}", reader.stream_position()?);
+        info!(
+            "PLT end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -2034,26 +2766,30 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<ComponentRegistrationSegment, Box<dyn error::Error>> {
-        info!("CRG start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<ComponentRegistrationSegment, CodestreamError> {
+        let start = reader.stream_position().at(0)? - 2;
+        info!("CRG start at byte offset {}", start);
         let mut segment = ComponentRegistrationSegment::default();
 
-        segment.offset = reader.stream_position()?;
-        segment.length = self.decode_length(reader)?;
+        segment.offset = reader.stream_position().at(start)?;
+        segment.length = self.decode_length(reader).at(start)?;
 
         segment.horizontal_offset = Vec::with_capacity(no_components as usize);
         segment.vertical_offset = Vec::with_capacity(no_components as usize);
         for _ in 0..no_components {
             // TODO: Consider putting into struct
             let mut horizontal_offset: [u8; 2] = [0; 2];
-            reader.read_exact(&mut horizontal_offset)?;
+            reader.read_exact(&mut horizontal_offset).at(start)?;
             segment.horizontal_offset.push(horizontal_offset);
 
             let mut vertical_offset: [u8; 2] = [0; 2];
-            reader.read_exact(&mut vertical_offset)?;
+            reader.read_exact(&mut vertical_offset).at(start)?;
             segment.vertical_offset.push(vertical_offset);
         }
-        info!("CRG end at byte offset {}", reader.stream_position()?);
+        info!(
+            "CRG end at byte offset {}",
+            reader.stream_position().at(start)?
+        );
 
         Ok(segment)
     }
@@ -2061,23 +2797,33 @@ impl ContiguousCodestream {
     fn decode_com<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<CommentMarkerSegment, Box<dyn error::Error>> {
-        info!("COM start at byte offset {}", reader.stream_position()? - 2);
+    ) -> Result<CommentMarkerSegment, CodestreamError> {
+        let offset = reader.stream_position().at(0)? - 2;
+        info!("COM start at byte offset {}", offset);
         let mut segment = CommentMarkerSegment::default();
 
         // Length of marker segment in bytes (not including the marker).
         let mut marker_segment_length: [u8; 2] = [0; 2];
-        reader.read_exact(&mut marker_segment_length)?;
-        reader.read_exact(&mut segment.registration_value)?;
-
-        let comment_length = u16::from_be_bytes(marker_segment_length) as usize
-            - marker_segment_length.len()
-            - segment.registration_value.len();
+        reader.read_exact(&mut marker_segment_length).at(offset)?;
+        reader
+            .read_exact(&mut segment.registration_value)
+            .at(offset)?;
+
+        // Lcom, Rcom
+        let comment_length = length_after(
+            MARKER_SYMBOL_COM,
+            offset,
+            u16::from_be_bytes(marker_segment_length),
+            4,
+        )?;
 
-        segment.comment = vec![0; comment_length];
+        segment.comment = self.allocate(comment_length as usize).at(offset)?;
 
-        reader.read_exact(&mut segment.comment)?;
-        info!("COM end at byte offset {}", reader.stream_position()?);
+        reader.read_exact(&mut segment.comment).at(offset)?;
+        info!(
+            "COM end at byte offset {}",
+            reader.stream_position().at(offset)?
+        );
 
         Ok(segment)
     }
@@ -2088,6 +2834,12 @@ pub struct Header {
     // SIZ (Required)
     image_and_tile_size_marker_segment: ImageAndTileSizeMarkerSegment,
 
+    // CAP (Optional)
+    extended_capabilities: Option<ExtendedCapabilitiesSegment>,
+
+    // CPF (Optional)
+    corresponding_profile: Option<CorrespondingProfileSegment>,
+
     // COD (Required)
     coding_style_marker_segment: Option<CodingStyleMarkerSegment>,
 
@@ -2218,6 +2970,18 @@ impl Header {
     pub fn comment_marker_segments(&self) -> &Vec<CommentMarkerSegment> {
         &self.comment_marker_segments
     }
+
+    /// Extended capabilities (CAP) segment
+    ///
+    /// Required when the codestream uses capabilities of other parts of
+    /// ISO/IEC 15444, such as the HT block coder of ISO/IEC 15444-15.
+    pub fn extended_capabilities_segment(&self) -> &Option<ExtendedCapabilitiesSegment> {
+        &self.extended_capabilities
+    }
+
+    pub fn corresponding_profile_segment(&self) -> &Option<CorrespondingProfileSegment> {
+        &self.corresponding_profile
+    }
 }
 
 // Many images have multiple components. This specification has a multiple component transformation to decorrelate threecomponents. This is the only function in this specification that relates components to each other
@@ -2304,39 +3068,29 @@ impl ContiguousCodestream {
     fn decode_main_header<R: io::Read + io::Seek>(
         &mut self,
         reader: &mut R,
-    ) -> Result<Header, Box<dyn error::Error>> {
-        let mut header = Header::default();
+    ) -> Result<Header, CodestreamError> {
+        let mut header = Header {
+            image_and_tile_size_marker_segment: self.decode_soc_siz(reader)?,
+            ..Default::default()
+        };
 
         let mut marker_type: MarkerSymbol = [0; 2];
-
-        // SOC (Required as the first marker)
-        reader.read_exact(&mut marker_type)?;
-        if marker_type != MARKER_SYMBOL_SOC {
-            return Err(CodestreamError::MarkerUnexpected {
-                marker: MARKER_SYMBOL_SOC,
-                offset: reader.stream_position()? - 2,
-            }
-            .into());
-        }
-        info!("SOC start at byte offset {}", reader.stream_position()? - 2);
-
-        // SIZ (Required as the second marker segment)
-        reader.read_exact(&mut marker_type)?;
-        if marker_type != MARKER_SYMBOL_SIZ {
-            return Err(CodestreamError::MarkerUnexpected {
-                marker: MARKER_SYMBOL_SIZ,
-                offset: reader.stream_position()? - 2,
-            }
-            .into());
-        }
-
-        header.image_and_tile_size_marker_segment = self.decode_siz(reader)?;
-
         let no_components = header.image_and_tile_size_marker_segment.no_components();
 
         loop {
+            let position = reader.stream_position().at(0)?;
             match reader.read_exact(&mut marker_type) {
                 Ok(_) => match marker_type {
+                    // CAP (Optional, required if Rsiz signals extended capabilities)
+                    MARKER_SYMBOL_CAP => {
+                        header.extended_capabilities = Some(self.decode_cap(reader)?);
+                    }
+
+                    // CPF (Optional)
+                    MARKER_SYMBOL_CPF => {
+                        header.corresponding_profile = Some(self.decode_cpf(reader)?);
+                    }
+
                     // COC (Optional, no more than one COC per component)
                     MARKER_SYMBOL_COC => {
                         header
@@ -2403,33 +3157,49 @@ impl ContiguousCodestream {
 
                     // Start of tile bit-stream
                     MARKER_SYMBOL_SOT => {
-                        reader.seek(io::SeekFrom::Current(-2))?;
+                        reader.seek(io::SeekFrom::Current(-2)).at(position)?;
                         break;
                     }
                     _ => {
                         return Err(CodestreamError::MarkerUnexpected {
                             marker: marker_type,
-                            offset: reader.stream_position()? - 2,
-                        }
-                        .into());
+                            offset: position,
+                        });
                     }
                 },
-                Err(e) => return Err(e.into()),
+                Err(source) => {
+                    return Err(CodestreamError::Io {
+                        offset: position,
+                        source,
+                    })
+                }
             }
         }
 
         // Required
+        if header
+            .image_and_tile_size_marker_segment
+            .decoder_capabilities()
+            & 0x4000
+            != 0
+            && header.extended_capabilities.is_none()
+        {
+            return Err(CodestreamError::MarkerMissing {
+                marker: MARKER_SYMBOL_CAP,
+                offset: reader.stream_position().at(0)?,
+            });
+        }
         if header.quantization_default_marker_segment.is_none() {
             return Err(CodestreamError::MarkerMissing {
                 marker: MARKER_SYMBOL_QCD,
-            }
-            .into());
+                offset: reader.stream_position().at(0)?,
+            });
         }
         if header.coding_style_marker_segment.is_none() {
             return Err(CodestreamError::MarkerMissing {
                 marker: MARKER_SYMBOL_COD,
-            }
-            .into());
+                offset: reader.stream_position().at(0)?,
+            });
         }
 
         // A.6.2
@@ -2437,13 +3207,13 @@ impl ContiguousCodestream {
         if header.coding_style_component_segment.len() > (no_components as usize) {
             return Err(CodestreamError::MarkerError {
                 marker: MARKER_SYMBOL_COC,
+                offset: header.coding_style_component_segment[no_components as usize].offset - 2,
                 error: format!(
                     "number of coding style component (COC) {:?} exceeds number of components {:?}",
-                    header.regions.len(),
+                    header.coding_style_component_segment.len(),
                     no_components
                 ),
-            }
-            .into());
+            });
         }
 
         // A.6.3 - here may be at most one
@@ -2451,13 +3221,13 @@ impl ContiguousCodestream {
         if header.regions.len() > (no_components as usize) {
             return Err(CodestreamError::MarkerError {
                 marker: MARKER_SYMBOL_RGN,
+                offset: header.regions[no_components as usize].offset - 2,
                 error: format!(
                     "number of region of interest (RGN) {:?} exceeds number of components {:?}",
                     header.regions.len(),
                     no_components
                 ),
-            }
-            .into());
+            });
         }
 
         // A.6.5
@@ -2465,13 +3235,13 @@ impl ContiguousCodestream {
         if header.quantization_component_segments.len() > (no_components as usize) {
             return Err(CodestreamError::MarkerError {
                 marker: MARKER_SYMBOL_QCC,
+                offset: header.quantization_component_segments[no_components as usize].offset - 2,
                 error: format!(
                     "number of quantization component (QCC) {:?} exceeds number of components {:?}",
-                    header.regions.len(),
+                    header.quantization_component_segments.len(),
                     no_components
                 ),
-            }
-            .into());
+            });
         }
 
         Ok(header)
@@ -2482,25 +3252,26 @@ impl ContiguousCodestream {
         &mut self,
         reader: &mut R,
         no_components: u16,
-    ) -> Result<TileHeader, Box<dyn error::Error>> {
+    ) -> Result<TileHeader, CodestreamError> {
         let mut tile_header = TileHeader::default();
 
         let mut marker_type: MarkerSymbol = [0; 2];
 
-        reader.read_exact(&mut marker_type)?;
+        let start = reader.stream_position().at(0)?;
+        reader.read_exact(&mut marker_type).at(start)?;
 
         // SOT (Required as the first marker segment of every tile-part header)
         if marker_type != MARKER_SYMBOL_SOT {
             return Err(CodestreamError::MarkerUnexpected {
                 marker: MARKER_SYMBOL_SOT,
-                offset: reader.stream_position()? - 2,
-            }
-            .into());
+                offset: start,
+            });
         }
 
         tile_header.start_of_tile_segment = self.decode_sot(reader)?;
 
         loop {
+            let position = reader.stream_position().at(0)?;
             match reader.read_exact(&mut marker_type) {
                 Ok(_) => match marker_type {
                     // COD (Optional)
@@ -2549,9 +3320,8 @@ impl ContiguousCodestream {
                         if !self.header.packed_packet_headers.is_empty() {
                             return Err(CodestreamError::MarkerUnexpected {
                                 marker: MARKER_SYMBOL_PPT,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
+                                offset: position,
+                            });
                         }
 
                         tile_header.packed_packet_headers = Some(self.decode_ppt(reader)?);
@@ -2569,23 +3339,30 @@ impl ContiguousCodestream {
                     }
                     // COM (Optional)
                     MARKER_SYMBOL_SOD => {
-                        reader.seek(io::SeekFrom::Current(-2))?;
+                        reader.seek(io::SeekFrom::Current(-2)).at(position)?;
                         break;
                     }
-                    _ => panic!(),
+                    _ => {
+                        return Err(CodestreamError::MarkerUnexpected {
+                            marker: marker_type,
+                            offset: position,
+                        });
+                    }
                 },
 
-                Err(e) => return Err(e.into()),
+                Err(source) => {
+                    return Err(CodestreamError::Io {
+                        offset: position,
+                        source,
+                    })
+                }
             }
         }
 
         Ok(tile_header)
     }
 
-    fn decode<R: io::Read + io::Seek>(
-        &mut self,
-        reader: &mut R,
-    ) -> Result<(), Box<dyn error::Error>> {
+    fn decode<R: io::Read + io::Seek>(&mut self, reader: &mut R) -> Result<(), CodestreamError> {
         // The main header is found at the beginning of the codestream
         self.header = self.decode_main_header(reader)?;
 
@@ -2599,39 +3376,39 @@ impl ContiguousCodestream {
         let mut marker_type: MarkerSymbol = [0; 2];
 
         // Required as the last marker segment of every tile-part header
-        reader.read_exact(&mut marker_type)?;
+        let start = reader.stream_position().at(0)?;
+        reader.read_exact(&mut marker_type).at(start)?;
         if marker_type != MARKER_SYMBOL_SOD {
             return Err(CodestreamError::MarkerUnexpected {
                 marker: MARKER_SYMBOL_SOD,
-                offset: reader.stream_position()?,
-            }
-            .into());
+                offset: start + 2,
+            });
         }
 
         let coding_styles = self.header.coding_style_marker_segment().coding_styles();
 
-        let start_of_data = reader.stream_position()?;
+        let start_of_data = start + 2;
         info!("SOD start at byte offset {}", start_of_data - 2);
 
         loop {
+            let position = reader.stream_position().at(0)?;
             match reader.read_exact(&mut marker_type) {
                 Ok(_) => match marker_type {
                     // in bit-stream markers
                     MARKER_SYMBOL_SOP => {
-                        info!("SOP start at byte offset {}", reader.stream_position()? - 2);
+                        info!("SOP start at byte offset {}", position);
                         if coding_styles.contains(&CodingStyleDefault::NoSOP) {
                             return Err(CodestreamError::MarkerUnexpected {
                                 marker: MARKER_SYMBOL_SOP,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
+                                offset: position,
+                            });
                         } else {
                             // ITU-T H.800 or ISO/IEC 15444-1 2024, Section A.8.1
                             let mut buf = [0u8; 2];
-                            reader.read_exact(&mut buf)?;
+                            reader.read_exact(&mut buf).at(position)?;
                             let lsop = u16::from_be_bytes(buf);
                             // TODO: if using strict parsing, check length == 4
-                            reader.read_exact(&mut buf)?;
+                            reader.read_exact(&mut buf).at(position)?;
                             let nsop = u16::from_be_bytes(buf);
                             // TODO: if using strict parsing, check nsop increment matches packet number,
                             // even if SOP wasn't present
@@ -2646,17 +3423,15 @@ impl ContiguousCodestream {
                         {
                             return Err(CodestreamError::MarkerUnexpected {
                                 marker: MARKER_SYMBOL_EPH,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
+                                offset: position,
+                            });
                         }
 
                         if coding_styles.contains(&CodingStyleDefault::NoEPH) {
                             return Err(CodestreamError::MarkerUnexpected {
                                 marker: MARKER_SYMBOL_EPH,
-                                offset: reader.stream_position()? - 2,
-                            }
-                            .into());
+                                offset: position,
+                            });
                         } else {
                             // ITU-T H.800 or ISO/IEC 15444-1 2024, Section A.8.2
                             // Empty marker, not even the length.
@@ -2664,12 +3439,16 @@ impl ContiguousCodestream {
                     }
                     // delimiting markers
                     MARKER_SYMBOL_EOC => {
-                        info!("EOC end at byte offset {}", reader.stream_position()?);
+                        info!("EOC end at byte offset {}", position + 2);
                         break;
                     }
                     MARKER_SYMBOL_SOT => {
                         // A.4.4
-                        todo!();
+                        // TODO: Support multiple SOT
+                        return Err(CodestreamError::Unsupported {
+                            marker: MARKER_SYMBOL_SOT,
+                            offset: position,
+                        });
                     }
                     _ => {
                         // TODO: See J.10.3 Packet headers
@@ -2712,20 +3491,25 @@ impl ContiguousCodestream {
 
                 Err(e) => match e.kind() {
                     io::ErrorKind::UnexpectedEof => break,
-                    _ => return Err(e.into()),
+                    _ => {
+                        return Err(CodestreamError::Io {
+                            offset: position,
+                            source: e,
+                        })
+                    }
                 },
             }
         }
 
-        let end_of_data = reader.stream_position()?;
+        let end_of_data = reader.stream_position().at(0)?;
         info!("SOD end at byte offset {}", end_of_data);
 
         // TODO: Support multiple SOT
 
         // TODO: avoid seeking
-        reader.seek(io::SeekFrom::Start(start_of_data))?;
+        reader.seek(io::SeekFrom::Start(start_of_data)).at(start)?;
 
-        reader.seek(io::SeekFrom::Start(end_of_data))?;
+        reader.seek(io::SeekFrom::Start(end_of_data)).at(start)?;
 
         self.tiles.push(Tile {
             header: tile_header,
@@ -2776,10 +3560,85 @@ struct Component {}
 // right hand reference grid point at location (Xsiz-1, Ysiz-1).
 struct ImageArea {}
 
+// Length of a marker segment after its parameters of the given length, the
+// marker segment is malformed if it is shorter.
+fn length_after(
+    marker: MarkerSymbol,
+    offset: u64,
+    length: u16,
+    parameters_length: u16,
+) -> Result<u16, CodestreamError> {
+    length
+        .checked_sub(parameters_length)
+        .ok_or_else(|| CodestreamError::MarkerError {
+            marker,
+            offset,
+            error: format!("invalid length {}", length),
+        })
+}
+
+// Number of decomposition levels of the quantization values of a QCD or QCC
+// marker segment, from their length in bytes.
+//
+// no_quantization               = 1 + 3 · number_decomposition_levels
+// scalar_quantization_derived   = 2
+// scalar_quantization_expounded = 2 + 6 · number_decomposition_levels
+fn quantization_levels(
+    marker: MarkerSymbol,
+    offset: u64,
+    quantization_style: QuantizationStyle,
+    length: u16,
+) -> Result<u8, CodestreamError> {
+    let levels = match quantization_style {
+        QuantizationStyle::No { guard: _ } => length.checked_sub(1).map(|length| length / 3),
+        QuantizationStyle::ScalarDerived { guard: _ } => Some(0),
+        QuantizationStyle::ScalarExpounded { guard: _ } => {
+            length.checked_sub(2).map(|length| length / 6)
+        }
+        QuantizationStyle::Reserved { value } => {
+            return Err(CodestreamError::MarkerError {
+                marker,
+                offset,
+                error: format!("reserved quantization style {}", value),
+            });
+        }
+    };
+    match levels {
+        // Up to 32 decomposition levels, A.6.1
+        Some(levels) if levels <= 32 => Ok(levels as u8),
+        _ => Err(CodestreamError::MarkerError {
+            marker,
+            offset,
+            error: format!("invalid length {}", length),
+        }),
+    }
+}
+
+/// Decode the SIZ marker segment at the start of a codestream, without the
+/// rest of the main header, such as to compare it with the header of a file
+/// format.
+pub fn decode_image_and_tile_size<R: io::Read + io::Seek>(
+    reader: &mut R,
+) -> Result<ImageAndTileSizeMarkerSegment, CodestreamError> {
+    ContiguousCodestream::default().decode_soc_siz(reader)
+}
+
 pub fn decode_jpc<R: io::Read + io::Seek>(
     reader: &mut R,
-) -> Result<ContiguousCodestream, Box<dyn error::Error>> {
-    let mut continuous_codestream = ContiguousCodestream::default();
+) -> Result<ContiguousCodestream, CodestreamError> {
+    decode_jpc_with_limits(reader, &Limits::default())
+}
+
+/// Decode a codestream, failing with a `CodestreamError::Limit` on a value
+/// exceeding the limits before memory is allocated for it.
+pub fn decode_jpc_with_limits<R: io::Read + io::Seek>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<ContiguousCodestream, CodestreamError> {
+    let mut continuous_codestream = ContiguousCodestream {
+        limits: *limits,
+        ..Default::default()
+    };
     continuous_codestream.decode(reader)?;
 
     // Tile: A rectangular array of points on the reference grid, registered
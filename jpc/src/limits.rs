@@ -0,0 +1,228 @@
+use std::error;
+use std::fmt;
+
+use super::ImageAndTileSizeMarkerSegment;
+
+/// Limits on the values read from a file, checked before memory is allocated
+/// for them so that hostile files fail to decode instead of exhausting
+/// memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length in bytes of the contents of a box read into memory.
+    pub max_box_size: u64,
+
+    /// Maximum number of components, Csiz of the SIZ marker segment or NC of
+    /// the Image Header box.
+    pub max_components: u16,
+
+    /// Maximum number of reference grid points of the image area.
+    pub max_image_area: u64,
+
+    /// Maximum number of tiles of the tile grid.
+    pub max_tiles: u64,
+
+    /// Maximum number of bytes allocated for the contents of the boxes and
+    /// marker segments of a file.
+    pub max_memory: u64,
+
+    /// Maximum number of superboxes, or of elements, containing a box or an
+    /// element.
+    pub max_nesting_depth: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_box_size: 256 << 20,
+            max_components: 16384,
+            max_image_area: 1 << 40,
+            max_tiles: 65535,
+            max_memory: 1 << 30,
+            max_nesting_depth: 64,
+        }
+    }
+}
+
+impl Limits {
+    /// No limits other than those of the file format.
+    pub fn unlimited() -> Limits {
+        Limits {
+            max_box_size: u64::MAX,
+            max_components: u16::MAX,
+            max_image_area: u64::MAX,
+            max_tiles: u64::MAX,
+            max_memory: u64::MAX,
+            max_nesting_depth: u32::MAX,
+        }
+    }
+
+    pub fn check_box_size(&self, length: u64) -> Result<(), LimitError> {
+        if length > self.max_box_size {
+            return Err(LimitError::BoxSize {
+                length,
+                max_box_size: self.max_box_size,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_components(&self, components: u16) -> Result<(), LimitError> {
+        if components > self.max_components {
+            return Err(LimitError::Components {
+                components,
+                max_components: self.max_components,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_image_area(&self, width: u32, height: u32) -> Result<(), LimitError> {
+        let area = width as u64 * height as u64;
+        if area > self.max_image_area {
+            return Err(LimitError::ImageArea {
+                area,
+                max_image_area: self.max_image_area,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_tiles(&self, tiles: u64) -> Result<(), LimitError> {
+        if tiles > self.max_tiles {
+            return Err(LimitError::Tiles {
+                tiles,
+                max_tiles: self.max_tiles,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn check_nesting_depth(&self, depth: u32) -> Result<(), LimitError> {
+        if depth > self.max_nesting_depth {
+            return Err(LimitError::NestingDepth {
+                depth,
+                max_nesting_depth: self.max_nesting_depth,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that `length` more bytes can be allocated, of which `allocated`
+    /// bytes were allocated before, and add them.
+    pub fn allocate(&self, allocated: &mut u64, length: u64) -> Result<(), LimitError> {
+        let total = allocated.saturating_add(length);
+        if total > self.max_memory {
+            return Err(LimitError::Memory {
+                allocated: total,
+                max_memory: self.max_memory,
+            });
+        }
+        *allocated = total;
+        Ok(())
+    }
+
+    // The components, image area and tiles of a SIZ marker segment whose
+    // tile grid has been checked.
+    pub(crate) fn check_image_and_tile_size(
+        &self,
+        segment: &ImageAndTileSizeMarkerSegment,
+    ) -> Result<(), LimitError> {
+        self.check_components(segment.no_components())?;
+        self.check_image_area(
+            segment.reference_grid_width() - segment.image_horizontal_offset(),
+            segment.reference_grid_height() - segment.image_vertical_offset(),
+        )?;
+        self.check_tiles(segment.num_x_tiles() as u64 * segment.num_y_tiles() as u64)
+    }
+}
+
+/// A value of a file exceeding the `Limits` it is decoded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitError {
+    BoxSize {
+        length: u64,
+        max_box_size: u64,
+    },
+    Components {
+        components: u16,
+        max_components: u16,
+    },
+    ImageArea {
+        area: u64,
+        max_image_area: u64,
+    },
+    Tiles {
+        tiles: u64,
+        max_tiles: u64,
+    },
+    Memory {
+        allocated: u64,
+        max_memory: u64,
+    },
+    NestingDepth {
+        depth: u32,
+        max_nesting_depth: u32,
+    },
+}
+
+impl error::Error for LimitError {}
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BoxSize {
+                length,
+                max_box_size,
+            } => {
+                write!(
+                    f,
+                    "box length {} exceeds the limit of {} bytes",
+                    length, max_box_size
+                )
+            }
+            Self::Components {
+                components,
+                max_components,
+            } => {
+                write!(
+                    f,
+                    "{} components exceed the limit of {}",
+                    components, max_components
+                )
+            }
+            Self::ImageArea {
+                area,
+                max_image_area,
+            } => {
+                write!(
+                    f,
+                    "image area of {} exceeds the limit of {}",
+                    area, max_image_area
+                )
+            }
+            Self::Tiles { tiles, max_tiles } => {
+                write!(f, "{} tiles exceed the limit of {}", tiles, max_tiles)
+            }
+            Self::Memory {
+                allocated,
+                max_memory,
+            } => {
+                write!(
+                    f,
+                    "allocating {} bytes exceeds the limit of {} bytes",
+                    allocated, max_memory
+                )
+            }
+            Self::NestingDepth {
+                depth,
+                max_nesting_depth,
+            } => {
+                write!(
+                    f,
+                    "nesting depth of {} exceeds the limit of {}",
+                    depth, max_nesting_depth
+                )
+            }
+        }
+    }
+}
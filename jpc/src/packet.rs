@@ -0,0 +1,706 @@
+// Annex B - Image and compressed image data ordering
+//
+// Tier-2 decoding of packet headers. Only the structure of the packets is
+// recovered, the code-block contributions are not decoded. This is enough to
+// locate every packet of a tile and to reorder or discard them.
+use std::cmp;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::transcode::TranscodeError;
+use crate::{
+    CodestreamError, CodingStyleParameters, ProgressionOrder, MARKER_SYMBOL_EPH, MARKER_SYMBOL_SOP,
+};
+
+// Table A.19 – Code-block style for the SPcod and SPcoc parameters
+const CODE_BLOCK_STYLE_BYPASS: u8 = 0b0000_0001;
+const CODE_BLOCK_STYLE_TERMINATION: u8 = 0b0000_0100;
+const CODE_BLOCK_STYLE_HIGH_THROUGHPUT: u8 = 0b0100_0000;
+
+// ⌈a / b⌉ for b > 0
+fn ceil_div(a: i64, b: i64) -> i64 {
+    -((-a).div_euclid(b))
+}
+
+// ⌊a / b⌋ for b > 0
+fn floor_div(a: i64, b: i64) -> i64 {
+    a.div_euclid(b)
+}
+
+// B.10.1 - Bit-stuffing routine
+//
+// If the value of a byte is 0xFF, the next byte includes an extra zero bit
+// stuffed into the MSB.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u8,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            position,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> Result<u32, String> {
+        if self.count == 0 {
+            let byte = match self.data.get(self.position) {
+                Some(byte) => *byte,
+                None => return Err("packet header exceeds the tile data".to_string()),
+            };
+            self.count = if self.buffer == 0xFF { 7 } else { 8 };
+            self.buffer = byte;
+            self.position += 1;
+        }
+        self.count -= 1;
+        Ok(((self.buffer >> self.count) & 1) as u32)
+    }
+
+    fn read_bits(&mut self, n: u32) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    // The packet header is packed to a byte boundary, if the last byte is
+    // 0xFF a single zero byte follows it.
+    fn finish(&mut self) -> Result<usize, String> {
+        if self.buffer == 0xFF {
+            if self.position >= self.data.len() {
+                return Err("packet header exceeds the tile data".to_string());
+            }
+            self.position += 1;
+        }
+        Ok(self.position)
+    }
+}
+
+// B.10.2 - Tag trees
+struct TagTree {
+    // Width of each level, starting with the leaves
+    widths: Vec<usize>,
+
+    // Offset of the nodes of each level
+    offsets: Vec<usize>,
+
+    values: Vec<u32>,
+    lows: Vec<u32>,
+}
+
+impl TagTree {
+    fn new(width: usize, height: usize) -> TagTree {
+        let mut widths = vec![];
+        let mut offsets = vec![];
+        let mut size = 0;
+        let (mut w, mut h) = (width, height);
+        loop {
+            widths.push(w);
+            offsets.push(size);
+            size += w * h;
+            if w <= 1 && h <= 1 {
+                break;
+            }
+            w = w.div_ceil(2);
+            h = h.div_ceil(2);
+        }
+        TagTree {
+            widths,
+            offsets,
+            values: vec![u32::MAX; size],
+            lows: vec![0; size],
+        }
+    }
+
+    // Decodes whether the value of the leaf at (x, y) is less than the
+    // threshold, the bits are only read once across calls.
+    fn decode(
+        &mut self,
+        reader: &mut BitReader,
+        x: usize,
+        y: usize,
+        threshold: u32,
+    ) -> Result<bool, String> {
+        let mut low = 0;
+        let mut index = 0;
+        for level in (0..self.widths.len()).rev() {
+            index = self.offsets[level] + (y >> level) * self.widths[level] + (x >> level);
+            if low > self.lows[index] {
+                self.lows[index] = low;
+            } else {
+                low = self.lows[index];
+            }
+            while low < threshold && low < self.values[index] {
+                if reader.read_bit()? == 1 {
+                    self.values[index] = low;
+                } else {
+                    low += 1;
+                }
+            }
+            self.lows[index] = low;
+        }
+        Ok(self.values[index] < threshold)
+    }
+
+    fn value(&self, x: usize, y: usize) -> u32 {
+        self.values[y * self.widths[0] + x]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ComponentCoding {
+    // Number of decomposition levels, N_L
+    pub(crate) decomposition_levels: u8,
+
+    // Code-block width and height exponents, xcb and ycb
+    code_block_width: u8,
+    code_block_height: u8,
+
+    code_block_style: u8,
+
+    // Precinct width and height exponents, (PPx, PPy), of each resolution
+    // level starting with the N_L LL sub-band
+    precinct_sizes: Vec<(u8, u8)>,
+}
+
+impl ComponentCoding {
+    pub(crate) fn new(parameters: &CodingStyleParameters) -> ComponentCoding {
+        let decomposition_levels = parameters.no_decomposition_levels();
+        let precinct_sizes = if parameters.has_defined_precinct_size() {
+            parameters
+                .precinct_size
+                .iter()
+                .map(|value| (value & 0x0F, value >> 4))
+                .collect()
+        } else {
+            vec![(15, 15); decomposition_levels as usize + 1]
+        };
+        ComponentCoding {
+            decomposition_levels,
+            code_block_width: (parameters.code_block_width[0] & 0x0F) + 2,
+            code_block_height: (parameters.code_block_height[0] & 0x0F) + 2,
+            code_block_style: parameters.code_block_style[0],
+            precinct_sizes,
+        }
+    }
+
+    // HT code-blocks, ISO/IEC 15444-15, signal their codeword segments
+    // differently.
+    pub(crate) fn is_high_throughput(&self) -> bool {
+        self.code_block_style & CODE_BLOCK_STYLE_HIGH_THROUGHPUT != 0
+    }
+
+    // Coding of the component once the highest resolution levels are
+    // discarded.
+    pub(crate) fn reduce(&self, discard: u8) -> ComponentCoding {
+        let decomposition_levels = self.decomposition_levels - discard;
+        let mut coding = self.clone();
+        coding.decomposition_levels = decomposition_levels;
+        coding
+            .precinct_sizes
+            .truncate(decomposition_levels as usize + 1);
+        coding
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct PacketIndex {
+    pub(crate) layer: u16,
+    pub(crate) resolution: u8,
+    pub(crate) component: u16,
+    pub(crate) precinct: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct Packet {
+    pub(crate) index: PacketIndex,
+
+    // Packet header, without the SOP marker segment and the EPH marker
+    pub(crate) header: Range<usize>,
+
+    // Code-block contributions
+    pub(crate) body: Range<usize>,
+}
+
+#[derive(Default)]
+struct CodeBlock {
+    included: bool,
+
+    // Lblock, the number of bits used to signal the lengths of the codeword
+    // segments
+    length_indicator: u32,
+
+    passes: u32,
+    zero_bit_planes: u32,
+}
+
+struct PrecinctBand {
+    // Number of code-blocks in the precinct
+    width: usize,
+    height: usize,
+
+    inclusion: TagTree,
+    zero_bit_planes: TagTree,
+    code_blocks: Vec<CodeBlock>,
+}
+
+impl PrecinctBand {
+    // B.7 - Division of the sub-bands into code-blocks
+    //
+    // The band and precinct areas are given in sub-band coordinates.
+    fn new(
+        band: (i64, i64, i64, i64),
+        precinct: (i64, i64, i64, i64),
+        code_block_width: u8,
+        code_block_height: u8,
+    ) -> PrecinctBand {
+        let x0 = cmp::max(band.0, precinct.0);
+        let y0 = cmp::max(band.1, precinct.1);
+        let x1 = cmp::min(band.2, precinct.2);
+        let y1 = cmp::min(band.3, precinct.3);
+        let (width, height) = if x1 > x0 && y1 > y0 {
+            let xcb = 1 << code_block_width;
+            let ycb = 1 << code_block_height;
+            (
+                (ceil_div(x1, xcb) - floor_div(x0, xcb)) as usize,
+                (ceil_div(y1, ycb) - floor_div(y0, ycb)) as usize,
+            )
+        } else {
+            (0, 0)
+        };
+        PrecinctBand {
+            width,
+            height,
+            inclusion: TagTree::new(width, height),
+            zero_bit_planes: TagTree::new(width, height),
+            code_blocks: (0..width * height)
+                .map(|_| CodeBlock {
+                    length_indicator: 3,
+                    ..CodeBlock::default()
+                })
+                .collect(),
+        }
+    }
+}
+
+struct Precinct {
+    // Upper left corner of the precinct on the reference grid, clipped to
+    // the tile, determines the order of position driven progressions
+    x: i64,
+    y: i64,
+
+    bands: Vec<PrecinctBand>,
+}
+
+struct TileComponent {
+    coding: ComponentCoding,
+
+    // Precincts of each resolution level in raster order
+    resolutions: Vec<Vec<Precinct>>,
+}
+
+pub(crate) struct TileLayout {
+    components: Vec<TileComponent>,
+}
+
+impl TileLayout {
+    // B.5 - B.7 - Resolution levels, precincts and code-blocks of a tile
+    //
+    // The tile area is on the reference grid and the separations are the
+    // component subsampling factors (XRsiz, YRsiz).
+    pub(crate) fn new(
+        tile: (u32, u32, u32, u32),
+        separations: &[(u8, u8)],
+        codings: Vec<ComponentCoding>,
+    ) -> TileLayout {
+        let (tx0, ty0, tx1, ty1) = (tile.0 as i64, tile.1 as i64, tile.2 as i64, tile.3 as i64);
+        let components = codings
+            .into_iter()
+            .zip(separations)
+            .map(|(coding, (xr, yr))| {
+                let (xr, yr) = (*xr as i64, *yr as i64);
+                // B-12
+                let tcx0 = ceil_div(tx0, xr);
+                let tcy0 = ceil_div(ty0, yr);
+                let tcx1 = ceil_div(tx1, xr);
+                let tcy1 = ceil_div(ty1, yr);
+                let nl = coding.decomposition_levels as i64;
+                let resolutions = (0..=nl)
+                    .map(|r| {
+                        let scale = 1 << (nl - r);
+                        // B-14
+                        let trx0 = ceil_div(tcx0, scale);
+                        let try0 = ceil_div(tcy0, scale);
+                        let trx1 = ceil_div(tcx1, scale);
+                        let try1 = ceil_div(tcy1, scale);
+                        let (ppx, ppy) = coding.precinct_sizes[r as usize];
+                        let precinct_width = 1i64 << ppx;
+                        let precinct_height = 1i64 << ppy;
+                        // B-16
+                        let (num_x, num_y) = if trx1 > trx0 && try1 > try0 {
+                            (
+                                ceil_div(trx1, precinct_width) - floor_div(trx0, precinct_width),
+                                ceil_div(try1, precinct_height) - floor_div(try0, precinct_height),
+                            )
+                        } else {
+                            (0, 0)
+                        };
+
+                        let mut precincts = vec![];
+                        for py in 0..num_y {
+                            for px in 0..num_x {
+                                let prx0 = (floor_div(trx0, precinct_width) + px) * precinct_width;
+                                let pry0 =
+                                    (floor_div(try0, precinct_height) + py) * precinct_height;
+                                let bands = if r == 0 {
+                                    vec![PrecinctBand::new(
+                                        (trx0, try0, trx1, try1),
+                                        (prx0, pry0, prx0 + precinct_width, pry0 + precinct_height),
+                                        cmp::min(coding.code_block_width, ppx),
+                                        cmp::min(coding.code_block_height, ppy),
+                                    )]
+                                } else {
+                                    // The HL, LH and HH sub-bands, nb = N_L - r + 1
+                                    let nb = nl - r + 1;
+                                    let band_scale = 1 << nb;
+                                    let offset = 1 << (nb - 1);
+                                    [(1, 0), (0, 1), (1, 1)]
+                                        .iter()
+                                        .map(|(xob, yob)| {
+                                            // B-15
+                                            let band = (
+                                                ceil_div(tcx0 - xob * offset, band_scale),
+                                                ceil_div(tcy0 - yob * offset, band_scale),
+                                                ceil_div(tcx1 - xob * offset, band_scale),
+                                                ceil_div(tcy1 - yob * offset, band_scale),
+                                            );
+                                            PrecinctBand::new(
+                                                band,
+                                                (
+                                                    prx0 / 2,
+                                                    pry0 / 2,
+                                                    (prx0 + precinct_width) / 2,
+                                                    (pry0 + precinct_height) / 2,
+                                                ),
+                                                cmp::min(
+                                                    coding.code_block_width,
+                                                    ppx.saturating_sub(1),
+                                                ),
+                                                cmp::min(
+                                                    coding.code_block_height,
+                                                    ppy.saturating_sub(1),
+                                                ),
+                                            )
+                                        })
+                                        .collect()
+                                };
+                                precincts.push(Precinct {
+                                    x: cmp::max(tx0, xr * prx0 * scale),
+                                    y: cmp::max(ty0, yr * pry0 * scale),
+                                    bands,
+                                });
+                            }
+                        }
+                        precincts
+                    })
+                    .collect();
+                TileComponent {
+                    coding,
+                    resolutions,
+                }
+            })
+            .collect();
+        TileLayout { components }
+    }
+
+    // B.12 - Progression order
+    pub(crate) fn packet_order(
+        &self,
+        progression_order: ProgressionOrder,
+        layers: u16,
+    ) -> Result<Vec<PacketIndex>, CodestreamError> {
+        let max_resolution = self
+            .components
+            .iter()
+            .map(|component| component.resolutions.len())
+            .max()
+            .unwrap_or(0);
+        let mut order = vec![];
+        let mut push = |layer: u16, resolution: usize, component: usize, precinct: usize| {
+            order.push(PacketIndex {
+                layer,
+                resolution: resolution as u8,
+                component: component as u16,
+                precinct,
+            })
+        };
+
+        match progression_order {
+            ProgressionOrder::LRLCPP => {
+                for l in 0..layers {
+                    for r in 0..max_resolution {
+                        for (c, component) in self.components.iter().enumerate() {
+                            if let Some(precincts) = component.resolutions.get(r) {
+                                for p in 0..precincts.len() {
+                                    push(l, r, c, p);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            ProgressionOrder::RLLCPP => {
+                for r in 0..max_resolution {
+                    for l in 0..layers {
+                        for (c, component) in self.components.iter().enumerate() {
+                            if let Some(precincts) = component.resolutions.get(r) {
+                                for p in 0..precincts.len() {
+                                    push(l, r, c, p);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            ProgressionOrder::RLPCLP | ProgressionOrder::PCRLLP | ProgressionOrder::CPRLLP => {
+                // The position driven progressions visit the precincts in
+                // the order of their upper left corner on the reference grid.
+                let mut precincts: Vec<(usize, usize, usize, i64, i64)> = vec![];
+                for (c, component) in self.components.iter().enumerate() {
+                    for (r, resolution) in component.resolutions.iter().enumerate() {
+                        for (p, precinct) in resolution.iter().enumerate() {
+                            precincts.push((c, r, p, precinct.y, precinct.x));
+                        }
+                    }
+                }
+                match progression_order {
+                    ProgressionOrder::RLPCLP => {
+                        precincts.sort_by_key(|(c, r, p, y, x)| (*r, *y, *x, *c, *p))
+                    }
+                    ProgressionOrder::PCRLLP => {
+                        precincts.sort_by_key(|(c, r, p, y, x)| (*y, *x, *c, *r, *p))
+                    }
+                    _ => precincts.sort_by_key(|(c, r, p, y, x)| (*c, *y, *x, *r, *p)),
+                }
+                for (c, r, p, _, _) in precincts {
+                    for l in 0..layers {
+                        push(l, r, c, p);
+                    }
+                }
+            }
+            ProgressionOrder::Reserved { value } => {
+                return Err(CodestreamError::TranscodeError {
+                    offset: 0,
+                    source: TranscodeError::ReservedProgressionOrder { value },
+                });
+            }
+        }
+        Ok(order)
+    }
+
+    // Locates the packets of a tile, the packets shall be given in the order
+    // they appear in the tile data.
+    pub(crate) fn decode_packets(
+        &mut self,
+        tile: u16,
+        data: &[u8],
+        order: &[PacketIndex],
+    ) -> Result<Vec<Packet>, CodestreamError> {
+        let error = |offset: usize, error: String| -> CodestreamError {
+            CodestreamError::PacketError {
+                tile,
+                offset: offset as u64,
+                error,
+            }
+        };
+
+        let mut packets = Vec::with_capacity(order.len());
+        let mut position = 0;
+        for index in order {
+            // A.8.1 - Start of packet (SOP), Lsop = 4
+            if data[position..].starts_with(&MARKER_SYMBOL_SOP) {
+                position += 6;
+            }
+            let header_start = position;
+            let (header_end, body_length) = self
+                .decode_packet_header(data, position, index)
+                .map_err(|e| error(header_start, e))?;
+            position = header_end;
+
+            // A.8.2 - End of packet header (EPH)
+            if data[position..].starts_with(&MARKER_SYMBOL_EPH) {
+                position += 2;
+            }
+            if position + body_length > data.len() {
+                return Err(error(
+                    position,
+                    "packet body exceeds the tile data".to_string(),
+                ));
+            }
+            packets.push(Packet {
+                index: *index,
+                header: header_start..header_end,
+                body: position..position + body_length,
+            });
+            position += body_length;
+        }
+        if position != data.len() {
+            return Err(error(
+                position,
+                format!("{} bytes follow the last packet", data.len() - position),
+            ));
+        }
+        Ok(packets)
+    }
+
+    // B.10 - Packet header information coding
+    //
+    // Returns the end of the packet header and the length of the packet body.
+    fn decode_packet_header(
+        &mut self,
+        data: &[u8],
+        position: usize,
+        index: &PacketIndex,
+    ) -> Result<(usize, usize), String> {
+        let component = self
+            .components
+            .get_mut(index.component as usize)
+            .ok_or_else(|| format!("component {} does not exist", index.component))?;
+        let code_block_style = component.coding.code_block_style;
+        let precinct = component
+            .resolutions
+            .get_mut(index.resolution as usize)
+            .and_then(|resolution| resolution.get_mut(index.precinct))
+            .ok_or_else(|| {
+                format!(
+                    "precinct {} of resolution {} does not exist",
+                    index.precinct, index.resolution
+                )
+            })?;
+
+        let mut reader = BitReader::new(data, position);
+        let mut body_length: u64 = 0;
+
+        // B.10.3 - Zero length packet
+        if reader.read_bit()? == 1 {
+            for band in precinct.bands.iter_mut() {
+                for y in 0..band.height {
+                    for x in 0..band.width {
+                        let code_block = &mut band.code_blocks[y * band.width + x];
+
+                        // B.10.4 - Code-block inclusion
+                        let included = if code_block.included {
+                            reader.read_bit()? == 1
+                        } else {
+                            band.inclusion
+                                .decode(&mut reader, x, y, index.layer as u32 + 1)?
+                        };
+                        if !included {
+                            continue;
+                        }
+
+                        // B.10.5 - Zero bit-plane information
+                        if !code_block.included {
+                            let mut threshold = 1;
+                            while !band.zero_bit_planes.decode(&mut reader, x, y, threshold)? {
+                                threshold += 1;
+                            }
+                            code_block.zero_bit_planes = band.zero_bit_planes.value(x, y);
+                            code_block.included = true;
+                        }
+
+                        // B.10.6 - Number of coding passes
+                        let passes = decode_coding_passes(&mut reader)?;
+
+                        // B.10.7.1 - Lblock
+                        while reader.read_bit()? == 1 {
+                            code_block.length_indicator += 1;
+                        }
+
+                        // B.10.7.2 - Length of the codeword segments
+                        for segment_passes in
+                            codeword_segments(code_block_style, code_block.passes, passes)
+                        {
+                            let bits =
+                                code_block.length_indicator + 31 - segment_passes.leading_zeros();
+                            if bits > 32 {
+                                return Err("codeword segment length exceeds 32 bits".to_string());
+                            }
+                            body_length += reader.read_bits(bits)?;
+                        }
+                        code_block.passes += passes;
+                    }
+                }
+            }
+        }
+
+        let end = reader.finish()?;
+        Ok((end, body_length as usize))
+    }
+}
+
+// Table B.4 - Codewords for the number of coding passes for each code-block
+fn decode_coding_passes(reader: &mut BitReader) -> Result<u32, String> {
+    if reader.read_bit()? == 0 {
+        return Ok(1);
+    }
+    if reader.read_bit()? == 0 {
+        return Ok(2);
+    }
+    let value = reader.read_bits(2)? as u32;
+    if value != 0b11 {
+        return Ok(3 + value);
+    }
+    let value = reader.read_bits(5)? as u32;
+    if value != 0b11111 {
+        return Ok(6 + value);
+    }
+    Ok(37 + reader.read_bits(7)? as u32)
+}
+
+// Table D.9 - Splits the coding passes of a code-block contribution into
+// codeword segments
+fn codeword_segments(code_block_style: u8, previous_passes: u32, passes: u32) -> Vec<u32> {
+    let mut segments = vec![];
+    let mut pass = previous_passes;
+    let mut remaining = passes;
+    while remaining > 0 {
+        let maximum = if code_block_style & CODE_BLOCK_STYLE_TERMINATION != 0 {
+            1
+        } else if code_block_style & CODE_BLOCK_STYLE_BYPASS != 0 {
+            // The first 10 passes are arithmetic coded, then the
+            // significance propagation and magnitude refinement passes of
+            // each bit-plane are raw coded together.
+            if pass < 10 {
+                10 - pass
+            } else if (pass - 10).is_multiple_of(3) {
+                2
+            } else {
+                1
+            }
+        } else {
+            u32::MAX
+        };
+        let segment = cmp::min(maximum, remaining);
+        segments.push(segment);
+        pass += segment;
+        remaining -= segment;
+    }
+    segments
+}
+
+// Maps each packet to its position in the tile data
+pub(crate) fn packet_map(packets: &[Packet]) -> HashMap<PacketIndex, usize> {
+    packets
+        .iter()
+        .enumerate()
+        .map(|(i, packet)| (packet.index, i))
+        .collect()
+}
@@ -0,0 +1,916 @@
+// Codestream transcoding
+//
+// Rewrites a codestream without decoding the code-block contributions. The
+// packets of each tile are located with the packet headers (Annex B), then
+// discarded or reordered, and the marker segments are updated to describe
+// the remaining packets.
+use std::cmp;
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::packet::{packet_map, ComponentCoding, TileLayout};
+use crate::{
+    AtOffset, CodestreamError, ContiguousCodestream, ImageAndTileSizeMarkerSegment, MarkerSymbol,
+    ProgressionOrder, MARKER_SYMBOL_COC, MARKER_SYMBOL_COD, MARKER_SYMBOL_EOC, MARKER_SYMBOL_EPH,
+    MARKER_SYMBOL_PLM, MARKER_SYMBOL_PLT, MARKER_SYMBOL_POC, MARKER_SYMBOL_PPM, MARKER_SYMBOL_PPT,
+    MARKER_SYMBOL_QCC, MARKER_SYMBOL_QCD, MARKER_SYMBOL_SIZ, MARKER_SYMBOL_SOC, MARKER_SYMBOL_SOD,
+    MARKER_SYMBOL_SOP, MARKER_SYMBOL_SOT, MARKER_SYMBOL_TLM,
+};
+
+// Table A.13 – Coding style parameter values for the Scod parameter
+const CODING_STYLE_PRECINCTS: u8 = 0b0000_0001;
+const CODING_STYLE_SOP: u8 = 0b0000_0010;
+const CODING_STYLE_EPH: u8 = 0b0000_0100;
+
+// Table A.28 - Stlm, Ttlm is 16 bits and Ptlm is 32 bits
+const TILE_PART_PARAMETER_SIZES: u8 = 0b0110_0000;
+
+// Marker segment length is limited to 16 bits
+const MAX_SEGMENT_LENGTH: usize = 65535;
+
+#[derive(Debug, Default)]
+pub struct TranscodeOptions {
+    // Number of the highest resolution levels to discard, the image size is
+    // divided by 2 for each discarded level.
+    pub discard_resolutions: u8,
+
+    // Number of quality layers to keep.
+    pub max_layers: Option<u16>,
+
+    // Indices of the tiles to keep, the tiles shall form a rectangle in the
+    // tile grid. The kept tiles are renumbered from 0.
+    pub tiles: Option<Vec<u16>>,
+
+    // Progression order of the packets.
+    pub progression_order: Option<ProgressionOrder>,
+
+    // Add (true) or strip (false) the SOP marker segments, the input
+    // codestream style is kept if unset.
+    pub start_of_packet: Option<bool>,
+
+    // Add (true) or strip (false) the EPH markers, the input codestream style
+    // is kept if unset.
+    pub end_of_packet_header: Option<bool>,
+
+    // Write a TLM marker segment in the main header.
+    pub tile_part_lengths: bool,
+
+    // Write PLT marker segments in the tile-part headers.
+    pub packet_lengths: bool,
+}
+
+/// A codestream which cannot be transcoded with the options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TranscodeError {
+    /// A tile-part of a tile precedes its first tile-part.
+    TilePartOrder {
+        tile: u16,
+        tile_part: u8,
+    },
+    /// More resolution levels to discard than decomposition levels.
+    DecompositionLevels {
+        discard_resolutions: u8,
+        decomposition_levels: u8,
+    },
+    /// A tile size not divisible by the scale of the discarded resolution
+    /// levels, the tiles would not be aligned with the reduced reference grid.
+    TileSizeNotDivisible {
+        tile_size: u32,
+        scale: u32,
+    },
+    NoTilesSelected,
+    TileNotFound {
+        tile: u32,
+    },
+    TilesNotRectangular,
+    /// A tile of the tile grid without tile-parts.
+    TileMissing {
+        tile: u32,
+    },
+    HighThroughputCodeBlocks,
+    PacketMissing {
+        layer: u16,
+        resolution: u8,
+        component: u16,
+        precinct: usize,
+    },
+    /// A tile whose tile-part would exceed the 32-bit Psot length.
+    TilePartLength {
+        tile: u16,
+    },
+    /// More than 256 TLM or PLT marker segments.
+    TooManyMarkerSegments {
+        marker: MarkerSymbol,
+    },
+    NoLayersKept,
+    ReservedProgressionOrder {
+        value: u8,
+    },
+}
+
+impl error::Error for TranscodeError {}
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TilePartOrder { tile, tile_part } => write!(
+                f,
+                "tile-part {} of tile {} precedes the first tile-part",
+                tile_part, tile
+            ),
+            Self::DecompositionLevels {
+                discard_resolutions,
+                decomposition_levels,
+            } => write!(
+                f,
+                "cannot discard {} resolution levels of {} decomposition levels",
+                discard_resolutions, decomposition_levels
+            ),
+            Self::TileSizeNotDivisible { tile_size, scale } => {
+                write!(f, "tile size {} is not divisible by {}", tile_size, scale)
+            }
+            Self::NoTilesSelected => write!(f, "no tiles selected"),
+            Self::TileNotFound { tile } => write!(f, "tile {} does not exist", tile),
+            Self::TilesNotRectangular => write!(f, "selected tiles do not form a rectangle"),
+            Self::TileMissing { tile } => write!(f, "tile {} is missing", tile),
+            Self::HighThroughputCodeBlocks => write!(f, "HT code-blocks are not supported"),
+            Self::PacketMissing {
+                layer,
+                resolution,
+                component,
+                precinct,
+            } => write!(
+                f,
+                "packet of layer {}, resolution {}, component {} and precinct {} is missing",
+                layer, resolution, component, precinct
+            ),
+            Self::TilePartLength { tile } => {
+                write!(f, "tile {} exceeds the tile-part length", tile)
+            }
+            Self::TooManyMarkerSegments { marker } => write!(
+                f,
+                "too many 0x{:0>2X?}{:0>2X?} marker segments",
+                marker[0], marker[1]
+            ),
+            Self::NoLayersKept => write!(f, "at least one layer shall be kept"),
+            Self::ReservedProgressionOrder { value } => {
+                write!(f, "reserved progression order {}", value)
+            }
+        }
+    }
+}
+
+pub(crate) struct MarkerSegment {
+    pub(crate) marker: MarkerSymbol,
+
+    // Offset of the marker in the input codestream, 0 for the output
+    pub(crate) offset: u64,
+
+    // Parameters of the marker segment, without the length
+    pub(crate) body: Vec<u8>,
+}
+
+impl MarkerSegment {
+    fn encode(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.marker);
+        output.extend_from_slice(&(self.body.len() as u16 + 2).to_be_bytes());
+        output.extend_from_slice(&self.body);
+    }
+
+    // Reader positioned on the length of the marker segment, as expected by
+    // the marker segment decoders.
+    pub(crate) fn reader(&self) -> io::Cursor<Vec<u8>> {
+        let mut encoded = vec![];
+        self.encode(&mut encoded);
+        let mut reader = io::Cursor::new(encoded);
+        reader.set_position(2);
+        reader
+    }
+
+    // Decode the marker segment with a marker segment decoder, the offsets of
+    // its errors are of the input codestream.
+    pub(crate) fn decode<T>(
+        &self,
+        decode: impl FnOnce(&mut io::Cursor<Vec<u8>>) -> Result<T, CodestreamError>,
+    ) -> Result<T, CodestreamError> {
+        let mut reader = self.reader();
+        decode(&mut reader).map_err(|error| error.relative_to(self.offset))
+    }
+}
+
+struct TileData {
+    index: u16,
+
+    // Offset of the SOT marker of the first tile-part
+    offset: u64,
+
+    // Marker segments of the first tile-part header
+    header: Vec<MarkerSegment>,
+
+    // Concatenated data of all tile-parts
+    data: Vec<u8>,
+}
+
+fn transcode_error(offset: u64, source: TranscodeError) -> CodestreamError {
+    CodestreamError::TranscodeError { offset, source }
+}
+
+pub(crate) fn decode_segment(
+    data: &[u8],
+    position: &mut usize,
+) -> Result<MarkerSegment, CodestreamError> {
+    let offset = *position;
+    if offset + 4 > data.len() || data[offset] != 0xFF {
+        return Err(CodestreamError::MarkerUnexpected {
+            marker: [
+                *data.get(offset).unwrap_or(&0),
+                *data.get(offset + 1).unwrap_or(&0),
+            ],
+            offset: offset as u64,
+        });
+    }
+    let marker = [data[offset], data[offset + 1]];
+    let length = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+    if length < 2 || offset + 2 + length > data.len() {
+        return Err(CodestreamError::MarkerError {
+            marker,
+            offset: offset as u64,
+            error: format!("invalid length {}", length),
+        });
+    }
+    *position = offset + 2 + length;
+    Ok(MarkerSegment {
+        marker,
+        offset: offset as u64,
+        body: data[offset + 4..offset + 2 + length].to_vec(),
+    })
+}
+
+// Splits the codestream into the main header and the tiles.
+fn decode_segments(data: &[u8]) -> Result<(Vec<MarkerSegment>, Vec<TileData>), CodestreamError> {
+    if !data.starts_with(&MARKER_SYMBOL_SOC) {
+        return Err(CodestreamError::MarkerMissing {
+            marker: MARKER_SYMBOL_SOC,
+            offset: 0,
+        });
+    }
+    let mut position = 2;
+
+    let mut main_header = vec![];
+    while !data[position..].starts_with(&MARKER_SYMBOL_SOT) {
+        let segment = decode_segment(data, &mut position)?;
+        if segment.marker == MARKER_SYMBOL_PPM || segment.marker == MARKER_SYMBOL_POC {
+            return Err(CodestreamError::Unsupported {
+                marker: segment.marker,
+                offset: segment.offset,
+            });
+        }
+        main_header.push(segment);
+    }
+
+    let mut tiles: Vec<TileData> = vec![];
+    while data[position..].starts_with(&MARKER_SYMBOL_SOT) {
+        let start = position;
+        let sot = decode_segment(data, &mut position)?;
+        if sot.body.len() != 8 {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SOT,
+                offset: sot.offset,
+                error: format!("invalid length {}", sot.body.len() + 2),
+            });
+        }
+        let index = u16::from_be_bytes([sot.body[0], sot.body[1]]);
+        let length =
+            u32::from_be_bytes([sot.body[2], sot.body[3], sot.body[4], sot.body[5]]) as usize;
+        let tile_part_index = sot.body[6];
+
+        let mut header = vec![];
+        while !data[position..].starts_with(&MARKER_SYMBOL_SOD) {
+            let segment = decode_segment(data, &mut position)?;
+            if segment.marker == MARKER_SYMBOL_PPT || segment.marker == MARKER_SYMBOL_POC {
+                return Err(CodestreamError::Unsupported {
+                    marker: segment.marker,
+                    offset: segment.offset,
+                });
+            }
+            header.push(segment);
+        }
+        position += 2;
+
+        // If Psot is 0, the tile-part contains all data until the EOC marker.
+        let end = if length == 0 {
+            if data.ends_with(&MARKER_SYMBOL_EOC) {
+                data.len() - 2
+            } else {
+                data.len()
+            }
+        } else {
+            start + length
+        };
+        if end < position || end > data.len() {
+            return Err(CodestreamError::MarkerError {
+                marker: MARKER_SYMBOL_SOT,
+                offset: sot.offset,
+                error: format!("invalid tile-part length {}", length),
+            });
+        }
+
+        match tiles.iter_mut().find(|tile| tile.index == index) {
+            Some(tile) => tile.data.extend_from_slice(&data[position..end]),
+            None if tile_part_index == 0 => tiles.push(TileData {
+                index,
+                offset: sot.offset,
+                header,
+                data: data[position..end].to_vec(),
+            }),
+            None => {
+                return Err(transcode_error(
+                    sot.offset,
+                    TranscodeError::TilePartOrder {
+                        tile: index,
+                        tile_part: tile_part_index,
+                    },
+                ))
+            }
+        }
+        position = end;
+    }
+
+    if !data[position..].starts_with(&MARKER_SYMBOL_EOC) {
+        return Err(CodestreamError::MarkerMissing {
+            marker: MARKER_SYMBOL_EOC,
+            offset: position as u64,
+        });
+    }
+    Ok((main_header, tiles))
+}
+
+// Coding style, progression order, number of layers and the coding of each
+// component of a tile.
+type TileCoding = (u8, ProgressionOrder, u16, Vec<ComponentCoding>);
+
+// A.6.1 - A.6.2 - Coding style of a tile and of its components
+//
+// The precedence is: tile-part COC > tile-part COD > main COC > main COD.
+fn tile_coding(
+    codestream: &mut ContiguousCodestream,
+    main_header: &[MarkerSegment],
+    tile: &TileData,
+    no_components: u16,
+) -> Result<TileCoding, CodestreamError> {
+    let mut coding = None;
+    let mut codings: Vec<Option<ComponentCoding>> = vec![None; no_components as usize];
+    for header in [main_header, &tile.header].iter() {
+        if let Some(segment) = header.iter().find(|s| s.marker == MARKER_SYMBOL_COD) {
+            let cod = segment.decode(|reader| codestream.decode_cod(reader))?;
+            let component_coding = ComponentCoding::new(cod.coding_style_parameters());
+            codings = vec![Some(component_coding); no_components as usize];
+            coding = Some((cod.coding_style(), cod.progression_order(), cod.no_layers()));
+        }
+        for segment in header.iter().filter(|s| s.marker == MARKER_SYMBOL_COC) {
+            let coc = segment.decode(|reader| codestream.decode_coc(reader, no_components))?;
+            let component = coc.component_index() as usize;
+            if component >= codings.len() {
+                return Err(CodestreamError::MarkerError {
+                    marker: MARKER_SYMBOL_COC,
+                    offset: segment.offset,
+                    error: format!("component {} does not exist", component),
+                });
+            }
+            codings[component] = Some(ComponentCoding::new(&coc.coding_style_parameters));
+        }
+    }
+
+    let (coding_style, progression_order, no_layers) =
+        coding.ok_or(CodestreamError::MarkerMissing {
+            marker: MARKER_SYMBOL_COD,
+            offset: tile.offset,
+        })?;
+    Ok((
+        coding_style,
+        progression_order,
+        no_layers,
+        codings.into_iter().flatten().collect(),
+    ))
+}
+
+// Number of bytes of the Ccoc and Cqcc parameters
+pub(crate) fn component_index_size(no_components: u16) -> usize {
+    if no_components < 257 {
+        1
+    } else {
+        2
+    }
+}
+
+// Updates the marker segments describing the coding of the tile-components,
+// returns None for the marker segments that are dropped.
+fn transcode_segment(
+    segment: &MarkerSegment,
+    options: &TranscodeOptions,
+    no_components: u16,
+) -> Result<Option<MarkerSegment>, CodestreamError> {
+    let malformed = || -> CodestreamError {
+        CodestreamError::MarkerError {
+            marker: segment.marker,
+            offset: segment.offset,
+            error: format!("invalid length {}", segment.body.len() + 2),
+        }
+    };
+    let discard = options.discard_resolutions;
+    let mut body = segment.body.clone();
+
+    match segment.marker {
+        MARKER_SYMBOL_TLM | MARKER_SYMBOL_PLM | MARKER_SYMBOL_PLT => return Ok(None),
+        MARKER_SYMBOL_COD | MARKER_SYMBOL_COC => {
+            // Offsets of the coding style and of the number of decomposition
+            // levels
+            let (style_offset, levels_offset) = if segment.marker == MARKER_SYMBOL_COD {
+                if body.len() < 10 {
+                    return Err(malformed());
+                }
+                if let Some(sop) = options.start_of_packet {
+                    body[0] =
+                        (body[0] & !CODING_STYLE_SOP) | if sop { CODING_STYLE_SOP } else { 0 };
+                }
+                if let Some(eph) = options.end_of_packet_header {
+                    body[0] =
+                        (body[0] & !CODING_STYLE_EPH) | if eph { CODING_STYLE_EPH } else { 0 };
+                }
+                if let Some(progression_order) = options.progression_order {
+                    body[1] = progression_order.value();
+                }
+                if let Some(max_layers) = options.max_layers {
+                    let layers = cmp::min(u16::from_be_bytes([body[2], body[3]]), max_layers);
+                    body[2..4].copy_from_slice(&layers.to_be_bytes());
+                }
+                (0, 5)
+            } else {
+                let offset = component_index_size(no_components);
+                if body.len() < offset + 6 {
+                    return Err(malformed());
+                }
+                (offset, offset + 1)
+            };
+
+            let coding_style = body[style_offset];
+            let levels = body[levels_offset];
+            if levels < discard {
+                return Err(transcode_error(
+                    segment.offset,
+                    TranscodeError::DecompositionLevels {
+                        discard_resolutions: discard,
+                        decomposition_levels: levels,
+                    },
+                ));
+            }
+            body[levels_offset] = levels - discard;
+            if coding_style & CODING_STYLE_PRECINCTS != 0 {
+                // One precinct size for each remaining resolution level
+                body.truncate(levels_offset + 5 + (levels - discard) as usize + 1);
+            }
+        }
+        MARKER_SYMBOL_QCD | MARKER_SYMBOL_QCC => {
+            let style_offset = if segment.marker == MARKER_SYMBOL_QCD {
+                0
+            } else {
+                component_index_size(no_components)
+            };
+            let style = *body.get(style_offset).ok_or_else(malformed)? & 0b0001_1111;
+            // The values of the derived quantization are independent of the
+            // number of decomposition levels, otherwise there is one value
+            // for the N_L LL sub-band and three for each resolution level.
+            if style != 1 {
+                let size = if style == 0 { 1 } else { 2 };
+                let values = (body.len() - style_offset - 1) / size;
+                if values < 3 * discard as usize + 1 {
+                    return Err(malformed());
+                }
+                body.truncate(style_offset + 1 + (values - 3 * discard as usize) * size);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(Some(MarkerSegment {
+        marker: segment.marker,
+        offset: segment.offset,
+        body,
+    }))
+}
+
+// B.3 - Image area of the kept tiles and of the remaining resolution levels
+fn transcode_siz(
+    siz: &ImageAndTileSizeMarkerSegment,
+    tiles: (u32, u32, u32, u32),
+    discard: u8,
+) -> Result<ImageAndTileSizeMarkerSegment, CodestreamError> {
+    let (p0, q0, p1, q1) = tiles;
+    let scale = 1u64 << discard;
+    let ceil = |value: u64| value.div_ceil(scale) as u32;
+
+    let axis =
+        |image_offset: u32, size: u32, tile_offset: u32, tile_size: u32, t0: u32, t1: u32| {
+            let (image_offset, size, tile_offset, tile_size) = (
+                image_offset as u64,
+                size as u64,
+                tile_offset as u64,
+                tile_size as u64,
+            );
+            let tile_offset_output = tile_offset + t0 as u64 * tile_size;
+            let image_offset_output = cmp::max(tile_offset_output, image_offset);
+            let size_output = cmp::min(tile_offset + t1 as u64 * tile_size, size);
+            // The tiles are only aligned with the reduced reference grid if the
+            // tile size is divisible by the scale.
+            if t1 - t0 > 1 && tile_size % scale != 0 {
+                return Err(transcode_error(
+                    0,
+                    TranscodeError::TileSizeNotDivisible {
+                        tile_size: tile_size as u32,
+                        scale: scale as u32,
+                    },
+                ));
+            }
+            Ok((
+                ceil(image_offset_output),
+                ceil(size_output),
+                ceil(tile_offset_output),
+                ceil(tile_size),
+            ))
+        };
+
+    let (xo, x, xto, xt) = axis(
+        siz.image_horizontal_offset(),
+        siz.reference_grid_width(),
+        siz.tile_horizontal_offset(),
+        siz.reference_tile_width(),
+        p0,
+        p1,
+    )?;
+    let (yo, y, yto, yt) = axis(
+        siz.image_vertical_offset(),
+        siz.reference_grid_height(),
+        siz.tile_vertical_offset(),
+        siz.reference_tile_height(),
+        q0,
+        q1,
+    )?;
+
+    Ok(ImageAndTileSizeMarkerSegment {
+        decoder_capabilities: siz.decoder_capabilities,
+        reference_grid_width: x.to_be_bytes(),
+        reference_grid_height: y.to_be_bytes(),
+        image_horizontal_offset: xo.to_be_bytes(),
+        image_vertical_offset: yo.to_be_bytes(),
+        reference_tile_width: xt.to_be_bytes(),
+        reference_tile_height: yt.to_be_bytes(),
+        tile_horizontal_offset: xto.to_be_bytes(),
+        tile_vertical_offset: yto.to_be_bytes(),
+        no_components: siz.no_components,
+        precision: siz.precision.clone(),
+        horizontal_separation: siz.horizontal_separation.clone(),
+        vertical_separation: siz.vertical_separation.clone(),
+        ..ImageAndTileSizeMarkerSegment::default()
+    })
+}
+
+// Rectangle (p0, q0, p1, q1) of the kept tiles in the tile grid
+fn tile_rectangle(
+    siz: &ImageAndTileSizeMarkerSegment,
+    tiles: &Option<Vec<u16>>,
+) -> Result<(u32, u32, u32, u32), CodestreamError> {
+    let (num_x, num_y) = (siz.num_x_tiles(), siz.num_y_tiles());
+    let tiles = match tiles {
+        None => return Ok((0, 0, num_x, num_y)),
+        Some(tiles) => tiles.iter().map(|t| *t as u32).collect::<HashSet<u32>>(),
+    };
+    if tiles.is_empty() {
+        return Err(transcode_error(0, TranscodeError::NoTilesSelected));
+    }
+    if let Some(t) = tiles.iter().find(|t| **t >= num_x * num_y) {
+        return Err(transcode_error(
+            0,
+            TranscodeError::TileNotFound { tile: *t },
+        ));
+    }
+    let p0 = tiles.iter().map(|t| t % num_x).min().unwrap();
+    let p1 = tiles.iter().map(|t| t % num_x).max().unwrap() + 1;
+    let q0 = tiles.iter().map(|t| t / num_x).min().unwrap();
+    let q1 = tiles.iter().map(|t| t / num_x).max().unwrap() + 1;
+    if ((p1 - p0) * (q1 - q0)) as usize != tiles.len() {
+        return Err(transcode_error(0, TranscodeError::TilesNotRectangular));
+    }
+    Ok((p0, q0, p1, q1))
+}
+
+// A.7.3 - Packet length, tile-part header (PLT)
+fn encode_packet_lengths(
+    tile: &TileData,
+    lengths: &[usize],
+) -> Result<Vec<MarkerSegment>, CodestreamError> {
+    let mut segments: Vec<MarkerSegment> = vec![];
+    let mut body: Vec<u8> = vec![0];
+    for length in lengths {
+        // Iplt: 7 bits per byte, the MSB is set on all but the last byte
+        let mut encoded = vec![(*length & 0x7F) as u8];
+        let mut value = *length >> 7;
+        while value > 0 {
+            encoded.insert(0, (value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+        // Every marker segment ends with a complete packet length
+        if body.len() + encoded.len() + 2 > MAX_SEGMENT_LENGTH {
+            segments.push(MarkerSegment {
+                marker: MARKER_SYMBOL_PLT,
+                offset: 0,
+                body,
+            });
+            body = vec![segments.len() as u8];
+        }
+        body.extend(encoded);
+    }
+    if body.len() > 1 {
+        segments.push(MarkerSegment {
+            marker: MARKER_SYMBOL_PLT,
+            offset: 0,
+            body,
+        });
+    }
+    if segments.len() > 256 {
+        return Err(transcode_error(
+            tile.offset,
+            TranscodeError::TooManyMarkerSegments {
+                marker: MARKER_SYMBOL_PLT,
+            },
+        ));
+    }
+    Ok(segments)
+}
+
+// A.7.1 - Tile-part lengths (TLM), one tile-part for each tile
+fn encode_tile_part_lengths(
+    tile_parts: &[(u16, u32)],
+) -> Result<Vec<MarkerSegment>, CodestreamError> {
+    let maximum = (MAX_SEGMENT_LENGTH - 4) / 6;
+    let segments: Vec<MarkerSegment> = tile_parts
+        .chunks(maximum)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut body = vec![i as u8, TILE_PART_PARAMETER_SIZES];
+            for (index, length) in chunk {
+                body.extend_from_slice(&index.to_be_bytes());
+                body.extend_from_slice(&length.to_be_bytes());
+            }
+            MarkerSegment {
+                marker: MARKER_SYMBOL_TLM,
+                offset: 0,
+                body,
+            }
+        })
+        .collect();
+    if segments.len() > 256 {
+        return Err(transcode_error(
+            0,
+            TranscodeError::TooManyMarkerSegments {
+                marker: MARKER_SYMBOL_TLM,
+            },
+        ));
+    }
+    Ok(segments)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transcode_tile(
+    codestream: &mut ContiguousCodestream,
+    main_header: &[MarkerSegment],
+    tile: &TileData,
+    siz: &ImageAndTileSizeMarkerSegment,
+    output_siz: &ImageAndTileSizeMarkerSegment,
+    tile_index: u32,
+    output_tile_index: u16,
+    options: &TranscodeOptions,
+) -> Result<Vec<u8>, CodestreamError> {
+    let no_components = siz.no_components();
+    let (coding_style, progression_order, no_layers, codings) =
+        tile_coding(codestream, main_header, tile, no_components)?;
+    let separations = (0..no_components as usize)
+        .map(|c| Ok((siz.horizontal_separation(c)?, siz.vertical_separation(c)?)))
+        .collect::<Result<Vec<(u8, u8)>, CodestreamError>>()?;
+    if codings.iter().any(|coding| coding.is_high_throughput()) {
+        return Err(transcode_error(
+            tile.offset,
+            TranscodeError::HighThroughputCodeBlocks,
+        ));
+    }
+    if let Some(coding) = codings
+        .iter()
+        .find(|coding| coding.decomposition_levels < options.discard_resolutions)
+    {
+        return Err(transcode_error(
+            tile.offset,
+            TranscodeError::DecompositionLevels {
+                discard_resolutions: options.discard_resolutions,
+                decomposition_levels: coding.decomposition_levels,
+            },
+        ));
+    }
+
+    let mut layout = TileLayout::new(siz.tile_area(tile_index), &separations, codings.clone());
+    let order = layout.packet_order(progression_order, no_layers)?;
+    let packets = layout.decode_packets(tile.index, &tile.data, &order)?;
+    let packet_indices = packet_map(&packets);
+
+    let output_layers = cmp::min(options.max_layers.unwrap_or(no_layers), no_layers);
+    let output_codings = codings
+        .iter()
+        .map(|coding| coding.reduce(options.discard_resolutions))
+        .collect();
+    let output_layout = TileLayout::new(
+        output_siz.tile_area(output_tile_index as u32),
+        &separations,
+        output_codings,
+    );
+    let output_order = output_layout.packet_order(
+        options.progression_order.unwrap_or(progression_order),
+        output_layers,
+    )?;
+    let start_of_packet = options
+        .start_of_packet
+        .unwrap_or(coding_style & CODING_STYLE_SOP != 0);
+    let end_of_packet_header = options
+        .end_of_packet_header
+        .unwrap_or(coding_style & CODING_STYLE_EPH != 0);
+
+    let mut data: Vec<u8> = vec![];
+    let mut packet_lengths = Vec::with_capacity(output_order.len());
+    for (n, index) in output_order.iter().enumerate() {
+        let packet = &packets[*packet_indices.get(index).ok_or_else(|| {
+            transcode_error(
+                tile.offset,
+                TranscodeError::PacketMissing {
+                    layer: index.layer,
+                    resolution: index.resolution,
+                    component: index.component,
+                    precinct: index.precinct,
+                },
+            )
+        })?];
+        let start = data.len();
+        if start_of_packet {
+            // Nsop: packet sequence number, modulo 65536
+            data.extend_from_slice(&MARKER_SYMBOL_SOP);
+            data.extend_from_slice(&4u16.to_be_bytes());
+            data.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        data.extend_from_slice(&tile.data[packet.header.clone()]);
+        if end_of_packet_header {
+            data.extend_from_slice(&MARKER_SYMBOL_EPH);
+        }
+        data.extend_from_slice(&tile.data[packet.body.clone()]);
+        packet_lengths.push(data.len() - start);
+    }
+
+    let mut header: Vec<u8> = vec![];
+    for segment in tile.header.iter() {
+        if let Some(segment) = transcode_segment(segment, options, no_components)? {
+            segment.encode(&mut header);
+        }
+    }
+    if options.packet_lengths {
+        for segment in encode_packet_lengths(tile, &packet_lengths)? {
+            segment.encode(&mut header);
+        }
+    }
+
+    // Psot, from the SOT marker to the end of the tile-part data
+    let length = 12 + header.len() + 2 + data.len();
+    if length > u32::MAX as usize {
+        return Err(transcode_error(
+            tile.offset,
+            TranscodeError::TilePartLength { tile: tile.index },
+        ));
+    }
+    let mut tile_part: Vec<u8> = Vec::with_capacity(length);
+    MarkerSegment {
+        marker: MARKER_SYMBOL_SOT,
+        offset: 0,
+        body: [
+            &output_tile_index.to_be_bytes()[..],
+            &(length as u32).to_be_bytes(),
+            &[0, 1],
+        ]
+        .concat(),
+    }
+    .encode(&mut tile_part);
+    tile_part.extend(header);
+    tile_part.extend_from_slice(&MARKER_SYMBOL_SOD);
+    tile_part.extend(data);
+    Ok(tile_part)
+}
+
+// The header, tile-parts and EOC marker of the output codestream.
+fn transcode(data: &[u8], options: &TranscodeOptions) -> Result<Vec<Vec<u8>>, CodestreamError> {
+    let (main_header, tiles) = decode_segments(data)?;
+
+    let mut codestream = ContiguousCodestream::default();
+    let siz = match main_header.first() {
+        Some(segment) if segment.marker == MARKER_SYMBOL_SIZ => {
+            segment.decode(|reader| codestream.decode_siz(reader))?
+        }
+        _ => {
+            return Err(CodestreamError::MarkerMissing {
+                marker: MARKER_SYMBOL_SIZ,
+                offset: 2,
+            })
+        }
+    };
+    if options.max_layers == Some(0) {
+        return Err(transcode_error(0, TranscodeError::NoLayersKept));
+    }
+
+    let (p0, q0, p1, q1) = tile_rectangle(&siz, &options.tiles)?;
+    let output_siz = transcode_siz(&siz, (p0, q0, p1, q1), options.discard_resolutions)?;
+
+    let mut tile_parts: Vec<Vec<u8>> = vec![];
+    for q in q0..q1 {
+        for p in p0..p1 {
+            let tile_index = q * siz.num_x_tiles() + p;
+            let tile = tiles
+                .iter()
+                .find(|tile| tile.index as u32 == tile_index)
+                .ok_or_else(|| {
+                    transcode_error(0, TranscodeError::TileMissing { tile: tile_index })
+                })?;
+            let output_tile_index = ((q - q0) * (p1 - p0) + p - p0) as u16;
+            tile_parts.push(transcode_tile(
+                &mut codestream,
+                &main_header,
+                tile,
+                &siz,
+                &output_siz,
+                tile_index,
+                output_tile_index,
+                options,
+            )?);
+        }
+    }
+
+    let mut header: Vec<u8> = vec![];
+    header.extend_from_slice(&MARKER_SYMBOL_SOC);
+    output_siz.encode(&mut header).at(0)?;
+    for segment in main_header.iter().skip(1) {
+        if let Some(segment) = transcode_segment(segment, options, siz.no_components())? {
+            segment.encode(&mut header);
+        }
+    }
+    if options.tile_part_lengths {
+        let lengths: Vec<(u16, u32)> = tile_parts
+            .iter()
+            .enumerate()
+            .map(|(i, tile_part)| (i as u16, tile_part.len() as u32))
+            .collect();
+        for segment in encode_tile_part_lengths(&lengths)? {
+            segment.encode(&mut header);
+        }
+    }
+
+    let mut output = vec![header];
+    output.extend(tile_parts);
+    output.push(MARKER_SYMBOL_EOC.to_vec());
+    Ok(output)
+}
+
+// Transcode a codestream without decoding the code-block contributions.
+//
+// Resolution levels and quality layers are discarded, tiles are cropped,
+// packets are reordered and SOP, EPH, TLM and PLT markers are added or
+// stripped. The output has a single tile-part for each tile.
+//
+// Codestreams with packed packet headers (PPM, PPT) or progression order
+// changes (POC) are not supported.
+pub fn transcode_jpc<R: io::Read + io::Seek, W: io::Write>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &TranscodeOptions,
+) -> Result<(), CodestreamError> {
+    let mut data: Vec<u8> = vec![];
+    if let Err(source) = reader.read_to_end(&mut data) {
+        return Err(CodestreamError::Io {
+            offset: data.len() as u64,
+            source,
+        });
+    }
+    let output = transcode(&data, options)?;
+
+    // The offsets of errors writing the output are of the output
+    let mut offset = 0;
+    for chunk in output {
+        writer
+            .write_all(&chunk)
+            .map_err(|source| CodestreamError::Io { offset, source })?;
+        offset += chunk.len() as u64;
+    }
+    Ok(())
+}
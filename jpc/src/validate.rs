@@ -0,0 +1,802 @@
+// Codestream validation
+//
+// Reports each requirement of Annex A checked on a codestream as a test which
+// passes or fails, with the properties of the marker segments, in the
+// structure of the reports of jpylyzer. The codestream is walked by its
+// marker segments and tile-part lengths, and the marker segments are decoded
+// no further than the tests need, so that a malformed codestream fails tests
+// of the report instead of failing the validation.
+use std::collections::HashMap;
+use std::io;
+
+use crate::transcode::{component_index_size, decode_segment, MarkerSegment};
+use crate::{
+    CodestreamError, ContiguousCodestream, ImageAndTileSizeMarkerSegment, Limits,
+    MARKER_SYMBOL_CAP, MARKER_SYMBOL_COC, MARKER_SYMBOL_COD, MARKER_SYMBOL_EOC, MARKER_SYMBOL_QCC,
+    MARKER_SYMBOL_QCD, MARKER_SYMBOL_RGN, MARKER_SYMBOL_SIZ, MARKER_SYMBOL_SOC, MARKER_SYMBOL_SOD,
+    MARKER_SYMBOL_SOT, MARKER_SYMBOL_TLM,
+};
+
+/// The tests of a box or marker segment, its properties and the reports of
+/// the boxes or marker segments it contains, named as the elements of
+/// jpylyzer reports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    name: String,
+    tests: Vec<(String, bool)>,
+    properties: Vec<(String, String)>,
+    children: Vec<ValidationReport>,
+}
+
+impl ValidationReport {
+    pub fn new(name: &str) -> ValidationReport {
+        ValidationReport {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of each test in the order run and whether it passed.
+    pub fn tests(&self) -> &[(String, bool)] {
+        &self.tests
+    }
+
+    pub fn properties(&self) -> &[(String, String)] {
+        &self.properties
+    }
+
+    pub fn children(&self) -> &[ValidationReport] {
+        &self.children
+    }
+
+    /// The first report of a contained box or marker segment of the name.
+    pub fn child(&self, name: &str) -> Option<&ValidationReport> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Whether the test of the name passed, if it was run.
+    pub fn test(&self, name: &str) -> Option<bool> {
+        self.tests
+            .iter()
+            .find(|(test, _)| test == name)
+            .map(|(_, passed)| *passed)
+    }
+
+    /// The value of the first property of the name.
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether all tests of the report and of the contained reports passed.
+    pub fn is_valid(&self) -> bool {
+        self.tests.iter().all(|(_, passed)| *passed)
+            && self.children.iter().all(ValidationReport::is_valid)
+    }
+
+    /// The paths of the failed tests, such as `siz/csizIsValid`, relative to
+    /// the report.
+    pub fn failed_tests(&self) -> Vec<String> {
+        let mut failed: Vec<String> = self
+            .tests
+            .iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(test, _)| test.clone())
+            .collect();
+        for child in &self.children {
+            for test in child.failed_tests() {
+                failed.push(format!("{}/{}", child.name, test));
+            }
+        }
+        failed
+    }
+
+    pub fn add_test(&mut self, name: &str, passed: bool) {
+        self.tests.push((name.to_owned(), passed));
+    }
+
+    pub fn add_property<T: ToString>(&mut self, name: &str, value: T) {
+        self.properties.push((name.to_owned(), value.to_string()));
+    }
+
+    pub fn add_child(&mut self, child: ValidationReport) {
+        self.children.push(child);
+    }
+
+    /// Add the tests, properties and contained reports of another report,
+    /// such as of a codestream to the report of the box containing it.
+    pub fn append(&mut self, other: ValidationReport) {
+        self.tests.extend(other.tests);
+        self.properties.extend(other.properties);
+        self.children.extend(other.children);
+    }
+
+    fn has_tests(&self) -> bool {
+        !self.tests.is_empty() || self.children.iter().any(ValidationReport::has_tests)
+    }
+
+    fn has_properties(&self) -> bool {
+        !self.properties.is_empty() || self.children.iter().any(ValidationReport::has_properties)
+    }
+
+    fn encode_tests<W: io::Write>(&self, writer: &mut W, depth: usize) -> io::Result<()> {
+        let indent = "  ".repeat(depth);
+        for (test, passed) in &self.tests {
+            writeln!(
+                writer,
+                "{}<{}>{}</{}>",
+                indent,
+                test,
+                python_bool(*passed),
+                test
+            )?;
+        }
+        for child in self.children.iter().filter(|child| child.has_tests()) {
+            writeln!(writer, "{}<{}>", indent, child.name)?;
+            child.encode_tests(writer, depth + 1)?;
+            writeln!(writer, "{}</{}>", indent, child.name)?;
+        }
+        Ok(())
+    }
+
+    fn encode_properties<W: io::Write>(&self, writer: &mut W, depth: usize) -> io::Result<()> {
+        let indent = "  ".repeat(depth);
+        for (property, value) in &self.properties {
+            writeln!(
+                writer,
+                "{}<{}>{}</{}>",
+                indent,
+                property,
+                escape(value),
+                property
+            )?;
+        }
+        for child in self.children.iter().filter(|child| child.has_properties()) {
+            writeln!(writer, "{}<{}>", indent, child.name)?;
+            child.encode_properties(writer, depth + 1)?;
+            writeln!(writer, "{}</{}>", indent, child.name)?;
+        }
+        Ok(())
+    }
+}
+
+// Booleans are written as by jpylyzer.
+fn python_bool(value: bool) -> &'static str {
+    if value {
+        "True"
+    } else {
+        "False"
+    }
+}
+
+// Flags are written as by jpylyzer.
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+// Text of an element, with the characters not allowed in XML replaced.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' | '\n' | '\r' => escaped.push(character),
+            _ if character.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Write the report of a file as a jpylyzer XML document, with the
+/// properties and the results of all tests, as `jpylyzer --verbose`. The
+/// name of the report is the format of the file, such as `jp2` or `j2c`.
+pub fn encode_validation_xml<W: io::Write>(
+    writer: &mut W,
+    report: &ValidationReport,
+    file_name: &str,
+) -> Result<(), io::Error> {
+    writeln!(writer, "<?xml version='1.0' encoding='UTF-8'?>")?;
+    writeln!(
+        writer,
+        "<jpylyzer xmlns=\"http://openpreservation.org/ns/jpylyzer/v2/\">"
+    )?;
+    writeln!(writer, "  <toolInfo>")?;
+    writeln!(writer, "    <toolName>jpeg2000</toolName>")?;
+    writeln!(
+        writer,
+        "    <toolVersion>{}</toolVersion>",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "  </toolInfo>")?;
+    writeln!(writer, "  <file>")?;
+    writeln!(writer, "    <fileInfo>")?;
+    writeln!(writer, "      <fileName>{}</fileName>", escape(file_name))?;
+    writeln!(writer, "    </fileInfo>")?;
+    writeln!(writer, "    <statusInfo>")?;
+    writeln!(writer, "      <success>True</success>")?;
+    writeln!(writer, "    </statusInfo>")?;
+    writeln!(
+        writer,
+        "    <isValid format=\"{}\">{}</isValid>",
+        report.name,
+        python_bool(report.is_valid())
+    )?;
+    writeln!(writer, "    <tests>")?;
+    report.encode_tests(writer, 3)?;
+    writeln!(writer, "    </tests>")?;
+    writeln!(writer, "    <properties>")?;
+    report.encode_properties(writer, 3)?;
+    writeln!(writer, "    </properties>")?;
+    writeln!(writer, "  </file>")?;
+    writeln!(writer, "</jpylyzer>")?;
+    Ok(())
+}
+
+/// Validate a codestream, read to its end, into a report named `j2c`. Only
+/// errors reading the codestream are returned, a malformed codestream fails
+/// tests of the report.
+pub fn validate_jpc<R: io::Read>(reader: &mut R) -> Result<ValidationReport, CodestreamError> {
+    let mut data: Vec<u8> = vec![];
+    if let Err(source) = reader.read_to_end(&mut data) {
+        return Err(CodestreamError::Io {
+            offset: data.len() as u64,
+            source,
+        });
+    }
+    Ok(validate_codestream(&data))
+}
+
+// A tile-part found in the codestream.
+struct TilePart {
+    tile_index: u16,
+    no_tile_parts: u8,
+    // Length from the SOT marker to the end of the tile-part data
+    length: usize,
+}
+
+fn validate_codestream(data: &[u8]) -> ValidationReport {
+    let mut report = ValidationReport::new("j2c");
+    report.add_test(
+        "codestreamStartsWithSOCMarker",
+        data.starts_with(&MARKER_SYMBOL_SOC),
+    );
+
+    // A.3 - The main header, up to the first tile-part
+    let mut position = 2;
+    let mut main_header: Vec<MarkerSegment> = vec![];
+    while position < data.len()
+        && !data[position..].starts_with(&MARKER_SYMBOL_SOT)
+        && !data[position..].starts_with(&MARKER_SYMBOL_EOC)
+    {
+        match decode_segment(data, &mut position) {
+            Ok(segment) => main_header.push(segment),
+            Err(_) => break,
+        }
+    }
+
+    // SIZ (Required as the second marker segment)
+    let siz = match main_header.first() {
+        Some(segment) if segment.marker == MARKER_SYMBOL_SIZ => {
+            report.add_test("foundSIZMarker", true);
+            let (siz_report, siz) = validate_siz(segment);
+            report.add_child(siz_report);
+            siz
+        }
+        _ => {
+            report.add_test("foundSIZMarker", false);
+            None
+        }
+    };
+    let count = |marker| {
+        main_header
+            .iter()
+            .filter(|segment| segment.marker == marker)
+            .count()
+    };
+    report.add_test("foundCODMarker", count(MARKER_SYMBOL_COD) > 0);
+    report.add_test("foundQCDMarker", count(MARKER_SYMBOL_QCD) > 0);
+    report.add_test("noMoreThanOneCODMarker", count(MARKER_SYMBOL_COD) <= 1);
+    report.add_test("noMoreThanOneQCDMarker", count(MARKER_SYMBOL_QCD) <= 1);
+    if let Some(siz) = &siz {
+        // Rsiz bit 14 signals extended capabilities, A.5.2
+        if siz.decoder_capabilities() & 0x4000 != 0 {
+            report.add_test("foundCAPMarker", count(MARKER_SYMBOL_CAP) > 0);
+        }
+
+        // A.6.2, A.6.3 and A.6.5 - At most one per component
+        let no_components = siz.no_components();
+        for (test, marker) in [
+            ("noMoreThanOneCOCMarkerPerComponent", MARKER_SYMBOL_COC),
+            ("noMoreThanOneQCCMarkerPerComponent", MARKER_SYMBOL_QCC),
+            ("noMoreThanOneRGNMarkerPerComponent", MARKER_SYMBOL_RGN),
+        ] {
+            let mut components: HashMap<Option<u16>, usize> = HashMap::new();
+            for segment in main_header
+                .iter()
+                .filter(|segment| segment.marker == marker)
+            {
+                *components
+                    .entry(component_index(segment, no_components))
+                    .or_default() += 1;
+            }
+            report.add_test(
+                test,
+                components
+                    .iter()
+                    .all(|(component, count)| *count == 1 && component.is_some()),
+            );
+        }
+    }
+
+    let mut levels = None;
+    if let Some(segment) = main_header
+        .iter()
+        .find(|segment| segment.marker == MARKER_SYMBOL_COD)
+    {
+        let (cod_report, cod_levels) = validate_cod(segment);
+        report.add_child(cod_report);
+        levels = cod_levels;
+    }
+    if let Some(segment) = main_header
+        .iter()
+        .find(|segment| segment.marker == MARKER_SYMBOL_QCD)
+    {
+        let (qcd_report, qcd_levels) = validate_qcd(segment);
+        report.add_child(qcd_report);
+        if let (Some(levels), Some(qcd_levels)) = (levels, qcd_levels) {
+            report.add_test("quantizationConsistentWithLevels", qcd_levels == levels);
+        }
+    }
+
+    let number_of_tiles = siz.as_ref().and_then(number_of_tiles);
+    if let Some(number_of_tiles) = number_of_tiles {
+        report.add_property("numberOfTiles", number_of_tiles);
+    }
+
+    // A.4 - The tile-parts, each ending where the next starts
+    let mut tile_parts: Vec<TilePart> = vec![];
+    let mut tile_parts_report = ValidationReport::new("tileParts");
+    while data[position.min(data.len())..].starts_with(&MARKER_SYMBOL_SOT) {
+        let start = position;
+        let mut tile_part_report = ValidationReport::new("tilePart");
+        let mut sot_report = ValidationReport::new("sot");
+        let sot = match decode_segment(data, &mut position) {
+            Ok(segment) if segment.body.len() == 8 => segment,
+            _ => {
+                sot_report.add_test("lsotIsValid", false);
+                tile_part_report.add_child(sot_report);
+                tile_parts_report.add_child(tile_part_report);
+                break;
+            }
+        };
+        let tile_index = u16::from_be_bytes([sot.body[0], sot.body[1]]);
+        let length =
+            u32::from_be_bytes([sot.body[2], sot.body[3], sot.body[4], sot.body[5]]) as usize;
+        let tile_part_index = sot.body[6];
+        let no_tile_parts = sot.body[7];
+        sot_report.add_property("lsot", 10);
+        sot_report.add_property("isot", tile_index);
+        sot_report.add_property("psot", length);
+        sot_report.add_property("tpsot", tile_part_index);
+        sot_report.add_property("tnsot", no_tile_parts);
+
+        // If Psot is 0, the tile-part contains all data until the EOC marker.
+        let end = if length == 0 {
+            if data.ends_with(&MARKER_SYMBOL_EOC) {
+                data.len() - 2
+            } else {
+                data.len()
+            }
+        } else {
+            start.saturating_add(length)
+        };
+        let previous_tile_parts = tile_parts
+            .iter()
+            .filter(|tile_part| tile_part.tile_index == tile_index)
+            .count();
+        sot_report.add_test("lsotIsValid", true);
+        sot_report.add_test(
+            "isotIsValid",
+            number_of_tiles.is_some_and(|number_of_tiles| (tile_index as u64) < number_of_tiles),
+        );
+        sot_report.add_test(
+            "psotIsValid",
+            length == 0 || (length >= 14 && end <= data.len()),
+        );
+        sot_report.add_test(
+            "tpsotIsValid",
+            tile_part_index as usize == previous_tile_parts,
+        );
+        tile_part_report.add_child(sot_report);
+
+        // The tile-part header, up to the SOD marker
+        let end = end.min(data.len());
+        while position < end && !data[position..].starts_with(&MARKER_SYMBOL_SOD) {
+            if decode_segment(data, &mut position).is_err() {
+                break;
+            }
+        }
+        tile_part_report.add_test(
+            "foundSODMarker",
+            position + 2 <= end && data[position..].starts_with(&MARKER_SYMBOL_SOD),
+        );
+        tile_part_report.add_test(
+            "foundNextTilePartOrEOC",
+            data[end..].starts_with(&MARKER_SYMBOL_SOT)
+                || data[end..].starts_with(&MARKER_SYMBOL_EOC),
+        );
+        tile_parts_report.add_child(tile_part_report);
+
+        tile_parts.push(TilePart {
+            tile_index,
+            no_tile_parts,
+            length: end - start,
+        });
+        position = end;
+    }
+    report.add_child(tile_parts_report);
+
+    let mut tile_indices: Vec<u16> = tile_parts
+        .iter()
+        .map(|tile_part| tile_part.tile_index)
+        .collect();
+    tile_indices.sort_unstable();
+    tile_indices.dedup();
+    report.add_test(
+        "foundExpectedNumberOfTiles",
+        number_of_tiles == Some(tile_indices.len() as u64),
+    );
+    // TNsot is either 0 or the number of tile-parts of the tile.
+    report.add_test(
+        "foundExpectedNumberOfTileParts",
+        tile_indices.iter().all(|tile_index| {
+            let parts = tile_parts
+                .iter()
+                .filter(|tile_part| tile_part.tile_index == *tile_index);
+            let count = parts.clone().count();
+            parts.clone().all(|tile_part| {
+                tile_part.no_tile_parts == 0 || tile_part.no_tile_parts as usize == count
+            })
+        }),
+    );
+
+    // A.7.1 - The TLM marker segments give the lengths of all tile-parts
+    let tlm_segments: Vec<&MarkerSegment> = main_header
+        .iter()
+        .filter(|segment| segment.marker == MARKER_SYMBOL_TLM)
+        .collect();
+    if !tlm_segments.is_empty() {
+        let mut tile_part_lengths: Vec<(Option<u16>, u32)> = vec![];
+        for segment in tlm_segments {
+            let (tlm_report, lengths) = validate_tlm(segment);
+            report.add_child(tlm_report);
+            tile_part_lengths.extend(lengths);
+        }
+        // Without Ttlm, there is one tile-part for each tile in order.
+        report.add_test(
+            "tilePartLengthsConsistentWithTLM",
+            tile_part_lengths.len() == tile_parts.len()
+                && tile_part_lengths.iter().zip(&tile_parts).enumerate().all(
+                    |(index, ((tile_index, length), tile_part))| {
+                        tile_index.unwrap_or(index as u16) == tile_part.tile_index
+                            && *length as usize == tile_part.length
+                    },
+                ),
+        );
+    }
+
+    // EOC (Required as the last marker)
+    report.add_test(
+        "foundEOCMarker",
+        data.get(position..) == Some(&MARKER_SYMBOL_EOC[..]),
+    );
+
+    report
+}
+
+// The component of a COC, QCC or RGN marker segment.
+fn component_index(segment: &MarkerSegment, no_components: u16) -> Option<u16> {
+    let index = match component_index_size(no_components) {
+        1 => *segment.body.first()? as u16,
+        _ => u16::from_be_bytes([*segment.body.first()?, *segment.body.get(1)?]),
+    };
+    if index < no_components {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+// B.3 - numXtiles * numYtiles, unknown for an invalid tile grid.
+fn number_of_tiles(siz: &ImageAndTileSizeMarkerSegment) -> Option<u64> {
+    let tiles = |size: u32, tile_offset: u32, tile_size: u32| {
+        if tile_size == 0 || size <= tile_offset {
+            None
+        } else {
+            Some(((size - tile_offset) as u64).div_ceil(tile_size as u64))
+        }
+    };
+    let x = tiles(
+        siz.reference_grid_width(),
+        siz.tile_horizontal_offset(),
+        siz.reference_tile_width(),
+    )?;
+    let y = tiles(
+        siz.reference_grid_height(),
+        siz.tile_vertical_offset(),
+        siz.reference_tile_height(),
+    )?;
+    Some(x * y)
+}
+
+// A.5.1 - Image and tile size (SIZ)
+fn validate_siz(
+    segment: &MarkerSegment,
+) -> (ValidationReport, Option<ImageAndTileSizeMarkerSegment>) {
+    let mut report = ValidationReport::new("siz");
+    let mut codestream = ContiguousCodestream {
+        limits: Limits::unlimited(),
+        ..Default::default()
+    };
+    let siz = match codestream.decode_siz_parameters(&mut segment.reader()) {
+        Ok(siz) => siz,
+        Err(_) => {
+            report.add_test("lsizIsValid", false);
+            return (report, None);
+        }
+    };
+    let no_components = siz.no_components();
+    let components = 0..no_components as usize;
+
+    report.add_property("lsiz", siz.length());
+    report.add_property("rsiz", siz.decoder_capabilities());
+    report.add_property("xsiz", siz.reference_grid_width());
+    report.add_property("ysiz", siz.reference_grid_height());
+    report.add_property("xOsiz", siz.image_horizontal_offset());
+    report.add_property("yOsiz", siz.image_vertical_offset());
+    report.add_property("xTsiz", siz.reference_tile_width());
+    report.add_property("yTsiz", siz.reference_tile_height());
+    report.add_property("xTOsiz", siz.tile_horizontal_offset());
+    report.add_property("yTOsiz", siz.tile_vertical_offset());
+    report.add_property("csiz", no_components);
+    for i in components.clone() {
+        let signedness = if siz.values_are_signed(i).unwrap_or_default() {
+            "signed"
+        } else {
+            "unsigned"
+        };
+        report.add_property("ssizSign", signedness);
+        report.add_property("ssizDepth", siz.precision(i).unwrap_or_default());
+        report.add_property("xRsiz", siz.horizontal_separation(i).unwrap_or_default());
+        report.add_property("yRsiz", siz.vertical_separation(i).unwrap_or_default());
+    }
+
+    report.add_test(
+        "lsizIsValid",
+        siz.length() as usize == 38 + 3 * no_components as usize
+            && segment.body.len() + 2 == siz.length() as usize,
+    );
+    report.add_test(
+        "xsizIsValid",
+        siz.reference_grid_width() > siz.image_horizontal_offset(),
+    );
+    report.add_test(
+        "ysizIsValid",
+        siz.reference_grid_height() > siz.image_vertical_offset(),
+    );
+    // 0 ≤ XTOsiz ≤ XOsiz and XTsiz + XTOsiz > XOsiz
+    report.add_test(
+        "xTsizIsValid",
+        siz.reference_tile_width() as u64 + siz.tile_horizontal_offset() as u64
+            > siz.image_horizontal_offset() as u64,
+    );
+    report.add_test(
+        "yTsizIsValid",
+        siz.reference_tile_height() as u64 + siz.tile_vertical_offset() as u64
+            > siz.image_vertical_offset() as u64,
+    );
+    report.add_test(
+        "xTOsizIsValid",
+        siz.tile_horizontal_offset() <= siz.image_horizontal_offset(),
+    );
+    report.add_test(
+        "yTOsizIsValid",
+        siz.tile_vertical_offset() <= siz.image_vertical_offset(),
+    );
+    report.add_test("csizIsValid", (1..=16384).contains(&no_components));
+    report.add_test(
+        "ssizIsValid",
+        components
+            .clone()
+            .all(|i| siz.precision(i).is_ok_and(|precision| precision <= 38)),
+    );
+    report.add_test(
+        "xRsizIsValid",
+        components.clone().all(|i| {
+            siz.horizontal_separation(i)
+                .is_ok_and(|separation| separation > 0)
+        }),
+    );
+    report.add_test(
+        "yRsizIsValid",
+        components.clone().all(|i| {
+            siz.vertical_separation(i)
+                .is_ok_and(|separation| separation > 0)
+        }),
+    );
+
+    (report, Some(siz))
+}
+
+// A.6.1 - Coding style default (COD), with the number of decomposition
+// levels.
+fn validate_cod(segment: &MarkerSegment) -> (ValidationReport, Option<u8>) {
+    let mut report = ValidationReport::new("cod");
+    let body = &segment.body;
+    if body.len() < 10 {
+        report.add_test("lcodIsValid", false);
+        return (report, None);
+    }
+    let coding_style = body[0];
+    let progression_order = body[1];
+    let layers = u16::from_be_bytes([body[2], body[3]]);
+    let multiple_component_transformation = body[4];
+    let levels = body[5];
+    let (width_exponent, height_exponent) = (body[6], body[7]);
+    let transformation = body[9];
+    let precincts = coding_style & 0b0000_0001 != 0;
+
+    report.add_property("lcod", body.len() + 2);
+    report.add_property("precincts", yes_no(precincts));
+    report.add_property("sop", yes_no(coding_style & 0b0000_0010 != 0));
+    report.add_property("eph", yes_no(coding_style & 0b0000_0100 != 0));
+    report.add_property(
+        "order",
+        ["LRCP", "RLCP", "RPCL", "PCRL", "CPRL"]
+            .get(progression_order as usize)
+            .copied()
+            .unwrap_or("reserved"),
+    );
+    report.add_property("layers", layers);
+    report.add_property(
+        "multipleComponentTransformation",
+        multiple_component_transformation,
+    );
+    report.add_property("levels", levels);
+    report.add_property("codeBlockWidth", 1u32 << (width_exponent.min(29) + 2));
+    report.add_property("codeBlockHeight", 1u32 << (height_exponent.min(29) + 2));
+    report.add_property("codeBlockStyle", body[8]);
+    report.add_property(
+        "transformation",
+        match transformation {
+            0 => "9-7 irreversible",
+            1 => "5-3 reversible",
+            _ => "reserved",
+        },
+    );
+
+    // One precinct size for each resolution level, LL and the levels
+    let precinct_sizes = if precincts { levels as usize + 1 } else { 0 };
+    report.add_test("lcodIsValid", body.len() == 10 + precinct_sizes);
+    report.add_test("scodIsValid", coding_style & 0b1111_1000 == 0);
+    report.add_test("orderIsValid", progression_order <= 4);
+    report.add_test("layersIsValid", layers > 0);
+    report.add_test(
+        "multipleComponentTransformationIsValid",
+        multiple_component_transformation <= 1,
+    );
+    report.add_test("levelsIsValid", levels <= 32);
+    report.add_test("codeBlockWidthExponentIsValid", width_exponent <= 8);
+    report.add_test("codeBlockHeightExponentIsValid", height_exponent <= 8);
+    report.add_test(
+        "sumHeightWidthExponentIsValid",
+        width_exponent as u16 + height_exponent as u16 <= 8,
+    );
+    report.add_test("transformationIsValid", transformation <= 1);
+
+    (report, Some(levels))
+}
+
+// A.6.4 - Quantization default (QCD), with the number of decomposition
+// levels its values are for.
+fn validate_qcd(segment: &MarkerSegment) -> (ValidationReport, Option<u8>) {
+    let mut report = ValidationReport::new("qcd");
+    let body = &segment.body;
+    let quantization_style = match body.first() {
+        Some(quantization_style) => *quantization_style,
+        None => {
+            report.add_test("lqcdIsValid", false);
+            return (report, None);
+        }
+    };
+    report.add_property("lqcd", body.len() + 2);
+    report.add_property(
+        "qStyle",
+        match quantization_style & 0b0001_1111 {
+            0 => "no quantization",
+            1 => "scalar derived",
+            2 => "scalar expounded",
+            _ => "reserved",
+        },
+    );
+    report.add_property("guardBits", quantization_style >> 5);
+    report.add_test("qStyleIsValid", quantization_style & 0b0001_1111 <= 2);
+
+    // 3 subbands for each level and LL, of 1 byte without quantization and
+    // 2 bytes with scalar quantization, only LL with derived quantization.
+    let values = body.len() - 1;
+    let levels = match quantization_style & 0b0001_1111 {
+        0 if values % 3 == 1 => Some((values / 3) as u8),
+        1 if values == 2 => None,
+        2 if values % 6 == 2 => Some((values / 6) as u8),
+        _ => {
+            report.add_test("lqcdIsValid", false);
+            return (report, None);
+        }
+    };
+    report.add_test("lqcdIsValid", true);
+    (report, levels)
+}
+
+// A.7.1 - Tile-part lengths (TLM), with the tile index, if present, and the
+// length of each tile-part.
+fn validate_tlm(segment: &MarkerSegment) -> (ValidationReport, Vec<(Option<u16>, u32)>) {
+    let mut report = ValidationReport::new("tlm");
+    let body = &segment.body;
+    if body.len() < 2 {
+        report.add_test("ltlmIsValid", false);
+        return (report, vec![]);
+    }
+    let parameter_sizes = body[1];
+    // Table A.35 - ST of 0, 1 or 2 bytes for Ttlm, SP of 2 or 4 bytes for
+    // Ptlm
+    let tile_index_size = ((parameter_sizes >> 4) & 0b11) as usize;
+    let length_size = if parameter_sizes & 0b0100_0000 != 0 {
+        4
+    } else {
+        2
+    };
+    report.add_property("ltlm", body.len() + 2);
+    report.add_property("ztlm", body[0]);
+    report.add_property("st", tile_index_size);
+    report.add_property("sp", length_size);
+    report.add_test(
+        "stlmIsValid",
+        tile_index_size != 3 && parameter_sizes & 0b1000_1111 == 0,
+    );
+
+    let entry_size = tile_index_size.min(2) + length_size;
+    let entries = &body[2..];
+    report.add_test("ltlmIsValid", entries.len().is_multiple_of(entry_size));
+    let lengths = entries
+        .chunks_exact(entry_size)
+        .map(|entry| {
+            let (tile_index, length) = entry.split_at(tile_index_size.min(2));
+            let tile_index = match tile_index {
+                [index] => Some(*index as u16),
+                [high, low] => Some(u16::from_be_bytes([*high, *low])),
+                _ => None,
+            };
+            let length = match length {
+                [a, b] => u16::from_be_bytes([*a, *b]) as u32,
+                [a, b, c, d] => u32::from_be_bytes([*a, *b, *c, *d]),
+                _ => 0,
+            };
+            (tile_index, length)
+        })
+        .collect();
+    (report, lengths)
+}
@@ -0,0 +1,101 @@
+// Helpers shared by the tests, each test crate uses some of them.
+#![allow(dead_code)]
+
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+pub fn paths() -> Vec<PathBuf> {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<PathBuf> = [
+        "file1.jp2",
+        "file2.jp2",
+        "file3.jp2",
+        "file4.jp2",
+        "file5.jp2",
+        "file6.jp2",
+        "file7.jp2",
+        "file8.jp2",
+        "file9.jp2",
+        "subsampling_1.jp2",
+        "subsampling_2.jp2",
+        "zoo2.jp2",
+    ]
+    .iter()
+    .map(|filename| manifest.join("../samples").join(filename))
+    .collect();
+    paths.extend(
+        ["blue.j2k", "eph.j2k", "sop.j2k"]
+            .iter()
+            .map(|filename| manifest.join("tests").join(filename)),
+    );
+    paths.extend(
+        ["geojp2.jp2", "hazard.jp2", "j2pi.jp2"]
+            .iter()
+            .map(|filename| manifest.join("../jp2/tests").join(filename)),
+    );
+    paths
+}
+
+// Codestream of a JPEG 2000 file, either a raw codestream or the first
+// Contiguous Codestream box of a JP2 file.
+pub fn read_codestream(path: &Path) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![];
+    File::open(path)
+        .expect("file should exist")
+        .read_to_end(&mut data)
+        .unwrap();
+    if data.starts_with(&[0xFF, 0x4F]) {
+        return data;
+    }
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut length =
+            u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        if length == 0 {
+            length = data.len() - position;
+        }
+        if &data[position + 4..position + 8] == b"jp2c" {
+            return data[position + 8..position + length].to_vec();
+        }
+        position += length;
+    }
+    panic!("codestream should exist");
+}
+
+// Codestream of 4 x 2 tiles of 32 x 32 samples with empty packets, the first
+// tile is split in two tile-parts.
+pub fn tiled_codestream() -> Vec<u8> {
+    let mut codestream: Vec<u8> = vec![0xFF, 0x4F];
+    // SIZ: 100 x 60 image, 1 component
+    codestream.extend_from_slice(&[0xFF, 0x51, 0x00, 0x29, 0x00, 0x00]);
+    for value in [100u32, 60, 0, 0, 32, 32, 0, 0] {
+        codestream.extend_from_slice(&value.to_be_bytes());
+    }
+    codestream.extend_from_slice(&[0x00, 0x01, 0x07, 0x01, 0x01]);
+    // COD: LRCP, 2 layers, 2 decomposition levels, 16 x 16 code-blocks
+    codestream.extend_from_slice(&[
+        0xFF, 0x52, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x02, 0x02, 0x00, 0x01,
+    ]);
+    // QCD: no quantization, 7 sub-bands
+    codestream.extend_from_slice(&[
+        0xFF, 0x5C, 0x00, 0x0A, 0x40, 0x48, 0x50, 0x50, 0x58, 0x58, 0x58, 0x60,
+    ]);
+    for tile in 0u16..8 {
+        // 2 layers of 3 resolution levels
+        let parts: Vec<usize> = if tile == 0 { vec![2, 4] } else { vec![6] };
+        for (i, packets) in parts.iter().enumerate() {
+            codestream.extend_from_slice(&[0xFF, 0x90, 0x00, 0x0A]);
+            codestream.extend_from_slice(&tile.to_be_bytes());
+            codestream.extend_from_slice(&(14 + *packets as u32).to_be_bytes());
+            codestream.extend_from_slice(&[i as u8, parts.len() as u8, 0xFF, 0x93]);
+            codestream.extend(vec![0; *packets]);
+        }
+    }
+    codestream.extend_from_slice(&[0xFF, 0xD9]);
+    codestream
+}
@@ -0,0 +1,58 @@
+use std::{error::Error, fs, io, io::Cursor, path::Path};
+
+use jpc::{decode_jpc, CodestreamError};
+
+fn blue() -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/blue.j2k");
+    fs::read(path).expect("file should exist")
+}
+
+// Offsets of the SIZ and COD marker segments of blue.j2k
+const SIZ: usize = 0x02;
+const COD: usize = 0x33;
+
+fn decode_error(codestream: &[u8]) -> CodestreamError {
+    decode_jpc(&mut Cursor::new(codestream)).expect_err("decoding should fail")
+}
+
+#[test]
+fn test_marker_offsets() {
+    // Csiz of 0 does not match the length of the SIZ marker segment
+    let mut codestream = blue();
+    codestream[SIZ + 39] = 0;
+    let error = decode_error(&codestream);
+    assert!(matches!(
+        error,
+        CodestreamError::MarkerError {
+            marker: [0xFF, 0x51],
+            offset,
+            ..
+        } if offset == SIZ as u64
+    ));
+    assert_eq!(error.offset(), SIZ as u64);
+    assert!(error.source().is_none());
+
+    // The SIZ marker segment shall follow SOC
+    let codestream = [&blue()[..SIZ], &blue()[COD..]].concat();
+    let error = decode_error(&codestream);
+    assert!(matches!(
+        error,
+        CodestreamError::MarkerUnexpected { offset, .. } if offset == SIZ as u64
+    ));
+    assert!(error.to_string().ends_with("at byte offset 2"));
+}
+
+#[test]
+fn test_io_error() {
+    // The SIZ marker segment is truncated, the error is at its marker
+    let error = decode_error(&blue()[..20]);
+    match &error {
+        CodestreamError::Io { offset, source } => {
+            assert_eq!(*offset, SIZ as u64);
+            assert_eq!(source.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+    let source = error.source().expect("the error should have a source");
+    assert!(source.downcast_ref::<io::Error>().is_some());
+}
@@ -0,0 +1,148 @@
+use std::{fs, io::Cursor, path::Path};
+
+use jpc::{decode_jpc, decode_jpc_with_limits, CodestreamError, LimitError, Limits};
+
+fn blue() -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/blue.j2k");
+    fs::read(path).expect("file should exist")
+}
+
+// Offset of the COM marker segment ending the main header of blue.j2k
+const COM: usize = 0x56;
+
+fn limit_error(codestream: &[u8], limits: &Limits) -> LimitError {
+    let error = decode_jpc_with_limits(&mut Cursor::new(codestream), limits)
+        .expect_err("decoding should fail");
+    match error {
+        CodestreamError::Limit { source, .. } => source,
+        error => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn test_limits() {
+    let codestream = blue();
+    decode_jpc_with_limits(&mut Cursor::new(&codestream), &Limits::default())
+        .expect("decoding should succeed");
+    decode_jpc_with_limits(&mut Cursor::new(&codestream), &Limits::unlimited())
+        .expect("decoding should succeed");
+
+    assert_eq!(
+        limit_error(
+            &codestream,
+            &Limits {
+                max_components: 2,
+                ..Limits::default()
+            }
+        ),
+        LimitError::Components {
+            components: 3,
+            max_components: 2
+        }
+    );
+    assert_eq!(
+        limit_error(
+            &codestream,
+            &Limits {
+                max_image_area: 128 * 64 - 1,
+                ..Limits::default()
+            }
+        ),
+        LimitError::ImageArea {
+            area: 128 * 64,
+            max_image_area: 128 * 64 - 1
+        }
+    );
+    assert_eq!(
+        limit_error(
+            &codestream,
+            &Limits {
+                max_tiles: 0,
+                ..Limits::default()
+            }
+        ),
+        LimitError::Tiles {
+            tiles: 1,
+            max_tiles: 0
+        }
+    );
+    // The comment of 33 bytes
+    assert_eq!(
+        limit_error(
+            &codestream,
+            &Limits {
+                max_memory: 32,
+                ..Limits::default()
+            }
+        ),
+        LimitError::Memory {
+            allocated: 33,
+            max_memory: 32
+        }
+    );
+    assert_eq!(
+        LimitError::Memory {
+            allocated: 33,
+            max_memory: 32
+        }
+        .to_string(),
+        "allocating 33 bytes exceeds the limit of 32 bytes"
+    );
+}
+
+#[test]
+fn test_malformed_lengths() {
+    let codestream = blue();
+    assert_eq!(codestream[COM..COM + 2], [0xFF, 0x64]);
+
+    // Lengths shorter than the parameters of the marker segment
+    for (marker, length) in [
+        (0x64, 3),
+        (0x5F, 2),
+        (0x60, 6),
+        (0x61, 2),
+        (0x55, 3),
+        (0x5C, 2),
+        (0x5D, 3),
+    ] {
+        let mut patched = codestream[..COM].to_vec();
+        patched.extend_from_slice(&[0xFF, marker, 0, length, 0, 0, 0, 0, 0, 0, 0]);
+        patched.extend_from_slice(&codestream[COM..]);
+        assert!(
+            decode_jpc(&mut Cursor::new(&patched)).is_err(),
+            "{:X}",
+            marker
+        );
+    }
+
+    // Empty tiles
+    let mut patched = codestream.clone();
+    patched[0x1B] = 0;
+    assert!(decode_jpc(&mut Cursor::new(&patched)).is_err());
+
+    // Every byte of the main header replaced
+    for position in 2..COM + 37 {
+        for value in [0x00, 0x01, 0xFF] {
+            let mut patched = codestream.clone();
+            patched[position] = value;
+            let _ = decode_jpc(&mut Cursor::new(&patched));
+        }
+    }
+    for length in 0..COM + 37 {
+        let _ = decode_jpc(&mut Cursor::new(&codestream[..length]));
+    }
+}
+
+#[test]
+fn test_tile_part_lengths_sizes() {
+    let codestream = blue();
+    // TLM of 8-bit Ttlm and 16-bit Ptlm, and of no Ttlm and 32-bit Ptlm
+    for tlm in [
+        &[0xFF, 0x55, 0x00, 0x07, 0x00, 0x10, 0x00, 0x01, 0x00][..],
+        &[0xFF, 0x55, 0x00, 0x08, 0x00, 0x40, 0x00, 0x00, 0x01, 0x00],
+    ] {
+        let patched = [&codestream[..COM], tlm, &codestream[COM..]].concat();
+        let decoded = decode_jpc(&mut Cursor::new(&patched)).expect("decoding should succeed");
+        assert!(decoded.header().tile_part_lengths_segment().is_some());
+    }
+}
@@ -1,7 +1,12 @@
-use std::{fs::File, io::BufReader, path::Path};
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::Path,
+};
 
 use jpc::{
-    decode_jpc, CodingBlockStyle, CommentRegistrationValue, MultipleComponentTransformation,
+    decode_jpc, CodingBlockStyle, CommentRegistrationValue, CorrespondingProfileSegment,
+    DecoderCapability, ExtendedCapabilitiesSegment, MultipleComponentTransformation,
     ProgressionOrder, QuantizationStyle, TransformationFilter,
 };
 
@@ -131,3 +136,178 @@ fn test_blue() {
         "Created by OpenJPEG version 2.5.0"
     );
 }
+
+// A single component 10x7 image with 4x4 tiles, so that the last column and
+// row of tiles are partial, with precincts of 2^3 x 2^5 at the lowest
+// resolution and a TLM marker segment in the main header.
+fn partial_tiles_codestream() -> Vec<u8> {
+    let mut codestream = vec![0xff, 0x4f]; // SOC
+    codestream.extend_from_slice(&[0xff, 0x51, 0x00, 0x29, 0x00, 0x00]); // SIZ, Lsiz, Rsiz
+    for value in [10u32, 7, 0, 0, 4, 4, 0, 0] {
+        codestream.extend_from_slice(&value.to_be_bytes());
+    }
+    codestream.extend_from_slice(&[0x00, 0x01, 0x07, 0x01, 0x01]); // Csiz, Ssiz, XRsiz, YRsiz
+    codestream.extend_from_slice(&[0xff, 0x52, 0x00, 0x0e]); // COD, Lcod
+    codestream.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00]); // Scod, SGcod
+    codestream.extend_from_slice(&[0x01, 0x04, 0x04, 0x00, 0x01, 0x53, 0x64]); // SPcod
+    codestream.extend_from_slice(&[0xff, 0x55, 0x00, 0x0a, 0x00, 0x60]); // TLM, Ltlm, Ztlm, Stlm
+    codestream.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x0f]); // Ttlm, Ptlm
+    codestream.extend_from_slice(&[0xff, 0x5c, 0x00, 0x07, 0x40]); // QCD, Lqcd, Sqcd
+    codestream.extend_from_slice(&[0x40, 0x48, 0x48, 0x50]); // SPqcd
+    codestream.extend_from_slice(&[0xff, 0x90, 0x00, 0x0a]); // SOT, Lsot
+    codestream.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x01]);
+    codestream.extend_from_slice(&[0xff, 0x93, 0x00]); // SOD, empty packet
+    codestream.extend_from_slice(&[0xff, 0xd9]); // EOC
+    codestream
+}
+
+#[test]
+fn test_precinct_size_exponents() {
+    let mut reader = Cursor::new(partial_tiles_codestream());
+    let codestream = decode_jpc(&mut reader).expect("codestream should decode");
+    let cod = codestream.header().coding_style_marker_segment();
+    let precincts = cod.coding_style_parameters().precinct_sizes().unwrap();
+    assert_eq!(precincts.len(), 2);
+
+    // PPx is in the 4 LSBs and PPy is in the 4 MSBs
+    assert_eq!(precincts[0].width_exponent(), 3);
+    assert_eq!(precincts[0].height_exponent(), 5);
+    assert_eq!(precincts[1].width_exponent(), 4);
+    assert_eq!(precincts[1].height_exponent(), 6);
+}
+
+#[test]
+fn test_partial_tile_dimensions() {
+    let mut reader = Cursor::new(partial_tiles_codestream());
+    let codestream = decode_jpc(&mut reader).expect("codestream should decode");
+    let siz = codestream.header().image_and_tile_size_marker_segment();
+
+    // 3 x 2 tiles, the tiles in the last column and row are clipped to the
+    // image area at Xsiz and Ysiz
+    assert_eq!(siz.tile_dimensions(0), (4, 4));
+    assert_eq!(siz.tile_dimensions(1), (4, 4));
+    assert_eq!(siz.tile_dimensions(2), (2, 4));
+    assert_eq!(siz.tile_dimensions(3), (4, 3));
+    assert_eq!(siz.tile_dimensions(5), (2, 3));
+}
+
+#[test]
+fn test_tlm_followed_by_marker_segment() {
+    // Ztlm is read before Stlm, so the QCD after the TLM is found
+    let mut reader = Cursor::new(partial_tiles_codestream());
+    let codestream = decode_jpc(&mut reader).expect("codestream should decode");
+    let header = codestream.header();
+    assert!(header.tile_part_lengths_segment().is_some());
+    assert_eq!(header.quantization_default_marker_segment().length(), 7);
+}
+
+#[test]
+fn test_extended_capabilities() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("blue.j2k");
+    let mut codestream: Vec<u8> = vec![];
+    File::open(path)
+        .expect("file should exist")
+        .read_to_end(&mut codestream)
+        .unwrap();
+
+    // Signal HT code-blocks in Rsiz and COD, then add CAP and CPF after SIZ
+    codestream[6..8].copy_from_slice(&0x4000u16.to_be_bytes());
+    let cod = codestream
+        .windows(2)
+        .position(|window| window == [0xFF, 0x52])
+        .unwrap();
+    codestream[cod + 12] = 0x40;
+    let siz_end = 4 + u16::from_be_bytes([codestream[4], codestream[5]]) as usize;
+    let segments: Vec<u8> = vec![
+        0xFF, 0x50, 0x00, 0x08, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, // CAP, Ccap15
+        0xFF, 0x59, 0x00, 0x04, 0x40, 0x00, // CPF
+    ];
+    let without_capabilities = codestream.clone();
+    codestream.splice(siz_end..siz_end, segments);
+
+    let decoded = decode_jpc(&mut Cursor::new(&codestream)).expect("decode should succeed");
+    let header = decoded.header();
+    assert!(header
+        .image_and_tile_size_marker_segment()
+        .decoder_capability()
+        .contains(&DecoderCapability::ExtendedCapabilities));
+
+    let cap = header.extended_capabilities_segment().as_ref().unwrap();
+    assert_eq!(cap.offset(), siz_end as u64 + 2);
+    assert_eq!(cap.length(), 8);
+    assert_eq!(cap.parts(), vec![15]);
+    assert_eq!(cap.capabilities(15), Some(0));
+    assert_eq!(cap.capabilities(2), None);
+
+    let cpf = header.corresponding_profile_segment().as_ref().unwrap();
+    assert_eq!(cpf.length(), 4);
+    assert_eq!(cpf.profiles(), vec![0x4000]);
+
+    assert!(header
+        .coding_style_marker_segment()
+        .coding_style_parameters()
+        .coding_block_styles()
+        .contains(&CodingBlockStyle::HighThroughputBlockCoding));
+
+    // Rsiz requires the CAP marker segment
+    assert!(decode_jpc(&mut Cursor::new(&without_capabilities)).is_err());
+}
+
+#[test]
+fn test_encode_extended_capabilities() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("blue.j2k");
+    let mut codestream: Vec<u8> = vec![];
+    File::open(path)
+        .expect("file should exist")
+        .read_to_end(&mut codestream)
+        .unwrap();
+
+    // Signal HT code-blocks in Rsiz and COD, and add the encoded CAP and CPF
+    // marker segments after SIZ
+    codestream[6..8].copy_from_slice(&0x4000u16.to_be_bytes());
+    let cod = codestream
+        .windows(2)
+        .position(|window| window == [0xFF, 0x52])
+        .unwrap();
+    codestream[cod + 12] = CodingBlockStyle::encode(&[
+        CodingBlockStyle::ResetContextProbabilities,
+        CodingBlockStyle::HighThroughputBlockCoding,
+    ]);
+    let mut segments: Vec<u8> = vec![];
+    ExtendedCapabilitiesSegment::high_throughput(0x0020)
+        .encode(&mut segments)
+        .unwrap();
+    CorrespondingProfileSegment::new(vec![0x4000, 0x0001])
+        .expect("profiles should fit")
+        .encode(&mut segments)
+        .unwrap();
+    assert_eq!(
+        segments,
+        [
+            0xFF, 0x50, 0x00, 0x08, 0x00, 0x02, 0x00, 0x00, 0x00, 0x20, // CAP, Ccap15
+            0xFF, 0x59, 0x00, 0x06, 0x40, 0x00, 0x00, 0x01, // CPF
+        ]
+    );
+    let siz_end = 4 + u16::from_be_bytes([codestream[4], codestream[5]]) as usize;
+    codestream.splice(siz_end..siz_end, segments);
+
+    let decoded = decode_jpc(&mut Cursor::new(&codestream)).expect("decode should succeed");
+    let header = decoded.header();
+    let cap = header.extended_capabilities_segment().as_ref().unwrap();
+    assert_eq!(cap.parts(), vec![15]);
+    assert_eq!(cap.capabilities(15), Some(0x0020));
+    let cpf = header.corresponding_profile_segment().as_ref().unwrap();
+    assert_eq!(cpf.profiles(), vec![0x4000, 0x0001]);
+    let coding_block_styles = header
+        .coding_style_marker_segment()
+        .coding_style_parameters()
+        .coding_block_styles();
+    assert!(coding_block_styles.contains(&CodingBlockStyle::ResetContextProbabilities));
+    assert!(coding_block_styles.contains(&CodingBlockStyle::HighThroughputBlockCoding));
+
+    assert!(CorrespondingProfileSegment::new(vec![0; 32767]).is_none());
+}
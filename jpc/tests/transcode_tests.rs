@@ -0,0 +1,405 @@
+use std::{convert::TryInto, io::Cursor, path::Path};
+
+use jpc::{
+    decode_jpc, transcode_jpc, CodestreamError, ProgressionOrder, TranscodeError, TranscodeOptions,
+};
+
+mod common;
+use common::{paths, read_codestream, tiled_codestream};
+
+const PROGRESSION_ORDERS: [ProgressionOrder; 5] = [
+    ProgressionOrder::LRLCPP,
+    ProgressionOrder::RLLCPP,
+    ProgressionOrder::RLPCLP,
+    ProgressionOrder::PCRLLP,
+    ProgressionOrder::CPRLLP,
+];
+
+fn transcode(codestream: &[u8], options: &TranscodeOptions) -> Vec<u8> {
+    let mut output: Vec<u8> = vec![];
+    transcode_jpc(&mut Cursor::new(codestream), &mut output, options)
+        .expect("transcode should succeed");
+    output
+}
+
+#[test]
+fn test_transcode_identity() {
+    for path in paths() {
+        let codestream = read_codestream(&path);
+        let output = transcode(&codestream, &TranscodeOptions::default());
+        assert!(output == codestream, "{:?}", path);
+    }
+}
+
+// Tiles of a codestream as (Isot, Psot, start of the tile-part header)
+fn tile_parts(codestream: &[u8]) -> Vec<(u16, u32, usize)> {
+    let mut position = codestream
+        .windows(2)
+        .position(|window| window == [0xFF, 0x90])
+        .expect("tile-part should exist");
+    let mut tile_parts = vec![];
+    while codestream[position..position + 2] == [0xFF, 0x90] {
+        let index = u16::from_be_bytes(codestream[position + 4..position + 6].try_into().unwrap());
+        let length =
+            u32::from_be_bytes(codestream[position + 6..position + 10].try_into().unwrap());
+        tile_parts.push((index, length, position + 12));
+        position += length as usize;
+    }
+    assert_eq!(codestream[position..], [0xFF, 0xD9]);
+    tile_parts
+}
+
+// Xsiz, Ysiz, XOsiz, YOsiz, XTsiz, YTsiz, XTOsiz and YTOsiz of the SIZ
+// marker segment, the codestream may have several tiles
+fn image_and_tile_size(codestream: &[u8]) -> Vec<u32> {
+    assert_eq!(codestream[2..4], [0xFF, 0x51]);
+    codestream[8..40]
+        .chunks(4)
+        .map(|value| u32::from_be_bytes(value.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_transcode_progression_order() {
+    for path in paths() {
+        let codestream = read_codestream(&path);
+        let progression_order = decode_jpc(&mut Cursor::new(&codestream))
+            .unwrap()
+            .header()
+            .coding_style_marker_segment()
+            .progression_order();
+        for order in PROGRESSION_ORDERS.iter() {
+            let reordered = transcode(
+                &codestream,
+                &TranscodeOptions {
+                    progression_order: Some(*order),
+                    ..TranscodeOptions::default()
+                },
+            );
+            assert_eq!(reordered.len(), codestream.len());
+            let header = decode_jpc(&mut Cursor::new(&reordered)).unwrap();
+            assert_eq!(
+                header
+                    .header()
+                    .coding_style_marker_segment()
+                    .progression_order(),
+                *order
+            );
+
+            // The packets are only located again if they were moved intact
+            let restored = transcode(
+                &reordered,
+                &TranscodeOptions {
+                    progression_order: Some(progression_order),
+                    ..TranscodeOptions::default()
+                },
+            );
+            assert!(restored == codestream, "{:?} {:?}", path, order);
+        }
+    }
+}
+
+#[test]
+fn test_transcode_discard_layers() {
+    for filename in ["subsampling_1.jp2", "subsampling_2.jp2", "zoo2.jp2"] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../samples")
+            .join(filename);
+        let codestream = read_codestream(&path);
+        let mut previous = codestream.len();
+        for layers in (1..5).rev() {
+            let output = transcode(
+                &codestream,
+                &TranscodeOptions {
+                    max_layers: Some(layers),
+                    ..TranscodeOptions::default()
+                },
+            );
+            assert!(output.len() < previous, "{:?}", path);
+            previous = output.len();
+
+            let decoded = decode_jpc(&mut Cursor::new(&output)).unwrap();
+            assert_eq!(
+                decoded.header().coding_style_marker_segment().no_layers(),
+                layers
+            );
+            assert!(transcode(&output, &TranscodeOptions::default()) == output);
+        }
+    }
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../samples")
+        .join("file1.jp2");
+    let codestream = read_codestream(&path);
+    let output = transcode(
+        &codestream,
+        &TranscodeOptions {
+            max_layers: Some(3),
+            ..TranscodeOptions::default()
+        },
+    );
+    assert!(output == codestream);
+}
+
+#[test]
+fn test_transcode_discard_resolutions() {
+    for path in paths() {
+        let codestream = read_codestream(&path);
+        let decoded = decode_jpc(&mut Cursor::new(&codestream)).unwrap();
+        let header = decoded.header();
+        let siz = header.image_and_tile_size_marker_segment();
+        let cod = header.coding_style_marker_segment();
+        let levels = cod.coding_style_parameters().no_decomposition_levels();
+        let quantization_values = header
+            .quantization_default_marker_segment()
+            .quantization_values()
+            .len();
+
+        let mut previous = codestream.len();
+        for discard in 1..=levels {
+            let output = transcode(
+                &codestream,
+                &TranscodeOptions {
+                    discard_resolutions: discard,
+                    ..TranscodeOptions::default()
+                },
+            );
+            assert!(output.len() <= previous, "{:?}", path);
+            previous = output.len();
+
+            let decoded = decode_jpc(&mut Cursor::new(&output)).unwrap();
+            let output_header = decoded.header();
+            let output_siz = output_header.image_and_tile_size_marker_segment();
+            let scale = 1 << discard;
+            assert_eq!(
+                output_siz.reference_grid_width(),
+                siz.reference_grid_width().div_ceil(scale)
+            );
+            assert_eq!(
+                output_siz.reference_grid_height(),
+                siz.reference_grid_height().div_ceil(scale)
+            );
+            assert_eq!(
+                output_header
+                    .coding_style_marker_segment()
+                    .coding_style_parameters()
+                    .no_decomposition_levels(),
+                levels - discard
+            );
+            if quantization_values > 1 {
+                assert_eq!(
+                    output_header
+                        .quantization_default_marker_segment()
+                        .quantization_values()
+                        .len(),
+                    quantization_values - 3 * discard as usize
+                );
+            }
+            assert!(transcode(&output, &TranscodeOptions::default()) == output);
+        }
+
+        let result = transcode_jpc(
+            &mut Cursor::new(&codestream),
+            &mut vec![],
+            &TranscodeOptions {
+                discard_resolutions: levels + 1,
+                ..TranscodeOptions::default()
+            },
+        );
+        match result {
+            Err(CodestreamError::TranscodeError {
+                source:
+                    TranscodeError::DecompositionLevels {
+                        discard_resolutions,
+                        decomposition_levels,
+                    },
+                ..
+            }) => {
+                assert_eq!(discard_resolutions, levels + 1);
+                assert_eq!(decomposition_levels, levels);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_transcode_packet_markers() {
+    for path in paths() {
+        let codestream = read_codestream(&path);
+        let coding_style = decode_jpc(&mut Cursor::new(&codestream))
+            .unwrap()
+            .header()
+            .coding_style_marker_segment()
+            .coding_style();
+
+        let output = transcode(
+            &codestream,
+            &TranscodeOptions {
+                start_of_packet: Some(true),
+                end_of_packet_header: Some(true),
+                ..TranscodeOptions::default()
+            },
+        );
+        let decoded = decode_jpc(&mut Cursor::new(&output)).unwrap();
+        assert_eq!(
+            decoded
+                .header()
+                .coding_style_marker_segment()
+                .coding_style()
+                & 0b110,
+            0b110
+        );
+        assert!(transcode(&output, &TranscodeOptions::default()) == output);
+
+        let restored = transcode(
+            &output,
+            &TranscodeOptions {
+                start_of_packet: Some(coding_style & 0b010 != 0),
+                end_of_packet_header: Some(coding_style & 0b100 != 0),
+                ..TranscodeOptions::default()
+            },
+        );
+        assert!(restored == codestream, "{:?}", path);
+    }
+}
+
+#[test]
+fn test_transcode_lengths() {
+    for path in paths() {
+        let codestream = read_codestream(&path);
+        let output = transcode(
+            &codestream,
+            &TranscodeOptions {
+                tile_part_lengths: true,
+                packet_lengths: true,
+                ..TranscodeOptions::default()
+            },
+        );
+
+        // TLM: Ztlm = 0, Stlm = 16-bit Ttlm and 32-bit Ptlm
+        let tile_parts = tile_parts(&output);
+        let start = output
+            .windows(2)
+            .position(|window| window == [0xFF, 0x55])
+            .unwrap();
+        let length = u16::from_be_bytes(output[start + 2..start + 4].try_into().unwrap());
+        assert_eq!(length as usize, 4 + 6 * tile_parts.len());
+        assert_eq!(output[start + 4..start + 6], [0x00, 0x60]);
+        for (i, (index, length, _)) in tile_parts.iter().enumerate() {
+            let entry = start + 6 + 6 * i;
+            assert_eq!(output[entry..entry + 2], index.to_be_bytes());
+            assert_eq!(output[entry + 2..entry + 6], length.to_be_bytes());
+        }
+
+        // PLT: the packet lengths add up to the tile data
+        for (_, length, header) in tile_parts {
+            let plt = output[header..]
+                .windows(2)
+                .position(|window| window == [0xFF, 0x58])
+                .unwrap()
+                + header;
+            let plt_length =
+                u16::from_be_bytes(output[plt + 2..plt + 4].try_into().unwrap()) as usize;
+            assert_eq!(output[plt + 4], 0);
+            let mut total = 0;
+            let mut value = 0;
+            for byte in &output[plt + 5..plt + 2 + plt_length] {
+                value = (value << 7) | (byte & 0x7F) as usize;
+                if byte & 0x80 == 0 {
+                    total += value;
+                    value = 0;
+                }
+            }
+            assert_eq!(
+                output[plt + 2 + plt_length..plt + 4 + plt_length],
+                [0xFF, 0x93]
+            );
+            assert_eq!(plt + 4 + plt_length + total, header - 12 + length as usize);
+        }
+
+        // The lengths are dropped by default
+        assert!(transcode(&output, &TranscodeOptions::default()) == codestream);
+    }
+}
+
+#[test]
+fn test_transcode_tiles() {
+    let codestream = tiled_codestream();
+
+    let output = transcode(&codestream, &TranscodeOptions::default());
+    let tiles = tile_parts(&output);
+    assert_eq!(tiles.len(), 8);
+    assert_eq!(tiles[0], (0, 20, tiles[0].2));
+    assert_eq!(output.len(), codestream.len() - 14);
+
+    let output = transcode(
+        &codestream,
+        &TranscodeOptions {
+            tiles: Some(vec![6, 5]),
+            ..TranscodeOptions::default()
+        },
+    );
+    assert_eq!(
+        image_and_tile_size(&output),
+        [96, 60, 32, 32, 32, 32, 32, 32]
+    );
+    let indices: Vec<u16> = tile_parts(&output).iter().map(|(i, _, _)| *i).collect();
+    assert_eq!(indices, vec![0, 1]);
+
+    let output = transcode(
+        &codestream,
+        &TranscodeOptions {
+            tiles: Some(vec![2, 3, 6, 7]),
+            discard_resolutions: 1,
+            ..TranscodeOptions::default()
+        },
+    );
+    assert_eq!(image_and_tile_size(&output), [50, 30, 32, 0, 16, 16, 32, 0]);
+    // 2 layers of 2 resolution levels
+    for (_, length, _) in tile_parts(&output) {
+        assert_eq!(length, 12 + 2 + 4);
+    }
+
+    // The tiles shall form a rectangle
+    let result = transcode_jpc(
+        &mut Cursor::new(&codestream),
+        &mut vec![],
+        &TranscodeOptions {
+            tiles: Some(vec![0, 5]),
+            ..TranscodeOptions::default()
+        },
+    );
+    assert!(matches!(
+        result,
+        Err(CodestreamError::TranscodeError {
+            offset: 0,
+            source: TranscodeError::TilesNotRectangular,
+        })
+    ));
+
+    let invalid = [
+        (Some(vec![]), None, TranscodeError::NoTilesSelected),
+        (
+            Some(vec![16]),
+            None,
+            TranscodeError::TileNotFound { tile: 16 },
+        ),
+        (None, Some(0), TranscodeError::NoLayersKept),
+    ];
+    for (tiles, max_layers, expected) in invalid.iter() {
+        let result = transcode_jpc(
+            &mut Cursor::new(&codestream),
+            &mut vec![],
+            &TranscodeOptions {
+                tiles: tiles.clone(),
+                max_layers: *max_layers,
+                ..TranscodeOptions::default()
+            },
+        );
+        match result {
+            Err(CodestreamError::TranscodeError { source, .. }) => assert_eq!(source, *expected),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::Cursor;
+
+use jpc::{encode_validation_xml, transcode_jpc, validate_jpc, TranscodeOptions, ValidationReport};
+
+mod common;
+use common::{paths, read_codestream, tiled_codestream};
+
+// Offset of the first SOT marker of the tiled codestream
+const FIRST_SOT: usize = 71;
+
+fn validate(codestream: &[u8]) -> ValidationReport {
+    validate_jpc(&mut Cursor::new(codestream)).expect("validation should succeed")
+}
+
+#[test]
+fn test_validate_samples() {
+    for path in paths() {
+        let report = validate(&read_codestream(&path));
+        assert!(report.is_valid(), "{:?} {:?}", path, report.failed_tests());
+        assert_eq!(report.name(), "j2c");
+        assert!(report.child("siz").is_some());
+        assert!(report.child("cod").is_some());
+        assert!(report.child("qcd").is_some());
+    }
+}
+
+#[test]
+fn test_validate_tiles() {
+    let codestream = tiled_codestream();
+    let report = validate(&codestream);
+    assert!(report.is_valid(), "{:?}", report.failed_tests());
+    assert_eq!(report.property("numberOfTiles"), Some("8"));
+    assert_eq!(report.test("foundExpectedNumberOfTiles"), Some(true));
+    assert_eq!(report.test("foundExpectedNumberOfTileParts"), Some(true));
+    assert_eq!(report.test("tilePartLengthsConsistentWithTLM"), None);
+    let tile_parts = report.child("tileParts").unwrap();
+    assert_eq!(tile_parts.children().len(), 9);
+    let sot = tile_parts.children()[1].child("sot").unwrap();
+    assert_eq!(sot.property("isot"), Some("0"));
+    assert_eq!(sot.property("psot"), Some("18"));
+    assert_eq!(sot.property("tpsot"), Some("1"));
+    assert_eq!(sot.property("tnsot"), Some("2"));
+
+    // Tile-part lengths of a TLM marker segment
+    let mut output: Vec<u8> = vec![];
+    transcode_jpc(
+        &mut Cursor::new(&codestream),
+        &mut output,
+        &TranscodeOptions {
+            tile_part_lengths: true,
+            ..TranscodeOptions::default()
+        },
+    )
+    .expect("transcode should succeed");
+    let report = validate(&output);
+    assert!(report.is_valid(), "{:?}", report.failed_tests());
+    assert_eq!(report.test("tilePartLengthsConsistentWithTLM"), Some(true));
+
+    // Ptlm of the last tile-part
+    let mut patched = output.clone();
+    let tlm = patched
+        .windows(2)
+        .position(|window| window == [0xFF, 0x55])
+        .unwrap();
+    let length = u16::from_be_bytes([patched[tlm + 2], patched[tlm + 3]]) as usize;
+    patched[tlm + length + 1] += 1;
+    let report = validate(&patched);
+    assert_eq!(report.failed_tests(), ["tilePartLengthsConsistentWithTLM"]);
+}
+
+#[test]
+fn test_validate_tile_parts() {
+    let codestream = tiled_codestream();
+
+    // Psot of the first tile-part beyond the next tile-part
+    let mut patched = codestream.clone();
+    patched[FIRST_SOT + 9] += 1;
+    let report = validate(&patched);
+    assert!(!report.is_valid());
+    assert_eq!(
+        report.child("tileParts").unwrap().children()[0].test("foundNextTilePartOrEOC"),
+        Some(false)
+    );
+
+    // Psot of the last tile-part beyond the end of the codestream
+    let mut patched = codestream.clone();
+    let last_sot = patched.len() - 2 - 20;
+    assert_eq!(patched[last_sot..last_sot + 2], [0xFF, 0x90]);
+    patched[last_sot + 9] = 0xFF;
+    let report = validate(&patched);
+    let tile_parts = report.child("tileParts").unwrap();
+    let last = tile_parts.children().last().unwrap();
+    assert_eq!(last.child("sot").unwrap().test("psotIsValid"), Some(false));
+
+    // Number of tile-parts of the first tile
+    let mut patched = codestream.clone();
+    patched[FIRST_SOT + 11] = 3;
+    let report = validate(&patched);
+    assert_eq!(report.test("foundExpectedNumberOfTileParts"), Some(false));
+
+    // Tile-part index repeated
+    let mut patched = codestream.clone();
+    patched[FIRST_SOT + 16 + 10] = 0;
+    let report = validate(&patched);
+    assert!(report
+        .failed_tests()
+        .contains(&"tileParts/tilePart/sot/tpsotIsValid".to_string()));
+
+    // Missing last tile and EOC marker
+    let report = validate(&codestream[..codestream.len() - 22]);
+    assert_eq!(report.test("foundExpectedNumberOfTiles"), Some(false));
+    assert_eq!(report.test("foundEOCMarker"), Some(false));
+}
+
+#[test]
+fn test_validate_main_header() {
+    let codestream = tiled_codestream();
+
+    // Csiz of 0
+    let mut patched = codestream.clone();
+    patched[40..42].copy_from_slice(&[0, 0]);
+    let report = validate(&patched);
+    assert!(report
+        .failed_tests()
+        .contains(&"siz/csizIsValid".to_string()));
+
+    // Missing COD marker segment
+    let patched = [&codestream[..45], &codestream[59..]].concat();
+    let report = validate(&patched);
+    assert_eq!(report.test("foundCODMarker"), Some(false));
+    assert!(report.child("cod").is_none());
+
+    // Repeated QCD marker segment
+    let patched = [&codestream[..71], &codestream[59..]].concat();
+    let report = validate(&patched);
+    assert_eq!(report.failed_tests(), ["noMoreThanOneQCDMarker"]);
+
+    // Missing SOC marker
+    let report = validate(&codestream[2..]);
+    assert_eq!(report.test("codestreamStartsWithSOCMarker"), Some(false));
+    assert!(!report.is_valid());
+}
+
+#[test]
+fn test_encode_validation_xml() {
+    let mut codestream = tiled_codestream();
+    codestream[40..42].copy_from_slice(&[0, 0]);
+    let report = validate(&codestream);
+
+    let mut output: Vec<u8> = vec![];
+    encode_validation_xml(&mut output, &report, "tiled & <broken>.j2c")
+        .expect("encoding should succeed");
+    let xml = String::from_utf8(output).unwrap();
+    assert!(xml.starts_with("<?xml version='1.0' encoding='UTF-8'?>\n"));
+    assert!(xml.contains("<fileName>tiled &amp; &lt;broken&gt;.j2c</fileName>"));
+    assert!(xml.contains("<isValid format=\"j2c\">False</isValid>"));
+    assert!(xml.contains("<csizIsValid>False</csizIsValid>"));
+    assert!(xml.contains("<numberOfTiles>8</numberOfTiles>"));
+    assert!(xml.trim_end().ends_with("</jpylyzer>"));
+}
@@ -10,8 +10,8 @@ use std::io::{self, BufReader, Seek};
 use std::path::Path;
 use std::str::FromStr;
 
-use jp2::decode_jp2;
-use jpc::decode_jpc;
+use jp2::{decode_jp2, decode_jpx, validate_jp2, BoxIterator};
+use jpc::{decode_jpc, encode_validation_xml, validate_jpc};
 use jpxml::{encode_jp2, encode_jpc, Representation};
 
 #[derive(Debug)]
@@ -46,13 +46,22 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Decode .jp2 container or .jpc codestream file (noop)
+    /// Decode .jp2 or .jpx container or .jpc codestream file (noop)
     #[command(name = "decode")]
     Decode(Decode),
 
     /// Encode .jp2 container or .jpc codestream file to JPXML document (stdout)
     #[command(name = "jpxml")]
     JpXml(JpXml),
+
+    /// List the boxes of a .jp2 file (stdout)
+    #[command(name = "boxes")]
+    Boxes(Boxes),
+
+    /// Validate .jp2 container or .jpc codestream file to jpylyzer XML
+    /// report (stdout)
+    #[command(name = "validate")]
+    Validate(Validate),
 }
 
 #[derive(Args)]
@@ -61,6 +70,18 @@ struct Decode {
     path: String,
 }
 
+#[derive(Args)]
+struct Boxes {
+    /// Path to .jp2 file
+    path: String,
+}
+
+#[derive(Args)]
+struct Validate {
+    /// Path to .jp2 or .jpc file
+    path: String,
+}
+
 #[derive(Args)]
 struct JpXml {
     /// Path to .jp2 file
@@ -114,6 +135,44 @@ fn run() -> Result<(), Box<dyn Error>> {
                         };
                     }
                 }
+                "jpx" | "jpf" => {
+                    let mut reader = BufReader::new(file);
+
+                    let jpx = match decode_jpx(&mut reader) {
+                        Ok(jpx) => jpx,
+                        Err(error) => {
+                            return Err(JP2000Error::DecodingContainer {
+                                error: error.to_string(),
+                            }
+                            .into())
+                        }
+                    };
+                    for contiguous_codestreams_box in jpx.contiguous_codestreams_boxes() {
+                        reader.seek(io::SeekFrom::Start(contiguous_codestreams_box.offset))?;
+                        if let Err(error) = decode_jpc(&mut reader) {
+                            return Err(JP2000Error::DecodingCodestream {
+                                error: error.to_string(),
+                            }
+                            .into());
+                        };
+                    }
+                    // Fragments in other files are read from the directory of
+                    // the file
+                    let directory = match path.parent() {
+                        Some(directory) if !directory.as_os_str().is_empty() => directory,
+                        _ => Path::new("."),
+                    };
+                    for index in 0..jpx.fragment_table_boxes().len() {
+                        let mut fragments =
+                            jpx.fragmented_codestream(index, &mut reader, Some(directory))?;
+                        if let Err(error) = decode_jpc(&mut fragments) {
+                            return Err(JP2000Error::DecodingCodestream {
+                                error: error.to_string(),
+                            }
+                            .into());
+                        };
+                    }
+                }
                 "jpc" | "j2c" => {
                     let mut reader = BufReader::new(file);
                     if let Err(error) = decode_jpc(&mut reader) {
@@ -166,6 +225,47 @@ fn run() -> Result<(), Box<dyn Error>> {
                 }
             }
         }
+        Commands::Boxes(c) => {
+            let mut reader = BufReader::new(File::open(Path::new(&c.path))?);
+
+            for info in BoxIterator::new(&mut reader) {
+                let info = match info {
+                    Ok(info) => info,
+                    Err(error) => {
+                        return Err(JP2000Error::DecodingContainer {
+                            error: error.to_string(),
+                        }
+                        .into())
+                    }
+                };
+                println!(
+                    "{}{} at {} length {}",
+                    "  ".repeat(info.depth),
+                    String::from_utf8_lossy(&info.box_type),
+                    info.start(),
+                    info.header_length as u64 + info.length
+                );
+            }
+        }
+        Commands::Validate(c) => {
+            let path = Path::new(&c.path);
+            let filename = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            let extension = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+
+            let mut reader = BufReader::new(File::open(path)?);
+
+            let report = match extension {
+                "jp2" => validate_jp2(&mut reader)?,
+                "jpc" | "j2c" | "j2k" => validate_jpc(&mut reader)?,
+                _ => {
+                    return Err(JP2000Error::UnsupportedExtension {
+                        extension: extension.to_owned(),
+                    }
+                    .into())
+                }
+            };
+            encode_validation_xml(&mut io::stdout(), &report, filename)?;
+        }
     }
 
     Ok(())
@@ -4,12 +4,12 @@ use core::fmt::Write;
 use jp2::{
     decode_jp2, BitsPerComponentBox, CaptureResolutionBox, ChannelDefinitionBox,
     ColourSpecificationBox, ComponentMappingBox, ContiguousCodestreamBox,
-    DefaultDisplayResolutionBox, FileTypeBox, HeaderSuperBox, JBox, PaletteBox, ResolutionSuperBox,
-    SignatureBox, UUIDBox, XMLBox,
+    DefaultDisplayResolutionBox, FileTypeBox, HeaderSuperBox, JBox, JP2Error, JP2File, PaletteBox,
+    ResolutionSuperBox, SignatureBox, UUIDBox, XMLBox,
 };
 use jpc::{
-    decode_jpc, CodingStyleMarkerSegment, CodingStyleParameters, ContiguousCodestream, Header,
-    ImageAndTileSizeMarkerSegment, QuantizationDefaultMarkerSegment,
+    decode_jpc, CodestreamError, CodingStyleMarkerSegment, CodingStyleParameters,
+    ContiguousCodestream, Header, ImageAndTileSizeMarkerSegment, QuantizationDefaultMarkerSegment,
 };
 use std::error;
 use std::fmt;
@@ -17,29 +17,105 @@ use std::fs::File;
 use std::io::{self, BufReader, Seek};
 use std::str;
 
-fn to_hex<'a, I>(iter: I) -> Result<String, Box<dyn error::Error>>
+fn to_hex<'a, I>(iter: I) -> String
 where
     I: Iterator<Item = &'a u8>,
 {
     let mut hex = String::new();
     for byte in iter {
-        write!(hex, "{:02x}", byte)?;
+        // Writing to a String does not fail
+        let _ = write!(hex, "{:02x}", byte);
     }
-    Ok(hex)
+    hex
 }
 
+/// An error encoding a JPXML document, the offset of each variant is the
+/// byte offset in the file being encoded, or 0 for an error which has no
+/// position in the file.
 #[derive(Debug)]
-enum JPXMLError {
-    InvalidRepresentation { representation: String },
+#[non_exhaustive]
+pub enum JPXMLError {
+    InvalidRepresentation {
+        representation: String,
+        offset: u64,
+    },
+    /// A representation not encoded yet, the offset is of the codestream.
+    UnsupportedRepresentation {
+        representation: String,
+        offset: u64,
+    },
+    /// An error decoding the file, the offset is that of the source.
+    JP2 {
+        source: JP2Error,
+    },
+    /// An error decoding a codestream, the offset is of the start of the
+    /// codestream.
+    Codestream {
+        offset: u64,
+        source: CodestreamError,
+    },
+    Io {
+        offset: u64,
+        source: io::Error,
+    },
+}
+
+impl JPXMLError {
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::JP2 { source } => source.offset(),
+            Self::InvalidRepresentation { offset, .. }
+            | Self::UnsupportedRepresentation { offset, .. }
+            | Self::Codestream { offset, .. }
+            | Self::Io { offset, .. } => *offset,
+        }
+    }
+}
+
+// Errors writing the document, reported at the offset of the box or
+// codestream being encoded.
+trait AtOffset<T> {
+    fn at(self, offset: u64) -> Result<T, JPXMLError>;
+}
+
+impl<T> AtOffset<T> for io::Result<T> {
+    fn at(self, offset: u64) -> Result<T, JPXMLError> {
+        self.map_err(|source| JPXMLError::Io { offset, source })
+    }
+}
+
+impl error::Error for JPXMLError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::JP2 { source } => Some(source),
+            Self::Codestream { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
 }
 
-impl error::Error for JPXMLError {}
 impl fmt::Display for JPXMLError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::InvalidRepresentation { representation } => {
+            Self::InvalidRepresentation { representation, .. } => {
                 write!(f, "invalid representation {:?}", representation)
             }
+            Self::UnsupportedRepresentation {
+                representation,
+                offset,
+            } => {
+                write!(
+                    f,
+                    "unsupported representation {:?} of the codestream at offset {}",
+                    representation, offset
+                )
+            }
+            Self::JP2 { source } => write!(f, "{}", source),
+            Self::Codestream { offset, source } => {
+                write!(f, "codestream at offset {}: {}", offset, source)
+            }
+            Self::Io { offset, source } => write!(f, "{} at offset {}", source, offset),
         }
     }
 }
@@ -53,7 +129,7 @@ impl fmt::Display for JPXMLError {
 // - "skeleton"
 // - "fat-skeleton"
 // - and "fat" representations.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Representation {
     // The first-level representation, the skeleton representation, shall
     // express only the structure of the image itself, and may contain an
@@ -93,7 +169,7 @@ pub enum Representation {
 }
 
 impl str::FromStr for Representation {
-    type Err = Box<dyn error::Error>;
+    type Err = JPXMLError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -102,8 +178,8 @@ impl str::FromStr for Representation {
             "fat" => Ok(Representation::Fat),
             _ => Err(JPXMLError::InvalidRepresentation {
                 representation: s.to_owned(),
-            }
-            .into()),
+                offset: 0,
+            }),
         }
     }
 }
@@ -113,7 +189,7 @@ impl str::FromStr for Representation {
 fn encode_signature_box<W: io::Write>(
     writer: &mut W,
     signature_box: &SignatureBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:jP__ type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -123,7 +199,7 @@ fn encode_signature_box<W: io::Write>(
     writeln!(
         writer,
         "    <xjp:signature length=\"8\" type=\"hexbyte\">{}</xjp:signature>",
-        to_hex(signature_box.signature().iter())?
+        to_hex(signature_box.signature().iter())
     )?;
     writer.write_all(b"  </xjp:jP__>\n")?;
     Ok(())
@@ -132,7 +208,7 @@ fn encode_signature_box<W: io::Write>(
 fn encode_file_type_box<W: io::Write>(
     writer: &mut W,
     file_type_box: &FileTypeBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:ftyp type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -164,7 +240,7 @@ fn encode_file_type_box<W: io::Write>(
 fn encode_header_super_box<W: io::Write>(
     writer: &mut W,
     header_super_box: &HeaderSuperBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     let image_header_box = &header_super_box.image_header_box;
 
     writeln!(
@@ -244,7 +320,7 @@ fn encode_header_super_box<W: io::Write>(
 fn encode_bits_per_component_box<W: io::Write>(
     writer: &mut W,
     bits_per_component_box: &BitsPerComponentBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:bpcc type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -267,7 +343,7 @@ fn encode_bits_per_component_box<W: io::Write>(
 fn encode_colour_specification_box<W: io::Write>(
     writer: &mut W,
     colour_specification_box: &ColourSpecificationBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:colr type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -300,10 +376,7 @@ fn encode_colour_specification_box<W: io::Write>(
     Ok(())
 }
 
-fn encode_palette_box<W: io::Write>(
-    writer: &mut W,
-    palette_box: &PaletteBox,
-) -> Result<(), Box<dyn error::Error>> {
+fn encode_palette_box<W: io::Write>(writer: &mut W, palette_box: &PaletteBox) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:pclr type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -330,7 +403,7 @@ fn encode_palette_box<W: io::Write>(
         writeln!(
             writer,
             "      <xjp:data length=\"1\" type=\"integer\">{}</xjp:data>",
-            to_hex(generated_component.values().iter())?
+            to_hex(generated_component.values().iter())
         )?;
     }
     writer.write_all(b"    </xjp:pclr>\n")?;
@@ -340,7 +413,7 @@ fn encode_palette_box<W: io::Write>(
 fn encode_component_mapping_box<W: io::Write>(
     writer: &mut W,
     component_mapping_box: &ComponentMappingBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:cmap type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -375,7 +448,7 @@ fn encode_component_mapping_box<W: io::Write>(
 fn encode_channel_definition_box<W: io::Write>(
     writer: &mut W,
     channel_definition_box: &ChannelDefinitionBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:cdef type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -411,7 +484,7 @@ fn encode_channel_definition_box<W: io::Write>(
 fn encode_resolution_box<W: io::Write>(
     writer: &mut W,
     resolution_box: &ResolutionSuperBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:res_ type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -433,7 +506,7 @@ fn encode_resolution_box<W: io::Write>(
 fn encode_capture_resolution_box<W: io::Write>(
     writer: &mut W,
     capture_resolution_box: &CaptureResolutionBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:resc type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -477,7 +550,7 @@ fn encode_capture_resolution_box<W: io::Write>(
 fn encode_default_display_resolution_box<W: io::Write>(
     writer: &mut W,
     default_display_resolution_box: &DefaultDisplayResolutionBox,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:resd type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -522,63 +595,75 @@ fn encode_default_display_resolution_box<W: io::Write>(
 fn encode_siz<W: io::Write>(
     writer: &mut W,
     segment: &ImageAndTileSizeMarkerSegment,
-) -> Result<(), Box<dyn error::Error>> {
+    offset: u64,
+) -> Result<(), JPXMLError> {
     writeln!(
         writer,
         "    <xjp:SIZ type=\"marker\" length=\"{}\" offset=\"{}\">",
         segment.length(),
         segment.offset()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:Rsiz>{}</xjp:Rsiz>",
         segment.decoder_capabilities()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:Xsiz>{}</xjp:Xsiz>",
         segment.reference_grid_width()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:Ysiz>{}</xjp:Ysiz>",
         segment.reference_grid_height()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:OXsiz>{}</xjp:OXsiz>",
         segment.image_horizontal_offset()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:OYsiz>{}</xjp:OYsiz>",
         segment.image_vertical_offset()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:XTsiz>{}</xjp:XTsiz>",
         segment.reference_tile_width()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:YTsiz>{}</xjp:YTsiz>",
         segment.reference_tile_height()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:XTOsiz>{}</xjp:XTOsiz>",
         segment.tile_horizontal_offset()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:YTOsiz>{}</xjp:YTOsiz>",
         segment.tile_vertical_offset()
-    )?;
+    )
+    .at(offset)?;
     writeln!(
         writer,
         "      <xjp:Csiz>{}</xjp:Csiz>",
         segment.no_components()
-    )?;
+    )
+    .at(offset)?;
 
     let no_components = segment.no_components() as usize;
 
@@ -587,25 +672,34 @@ fn encode_siz<W: io::Write>(
         writeln!(
             writer,
             "      <xjp:Ssiz>{}</xjp:Ssiz>",
-            segment.precision(i)?
-        )?;
+            segment
+                .precision(i)
+                .map_err(|source| JPXMLError::Codestream { offset, source })?
+        )
+        .at(offset)?;
         writeln!(
             writer,
             "      <xjp:XRsiz>{}</xjp:XRsiz>",
-            segment.horizontal_separation(i)?
-        )?;
+            segment
+                .horizontal_separation(i)
+                .map_err(|source| JPXMLError::Codestream { offset, source })?
+        )
+        .at(offset)?;
         writeln!(
             writer,
             "      <xjp:YRsiz>{}</xjp:YRsiz>",
-            segment.vertical_separation(i)?
-        )?;
+            segment
+                .vertical_separation(i)
+                .map_err(|source| JPXMLError::Codestream { offset, source })?
+        )
+        .at(offset)?;
 
         i += 1;
         if i == no_components {
             break;
         }
     }
-    writeln!(writer, "    </xjp:SIZ>",)?;
+    writeln!(writer, "    </xjp:SIZ>",).at(offset)?;
 
     Ok(())
 }
@@ -613,7 +707,7 @@ fn encode_siz<W: io::Write>(
 fn encode_coding_style_parameters<W: io::Write>(
     writer: &mut W,
     coding_style_parameters: &CodingStyleParameters,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(
         writer,
         "        <xjp:num_levels>{}</xjp:num_levels>",
@@ -658,10 +752,7 @@ fn encode_coding_style_parameters<W: io::Write>(
     Ok(())
 }
 
-fn encode_cod<W: io::Write>(
-    writer: &mut W,
-    segment: &CodingStyleMarkerSegment,
-) -> Result<(), Box<dyn error::Error>> {
+fn encode_cod<W: io::Write>(writer: &mut W, segment: &CodingStyleMarkerSegment) -> io::Result<()> {
     writeln!(
         writer,
         "    <xjp:COD type=\"marker\" length=\"{}\" offset=\"{}\">",
@@ -705,7 +796,7 @@ fn encode_cod<W: io::Write>(
 fn encode_qcd<W: io::Write>(
     writer: &mut W,
     segment: &QuantizationDefaultMarkerSegment,
-) -> Result<(), Box<dyn error::Error>> {
+) -> io::Result<()> {
     writeln!(writer, "    <xjp:QCD>",)?;
     writeln!(
         writer,
@@ -721,10 +812,7 @@ fn encode_qcd<W: io::Write>(
     Ok(())
 }
 
-fn encode_coc<W: io::Write>(
-    writer: &mut W,
-    _segment: &CodingStyleMarkerSegment,
-) -> Result<(), Box<dyn error::Error>> {
+fn encode_coc<W: io::Write>(writer: &mut W, _segment: &CodingStyleMarkerSegment) -> io::Result<()> {
     writeln!(writer, "    <xjp:COC>",)?;
     writeln!(writer, "    </xjp:COC>",)?;
     todo!();
@@ -733,10 +821,11 @@ fn encode_coc<W: io::Write>(
 fn encode_contiguous_codestream_header<W: io::Write>(
     writer: &mut W,
     header: &Header,
-) -> Result<(), Box<dyn error::Error>> {
-    encode_siz(writer, header.image_and_tile_size_marker_segment())?;
-    encode_cod(writer, header.coding_style_marker_segment())?;
-    encode_qcd(writer, header.quantization_default_marker_segment())?;
+    offset: u64,
+) -> Result<(), JPXMLError> {
+    encode_siz(writer, header.image_and_tile_size_marker_segment(), offset)?;
+    encode_cod(writer, header.coding_style_marker_segment()).at(offset)?;
+    encode_qcd(writer, header.quantization_default_marker_segment()).at(offset)?;
     // QCC
     // RGN
     // POC
@@ -754,7 +843,8 @@ fn encode_contiguous_codestream<W: io::Write>(
     representation: &Representation,
     contiguous_codestream: &ContiguousCodestream,
     contiguous_codestream_box: Option<&ContiguousCodestreamBox>,
-) -> Result<(), Box<dyn error::Error>> {
+) -> Result<(), JPXMLError> {
+    let offset = contiguous_codestream_box.map_or(0, JBox::offset);
     match contiguous_codestream_box {
         Some(cc_box) => {
             writeln!(
@@ -762,27 +852,30 @@ fn encode_contiguous_codestream<W: io::Write>(
                 "  <xjp:jp2c type=\"box\" length=\"{}\" offset=\"{}\">",
                 cc_box.length(),
                 cc_box.offset()
-            )?;
+            )
+            .at(offset)?;
         }
         None => {
-            writeln!(writer, "  <xjp:jp2c type=\"box\">",)?;
+            writeln!(writer, "  <xjp:jp2c type=\"box\">",).at(offset)?;
         }
     }
 
-    encode_contiguous_codestream_header(writer, contiguous_codestream.header())?;
+    encode_contiguous_codestream_header(writer, contiguous_codestream.header(), offset)?;
 
+    // The codestream data of the fat-skeleton and fat representations is not
+    // encoded yet
     if *representation != Representation::Skeleton {
-        todo!();
+        return Err(JPXMLError::UnsupportedRepresentation {
+            representation: format!("{:?}", representation),
+            offset,
+        });
     }
 
-    writer.write_all(b"  </xjp:jp2c>\n")?;
+    writer.write_all(b"  </xjp:jp2c>\n").at(offset)?;
     Ok(())
 }
 
-fn encode_xml_box<W: io::Write>(
-    writer: &mut W,
-    xml_box: &XMLBox,
-) -> Result<(), Box<dyn error::Error>> {
+fn encode_xml_box<W: io::Write>(writer: &mut W, xml_box: &XMLBox) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:_xml_ type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -804,10 +897,7 @@ fn encode_xml_box<W: io::Write>(
     Ok(())
 }
 
-fn encode_uuid_box<W: io::Write>(
-    writer: &mut W,
-    uuid_box: &UUIDBox,
-) -> Result<(), Box<dyn error::Error>> {
+fn encode_uuid_box<W: io::Write>(writer: &mut W, uuid_box: &UUIDBox) -> io::Result<()> {
     writeln!(
         writer,
         "  <xjp:uuid type=\"box\" length=\"{}\" offset=\"{}\">",
@@ -823,7 +913,7 @@ fn encode_uuid_box<W: io::Write>(
         writer,
         "    <xjp:data length=\"{}\" type=\"hexbyte\">{}</xjp:data>",
         uuid_box.data().len(),
-        to_hex(uuid_box.data().iter())?
+        to_hex(uuid_box.data().iter())
     )?;
     writer.write_all(b"  </xjp:uuid>\n")?;
     Ok(())
@@ -847,43 +937,54 @@ pub fn encode_jp2<W: io::Write>(
     file: &File,
     representation: Representation,
     name: &str,
-) -> Result<(), Box<dyn error::Error>> {
+) -> Result<(), JPXMLError> {
     let mut reader = BufReader::new(file);
 
-    let jp2 = decode_jp2(&mut reader)?;
+    let jp2 = decode_jp2(&mut reader).map_err(|source| JPXMLError::JP2 { source })?;
+    encode_jp2_document(writer, &mut reader, &jp2, representation, name)
+}
 
-    writer.write_all(b"<?xml version=\"1.0\"?>\n")?;
-    writer.write_all(b"<xjp:jpxml xmlns:xjp=\"http://www.jpeg.org/jpxml/1.0\" xmlns:xs=\"http://www.w3.org/2001/XMLSchema\"")?;
+fn encode_jp2_document<W: io::Write>(
+    writer: &mut W,
+    reader: &mut BufReader<&File>,
+    jp2: &JP2File,
+    representation: Representation,
+    name: &str,
+) -> Result<(), JPXMLError> {
+    writer.write_all(b"<?xml version=\"1.0\"?>\n").at(0)?;
+    writer.write_all(b"<xjp:jpxml xmlns:xjp=\"http://www.jpeg.org/jpxml/1.0\" xmlns:xs=\"http://www.w3.org/2001/XMLSchema\"").at(0)?;
     // Length is required?
     if !name.is_empty() {
-        write!(writer, " length=\"{}\"", jp2.length())?;
-        write!(writer, " name=\"{}\"", name)?;
+        write!(writer, " length=\"{}\"", jp2.length()).at(0)?;
+        write!(writer, " name=\"{}\"", name).at(0)?;
     }
-    writer.write_all(b">\n")?;
+    writer.write_all(b">\n").at(0)?;
 
     if let Some(signature_box) = jp2.signature_box() {
-        encode_signature_box(writer, signature_box)?;
+        encode_signature_box(writer, signature_box).at(signature_box.offset())?;
     }
 
     if let Some(file_type_box) = jp2.file_type_box() {
-        encode_file_type_box(writer, file_type_box)?;
+        encode_file_type_box(writer, file_type_box).at(file_type_box.offset())?;
     }
 
     // TODO: Check if header box is optional
     if let Some(header_box) = jp2.header_box() {
-        encode_header_super_box(writer, header_box)?;
+        encode_header_super_box(writer, header_box).at(header_box.offset())?;
     }
 
     for xml_box in jp2.xml_boxes() {
-        encode_xml_box(writer, xml_box)?;
+        encode_xml_box(writer, xml_box).at(xml_box.offset())?;
     }
     for uuid_box in jp2.uuid_boxes() {
-        encode_uuid_box(writer, uuid_box)?;
+        encode_uuid_box(writer, uuid_box).at(uuid_box.offset())?;
     }
 
     for contiguous_codestream_box in jp2.contiguous_codestreams_boxes() {
-        reader.seek(io::SeekFrom::Start(contiguous_codestream_box.offset))?;
-        let contiguous_codestream = decode_jpc(&mut reader)?;
+        let offset = contiguous_codestream_box.offset;
+        reader.seek(io::SeekFrom::Start(offset)).at(offset)?;
+        let contiguous_codestream =
+            decode_jpc(reader).map_err(|source| JPXMLError::Codestream { offset, source })?;
 
         encode_contiguous_codestream(
             writer,
@@ -892,7 +993,7 @@ pub fn encode_jp2<W: io::Write>(
             Some(contiguous_codestream_box),
         )?;
     }
-    writer.write_all(b"</xjp:jpxml>\n")?;
+    writer.write_all(b"</xjp:jpxml>\n").at(0)?;
 
     Ok(())
 }
@@ -901,13 +1002,21 @@ pub fn encode_jpc<W: io::Write>(
     writer: &mut W,
     file: &File,
     representation: Representation,
-) -> Result<(), Box<dyn error::Error>> {
+) -> Result<(), JPXMLError> {
     let mut reader = BufReader::new(file);
+    encode_jpc_document(writer, &mut reader, representation)
+}
 
-    writer.write_all(b"<?xml version=\"1.0\"?>\n")?;
-    writer.write_all(b"<xjp:jpxml xmlns:xjp=\"http://www.jpeg.org/jpxml/1.0\" xmlns:xs=\"http://www.w3.org/2001/XMLSchema\"")?;
-    let contiguous_codestream = decode_jpc(&mut reader)?;
+fn encode_jpc_document<W: io::Write>(
+    writer: &mut W,
+    reader: &mut BufReader<&File>,
+    representation: Representation,
+) -> Result<(), JPXMLError> {
+    writer.write_all(b"<?xml version=\"1.0\"?>\n").at(0)?;
+    writer.write_all(b"<xjp:jpxml xmlns:xjp=\"http://www.jpeg.org/jpxml/1.0\" xmlns:xs=\"http://www.w3.org/2001/XMLSchema\"").at(0)?;
+    let contiguous_codestream =
+        decode_jpc(reader).map_err(|source| JPXMLError::Codestream { offset: 0, source })?;
     encode_contiguous_codestream(writer, &representation, &contiguous_codestream, None)?;
-    writer.write_all(b"</xjp:jpxml>\n")?;
+    writer.write_all(b"</xjp:jpxml>\n").at(0)?;
     Ok(())
 }
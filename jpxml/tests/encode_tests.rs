@@ -0,0 +1,71 @@
+use std::{env, error::Error, fs, fs::File, io, path::Path, str::FromStr};
+
+use jpxml::{encode_jpc, JPXMLError, Representation};
+
+fn blue() -> File {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../jpc/tests/blue.j2k");
+    File::open(path).expect("file should exist")
+}
+
+#[test]
+fn test_encode_jpc() {
+    let mut xml: Vec<u8> = vec![];
+    encode_jpc(&mut xml, &blue(), Representation::Skeleton).expect("encoding should succeed");
+    let xml = String::from_utf8(xml).unwrap();
+    assert!(xml.starts_with("<?xml version=\"1.0\"?>\n"));
+    assert!(xml.contains("<xjp:jp2c type=\"box\">"));
+    assert!(xml.ends_with("</xjp:jpxml>\n"));
+}
+
+#[test]
+fn test_encode_unsupported_representation() {
+    // The codestream data of the representations other than the skeleton is
+    // not encoded yet
+    for representation in [Representation::FatSkeleton, Representation::Fat] {
+        let error =
+            encode_jpc(&mut vec![], &blue(), representation).expect_err("encoding should fail");
+        assert!(error.to_string().starts_with("unsupported representation"));
+        assert!(matches!(
+            error,
+            JPXMLError::UnsupportedRepresentation { offset: 0, .. }
+        ));
+    }
+}
+
+#[test]
+fn test_encode_errors() {
+    let error = Representation::from_str("thin").expect_err("parsing should fail");
+    assert!(matches!(error, JPXMLError::InvalidRepresentation { .. }));
+
+    // The error decoding the codestream is the source
+    let path = env::temp_dir().join(format!("encode_errors-{}.j2k", std::process::id()));
+    fs::write(&path, b"\xff\x4f\xff\x52").unwrap();
+    let error = encode_jpc(
+        &mut vec![],
+        &File::open(&path).unwrap(),
+        Representation::Skeleton,
+    )
+    .expect_err("encoding should fail");
+    fs::remove_file(&path).unwrap();
+    match &error {
+        JPXMLError::Codestream { offset: 0, .. } => {}
+        _ => panic!("unexpected error {:?}", error),
+    }
+    assert!(error.source().is_some());
+}
+
+#[test]
+fn test_encode_write_error() {
+    // A writer with room for the start of the document only
+    let mut xml = [0u8; 64];
+    let error = encode_jpc(&mut &mut xml[..], &blue(), Representation::Skeleton)
+        .expect_err("encoding should fail");
+    match &error {
+        JPXMLError::Io { offset: 0, source } => {
+            assert_eq!(source.kind(), io::ErrorKind::WriteZero)
+        }
+        _ => panic!("unexpected error {:?}", error),
+    }
+    let source = error.source().expect("error should have a source");
+    assert!(source.downcast_ref::<io::Error>().is_some());
+}